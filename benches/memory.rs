@@ -0,0 +1,178 @@
+// Benchmarks for address decoding: reads through the PET's memory map, using
+// BranchMemory's page table and the linear scan it replaced, and booting each
+// of the bundled Commodore systems.
+//
+//   cargo bench --bench memory
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use libnoentiendo::{
+  memory::{ActiveInterrupt, BlockMemory, BranchMemory, Memory, NullMemory},
+  platform::{HeadlessPlatform, Platform, SyncPlatform},
+  systems::{
+    c64::{C64System, C64SystemConfig, C64SystemRoms},
+    pet::{PetSystem, PetSystemConfig, PetSystemRoms},
+    vic::{Vic20System, Vic20SystemConfig, Vic20SystemRoms},
+    BuildableSystem,
+  },
+};
+
+/// The number of frames to run each system for when booting it.
+const BOOT_FRAMES: u64 = 60;
+
+/// Address decoding as BranchMemory did it before the page table: search
+/// every mapping on every access.
+#[derive(Default)]
+struct LinearBranchMemory {
+  mapping: Vec<(usize, Box<dyn Memory>)>,
+}
+
+impl LinearBranchMemory {
+  fn map(mut self, address: usize, memory: impl Memory + 'static) -> Self {
+    self.mapping.push((address, Box::new(memory)));
+    self
+  }
+
+  /// Find the index of the mapping which handles the given address, if any.
+  fn find(&self, address: u16) -> Option<usize> {
+    let mut found = None;
+
+    for (index, (start, _)) in self.mapping.iter().enumerate() {
+      if address as usize >= *start {
+        found = Some(index);
+      }
+    }
+
+    found
+  }
+}
+
+impl Memory for LinearBranchMemory {
+  fn read(&mut self, address: u16) -> u8 {
+    match self.find(address) {
+      Some(index) => {
+        let (start, memory) = &mut self.mapping[index];
+        memory.read(address - *start as u16)
+      }
+      None => 0,
+    }
+  }
+
+  fn peek(&self, address: u16) -> u8 {
+    match self.find(address) {
+      Some(index) => {
+        let (start, memory) = &self.mapping[index];
+        memory.peek(address - *start as u16)
+      }
+      None => 0,
+    }
+  }
+
+  fn write(&mut self, address: u16, value: u8) {
+    if let Some(index) = self.find(address) {
+      let (start, memory) = &mut self.mapping[index];
+      memory.write(address - *start as u16, value);
+    }
+  }
+
+  fn reset(&mut self) {}
+
+  fn poll(&mut self, _cycles_since_poll: u64, _total_cycle_count: u64) -> ActiveInterrupt {
+    ActiveInterrupt::None
+  }
+}
+
+/// Build the PET's memory map, with plain memory standing in for its chips.
+macro_rules! pet_memory {
+  ($memory:expr) => {
+    $memory
+      .map(0x0000, BlockMemory::ram(0x8000))
+      .map(0x8000, BlockMemory::ram(0x1000))
+      .map(0x9000, NullMemory::new())
+      .map(0xA000, NullMemory::new())
+      .map(0xB000, NullMemory::new())
+      .map(0xC000, BlockMemory::rom(0x2000))
+      .map(0xE000, BlockMemory::rom(0x0800))
+      .map(0xE810, BlockMemory::ram(0x10))
+      .map(0xE820, BlockMemory::ram(0x10))
+      .map(0xE840, BlockMemory::ram(0x10))
+      .map(0xF000, BlockMemory::rom(0x1000))
+  };
+}
+
+/// Read every address in turn.
+fn read_all(memory: &mut impl Memory) -> u32 {
+  (0..=0xFFFF)
+    .map(|address| memory.read(address) as u32)
+    .sum()
+}
+
+fn bench_decoding(c: &mut Criterion) {
+  let mut group = c.benchmark_group("pet_memory_map");
+
+  let mut memory = pet_memory!(BranchMemory::new());
+  group.bench_function("page_table", |b| {
+    b.iter(|| read_all(black_box(&mut memory)))
+  });
+
+  let mut memory = pet_memory!(LinearBranchMemory::default());
+  group.bench_function("linear_scan", |b| {
+    b.iter(|| read_all(black_box(&mut memory)))
+  });
+
+  group.finish();
+}
+
+fn bench_boot(c: &mut Criterion) {
+  let mut group = c.benchmark_group("boot");
+  group.sample_size(10);
+
+  group.bench_function("pet", |b| {
+    b.iter_batched(
+      || HeadlessPlatform::new().with_frames(BOOT_FRAMES),
+      |mut platform| {
+        let system = PetSystem::build(
+          PetSystemRoms::from_disk(),
+          PetSystemConfig::default(),
+          platform.provider(),
+        );
+        platform.run(system);
+      },
+      BatchSize::PerIteration,
+    )
+  });
+
+  group.bench_function("vic", |b| {
+    b.iter_batched(
+      || HeadlessPlatform::new().with_frames(BOOT_FRAMES),
+      |mut platform| {
+        let system = Vic20System::build(
+          Vic20SystemRoms::from_disk(None),
+          Vic20SystemConfig::default(),
+          platform.provider(),
+        );
+        platform.run(system);
+      },
+      BatchSize::PerIteration,
+    )
+  });
+
+  group.bench_function("c64", |b| {
+    b.iter_batched(
+      || HeadlessPlatform::new().with_frames(BOOT_FRAMES),
+      |mut platform| {
+        let system = C64System::build(
+          C64SystemRoms::from_disk(),
+          C64SystemConfig::default(),
+          platform.provider(),
+        );
+        platform.run(system);
+      },
+      BatchSize::PerIteration,
+    )
+  });
+
+  group.finish();
+}
+
+criterion_group!(benches, bench_decoding, bench_boot);
+criterion_main!(benches);
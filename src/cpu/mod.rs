@@ -1,6 +1,48 @@
+use crate::debugger::DebugHandler;
+use crate::state::{StateError, StateReader, StateWriter};
 use crate::trace::TraceHandler;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
 pub mod mos6502;
+pub mod w65c816;
+pub mod z80;
+
+/// A condition which stops the CPU from executing any further instructions.
+/// Once a CPU reports an error, it remains halted until it is reset.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CpuError {
+  /// The CPU executed a JAM (also known as KIL or HLT) opcode, which locks up
+  /// the processor on real hardware.
+  Jammed { address: u16, opcode: u8 },
+
+  /// An instruction used an addressing mode which is not valid for its
+  /// operation.
+  InvalidAddressingMode { address: u16, opcode: u8 },
+
+  /// The CPU executed a `STP` instruction, which stops its clock until it is
+  /// reset. The address includes the program bank on CPUs which have one.
+  Stopped { address: u32 },
+
+  /// The user quit from an attached debugger, which ends the session.
+  Quit,
+}
+
+impl fmt::Display for CpuError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      CpuError::Jammed { address, opcode } => {
+        write!(f, "CPU jammed by opcode {opcode:02X} at {address:04X}")
+      }
+      CpuError::InvalidAddressingMode { address, opcode } => write!(
+        f,
+        "invalid addressing mode for opcode {opcode:02X} at {address:04X}"
+      ),
+      CpuError::Stopped { address } => write!(f, "CPU stopped by STP at {address:06X}"),
+      CpuError::Quit => write!(f, "quit from the debugger"),
+    }
+  }
+}
 
 pub trait Cpu {
   /// Reset this CPU, clearing internal state.
@@ -9,12 +51,34 @@ pub trait Cpu {
   /// Attach the given handler to receive trace events from this CPU.
   fn attach_trace_handler(&mut self, trace: Box<dyn TraceHandler>);
 
+  /// Attach an interactive debugger, which is consulted before each instruction.
+  fn attach_debugger(&mut self, debugger: Box<dyn DebugHandler>);
+
+  /// Choose whether to execute in cycle-stepped mode, where every bus access
+  /// (including the dummy reads and writes of real hardware) takes its own
+  /// cycle and the memory is clocked after each one. This is slower, but lets
+  /// chips observe accesses at exactly the right time. CPUs which only support
+  /// stepping whole instructions ignore this.
+  fn set_cycle_stepped(&mut self, _enabled: bool) {}
+
   /// Return the number of cycles elapsed since the system last reset.
   fn get_cycle_count(&self) -> u64;
 
-  /// Execute a single instruction. Return the number of cycles elapsed.
-  fn tick(&mut self) -> u8;
+  /// Execute a single instruction. Return the number of cycles elapsed, or an
+  /// error if the CPU has halted.
+  fn tick(&mut self) -> Result<u8, CpuError>;
 
   /// Clean up any resources used by this CPU.
   fn cleanup(&mut self) -> Result<(), &str>;
+
+  /// Write the state of this CPU, followed by the state of its memory, to the
+  /// given save state.
+  fn save_state(&self, _state: &mut StateWriter) -> Result<(), StateError> {
+    Err(StateError::Unsupported)
+  }
+
+  /// Restore the state of this CPU and its memory from the given save state.
+  fn load_state(&mut self, _state: &mut StateReader) -> Result<(), StateError> {
+    Err(StateError::Unsupported)
+  }
 }
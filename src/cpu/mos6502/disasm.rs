@@ -0,0 +1,399 @@
+use super::Mos6502Variant;
+use crate::symbols::SymbolTable;
+
+/// The ways in which an instruction can specify its operand.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AddressingMode {
+  /// No operand (e.g. `INX`).
+  Implied,
+  /// Operates on the accumulator (e.g. `ASL A`).
+  Accumulator,
+  /// A one-byte constant (e.g. `LDA #$00`).
+  Immediate,
+  /// An address in the zero page (e.g. `LDA $00`).
+  ZeroPage,
+  /// A zero page address, indexed by X (e.g. `LDA $00,X`).
+  ZeroPageX,
+  /// A zero page address, indexed by Y (e.g. `LDX $00,Y`).
+  ZeroPageY,
+  /// A full two-byte address (e.g. `LDA $1234`).
+  Absolute,
+  /// A full address, indexed by X (e.g. `LDA $1234,X`).
+  AbsoluteX,
+  /// A full address, indexed by Y (e.g. `LDA $1234,Y`).
+  AbsoluteY,
+  /// A pointer to the target address (only used by `JMP ($1234)`).
+  Indirect,
+  /// A zero page pointer, indexed by X before dereferencing (e.g. `LDA ($00,X)`).
+  IndirectX,
+  /// A zero page pointer, indexed by Y after dereferencing (e.g. `LDA ($00),Y`).
+  IndirectY,
+  /// A zero page pointer, without indexing (65C02 only, e.g. `LDA ($00)`).
+  ZeroPageIndirect,
+  /// A pointer, indexed by X before dereferencing (65C02 only, `JMP ($1234,X)`).
+  AbsoluteIndirectX,
+  /// A signed offset from the following instruction (e.g. `BNE $1234`).
+  Relative,
+  /// A zero page address followed by a relative offset (65C02 only, e.g.
+  /// `BBR0 $00,$1234`).
+  ZeroPageRelative,
+}
+
+impl AddressingMode {
+  /// The number of operand bytes following the opcode.
+  pub fn operand_length(&self) -> u16 {
+    match self {
+      AddressingMode::Implied | AddressingMode::Accumulator => 0,
+      AddressingMode::Immediate
+      | AddressingMode::ZeroPage
+      | AddressingMode::ZeroPageX
+      | AddressingMode::ZeroPageY
+      | AddressingMode::IndirectX
+      | AddressingMode::IndirectY
+      | AddressingMode::ZeroPageIndirect
+      | AddressingMode::Relative => 1,
+      AddressingMode::Absolute
+      | AddressingMode::AbsoluteX
+      | AddressingMode::AbsoluteY
+      | AddressingMode::Indirect
+      | AddressingMode::AbsoluteIndirectX
+      | AddressingMode::ZeroPageRelative => 2,
+    }
+  }
+}
+
+#[rustfmt::skip]
+const NMOS_MNEMONICS: [&str; 256] = [
+  "BRK", "ORA", "JAM", "SLO", "NOP", "ORA", "ASL", "SLO", "PHP", "ORA", "ASL", "ANC", "NOP", "ORA", "ASL", "SLO",
+  "BPL", "ORA", "JAM", "SLO", "NOP", "ORA", "ASL", "SLO", "CLC", "ORA", "NOP", "SLO", "NOP", "ORA", "ASL", "SLO",
+  "JSR", "AND", "JAM", "RLA", "BIT", "AND", "ROL", "RLA", "PLP", "AND", "ROL", "ANC", "BIT", "AND", "ROL", "RLA",
+  "BMI", "AND", "JAM", "RLA", "NOP", "AND", "ROL", "RLA", "SEC", "AND", "NOP", "RLA", "NOP", "AND", "ROL", "RLA",
+  "RTI", "EOR", "JAM", "SRE", "NOP", "EOR", "LSR", "SRE", "PHA", "EOR", "LSR", "ALR", "JMP", "EOR", "LSR", "SRE",
+  "BVC", "EOR", "JAM", "SRE", "NOP", "EOR", "LSR", "SRE", "CLI", "EOR", "NOP", "SRE", "NOP", "EOR", "LSR", "SRE",
+  "RTS", "ADC", "JAM", "RRA", "NOP", "ADC", "ROR", "RRA", "PLA", "ADC", "ROR", "ARR", "JMP", "ADC", "ROR", "RRA",
+  "BVS", "ADC", "JAM", "RRA", "NOP", "ADC", "ROR", "RRA", "SEI", "ADC", "NOP", "RRA", "NOP", "ADC", "ROR", "RRA",
+  "NOP", "STA", "NOP", "SAX", "STY", "STA", "STX", "SAX", "DEY", "NOP", "TXA", "XAA", "STY", "STA", "STX", "SAX",
+  "BCC", "STA", "JAM", "AHX", "STY", "STA", "STX", "SAX", "TYA", "STA", "TXS", "TAS", "SHY", "STA", "SHX", "AHX",
+  "LDY", "LDA", "LDX", "LAX", "LDY", "LDA", "LDX", "LAX", "TAY", "LDA", "TAX", "LXA", "LDY", "LDA", "LDX", "LAX",
+  "BCS", "LDA", "JAM", "LAX", "LDY", "LDA", "LDX", "LAX", "CLV", "LDA", "TSX", "LAS", "LDY", "LDA", "LDX", "LAX",
+  "CPY", "CMP", "NOP", "DCP", "CPY", "CMP", "DEC", "DCP", "INY", "CMP", "DEX", "AXS", "CPY", "CMP", "DEC", "DCP",
+  "BNE", "CMP", "JAM", "DCP", "NOP", "CMP", "DEC", "DCP", "CLD", "CMP", "NOP", "DCP", "NOP", "CMP", "DEC", "DCP",
+  "CPX", "SBC", "NOP", "ISC", "CPX", "SBC", "INC", "ISC", "INX", "SBC", "NOP", "SBC", "CPX", "SBC", "INC", "ISC",
+  "BEQ", "SBC", "JAM", "ISC", "NOP", "SBC", "INC", "ISC", "SED", "SBC", "NOP", "ISC", "NOP", "SBC", "INC", "ISC",
+];
+
+#[rustfmt::skip]
+const CMOS_MNEMONICS: [&str; 256] = [
+  "BRK", "ORA", "NOP", "NOP", "TSB", "ORA", "ASL", "RMB0", "PHP", "ORA", "ASL", "NOP", "TSB", "ORA", "ASL", "BBR0",
+  "BPL", "ORA", "ORA", "NOP", "TRB", "ORA", "ASL", "RMB1", "CLC", "ORA", "INC", "NOP", "TRB", "ORA", "ASL", "BBR1",
+  "JSR", "AND", "NOP", "NOP", "BIT", "AND", "ROL", "RMB2", "PLP", "AND", "ROL", "NOP", "BIT", "AND", "ROL", "BBR2",
+  "BMI", "AND", "AND", "NOP", "BIT", "AND", "ROL", "RMB3", "SEC", "AND", "DEC", "NOP", "BIT", "AND", "ROL", "BBR3",
+  "RTI", "EOR", "NOP", "NOP", "NOP", "EOR", "LSR", "RMB4", "PHA", "EOR", "LSR", "NOP", "JMP", "EOR", "LSR", "BBR4",
+  "BVC", "EOR", "EOR", "NOP", "NOP", "EOR", "LSR", "RMB5", "CLI", "EOR", "PHY", "NOP", "NOP", "EOR", "LSR", "BBR5",
+  "RTS", "ADC", "NOP", "NOP", "STZ", "ADC", "ROR", "RMB6", "PLA", "ADC", "ROR", "NOP", "JMP", "ADC", "ROR", "BBR6",
+  "BVS", "ADC", "ADC", "NOP", "STZ", "ADC", "ROR", "RMB7", "SEI", "ADC", "PLY", "NOP", "JMP", "ADC", "ROR", "BBR7",
+  "BRA", "STA", "NOP", "NOP", "STY", "STA", "STX", "SMB0", "DEY", "BIT", "TXA", "NOP", "STY", "STA", "STX", "BBS0",
+  "BCC", "STA", "STA", "NOP", "STY", "STA", "STX", "SMB1", "TYA", "STA", "TXS", "NOP", "STZ", "STA", "STZ", "BBS1",
+  "LDY", "LDA", "LDX", "NOP", "LDY", "LDA", "LDX", "SMB2", "TAY", "LDA", "TAX", "NOP", "LDY", "LDA", "LDX", "BBS2",
+  "BCS", "LDA", "LDA", "NOP", "LDY", "LDA", "LDX", "SMB3", "CLV", "LDA", "TSX", "NOP", "LDY", "LDA", "LDX", "BBS3",
+  "CPY", "CMP", "NOP", "NOP", "CPY", "CMP", "DEC", "SMB4", "INY", "CMP", "DEX", "NOP", "CPY", "CMP", "DEC", "BBS4",
+  "BNE", "CMP", "CMP", "NOP", "NOP", "CMP", "DEC", "SMB5", "CLD", "CMP", "PHX", "NOP", "NOP", "CMP", "DEC", "BBS5",
+  "CPX", "SBC", "NOP", "NOP", "CPX", "SBC", "INC", "SMB6", "INX", "SBC", "NOP", "NOP", "CPX", "SBC", "INC", "BBS6",
+  "BEQ", "SBC", "SBC", "NOP", "NOP", "SBC", "INC", "SMB7", "SED", "SBC", "PLX", "NOP", "NOP", "SBC", "INC", "BBS7",
+];
+
+/// Mnemonics which are only used by the illegal opcodes of the NMOS 6502.
+const NMOS_ILLEGAL_MNEMONICS: [&str; 20] = [
+  "SLO", "RLA", "SRE", "RRA", "SAX", "LAX", "DCP", "ISC", "ANC", "ALR", "ARR", "XAA", "AXS", "SHY",
+  "SHX", "AHX", "TAS", "LAS", "LXA", "JAM",
+];
+
+/// Determine the addressing mode of an opcode on the NMOS 6502. This mirrors
+/// the decoding in `fetch_operand_address`, which is based on the low 5 bits.
+fn nmos_addressing_mode(opcode: u8) -> AddressingMode {
+  match opcode & 0x1F {
+    0x00 => match opcode {
+      0x20 => AddressingMode::Absolute,
+      0x00 | 0x40 | 0x60 => AddressingMode::Implied,
+      _ => AddressingMode::Immediate,
+    },
+    0x01 | 0x03 => AddressingMode::IndirectX,
+    0x02 => match opcode {
+      0x00..=0x7F => AddressingMode::Implied,
+      _ => AddressingMode::Immediate,
+    },
+    0x04..=0x07 => AddressingMode::ZeroPage,
+    0x08 | 0x18 | 0x1A => AddressingMode::Implied,
+    0x09 | 0x0B => AddressingMode::Immediate,
+    0x0A => match opcode {
+      0x00..=0x7F => AddressingMode::Accumulator,
+      _ => AddressingMode::Implied,
+    },
+    0x0C..=0x0F => match opcode {
+      0x6C => AddressingMode::Indirect,
+      _ => AddressingMode::Absolute,
+    },
+    0x10 => AddressingMode::Relative,
+    0x11 | 0x13 => AddressingMode::IndirectY,
+    0x12 => AddressingMode::Implied,
+    0x14 | 0x15 => AddressingMode::ZeroPageX,
+    0x16 | 0x17 => match opcode & 0xC0 {
+      0x80 => AddressingMode::ZeroPageY,
+      _ => AddressingMode::ZeroPageX,
+    },
+    0x19 | 0x1B => AddressingMode::AbsoluteY,
+    0x1C | 0x1D => AddressingMode::AbsoluteX,
+    0x1E | 0x1F => match opcode & 0xC0 {
+      0x80 => AddressingMode::AbsoluteY,
+      _ => AddressingMode::AbsoluteX,
+    },
+    _ => unreachable!(),
+  }
+}
+
+/// Determine the addressing mode of an opcode on the 65C02, which shares most
+/// modes with the NMOS 6502 but replaces its illegal opcodes.
+fn cmos_addressing_mode(opcode: u8) -> AddressingMode {
+  match opcode {
+    0x80 => AddressingMode::Relative,
+    0x14 => AddressingMode::ZeroPage,
+    0x1C | 0x5C | 0x9C | 0xDC | 0xFC => AddressingMode::Absolute,
+    0x9E => AddressingMode::AbsoluteX,
+    0x1A | 0x3A => AddressingMode::Accumulator,
+    0x7C => AddressingMode::AbsoluteIndirectX,
+    _ => match opcode & 0x0F {
+      0x02 if opcode & 0x10 != 0 => AddressingMode::ZeroPageIndirect,
+      0x02 => AddressingMode::Immediate,
+      0x03 | 0x0B => AddressingMode::Implied,
+      0x07 => AddressingMode::ZeroPage,
+      0x0F => AddressingMode::ZeroPageRelative,
+      _ => nmos_addressing_mode(opcode),
+    },
+  }
+}
+
+/// A decoded instruction, without its operand.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Instruction {
+  pub opcode: u8,
+  pub mnemonic: &'static str,
+  pub mode: AddressingMode,
+}
+
+impl Instruction {
+  /// Decode the given opcode, as interpreted by the given processor variant.
+  pub fn decode(opcode: u8, variant: Mos6502Variant) -> Self {
+    let (mnemonic, mode) = match variant {
+      Mos6502Variant::NMOS => (
+        NMOS_MNEMONICS[opcode as usize],
+        nmos_addressing_mode(opcode),
+      ),
+      Mos6502Variant::CMOS => (
+        CMOS_MNEMONICS[opcode as usize],
+        cmos_addressing_mode(opcode),
+      ),
+    };
+
+    Self {
+      opcode,
+      mnemonic,
+      mode,
+    }
+  }
+
+  /// Whether this opcode is part of the documented instruction set of its
+  /// processor variant (as opposed to an illegal opcode or a reserved NOP).
+  pub fn is_documented(&self) -> bool {
+    match (self.mnemonic, self.opcode) {
+      ("NOP", 0xEA) => true,
+      ("NOP", _) => false,
+      ("SBC", 0xEB) => false,
+      (mnemonic, _) => !NMOS_ILLEGAL_MNEMONICS.contains(&mnemonic),
+    }
+  }
+
+  /// The total length of this instruction in bytes, including the opcode.
+  pub fn length(&self) -> u16 {
+    1 + self.mode.operand_length()
+  }
+
+  /// Format this instruction in assembler syntax, given the address of the
+  /// opcode and the operand bytes following it. Missing operand bytes are
+  /// treated as zero.
+  pub fn format(&self, address: u16, operand: &[u8]) -> String {
+    self.format_with_symbols(address, operand, &SymbolTable::new())
+  }
+
+  /// Format this instruction like [`Instruction::format`], but with addresses
+  /// in the operand shown by name where the symbol table has one. Zero page
+  /// addresses are only named if a symbol is exactly there, since neighboring
+  /// zero page locations are usually unrelated.
+  pub fn format_with_symbols(&self, address: u16, operand: &[u8], symbols: &SymbolTable) -> String {
+    let byte = |index: usize| operand.get(index).copied().unwrap_or(0);
+    let word = (byte(1) as u16) << 8 | byte(0) as u16;
+    let branch = |offset: u8, length: u16| {
+      symbols.format(
+        address
+          .wrapping_add(length)
+          .wrapping_add(offset as i8 as u16),
+      )
+    };
+    let absolute = || symbols.format(word);
+    let zero_page = |index: usize| match symbols.name(byte(index) as u16) {
+      Some(name) => name.to_owned(),
+      None => format!("${:02X}", byte(index)),
+    };
+
+    let operand = match self.mode {
+      AddressingMode::Implied => return self.mnemonic.to_owned(),
+      AddressingMode::Accumulator => "A".to_owned(),
+      AddressingMode::Immediate => format!("#${:02X}", byte(0)),
+      AddressingMode::ZeroPage => zero_page(0),
+      AddressingMode::ZeroPageX => format!("{},X", zero_page(0)),
+      AddressingMode::ZeroPageY => format!("{},Y", zero_page(0)),
+      AddressingMode::Absolute => absolute(),
+      AddressingMode::AbsoluteX => format!("{},X", absolute()),
+      AddressingMode::AbsoluteY => format!("{},Y", absolute()),
+      AddressingMode::Indirect => format!("({})", absolute()),
+      AddressingMode::IndirectX => format!("({},X)", zero_page(0)),
+      AddressingMode::IndirectY => format!("({}),Y", zero_page(0)),
+      AddressingMode::ZeroPageIndirect => format!("({})", zero_page(0)),
+      AddressingMode::AbsoluteIndirectX => format!("({},X)", absolute()),
+      AddressingMode::Relative => branch(byte(0), 2),
+      AddressingMode::ZeroPageRelative => format!("{},{}", zero_page(0), branch(byte(1), 3)),
+    };
+
+    format!("{} {}", self.mnemonic, operand)
+  }
+}
+
+/// Disassemble the instruction at the start of `bytes`, which is located at
+/// `address`. Returns the assembler text and the length of the instruction.
+pub fn disassemble(address: u16, bytes: &[u8], variant: Mos6502Variant) -> (String, u16) {
+  let instruction = Instruction::decode(bytes.first().copied().unwrap_or(0), variant);
+  let operand = bytes.get(1..).unwrap_or(&[]);
+
+  (instruction.format(address, operand), instruction.length())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cpu::{mos6502::Mos6502, Cpu};
+  use crate::memory::{BlockMemory, Memory};
+
+  fn nmos(bytes: &[u8]) -> (String, u16) {
+    disassemble(0xC000, bytes, Mos6502Variant::NMOS)
+  }
+
+  fn cmos(bytes: &[u8]) -> (String, u16) {
+    disassemble(0xC000, bytes, Mos6502Variant::CMOS)
+  }
+
+  #[test]
+  fn test_official_opcodes() {
+    assert_eq!(("BRK".to_owned(), 1), nmos(&[0x00]));
+    assert_eq!(("LDA #$42".to_owned(), 2), nmos(&[0xA9, 0x42]));
+    assert_eq!(("STA $D020".to_owned(), 3), nmos(&[0x8D, 0x20, 0xD0]));
+    assert_eq!(("LDX $10,Y".to_owned(), 2), nmos(&[0xB6, 0x10]));
+    assert_eq!(("LDA ($FB),Y".to_owned(), 2), nmos(&[0xB1, 0xFB]));
+    assert_eq!(("JMP ($0314)".to_owned(), 3), nmos(&[0x6C, 0x14, 0x03]));
+    assert_eq!(("ASL A".to_owned(), 1), nmos(&[0x0A]));
+    assert_eq!(("JSR $FFD2".to_owned(), 3), nmos(&[0x20, 0xD2, 0xFF]));
+  }
+
+  #[test]
+  fn test_relative() {
+    assert_eq!(("BNE $BFFE".to_owned(), 2), nmos(&[0xD0, 0xFC]));
+    assert_eq!(("BEQ $C012".to_owned(), 2), nmos(&[0xF0, 0x10]));
+    assert_eq!(("BRA $C002".to_owned(), 2), cmos(&[0x80, 0x00]));
+    assert_eq!(("BBS7 $12,$C000".to_owned(), 3), cmos(&[0xFF, 0x12, 0xFD]));
+  }
+
+  #[test]
+  fn test_symbols() {
+    let mut symbols = SymbolTable::new();
+    symbols.insert("CHROUT", 0xFFD2);
+    symbols.insert("loop", 0xBFF0);
+    symbols.insert("TXTPTR", 0x7A);
+
+    let format = |bytes: &[u8]| {
+      let instruction = Instruction::decode(bytes[0], Mos6502Variant::NMOS);
+      instruction.format_with_symbols(0xC000, &bytes[1..], &symbols)
+    };
+
+    assert_eq!("JSR CHROUT", format(&[0x20, 0xD2, 0xFF]));
+    assert_eq!("JMP (CHROUT+2)", format(&[0x6C, 0xD4, 0xFF]));
+    assert_eq!("BNE loop+14", format(&[0xD0, 0xFC]));
+    assert_eq!("LDA (TXTPTR),Y", format(&[0xB1, 0x7A]));
+    assert_eq!("LDA $7B", format(&[0xA5, 0x7B]));
+    assert_eq!("LDA #$7A", format(&[0xA9, 0x7A]));
+  }
+
+  #[test]
+  fn test_illegal_opcodes() {
+    assert_eq!(("JAM".to_owned(), 1), nmos(&[0x02]));
+    assert_eq!(("JAM".to_owned(), 1), nmos(&[0x12]));
+    assert_eq!(("LAX ($10,X)".to_owned(), 2), nmos(&[0xA3, 0x10]));
+    assert_eq!(("SAX $10,Y".to_owned(), 2), nmos(&[0x97, 0x10]));
+    assert_eq!(("SHX $1000,Y".to_owned(), 3), nmos(&[0x9E, 0x00, 0x10]));
+    assert_eq!(("NOP $1000,X".to_owned(), 3), nmos(&[0x1C, 0x00, 0x10]));
+    assert_eq!(("NOP".to_owned(), 1), nmos(&[0xFA]));
+  }
+
+  #[test]
+  fn test_cmos_opcodes() {
+    assert_eq!(("LDA ($10)".to_owned(), 2), cmos(&[0xB2, 0x10]));
+    assert_eq!(("STZ $1000".to_owned(), 3), cmos(&[0x9C, 0x00, 0x10]));
+    assert_eq!(("STZ $1000,X".to_owned(), 3), cmos(&[0x9E, 0x00, 0x10]));
+    assert_eq!(("TRB $10".to_owned(), 2), cmos(&[0x14, 0x10]));
+    assert_eq!(("TSB $1000".to_owned(), 3), cmos(&[0x0C, 0x00, 0x10]));
+    assert_eq!(("RMB1 $10".to_owned(), 2), cmos(&[0x17, 0x10]));
+    assert_eq!(("JMP ($1000,X)".to_owned(), 3), cmos(&[0x7C, 0x00, 0x10]));
+    assert_eq!(("INC A".to_owned(), 1), cmos(&[0x1A]));
+    assert_eq!(("PHX".to_owned(), 1), cmos(&[0xDA]));
+    assert_eq!(("NOP #$00".to_owned(), 2), cmos(&[0x02, 0x00]));
+    assert_eq!(("NOP".to_owned(), 1), cmos(&[0x03]));
+  }
+
+  #[test]
+  fn test_documented_opcodes() {
+    let count = |variant| {
+      (0..=0xFF)
+        .filter(|&opcode| Instruction::decode(opcode, variant).is_documented())
+        .count()
+    };
+
+    assert_eq!(151, count(Mos6502Variant::NMOS));
+    assert_eq!(210, count(Mos6502Variant::CMOS));
+  }
+
+  #[test]
+  fn test_lengths_match_execution() {
+    for variant in [Mos6502Variant::NMOS, Mos6502Variant::CMOS] {
+      for opcode in 0..=0xFF {
+        let instruction = Instruction::decode(opcode, variant);
+
+        if let "BRK" | "JSR" | "RTI" | "RTS" | "JMP" | "JAM" = instruction.mnemonic {
+          continue;
+        }
+
+        // zero operands make every branch land on the next instruction
+        let mut memory = BlockMemory::ram(0x10000);
+        memory.write(0x0200, opcode);
+
+        let mut cpu = Mos6502::new(memory, variant);
+        cpu.registers.pc.load(0x0200);
+        cpu.tick().unwrap();
+
+        assert_eq!(
+          0x0200 + instruction.length(),
+          cpu.registers.pc.address(),
+          "length of {} (opcode {:02X})",
+          instruction.mnemonic,
+          opcode
+        );
+      }
+    }
+  }
+}
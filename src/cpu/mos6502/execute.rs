@@ -3,21 +3,22 @@ use crate::cpu::mos6502::{
   registers::{flags, Alu},
   InterruptHandler, MemoryIO, Mos6502, Stack,
 };
+use crate::cpu::CpuError;
 
 use super::Mos6502Variant;
 
 pub trait Execute {
   /// Execute the given opcode, returning either the number of cycles used or an error.
-  fn execute(&mut self, opcode: u8) -> Result<u8, ()>;
+  fn execute(&mut self, opcode: u8) -> Result<u8, CpuError>;
 }
 
 impl Execute for Mos6502 {
-  fn execute(&mut self, opcode: u8) -> Result<u8, ()> {
+  fn execute(&mut self, opcode: u8) -> Result<u8, CpuError> {
     match opcode {
       // === LOAD ===
       0xA1 | 0xA5 | 0xA9 | 0xAD | 0xB1 | 0xB2 | 0xB5 | 0xB9 | 0xBD => {
         // LDA
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         self.registers.a = value;
         self.registers.sr.set_nz(value);
         Ok(cycles)
@@ -25,7 +26,7 @@ impl Execute for Mos6502 {
 
       0xA2 | 0xA6 | 0xAE | 0xB6 | 0xBE => {
         // LDX
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         self.registers.x = value;
         self.registers.sr.set_nz(value);
         Ok(cycles)
@@ -33,7 +34,7 @@ impl Execute for Mos6502 {
 
       0xA0 | 0xA4 | 0xAC | 0xB4 | 0xBC => {
         // LDY
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         self.registers.y = value;
         self.registers.sr.set_nz(value);
         Ok(cycles)
@@ -42,21 +43,21 @@ impl Execute for Mos6502 {
       // === STORE ===
       0x81 | 0x85 | 0x8D | 0x91 | 0x92 | 0x95 | 0x99 | 0x9D => {
         // STA
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         self.write(address, self.registers.a);
         Ok(cycles)
       }
 
       // STX
       0x86 | 0x8E | 0x96 => {
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         self.write(address, self.registers.x);
         Ok(cycles)
       }
 
       // STY
       0x84 | 0x8C | 0x94 => {
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         self.write(address, self.registers.y);
         Ok(cycles)
       }
@@ -64,35 +65,41 @@ impl Execute for Mos6502 {
       // === TRANSFER ===
       0xAA => {
         // TAX
+        self.dummy_fetch();
         self.registers.x = self.registers.a;
         self.registers.sr.set_nz(self.registers.a);
         Ok(2)
       }
       0xA8 => {
         // TAY
+        self.dummy_fetch();
         self.registers.y = self.registers.a;
         self.registers.sr.set_nz(self.registers.a);
         Ok(2)
       }
       0xBA => {
         // TSX
+        self.dummy_fetch();
         self.registers.x = self.registers.sp.get();
         self.registers.sr.set_nz(self.registers.sp.get());
         Ok(2)
       }
       0x8A => {
         // TXA
+        self.dummy_fetch();
         self.registers.a = self.registers.x;
         self.registers.sr.set_nz(self.registers.x);
         Ok(2)
       }
       0x9A => {
         // TXS
+        self.dummy_fetch();
         self.registers.sp.set(self.registers.x);
         Ok(2)
       }
       0x98 => {
         // TYA
+        self.dummy_fetch();
         self.registers.a = self.registers.y;
         self.registers.sr.set_nz(self.registers.y);
         Ok(2)
@@ -101,16 +108,20 @@ impl Execute for Mos6502 {
       // === STACK ===
       0x48 => {
         // PHA
+        self.dummy_fetch();
         self.push(self.registers.a);
         Ok(3)
       }
       0x08 => {
         // PHP
+        self.dummy_fetch();
         self.push(self.registers.sr.get() | flags::BREAK);
         Ok(3)
       }
       0x68 => {
         // PLA
+        self.dummy_fetch();
+        self.dummy_read(self.registers.sp.address());
         let value = self.pop();
         self.registers.a = value;
         self.registers.sr.set_nz(value);
@@ -118,6 +129,8 @@ impl Execute for Mos6502 {
       }
       0x28 => {
         // PLP
+        self.dummy_fetch();
+        self.dummy_read(self.registers.sp.address());
         let status = self.pop();
         self.registers.sr.load(status);
         Ok(4)
@@ -126,6 +139,7 @@ impl Execute for Mos6502 {
       // === SHIFT ===
       0x0A => {
         // ASL a
+        self.dummy_fetch();
         let value = self.registers.a;
         self.registers.a = value << 1;
 
@@ -135,12 +149,10 @@ impl Execute for Mos6502 {
       }
       0x06 | 0x0E | 0x16 | 0x1E => {
         // ASL
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         let value = self.read(address);
 
-        if let Mos6502Variant::NMOS = self.variant {
-          self.write(address, value);
-        }
+        self.write_back(address, value);
         let result = value << 1;
 
         self.registers.sr.write(flags::CARRY, value & 0x80 != 0);
@@ -151,6 +163,7 @@ impl Execute for Mos6502 {
 
       0x4A => {
         // LSR a
+        self.dummy_fetch();
         let value = self.registers.a;
         self.registers.a = value >> 1;
 
@@ -160,12 +173,10 @@ impl Execute for Mos6502 {
       }
       0x46 | 0x4E | 0x56 | 0x5E => {
         // LSR
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         let value = self.read(address);
 
-        if let Mos6502Variant::NMOS = self.variant {
-          self.write(address, value);
-        }
+        self.write_back(address, value);
 
         let result = value >> 1;
 
@@ -177,6 +188,7 @@ impl Execute for Mos6502 {
 
       0x2A => {
         // ROL a
+        self.dummy_fetch();
         let value = self.registers.a;
         let result = (value << 1) | (self.registers.sr.read(flags::CARRY) as u8);
 
@@ -187,12 +199,10 @@ impl Execute for Mos6502 {
       }
       0x26 | 0x2E | 0x36 | 0x3E => {
         // ROL
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         let value = self.read(address);
 
-        if let Mos6502Variant::NMOS = self.variant {
-          self.write(address, value);
-        }
+        self.write_back(address, value);
 
         let result = (value << 1) | (self.registers.sr.read(flags::CARRY) as u8);
 
@@ -204,6 +214,7 @@ impl Execute for Mos6502 {
 
       0x6A => {
         // ROR a
+        self.dummy_fetch();
         let value = self.registers.a;
         let result = (value >> 1) | (self.registers.sr.read(flags::CARRY) as u8) << 7;
 
@@ -214,12 +225,10 @@ impl Execute for Mos6502 {
       }
       0x66 | 0x6E | 0x76 | 0x7E => {
         // ROR
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         let value = self.read(address);
 
-        if let Mos6502Variant::NMOS = self.variant {
-          self.write(address, value);
-        }
+        self.write_back(address, value);
 
         let result = value >> 1 | (self.registers.sr.read(flags::CARRY) as u8) << 7;
 
@@ -232,7 +241,7 @@ impl Execute for Mos6502 {
       // === LOGIC ===
       0x21 | 0x25 | 0x29 | 0x2D | 0x31 | 0x32 | 0x35 | 0x39 | 0x3D => {
         // AND
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         self.registers.a &= value;
         self.registers.sr.set_nz(self.registers.a);
         Ok(cycles)
@@ -240,7 +249,7 @@ impl Execute for Mos6502 {
 
       0x24 | 0x2C => {
         // BIT
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         self.registers.sr.write(flags::NEGATIVE, value & 0x80 != 0);
         self.registers.sr.write(flags::OVERFLOW, value & 0x40 != 0);
         self
@@ -252,7 +261,7 @@ impl Execute for Mos6502 {
 
       0x41 | 0x45 | 0x49 | 0x4D | 0x51 | 0x52 | 0x55 | 0x59 | 0x5D => {
         // EOR
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         self.registers.a ^= value;
         self.registers.sr.set_nz(self.registers.a);
         Ok(cycles)
@@ -260,7 +269,7 @@ impl Execute for Mos6502 {
 
       0x01 | 0x05 | 0x09 | 0x0D | 0x11 | 0x12 | 0x15 | 0x19 | 0x1D => {
         // ORA
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         self.registers.a |= value;
         self.registers.sr.set_nz(self.registers.a);
         Ok(cycles)
@@ -269,44 +278,47 @@ impl Execute for Mos6502 {
       // === ARITHMETIC ===
       0x61 | 0x65 | 0x69 | 0x6D | 0x71 | 0x72 | 0x75 | 0x79 | 0x7D => {
         // ADC
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         self.registers.alu_add(value);
+        self.decimal_fix_up();
         Ok(cycles)
       }
 
       0xC1 | 0xC5 | 0xC9 | 0xCD | 0xD1 | 0xD2 | 0xD5 | 0xD9 | 0xDD => {
         // CMP
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         self.registers.alu_compare(self.registers.a, value);
         Ok(cycles)
       }
 
       0xE0 | 0xE4 | 0xEC => {
         // CPX
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         self.registers.alu_compare(self.registers.x, value);
         Ok(cycles)
       }
 
       0xC0 | 0xC4 | 0xCC => {
         // CPY
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         self.registers.alu_compare(self.registers.y, value);
         Ok(cycles)
       }
 
       0xE1 | 0xE5 | 0xE9 | 0xED | 0xF1 | 0xF2 | 0xF5 | 0xF9 | 0xFD => {
         // SBC
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         self.registers.alu_subtract(value);
+        self.decimal_fix_up();
         Ok(cycles)
       }
 
       // === INCREMENT ===
       0xC6 | 0xCE | 0xD6 | 0xDE => {
         // DEC
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         let value = self.read(address);
+        self.write_back(address, value);
         let result = value.wrapping_sub(1);
         self.registers.sr.set_nz(result);
         self.write(address, result);
@@ -315,6 +327,7 @@ impl Execute for Mos6502 {
 
       0xCA => {
         // DEX
+        self.dummy_fetch();
         self.registers.x = self.registers.x.wrapping_sub(1);
         self.registers.sr.set_nz(self.registers.x);
         Ok(2)
@@ -322,6 +335,7 @@ impl Execute for Mos6502 {
 
       0x88 => {
         // DEY
+        self.dummy_fetch();
         self.registers.y = self.registers.y.wrapping_sub(1);
         self.registers.sr.set_nz(self.registers.y);
         Ok(2)
@@ -329,8 +343,9 @@ impl Execute for Mos6502 {
 
       0xE6 | 0xEE | 0xF6 | 0xFE => {
         // INC
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         let value = self.read(address);
+        self.write_back(address, value);
         let result = value.wrapping_add(1);
         self.registers.sr.set_nz(result);
         self.write(address, result);
@@ -339,6 +354,7 @@ impl Execute for Mos6502 {
 
       0xE8 => {
         // INX
+        self.dummy_fetch();
         self.registers.x = self.registers.x.wrapping_add(1);
         self.registers.sr.set_nz(self.registers.x);
         Ok(2)
@@ -346,6 +362,7 @@ impl Execute for Mos6502 {
 
       0xC8 => {
         // INY
+        self.dummy_fetch();
         self.registers.y = self.registers.y.wrapping_add(1);
         self.registers.sr.set_nz(self.registers.y);
         Ok(2)
@@ -354,6 +371,7 @@ impl Execute for Mos6502 {
       // === CONTROL ===
       0x00 => {
         // BRK
+        self.dummy_fetch();
         self.registers.pc.increment();
         self.interrupt(true, true);
         Ok(7)
@@ -370,7 +388,10 @@ impl Execute for Mos6502 {
               let hi = self.read(indirect & 0xFF00);
               ((hi as u16) << 8 | lo as u16, 5)
             } else {
-              // normal behavior
+              // normal behavior (the 65C02 takes an extra cycle to fix the bug)
+              if self.variant == Mos6502Variant::CMOS {
+                self.dummy_read(self.registers.pc.address().wrapping_sub(1));
+              }
               (self.read_word(indirect), 5)
             }
           }
@@ -382,14 +403,20 @@ impl Execute for Mos6502 {
       }
       0x20 => {
         // JSR absolute
-        let address = self.fetch_word();
-        self.push_word(self.registers.pc.address().wrapping_sub(1));
-
-        self.registers.pc.load(address);
+        // The high byte of the target is only fetched after pushing the
+        // return address, which points to it.
+        let lo = self.fetch();
+        self.dummy_read(self.registers.sp.address());
+        self.push_word(self.registers.pc.address());
+        let hi = self.fetch();
+
+        self.registers.pc.load((hi as u16) << 8 | lo as u16);
         Ok(6)
       }
       0x40 => {
         // RTI
+        self.dummy_fetch();
+        self.dummy_read(self.registers.sp.address());
         let status = self.pop();
         self.registers.sr.load(status);
         let dest = self.pop_word();
@@ -398,8 +425,11 @@ impl Execute for Mos6502 {
       }
       0x60 => {
         // RTS
-        let dest = self.pop_word().wrapping_add(1);
-        self.registers.pc.load(dest);
+        self.dummy_fetch();
+        self.dummy_read(self.registers.sp.address());
+        let dest = self.pop_word();
+        self.dummy_read(dest);
+        self.registers.pc.load(dest.wrapping_add(1));
         Ok(6)
       }
 
@@ -420,7 +450,7 @@ impl Execute for Mos6502 {
         };
 
         if condition {
-          self.registers.pc.offset(offset);
+          self.branch(offset);
           Ok(3)
         } else {
           Ok(2)
@@ -429,6 +459,7 @@ impl Execute for Mos6502 {
 
       // === FLAGS ===
       0x18 | 0xD8 | 0x58 | 0xB8 => {
+        self.dummy_fetch();
         self.registers.sr.clear(match opcode {
           0x18 => flags::CARRY,     // CLC
           0xD8 => flags::DECIMAL,   // CLD
@@ -441,6 +472,7 @@ impl Execute for Mos6502 {
       }
 
       0x38 | 0xF8 | 0x78 => {
+        self.dummy_fetch();
         self.registers.sr.set(match opcode {
           0x38 => flags::CARRY,     // SEC
           0xF8 => flags::DECIMAL,   // SED
@@ -454,6 +486,7 @@ impl Execute for Mos6502 {
       // === NOP ===
       0xEA => {
         // NOP
+        self.dummy_fetch();
         Ok(2)
       }
 
@@ -466,19 +499,56 @@ impl Execute for Mos6502 {
 }
 
 impl Mos6502 {
-  fn execute_nmos_extensions(&mut self, opcode: u8) -> Result<u8, ()> {
+  /// Spend the cycle between reading a value and writing back the result of a
+  /// read-modify-write instruction. The NMOS 6502 writes the unmodified value
+  /// back, while the 65C02 reads it again.
+  fn write_back(&mut self, address: u16, value: u8) {
+    match self.variant {
+      Mos6502Variant::NMOS => self.write(address, value),
+      Mos6502Variant::CMOS => self.dummy_read(address),
+    }
+  }
+
+  /// Take a branch. This costs an extra cycle, plus another if the target is
+  /// on a different page, during which the 6502 reads from the target address
+  /// before its high byte is fixed up.
+  fn branch(&mut self, offset: i8) {
+    let from = self.registers.pc.address();
+    self.dummy_fetch();
+    self.registers.pc.offset(offset);
+
+    let to = self.registers.pc.address();
+    if from & 0xFF00 != to & 0xFF00 {
+      self.dummy_read(from & 0xFF00 | to & 0x00FF);
+    }
+  }
+
+  /// The 65C02 spends an extra cycle correcting the flags after an addition or
+  /// subtraction in decimal mode.
+  fn decimal_fix_up(&mut self) {
+    if self.variant == Mos6502Variant::CMOS && self.registers.sr.read(flags::DECIMAL) {
+      self.dummy_fetch();
+    }
+  }
+
+  fn execute_nmos_extensions(&mut self, opcode: u8) -> Result<u8, CpuError> {
     match opcode {
       // === ILLEGAL OPCODES ===
       0x02 | 0x22 | 0x42 | 0x62 => {
         // STP or KIL or JAM or HLT depending on who you ask
-        println!("Execution stopped");
-        Err(())
+        // (the other JAM opcodes share the 65C02's (Indirect) addressing mode,
+        // and are caught when fetching the operand)
+        Err(CpuError::Jammed {
+          address: self.registers.pc.address().wrapping_sub(1),
+          opcode,
+        })
       }
 
       0x03 | 0x07 | 0x0F | 0x13 | 0x17 | 0x1B | 0x1F => {
         // SLO: ASL -> ORA
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         let value = self.read(address);
+        self.write_back(address, value);
         self.registers.sr.write(flags::CARRY, value & 0x80 != 0);
 
         let result = value << 1;
@@ -492,8 +562,9 @@ impl Mos6502 {
 
       0x23 | 0x27 | 0x2F | 0x33 | 0x37 | 0x3B | 0x3F => {
         // RLA: ROL -> AND
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         let value = self.read(address);
+        self.write_back(address, value);
 
         let result = (value << 1) | (self.registers.sr.read(flags::CARRY) as u8);
         self.registers.sr.write(flags::CARRY, result & 0x80 != 0);
@@ -507,8 +578,9 @@ impl Mos6502 {
 
       0x43 | 0x47 | 0x4F | 0x53 | 0x57 | 0x5B | 0x5F => {
         // SRE: LSR -> EOR
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         let value = self.read(address);
+        self.write_back(address, value);
         let result = value >> 1;
 
         self.registers.sr.write(flags::CARRY, value & 0x01 != 0);
@@ -522,8 +594,9 @@ impl Mos6502 {
 
       0x63 | 0x67 | 0x6F | 0x73 | 0x77 | 0x7B | 0x7F => {
         // RRA: ROR -> ADC
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         let value = self.read(address);
+        self.write_back(address, value);
         let result = value >> 1 | (self.registers.sr.read(flags::CARRY) as u8) << 7;
 
         self.registers.sr.write(flags::CARRY, value & 0x01 != 0);
@@ -537,7 +610,7 @@ impl Mos6502 {
 
       0x83 | 0x87 | 0x8F | 0x97 => {
         // SAX: AND -> STA
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         let value = self.registers.x & self.registers.a;
         self.registers.sr.set_nz(value);
         self.write(address, value);
@@ -547,7 +620,7 @@ impl Mos6502 {
 
       0xA3 | 0xA7 | 0xAF | 0xB3 | 0xB7 | 0xBF => {
         // LAX: LDA & LDX
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         self.registers.a = value;
         self.registers.x = value;
         self.registers.sr.set_nz(value);
@@ -559,8 +632,9 @@ impl Mos6502 {
         // DCP: DEC + SEC
         self.registers.sr.set(flags::CARRY);
 
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         let value = self.read(address);
+        self.write_back(address, value);
         let result = value.wrapping_sub(1);
         self.registers.sr.set_nz(result);
         self.write(address, result);
@@ -570,8 +644,9 @@ impl Mos6502 {
 
       0xE3 | 0xE7 | 0xEF | 0xF3 | 0xF7 | 0xFB | 0xFF => {
         // ISC: INC => SBC
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         let value = self.read(address);
+        self.write_back(address, value);
         let result = value.wrapping_add(1);
         self.registers.alu_subtract(value);
         self.registers.sr.set_nz(result);
@@ -582,7 +657,7 @@ impl Mos6502 {
 
       0x0B | 0x2B => {
         // ANC: AND byte with accumulator. If result is negative then carry is set.
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         let new_val = self.registers.a & value;
         self.registers.sr.write(flags::CARRY, new_val & 0x80 != 0);
 
@@ -591,7 +666,7 @@ impl Mos6502 {
 
       0x4B => {
         // ALR: AND + LSR
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         let new_val = (self.registers.a & value) >> 1;
 
         self.registers.sr.write(flags::CARRY, new_val & 0x01 != 0);
@@ -602,7 +677,7 @@ impl Mos6502 {
 
       0x6B => {
         // ARR: AND + ROR
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         let new_val = self.registers.a & value;
 
         let new_val = (new_val >> 1) | (self.registers.sr.read(flags::CARRY) as u8) << 7;
@@ -621,7 +696,7 @@ impl Mos6502 {
         // XAA: AND X + AND immediate
         // Oooo she's highly unstable xx "Do not use" or whatever
 
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         let magic: u8;
         #[cfg(not(target_arch = "wasm32"))]
         {
@@ -640,7 +715,7 @@ impl Mos6502 {
 
       0xCB => {
         // AXS: AND -> DEX -> STX
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         self.registers.x &= self.registers.a;
 
         self.registers.alu_compare(self.registers.x, value);
@@ -651,14 +726,14 @@ impl Mos6502 {
 
       0xEB => {
         // SBC (same as official sbc)
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         self.registers.alu_subtract(value);
         Ok(cycles)
       }
 
       0x9C => {
         // SHY: (Y & (high(addr) + 1)) -> addr
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         let value = (address >> 8) as u8;
         let result = self.registers.y & (value.wrapping_add(1));
         self.registers.sr.set_nz(result);
@@ -669,7 +744,7 @@ impl Mos6502 {
 
       0x9E => {
         // SHX: (X & (high(addr) + 1)) -> addr
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         let value = (address >> 8) as u8;
         let result = self.registers.x & (value.wrapping_add(1));
         self.registers.sr.set_nz(result);
@@ -680,7 +755,7 @@ impl Mos6502 {
 
       0x93 | 0x9F => {
         // AHX: (A & X & (high(addr) + 1)) -> addr
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         let value = (address >> 8) as u8;
         let result = self.registers.a & self.registers.x & (value.wrapping_add(1));
         self.registers.sr.set_nz(result);
@@ -695,7 +770,7 @@ impl Mos6502 {
         // A AND X AND (H+1) -> M
         self.registers.sp.set(self.registers.a & self.registers.x);
 
-        let (address, cycles) = self.fetch_operand_address(opcode);
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
         let value = (address >> 8) as u8;
         let result = self.registers.a & self.registers.x & (value.wrapping_add(1));
         self.write(address, result);
@@ -706,7 +781,7 @@ impl Mos6502 {
       0xBB => {
         // LAS: LDA + TSX unholy matrimony
         // M AND SP -> A, X, SP
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         let result = value & self.registers.sp.get();
 
         self.registers.a = result;
@@ -719,7 +794,7 @@ impl Mos6502 {
 
       0xAB => {
         // ATX or LXA: XAA but instead of and X we store in X
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
         let magic: u8;
         #[cfg(not(target_arch = "wasm32"))]
         {
@@ -743,11 +818,12 @@ impl Mos6502 {
         match opcode {
           0x1A | 0x3A | 0x5A | 0x7A | 0xDA | 0xFA => {
             // No address
+            self.dummy_fetch();
             Ok(2)
           }
           _ => {
             // Address
-            let (_value, cycles) = self.fetch_operand_value(opcode);
+            let (_value, cycles) = self.fetch_operand_value(opcode)?;
             Ok(cycles)
           }
         }
@@ -755,11 +831,11 @@ impl Mos6502 {
     }
   }
 
-  fn execute_cmos_extensions(&mut self, opcode: u8) -> Result<u8, ()> {
+  fn execute_cmos_extensions(&mut self, opcode: u8) -> Result<u8, CpuError> {
     match opcode {
       0x89 | 0x34 | 0x3C => {
         // BIT (3 extra addressing modes)
-        let (value, cycles) = self.fetch_operand_value(opcode);
+        let (value, cycles) = self.fetch_operand_value(opcode)?;
 
         if opcode != 0x89 {
           // N, V flags not set for immediate
@@ -776,6 +852,7 @@ impl Mos6502 {
 
       0x3A => {
         // DEC (like DEX/DEY but for accumulator)
+        self.dummy_fetch();
         self.registers.a = self.registers.a.wrapping_sub(1);
         self.registers.sr.set_nz(self.registers.a);
         Ok(2)
@@ -783,6 +860,7 @@ impl Mos6502 {
 
       0x1A => {
         // INC (like INX/INY but for accumulator)
+        self.dummy_fetch();
         self.registers.a = self.registers.a.wrapping_add(1);
         self.registers.sr.set_nz(self.registers.a);
         Ok(2)
@@ -791,7 +869,8 @@ impl Mos6502 {
       0x7C => {
         // JMP (abs,X)
         let address = self.fetch_word();
-        let pointer = address + self.registers.x as u16;
+        self.dummy_read(self.registers.pc.address().wrapping_sub(1));
+        let pointer = address.wrapping_add(self.registers.x as u16);
         let address = self.read_word(pointer);
         self.registers.pc.load(address);
         Ok(6)
@@ -800,23 +879,27 @@ impl Mos6502 {
       0x80 => {
         // BRA (branch Always)
         let offset = self.fetch() as i8;
-        self.registers.pc.offset(offset);
+        self.branch(offset);
         Ok(3)
       }
 
       // New Stack Instructions
       0xDA => {
         // PHX (push X onto stack)
+        self.dummy_fetch();
         self.push(self.registers.x);
         Ok(3)
       }
       0x5A => {
         // PHY (push Y onto stack)
+        self.dummy_fetch();
         self.push(self.registers.y);
         Ok(3)
       }
       0xFA => {
         // PLX (pull X from stack)
+        self.dummy_fetch();
+        self.dummy_read(self.registers.sp.address());
         let value = self.pop();
         self.registers.x = value;
         self.registers.sr.set_nz(value);
@@ -824,6 +907,8 @@ impl Mos6502 {
       }
       0x7A => {
         // PLY (pull Y from stack)
+        self.dummy_fetch();
+        self.dummy_read(self.registers.sp.address());
         let value = self.pop();
         self.registers.y = value;
         self.registers.sr.set_nz(value);
@@ -837,10 +922,11 @@ impl Mos6502 {
           0x9C => (self.fetch_word(), 4),
           0x9E => {
             let base = self.fetch_word();
-            let indexed = base + self.registers.x as u16;
+            let previous = self.registers.pc.address().wrapping_sub(1);
+            let (indexed, _) = self.index(base, self.registers.x, opcode, previous);
             (indexed, 4)
           }
-          _ => self.fetch_operand_address(opcode),
+          _ => self.fetch_operand_address(opcode)?,
         };
 
         self.write(address, 0);
@@ -855,6 +941,7 @@ impl Mos6502 {
           _ => unreachable!(),
         };
         let value = self.read(address);
+        self.write_back(address, value);
 
         self
           .registers
@@ -873,6 +960,7 @@ impl Mos6502 {
           _ => unreachable!(),
         };
         let value = self.read(address);
+        self.write_back(address, value);
 
         self
           .registers
@@ -888,6 +976,7 @@ impl Mos6502 {
         // BBS and BBR
         let address = self.fetch() as u16;
         let value = self.read(address);
+        self.dummy_read(address);
         let offset = self.fetch() as i8;
 
         let bit = (opcode >> 4) & 0b111;
@@ -895,7 +984,7 @@ impl Mos6502 {
         let target_value = opcode & 0x80 != 0;
 
         if target_value == bit_value {
-          self.registers.pc.offset(offset);
+          self.branch(offset);
           Ok(3)
         } else {
           Ok(2)
@@ -907,6 +996,7 @@ impl Mos6502 {
         // RMB and SMB
         let address = self.fetch() as u16;
         let value = self.read(address);
+        self.write_back(address, value);
 
         let bit = (opcode >> 4) & 0b111;
 
@@ -926,19 +1016,30 @@ impl Mos6502 {
         Ok(2)
       }
       0x44 => {
-        self.fetch();
+        // NOP (zero page)
+        let address = self.fetch() as u16;
+        self.dummy_read(address);
         Ok(3)
       }
       0x54 | 0xD4 | 0xF4 => {
-        self.fetch();
+        // NOP (zero page,X)
+        let base = self.fetch();
+        self.dummy_read(base as u16);
+        self.dummy_read(base.wrapping_add(self.registers.x) as u16);
         Ok(4)
       }
       0x5C => {
-        self.fetch_word();
+        // NOP (8 cycles, spent reading from the top page of memory)
+        let address = self.fetch_word();
+        for _ in 0..5 {
+          self.dummy_read(0xFF00 | address & 0x00FF);
+        }
         Ok(8)
       }
       0xDC | 0xFC => {
-        self.fetch_word();
+        // NOP (absolute)
+        let address = self.fetch_word();
+        self.dummy_read(address);
         Ok(4)
       }
 
@@ -1,4 +1,5 @@
 use crate::cpu::mos6502::{MemoryIO, Mos6502};
+use crate::cpu::CpuError;
 
 use super::Mos6502Variant;
 
@@ -13,12 +14,12 @@ pub trait Fetch {
   fn fetch_word(&mut self) -> u16;
 
   /// Fetch the next operand value, based on the current opcode.
-  /// Panics if the opcode is not valid for this operation.
-  fn fetch_operand_value(&mut self, opcode: u8) -> (u8, u8);
+  /// Returns an error if the opcode is not valid for this operation.
+  fn fetch_operand_value(&mut self, opcode: u8) -> Result<(u8, u8), CpuError>;
 
   /// Fetch the next operand address, based on the current opcode.
-  /// Panics if the opcode is not valid for this operation.
-  fn fetch_operand_address(&mut self, opcode: u8) -> (u16, u8);
+  /// Returns an error if the opcode is not valid for this operation.
+  fn fetch_operand_address(&mut self, opcode: u8) -> Result<(u16, u8), CpuError>;
 }
 
 impl Fetch for Mos6502 {
@@ -34,98 +35,161 @@ impl Fetch for Mos6502 {
     (hi as u16) << 8 | lo as u16
   }
 
-  fn fetch_operand_value(&mut self, opcode: u8) -> (u8, u8) {
+  fn fetch_operand_value(&mut self, opcode: u8) -> Result<(u8, u8), CpuError> {
     match opcode & 0x1F {
-      0x00 | 0x02 | 0x09 | 0x0B => (self.fetch(), 2), // Immediate
-      0x08 | 0x18 | 0x1A => panic!("Implied operand has no value"),
-      0x0A => (self.registers.a, 0),
+      0x00 | 0x02 | 0x09 | 0x0B => Ok((self.fetch(), 2)), // Immediate
+      0x08 | 0x18 | 0x1A => Err(self.invalid_addressing_mode(opcode)), // Implied
+      0x0A => Ok((self.registers.a, 0)),
       _ => {
-        let (address, cycles) = self.fetch_operand_address(opcode);
-        (self.read(address), cycles)
+        let (address, cycles) = self.fetch_operand_address(opcode)?;
+        Ok((self.read(address), cycles))
       }
     }
   }
 
   #[allow(clippy::manual_range_patterns)]
-  fn fetch_operand_address(&mut self, opcode: u8) -> (u16, u8) {
-    match opcode & 0x1F {
-      0x00 | 0x02 | 0x09 | 0x0B => panic!("Immediate operand has no address"),
+  fn fetch_operand_address(&mut self, opcode: u8) -> Result<(u16, u8), CpuError> {
+    let result = match opcode & 0x1F {
+      0x00 | 0x02 | 0x09 | 0x0B => return Err(self.invalid_addressing_mode(opcode)), // Immediate
       0x01 | 0x03 => {
         // (Indirect,X)
         let base = self.fetch();
-        let pointer = (base as u16 + self.registers.x as u16) & 0xFF;
-        (self.read_word(pointer), 6)
+        self.dummy_read(base as u16);
+        let pointer = base.wrapping_add(self.registers.x);
+        (self.read_zero_page_word(pointer), 6)
       }
       0x04 | 0x05 | 0x06 | 0x07 => (self.fetch() as u16, 3), // Zero page
-      0x08 | 0x0A | 0x18 | 0x1A => panic!("Implied operand has no address"),
-      0x0C | 0x0D | 0x0E | 0x0F => (self.fetch_word(), 4), // Absolute
-      0x10 => (self.fetch() as i8 as u16, 2),              // Relative
+      0x08 | 0x0A | 0x18 | 0x1A => return Err(self.invalid_addressing_mode(opcode)), // Implied
+      0x0C | 0x0D | 0x0E | 0x0F => (self.fetch_word(), 4),   // Absolute
+      0x10 => (self.fetch() as i8 as u16, 2),                // Relative
       0x11 | 0x13 => {
         // (Indirect),Y
         let base = self.fetch();
-        let pointer = self.read_word(base as u16);
-        (pointer + self.registers.y as u16, 5)
+        let pointer = self.read_zero_page_word(base);
+        let (address, _) = self.index(
+          pointer,
+          self.registers.y,
+          opcode,
+          base.wrapping_add(1) as u16,
+        );
+        (address, 5)
       }
       0x12 => match self.variant {
         Mos6502Variant::NMOS => {
           // These all halt the processor on an NMOS chip
-          panic!("Invalid opcode");
+          return Err(CpuError::Jammed {
+            address: self.registers.pc.address().wrapping_sub(1),
+            opcode,
+          });
         }
         Mos6502Variant::CMOS => {
           // (Indirect)
           let base = self.fetch();
-          let pointer = self.read_word(base as u16);
+          let pointer = self.read_zero_page_word(base);
           (pointer, 5)
         }
       },
       0x14 | 0x15 => {
         // Zero page,X
         let base = self.fetch();
-        ((base as u16 + self.registers.x as u16) & 0xFF, 4)
+        self.dummy_read(base as u16);
+        (base.wrapping_add(self.registers.x) as u16, 4)
       }
       0x16 | 0x17 => {
         // Zero page,X or Zero page,Y
         let base = self.fetch();
+        self.dummy_read(base as u16);
         if opcode & 0xC0 == 0x80 {
-          ((base as u16 + self.registers.y as u16) & 0xFF, 5)
+          (base.wrapping_add(self.registers.y) as u16, 5)
         } else {
-          ((base as u16 + self.registers.x as u16) & 0xFF, 5)
+          (base.wrapping_add(self.registers.x) as u16, 5)
         }
       }
       0x19 | 0x1B => {
         // Absolute,Y
         let base = self.fetch_word();
-        (base + self.registers.y as u16, 4)
+        let previous = self.registers.pc.address().wrapping_sub(1);
+        let (address, _) = self.index(base, self.registers.y, opcode, previous);
+        (address, 4)
       }
-      0x1C | 0x1D => {
-        // Absolute,X
+      0x1C | 0x1D | 0x1E | 0x1F => {
+        // Absolute,X (or Absolute,Y for 0x9E, 0x9F, 0xBE and 0xBF)
         let base = self.fetch_word();
-        let indexed = base + self.registers.x as u16;
-
-        if self.variant == Mos6502Variant::NMOS && base & 0xFF00 != indexed & 0xFF00 {
-          self.read(base & 0xFF00 | indexed & 0x00FF);
-          (indexed, 5)
+        let index = if opcode & 0xDE == 0x9E {
+          self.registers.y
         } else {
-          (indexed, 4)
-        }
-      }
-      0x1E | 0x1F => {
-        // Absolute,X or Absolute,Y
-        let base = self.fetch_word();
-        let indexed = if opcode & 0xC0 == 0x80 {
-          base + self.registers.y as u16
-        } else {
-          base + self.registers.x as u16
+          self.registers.x
         };
 
-        if self.variant == Mos6502Variant::NMOS && base & 0xFF00 != indexed & 0xFF00 {
-          self.read(base & 0xFF00 | indexed & 0x00FF);
-          (indexed, 5)
+        let previous = self.registers.pc.address().wrapping_sub(1);
+        let (address, crossed) = self.index(base, index, opcode, previous);
+
+        if self.variant == Mos6502Variant::NMOS && crossed {
+          (address, 5)
         } else {
-          (indexed, 4)
+          (address, 4)
         }
       }
       _ => unreachable!(),
+    };
+
+    Ok(result)
+  }
+}
+
+impl Mos6502 {
+  /// Read the byte at the program counter without advancing past it. The 6502
+  /// does this on the second cycle of instructions which have no operand.
+  pub(super) fn dummy_fetch(&mut self) {
+    self.dummy_read(self.registers.pc.address());
+  }
+
+  /// Read a word (little-endian) from the zero page, wrapping around within it.
+  fn read_zero_page_word(&mut self, pointer: u8) -> u16 {
+    let lo = self.read(pointer as u16);
+    let hi = self.read(pointer.wrapping_add(1) as u16);
+    (hi as u16) << 8 | lo as u16
+  }
+
+  /// Whether the given instruction always spends a cycle fixing up the high
+  /// byte of an indexed address, rather than only when the index crosses a page
+  /// boundary. Stores and read-modify-write instructions can't take back an
+  /// access to the wrong address, so they always wait.
+  fn always_fixes_up(&self, opcode: u8) -> bool {
+    match opcode {
+      0x80..=0x9F => true,
+      0xA0..=0xBF => false,
+      // The 65C02 only waits on a page crossing for shifts and rotates
+      0x1E | 0x3E | 0x5E | 0x7E => self.variant == Mos6502Variant::NMOS,
+      _ => opcode & 0x03 == 0x03 || opcode & 0x07 == 0x06,
+    }
+  }
+
+  /// Add an index to a base address, returning the result and whether a page
+  /// boundary was crossed. While the high byte is fixed up, the NMOS 6502 reads
+  /// from the address with only the low byte adjusted, and the 65C02 reads the
+  /// `previous` address on the bus again.
+  pub(super) fn index(&mut self, base: u16, index: u8, opcode: u8, previous: u16) -> (u16, bool) {
+    let indexed = base.wrapping_add(index as u16);
+    let crossed = base & 0xFF00 != indexed & 0xFF00;
+
+    if crossed || self.always_fixes_up(opcode) {
+      match self.variant {
+        Mos6502Variant::NMOS => self.dummy_read(base & 0xFF00 | indexed & 0x00FF),
+        Mos6502Variant::CMOS if crossed => self.dummy_read(previous),
+        Mos6502Variant::CMOS => self.dummy_read(indexed),
+      }
+    }
+
+    (indexed, crossed)
+  }
+
+  /// Construct an error for an opcode (which has just been fetched) whose
+  /// addressing mode does not fit the operation.
+  fn invalid_addressing_mode(&self, opcode: u8) -> CpuError {
+    CpuError::InvalidAddressingMode {
+      address: self.registers.pc.address().wrapping_sub(1),
+      opcode,
     }
   }
 }
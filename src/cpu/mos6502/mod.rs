@@ -1,13 +1,19 @@
+pub mod disasm;
 mod execute;
 mod fetch;
-mod registers;
-use crate::memory::{ActiveInterrupt, Memory};
+pub mod registers;
+#[cfg(test)]
+mod single_step;
+use crate::debugger::{DebugAction, DebugHandler};
+use crate::memory::{ActiveInterrupt, Memory, NullMemory, WatchMemory, Watchpoints};
+use crate::state::{StateError, StateReader, StateWriter};
 use crate::trace::{CpuTrace, TraceHandler};
+use disasm::{AddressingMode, Instruction};
 use execute::Execute;
 use fetch::Fetch;
 use registers::{flags, Registers};
 
-use super::Cpu;
+use super::{Cpu, CpuError};
 
 const CLOCKS_PER_POLL: u64 = 100;
 
@@ -27,6 +33,23 @@ pub struct Mos6502 {
   cycles_since_poll: u64,
   variant: Mos6502Variant,
   trace: Option<Box<dyn TraceHandler>>,
+  debugger: Option<Box<dyn DebugHandler>>,
+
+  /// Set when the CPU has stopped executing instructions (e.g. after a JAM
+  /// opcode), until it is reset.
+  halted: Option<CpuError>,
+
+  /// Whether every bus access is treated as its own cycle, clocking the
+  /// memory (and any chips within it) after each one.
+  cycle_stepped: bool,
+
+  /// The number of bus accesses made so far by the current instruction, when
+  /// executing in cycle-stepped mode.
+  bus_cycles: Option<u8>,
+
+  /// The highest-priority interrupt raised by the memory while executing the
+  /// current instruction in cycle-stepped mode.
+  pending_interrupt: ActiveInterrupt,
 }
 
 /// Read and write from the system's memory.
@@ -46,22 +69,25 @@ pub trait MemoryIO {
 
 impl MemoryIO for Mos6502 {
   fn read(&mut self, address: u16) -> u8 {
-    self.memory.read(address)
+    let value = self.memory.read(address);
+    self.clock_bus();
+    value
   }
 
   fn read_word(&mut self, address: u16) -> u16 {
-    let lo = self.memory.read(address);
-    let hi = self.memory.read(address + 1);
+    let lo = self.read(address);
+    let hi = self.read(address.wrapping_add(1));
     (hi as u16) << 8 | lo as u16
   }
 
   fn write(&mut self, address: u16, value: u8) {
     self.memory.write(address, value);
+    self.clock_bus();
   }
 
   fn write_word(&mut self, address: u16, value: u16) {
-    self.memory.write(address, value as u8);
-    self.memory.write(address + 1, (value >> 8) as u8);
+    self.write(address, value as u8);
+    self.write(address.wrapping_add(1), (value >> 8) as u8);
   }
 }
 
@@ -115,6 +141,12 @@ impl InterruptHandler for Mos6502 {
       return;
     }
 
+    if !break_instr {
+      // A hardware interrupt replaces the opcode fetch and the following read
+      self.dummy_read(self.registers.pc.address());
+      self.dummy_read(self.registers.pc.address());
+    }
+
     self.push_word(self.registers.pc.address());
 
     if break_instr {
@@ -147,12 +179,183 @@ impl Mos6502 {
       cycles_since_poll: 0,
       variant,
       trace: None,
+      debugger: None,
+      halted: None,
+      cycle_stepped: false,
+      bus_cycles: None,
+      pending_interrupt: ActiveInterrupt::None,
+    }
+  }
+
+  /// Read from the given address and discard the result. The 6502 makes these
+  /// reads on cycles where it is busy internally, such as while fixing up the
+  /// high byte of an indexed address. They are only performed in cycle-stepped
+  /// mode.
+  fn dummy_read(&mut self, address: u16) {
+    if self.cycle_stepped {
+      self.read(address);
+    }
+  }
+
+  /// Count a bus access as a cycle of the current instruction, and clock the
+  /// memory by one cycle, if executing in cycle-stepped mode.
+  fn clock_bus(&mut self) {
+    if let Some(cycles) = self.bus_cycles {
+      self.bus_cycles = Some(cycles + 1);
+
+      let total_cycle_count = self.cycle_count + cycles as u64 + 1;
+      match self.memory.poll(1, total_cycle_count) {
+        ActiveInterrupt::None => (),
+        ActiveInterrupt::NMI => self.pending_interrupt = ActiveInterrupt::NMI,
+        ActiveInterrupt::IRQ => {
+          if self.pending_interrupt == ActiveInterrupt::None {
+            self.pending_interrupt = ActiveInterrupt::IRQ;
+          }
+        }
+      }
     }
   }
+
+  /// Describe the instruction at the program counter, and the current state of
+  /// the CPU, for a trace handler.
+  fn trace_instruction(&self) -> CpuTrace {
+    let address = self.registers.pc.address();
+    let instruction = Instruction::decode(self.peek(address), self.variant);
+    let operand: Vec<u8> = (1..instruction.length())
+      .map(|offset| self.peek(address.wrapping_add(offset)))
+      .collect();
+
+    CpuTrace {
+      address,
+      opcode: instruction.opcode,
+      effective_address: self.effective_address(instruction.mode, &operand),
+      operand,
+      a: self.registers.a,
+      x: self.registers.x,
+      y: self.registers.y,
+      sp: self.registers.sp.get(),
+      p: self.registers.sr.get(),
+      cycle_count: self.cycle_count,
+      variant: self.variant,
+    }
+  }
+
+  /// Compute the address that an instruction with the given addressing mode and
+  /// operand would access, without executing it (or otherwise disturbing the
+  /// machine).
+  fn effective_address(&self, mode: AddressingMode, operand: &[u8]) -> Option<u16> {
+    let byte = operand.first().copied().unwrap_or(0);
+    let word = (operand.get(1).copied().unwrap_or(0) as u16) << 8 | byte as u16;
+    let (x, y) = (self.registers.x, self.registers.y);
+
+    let zero_page_pointer = |cpu: &Self, pointer: u8| {
+      let lo = cpu.peek(pointer as u16);
+      let hi = cpu.peek(pointer.wrapping_add(1) as u16);
+      (hi as u16) << 8 | lo as u16
+    };
+
+    match mode {
+      AddressingMode::Implied | AddressingMode::Accumulator | AddressingMode::Immediate => None,
+      AddressingMode::ZeroPage | AddressingMode::ZeroPageRelative => Some(byte as u16),
+      AddressingMode::ZeroPageX => Some(byte.wrapping_add(x) as u16),
+      AddressingMode::ZeroPageY => Some(byte.wrapping_add(y) as u16),
+      AddressingMode::Absolute => Some(word),
+      AddressingMode::AbsoluteX => Some(word.wrapping_add(x as u16)),
+      AddressingMode::AbsoluteY => Some(word.wrapping_add(y as u16)),
+      AddressingMode::Indirect => {
+        let lo = self.peek(word);
+        let hi = if self.variant == Mos6502Variant::NMOS && word & 0xFF == 0xFF {
+          self.peek(word & 0xFF00)
+        } else {
+          self.peek(word.wrapping_add(1))
+        };
+        Some((hi as u16) << 8 | lo as u16)
+      }
+      AddressingMode::IndirectX => Some(zero_page_pointer(self, byte.wrapping_add(x))),
+      AddressingMode::IndirectY => Some(zero_page_pointer(self, byte).wrapping_add(y as u16)),
+      AddressingMode::ZeroPageIndirect => Some(zero_page_pointer(self, byte)),
+      AddressingMode::AbsoluteIndirectX => {
+        let pointer = word.wrapping_add(x as u16);
+        let lo = self.peek(pointer);
+        let hi = self.peek(pointer.wrapping_add(1));
+        Some((hi as u16) << 8 | lo as u16)
+      }
+      AddressingMode::Relative => {
+        let target = self.registers.pc.address().wrapping_add(2);
+        Some(target.wrapping_add(byte as i8 as u16))
+      }
+    }
+  }
+
+  /// Execute a single instruction, making every bus access (including dummy
+  /// reads and writes) on its own cycle. The memory is clocked after each
+  /// access, and any interrupt it raises is serviced once the instruction
+  /// completes.
+  fn tick_cycle_stepped(&mut self) -> Result<u8, CpuError> {
+    self.bus_cycles = Some(0);
+    self.pending_interrupt = ActiveInterrupt::None;
+
+    let opcode = self.fetch();
+    let result = self.execute(opcode);
+
+    if result.is_ok() {
+      match self.pending_interrupt {
+        ActiveInterrupt::None => (),
+        ActiveInterrupt::NMI => self.interrupt(false, false),
+        ActiveInterrupt::IRQ => self.interrupt(true, false),
+      }
+    }
+
+    let cycles = self.bus_cycles.take().unwrap_or(0);
+    self.cycle_count += cycles as u64;
+
+    match result {
+      Ok(_) => Ok(cycles),
+      Err(error) => self.halt(error),
+    }
+  }
+
+  /// Stop the CPU after an error, giving an attached debugger the chance to
+  /// inspect (or reset) it.
+  fn halt(&mut self, error: CpuError) -> Result<u8, CpuError> {
+    self.halted = Some(error);
+
+    if let Some(mut debugger) = self.debugger.take() {
+      if debugger.on_error(self, error) == DebugAction::Quit {
+        self.halted = Some(CpuError::Quit);
+      }
+      self.debugger = Some(debugger);
+    }
+
+    // The debugger may have reset the CPU
+    match self.halted {
+      Some(error) => Err(error),
+      None => Ok(0),
+    }
+  }
+
+  /// Return the byte at the given address, without clocking the bus or causing
+  /// any of the side effects of a read (see [`Memory::peek`]).
+  pub fn peek(&self, address: u16) -> u8 {
+    self.memory.peek(address)
+  }
+
+  /// Check every access to memory against the given watchpoints, anywhere in
+  /// the address space.
+  pub fn watch_memory(&mut self, watchpoints: Watchpoints) {
+    let memory = std::mem::replace(&mut self.memory, Box::new(NullMemory::new()));
+    self.memory = Box::new(WatchMemory::from_boxed(memory, watchpoints, 0));
+  }
+
+  /// Return the variant of the 6502 that this CPU emulates.
+  pub fn get_variant(&self) -> Mos6502Variant {
+    self.variant
+  }
 }
 
 impl Cpu for Mos6502 {
   fn reset(&mut self) {
+    self.halted = None;
     self.memory.reset();
     self.registers.reset();
     let pc_address = self.read_word(0xFFFC);
@@ -168,17 +371,43 @@ impl Cpu for Mos6502 {
     self.trace = Some(trace);
   }
 
+  fn attach_debugger(&mut self, debugger: Box<dyn DebugHandler>) {
+    self.debugger = Some(debugger);
+  }
+
+  fn set_cycle_stepped(&mut self, enabled: bool) {
+    self.cycle_stepped = enabled;
+  }
+
   /// Execute a single instruction.
-  fn tick(&mut self) -> u8 {
-    let opcode = self.fetch();
+  fn tick(&mut self) -> Result<u8, CpuError> {
+    if let Some(error) = self.halted {
+      return Err(error);
+    }
 
-    if let Some(tracer) = &mut self.trace {
-      tracer.handle(&CpuTrace {
-        address: self.registers.pc.address(),
-        opcode,
-      });
+    if let Some(mut debugger) = self.debugger.take() {
+      let action = debugger.before_instruction(self);
+      self.debugger = Some(debugger);
+
+      if action == DebugAction::Quit {
+        self.halted = Some(CpuError::Quit);
+        return Err(CpuError::Quit);
+      }
+    }
+
+    if self.trace.is_some() {
+      let trace = self.trace_instruction();
+      if let Some(tracer) = &mut self.trace {
+        tracer.handle(&trace);
+      }
     }
 
+    if self.cycle_stepped {
+      return self.tick_cycle_stepped();
+    }
+
+    let opcode = self.fetch();
+
     match self.execute(opcode) {
       Ok(cycles) => {
         self.cycle_count += cycles as u64;
@@ -196,15 +425,9 @@ impl Cpu for Mos6502 {
           self.cycles_since_poll = 0;
         }
 
-        cycles
-      }
-      Err(_) => {
-        panic!(
-          "Failed to execute instruction {:02x} at {:04x}",
-          opcode,
-          self.registers.pc.address()
-        );
+        Ok(cycles)
       }
+      Err(error) => self.halt(error),
     }
   }
 
@@ -215,4 +438,273 @@ impl Cpu for Mos6502 {
       Ok(())
     }
   }
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    // Save states are taken between instructions, so there is never a
+    // cycle-stepped instruction in progress
+    state.write(&self.registers)?;
+    state.write(&(self.cycle_count, self.cycles_since_poll, self.halted))?;
+    self.memory.save_state(state)
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    self.registers = state.read()?;
+    (self.cycle_count, self.cycles_since_poll, self.halted) = state.read()?;
+    self.memory.load_state(state)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::memory::BlockMemory;
+  use std::{cell::RefCell, rc::Rc};
+
+  fn cpu_with_program(program: &[u8], variant: Mos6502Variant) -> Mos6502 {
+    let mut memory = BlockMemory::ram(0x10000);
+    for (i, byte) in program.iter().enumerate() {
+      memory.write(0x0200 + i as u16, *byte);
+    }
+    memory.write(0xFFFC, 0x00);
+    memory.write(0xFFFD, 0x02);
+
+    let mut cpu = Mos6502::new(memory, variant);
+    cpu.registers.pc.load(0x0200);
+    cpu
+  }
+
+  #[test]
+  fn test_jam_halts_until_reset() {
+    let mut cpu = cpu_with_program(&[0xE8, 0x02], Mos6502Variant::NMOS);
+    let jammed = Err(CpuError::Jammed {
+      address: 0x0201,
+      opcode: 0x02,
+    });
+
+    assert_eq!(Ok(2), cpu.tick());
+    assert_eq!(jammed, cpu.tick());
+    assert_eq!(jammed, cpu.tick());
+    assert_eq!(1, cpu.registers.x);
+
+    // resetting clears RAM, so the CPU runs into a BRK instead of the JAM
+    cpu.reset();
+    assert_eq!(Ok(7), cpu.tick());
+  }
+
+  #[test]
+  fn test_indirect_jams_on_nmos_only() {
+    let mut nmos = cpu_with_program(&[0xB2, 0x10], Mos6502Variant::NMOS);
+    assert_eq!(
+      Err(CpuError::Jammed {
+        address: 0x0200,
+        opcode: 0xB2
+      }),
+      nmos.tick()
+    );
+
+    let mut cmos = cpu_with_program(&[0xB2, 0x10], Mos6502Variant::CMOS);
+    assert_eq!(Ok(5), cmos.tick());
+  }
+
+  /// Cycle counts of each opcode on the NMOS 6502 with no page crossings,
+  /// where 0 marks a JAM.
+  #[rustfmt::skip]
+  const NMOS_CYCLES: [u8; 256] = [
+    7, 6, 0, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 0, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 0, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6,
+    2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 0, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6,
+    2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
+    2, 6, 0, 6, 4, 4, 4, 4, 2, 5, 2, 5, 5, 5, 5, 5,
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
+    2, 5, 0, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4,
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+  ];
+
+  /// Cycle counts of each opcode on the 65C02 with no page crossings.
+  #[rustfmt::skip]
+  const CMOS_CYCLES: [u8; 256] = [
+    7, 6, 2, 1, 5, 3, 5, 5, 3, 2, 2, 1, 6, 4, 6, 5,
+    2, 5, 5, 1, 5, 4, 6, 5, 2, 4, 2, 1, 6, 4, 6, 5,
+    6, 6, 2, 1, 3, 3, 5, 5, 4, 2, 2, 1, 4, 4, 6, 5,
+    2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 2, 1, 4, 4, 6, 5,
+    6, 6, 2, 1, 3, 3, 5, 5, 3, 2, 2, 1, 3, 4, 6, 5,
+    2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 3, 1, 8, 4, 6, 5,
+    6, 6, 2, 1, 3, 3, 5, 5, 4, 2, 2, 1, 6, 4, 6, 5,
+    2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 4, 1, 6, 4, 6, 5,
+    3, 6, 2, 1, 3, 3, 3, 5, 2, 2, 2, 1, 4, 4, 4, 5,
+    2, 6, 5, 1, 4, 4, 4, 5, 2, 5, 2, 1, 4, 5, 5, 5,
+    2, 6, 2, 1, 3, 3, 3, 5, 2, 2, 2, 1, 4, 4, 4, 5,
+    2, 5, 5, 1, 4, 4, 4, 5, 2, 4, 2, 1, 4, 4, 4, 5,
+    2, 6, 2, 1, 3, 3, 5, 5, 2, 2, 2, 1, 4, 4, 6, 5,
+    2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 3, 1, 4, 4, 7, 5,
+    2, 6, 2, 1, 3, 3, 5, 5, 2, 2, 2, 1, 4, 4, 6, 5,
+    2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 4, 1, 4, 4, 7, 5,
+  ];
+
+  #[test]
+  fn test_cycle_stepped_counts() {
+    for (variant, table) in [
+      (Mos6502Variant::NMOS, NMOS_CYCLES),
+      (Mos6502Variant::CMOS, CMOS_CYCLES),
+    ] {
+      for opcode in 0..=255u8 {
+        let mode = Instruction::decode(opcode, variant).mode;
+        if table[opcode as usize] == 0
+          || matches!(
+            mode,
+            AddressingMode::Relative | AddressingMode::ZeroPageRelative
+          )
+        {
+          continue;
+        }
+
+        let mut cpu = cpu_with_program(&[opcode, 0x00, 0x00], variant);
+        cpu.set_cycle_stepped(true);
+
+        assert_eq!(
+          Ok(table[opcode as usize]),
+          cpu.tick(),
+          "opcode {opcode:02X}"
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn test_cycle_stepped_branches() {
+    // BNE taken within the same page
+    let mut cpu = cpu_with_program(&[0xD0, 0x7E], Mos6502Variant::NMOS);
+    cpu.set_cycle_stepped(true);
+    assert_eq!(Ok(3), cpu.tick());
+    assert_eq!(0x0280, cpu.registers.pc.address());
+
+    // BEQ not taken
+    let mut cpu = cpu_with_program(&[0xF0, 0x10], Mos6502Variant::NMOS);
+    cpu.set_cycle_stepped(true);
+    assert_eq!(Ok(2), cpu.tick());
+
+    // BNE taken across a page boundary
+    let mut cpu = cpu_with_program(&[0xD0, 0x80], Mos6502Variant::NMOS);
+    cpu.set_cycle_stepped(true);
+    assert_eq!(Ok(4), cpu.tick());
+    assert_eq!(0x0182, cpu.registers.pc.address());
+  }
+
+  /// RAM which records each access made to it, along with the number of cycles
+  /// it had been clocked for at the time.
+  struct BusLog {
+    ram: BlockMemory,
+    cycles: u64,
+    irq_at: Option<u64>,
+    log: Rc<RefCell<Vec<String>>>,
+  }
+
+  impl Memory for BusLog {
+    fn read(&mut self, address: u16) -> u8 {
+      let value = self.ram.read(address);
+      let entry = format!("{} R {address:04X} {value:02X}", self.cycles);
+      self.log.borrow_mut().push(entry);
+      value
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+      self.ram.peek(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+      let entry = format!("{} W {address:04X} {value:02X}", self.cycles);
+      self.log.borrow_mut().push(entry);
+      self.ram.write(address, value);
+    }
+
+    fn reset(&mut self) {}
+
+    fn poll(&mut self, cycles_since_poll: u64, total_cycle_count: u64) -> ActiveInterrupt {
+      self.cycles += cycles_since_poll;
+      assert_eq!(self.cycles, total_cycle_count);
+
+      match self.irq_at {
+        Some(cycle) if cycle == self.cycles => ActiveInterrupt::IRQ,
+        _ => ActiveInterrupt::None,
+      }
+    }
+  }
+
+  fn cpu_with_bus_log(program: &[u8], irq_at: Option<u64>) -> (Mos6502, Rc<RefCell<Vec<String>>>) {
+    let mut ram = BlockMemory::ram(0x10000);
+    for (i, byte) in program.iter().enumerate() {
+      ram.write(0x0200 + i as u16, *byte);
+    }
+    ram.write(0xFFFE, 0x00);
+    ram.write(0xFFFF, 0x03);
+
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let memory = BusLog {
+      ram,
+      cycles: 0,
+      irq_at,
+      log: log.clone(),
+    };
+
+    let mut cpu = Mos6502::new(memory, Mos6502Variant::NMOS);
+    cpu.registers.pc.load(0x0200);
+    cpu.set_cycle_stepped(true);
+    (cpu, log)
+  }
+
+  #[test]
+  fn test_cycle_stepped_bus_accesses() {
+    // LDX #$01; INC $10FF,X
+    let (mut cpu, log) = cpu_with_bus_log(&[0xA2, 0x01, 0xFE, 0xFF, 0x10], None);
+    assert_eq!(Ok(2), cpu.tick());
+    assert_eq!(Ok(7), cpu.tick());
+
+    assert_eq!(
+      vec![
+        "0 R 0200 A2",
+        "1 R 0201 01",
+        "2 R 0202 FE",
+        "3 R 0203 FF",
+        "4 R 0204 10",
+        "5 R 1000 00",
+        "6 R 1100 00",
+        "7 W 1100 00",
+        "8 W 1100 01",
+      ],
+      *log.borrow()
+    );
+  }
+
+  #[test]
+  fn test_cycle_stepped_interrupt() {
+    // CLI; NOP, with an IRQ raised during the NOP
+    let (mut cpu, log) = cpu_with_bus_log(&[0x58, 0xEA], Some(3));
+    assert_eq!(Ok(2), cpu.tick());
+    assert_eq!(Ok(9), cpu.tick());
+    assert_eq!(0x0300, cpu.registers.pc.address());
+    assert_eq!(11, cpu.get_cycle_count());
+
+    assert_eq!(
+      vec![
+        "2 R 0201 EA",
+        "3 R 0202 00",
+        "4 R 0202 00",
+        "5 R 0202 00",
+        "6 W 01FF 02",
+        "7 W 01FE 02",
+        "8 W 01FD 20",
+        "9 R FFFE 00",
+        "10 R FFFF 03",
+      ],
+      log.borrow()[2..]
+    );
+  }
 }
@@ -1,5 +1,7 @@
+use serde::{Deserialize, Serialize};
+
 /// The registers inside of a MOS 6502 processor.
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct Registers {
   /// The accumulator register, used in ALU operations.
   pub a: u8,
@@ -55,7 +57,7 @@ pub mod flags {
 /// The stack register is usually initialized by the operating system to 0x01FF,
 /// and grows upward (i.e., pushing decrements the value, while popping
 /// increments it).
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct StackPointer {
   value: u8,
 }
@@ -94,7 +96,7 @@ impl StackPointer {
 
 /// The program counter register, which points to the current instruction being
 /// executed.
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct ProgramCounter {
   value: u16,
 }
@@ -127,7 +129,7 @@ impl ProgramCounter {
 
 /// The processor status register, which contains a series of flags that
 /// represent the current state of the processor.
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct StatusRegister {
   value: u8,
 }
@@ -0,0 +1,248 @@
+use crate::cpu::mos6502::{Mos6502, Mos6502Variant};
+use crate::cpu::Cpu;
+use crate::memory::{BlockMemory, Memory};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// The registers and (relevant) memory contents before or after a test.
+#[derive(Deserialize)]
+struct State {
+  pc: u16,
+  s: u8,
+  a: u8,
+  x: u8,
+  y: u8,
+  p: u8,
+  ram: Vec<(u16, u8)>,
+}
+
+/// A single test vector: the state before and after executing one
+/// instruction, and the bus activity on each cycle it should take.
+#[derive(Deserialize)]
+struct TestCase {
+  name: String,
+  initial: State,
+  #[serde(rename = "final")]
+  expected: State,
+  cycles: Vec<(u16, u8, String)>,
+}
+
+impl TestCase {
+  /// Execute this test case, returning a description of the first mismatch.
+  fn run(&self, variant: Mos6502Variant) -> Result<(), String> {
+    let mut memory = BlockMemory::ram(0x10000);
+    for &(address, value) in &self.initial.ram {
+      memory.write(address, value);
+    }
+
+    let mut cpu = Mos6502::new(memory, variant);
+    cpu.set_cycle_stepped(true);
+    cpu.registers.pc.load(self.initial.pc);
+    cpu.registers.sp.set(self.initial.s);
+    cpu.registers.a = self.initial.a;
+    cpu.registers.x = self.initial.x;
+    cpu.registers.y = self.initial.y;
+    cpu.registers.sr.load(self.initial.p);
+
+    let cycles = cpu.tick().map_err(|error| error.to_string())? as usize;
+
+    // The B and unused bits of the status register don't physically exist,
+    // so only the values pushed onto the stack are meaningful.
+    let actual = [
+      ("pc", cpu.registers.pc.address(), self.expected.pc),
+      ("s", cpu.registers.sp.get() as u16, self.expected.s as u16),
+      ("a", cpu.registers.a as u16, self.expected.a as u16),
+      ("x", cpu.registers.x as u16, self.expected.x as u16),
+      ("y", cpu.registers.y as u16, self.expected.y as u16),
+      (
+        "p",
+        (cpu.registers.sr.get() & 0xCF) as u16,
+        (self.expected.p & 0xCF) as u16,
+      ),
+      ("cycles", cycles as u16, self.cycles.len() as u16),
+    ];
+
+    for (name, actual, expected) in actual {
+      if actual != expected {
+        return Err(format!("{name} is {actual:02X}, expected {expected:02X}"));
+      }
+    }
+
+    for &(address, expected) in &self.expected.ram {
+      let actual = cpu.memory.read(address);
+      if actual != expected {
+        return Err(format!(
+          "memory at {address:04X} is {actual:02X}, expected {expected:02X}"
+        ));
+      }
+    }
+
+    Ok(())
+  }
+}
+
+/// The results of running every test vector for a single opcode.
+struct OpcodeReport {
+  opcode: u8,
+  passed: usize,
+  total: usize,
+  first_failure: Option<String>,
+}
+
+/// Run every test vector in the given file, which contains a JSON array of
+/// test cases for a single opcode.
+fn run_file(path: &Path, opcode: u8, variant: Mos6502Variant) -> Result<OpcodeReport, String> {
+  let contents = fs::read_to_string(path).map_err(|error| error.to_string())?;
+  let cases: Vec<TestCase> = serde_json::from_str(&contents).map_err(|error| error.to_string())?;
+
+  let mut report = OpcodeReport {
+    opcode,
+    passed: 0,
+    total: 0,
+    first_failure: None,
+  };
+
+  for case in cases {
+    report.total += 1;
+
+    match case.run(variant) {
+      Ok(()) => report.passed += 1,
+      Err(reason) => {
+        if report.first_failure.is_none() {
+          report.first_failure = Some(format!("\"{}\": {}", case.name, reason));
+        }
+      }
+    }
+  }
+
+  Ok(report)
+}
+
+/// Run the test vectors for every opcode found in the given directory (named
+/// `00.json` to `ff.json`), printing the pass rate of each.
+fn run_suite(directory: &Path, variant: Mos6502Variant) -> Vec<OpcodeReport> {
+  let mut reports = Vec::new();
+
+  for opcode in 0..=255u8 {
+    let path = directory.join(format!("{opcode:02x}.json"));
+    if !path.exists() {
+      continue;
+    }
+
+    let report = run_file(&path, opcode, variant)
+      .unwrap_or_else(|error| panic!("Failed to load {}: {error}", path.display()));
+
+    println!(
+      "{:02X}  {:>5}/{:<5}  {:>5.1}%  {}",
+      report.opcode,
+      report.passed,
+      report.total,
+      100.0 * report.passed as f64 / report.total.max(1) as f64,
+      report.first_failure.as_deref().unwrap_or("")
+    );
+    reports.push(report);
+  }
+
+  let passed: usize = reports.iter().map(|report| report.passed).sum();
+  let total: usize = reports.iter().map(|report| report.total).sum();
+  println!(
+    "{passed}/{total} tests passed; {} of {} opcodes fully passing",
+    reports
+      .iter()
+      .filter(|report| report.passed == report.total)
+      .count(),
+    reports.len()
+  );
+
+  reports
+}
+
+/// Find the directory of test vectors for the given processor, within a
+/// checkout of <https://github.com/SingleStepTests/65x02> whose location is
+/// given by the `SINGLE_STEP_TESTS` environment variable.
+fn suite_directory(processor: &str) -> std::path::PathBuf {
+  let root = std::env::var("SINGLE_STEP_TESTS")
+    .expect("Set SINGLE_STEP_TESTS to the path of the SingleStepTests/65x02 repository");
+  let directory = Path::new(&root).join(processor).join("v1");
+  assert!(
+    directory.is_dir(),
+    "{} is not a directory",
+    directory.display()
+  );
+  directory
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SAMPLE: &str = r#"[
+    {
+      "name": "b1 28 b5",
+      "initial": {
+        "pc": 59082, "s": 39, "a": 57, "x": 33, "y": 30, "p": 96,
+        "ram": [[59082, 177], [59083, 40], [59084, 181], [40, 160], [41, 233], [59838, 119]]
+      },
+      "final": {
+        "pc": 59084, "s": 39, "a": 119, "x": 33, "y": 30, "p": 96,
+        "ram": [[40, 160], [41, 233], [59082, 177], [59083, 40], [59084, 181], [59838, 119]]
+      },
+      "cycles": [
+        [59082, 177, "read"], [59083, 40, "read"], [40, 160, "read"],
+        [41, 233, "read"], [59838, 119, "read"]
+      ]
+    },
+    {
+      "name": "91 10 00",
+      "initial": {
+        "pc": 512, "s": 255, "a": 66, "x": 0, "y": 255, "p": 36,
+        "ram": [[512, 145], [513, 16], [16, 1], [17, 3]]
+      },
+      "final": {
+        "pc": 514, "s": 255, "a": 66, "x": 0, "y": 255, "p": 36,
+        "ram": [[1024, 66]]
+      },
+      "cycles": [
+        [512, 145, "read"], [513, 16, "read"], [16, 1, "read"],
+        [17, 3, "read"], [768, 0, "read"], [1024, 66, "write"]
+      ]
+    }
+  ]"#;
+
+  #[test]
+  fn test_sample_vectors() {
+    let mut cases: Vec<TestCase> = serde_json::from_str(SAMPLE).unwrap();
+
+    for case in &cases {
+      assert_eq!(Ok(()), case.run(Mos6502Variant::NMOS), "{}", case.name);
+    }
+
+    // A mismatch is reported along with the expected value
+    let case = &mut cases[0];
+    case.expected.a = 0x00;
+    assert_eq!(
+      Err("a is 77, expected 00".to_owned()),
+      case.run(Mos6502Variant::NMOS)
+    );
+  }
+
+  /// Run the full suite for the NMOS 6502. The test vectors are not included
+  /// in this repository, so this must be run explicitly, e.g. with
+  /// `SINGLE_STEP_TESTS=../65x02 cargo test --release single_step -- --ignored --nocapture`.
+  #[test]
+  #[ignore]
+  fn test_single_step_nmos() {
+    let reports = run_suite(&suite_directory("6502"), Mos6502Variant::NMOS);
+    assert!(!reports.is_empty(), "No test vectors found");
+  }
+
+  /// Run the full suite for the 65C02. This uses the Rockwell variant, which
+  /// includes the bit manipulation instructions.
+  #[test]
+  #[ignore]
+  fn test_single_step_cmos() {
+    let reports = run_suite(&suite_directory("rockwell65c02"), Mos6502Variant::CMOS);
+    assert!(!reports.is_empty(), "No test vectors found");
+  }
+}
@@ -0,0 +1,280 @@
+use crate::cpu::mos6502::{
+  disasm::{AddressingMode, Instruction as Mos6502Instruction},
+  Mos6502Variant,
+};
+
+/// The ways in which a 65C816 instruction can specify its operand.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+  /// No operand (e.g. `INX`).
+  Implied,
+  /// Operates on the accumulator (e.g. `ASL A`).
+  Accumulator,
+  /// A constant as wide as the accumulator (e.g. `LDA #$00`).
+  ImmediateM,
+  /// A constant as wide as the index registers (e.g. `LDX #$00`).
+  ImmediateX,
+  /// A one-byte constant, regardless of register widths (e.g. `REP #$30`).
+  Immediate8,
+  /// An offset into the direct page (e.g. `LDA $00`).
+  Direct,
+  /// A direct page offset, indexed by X (e.g. `LDA $00,X`).
+  DirectX,
+  /// A direct page offset, indexed by Y (e.g. `LDX $00,Y`).
+  DirectY,
+  /// A pointer in the direct page (e.g. `LDA ($00)`).
+  DirectIndirect,
+  /// A direct page pointer, indexed by X before dereferencing (e.g. `LDA ($00,X)`).
+  DirectIndirectX,
+  /// A direct page pointer, indexed by Y after dereferencing (e.g. `LDA ($00),Y`).
+  DirectIndirectY,
+  /// A 24-bit pointer in the direct page (e.g. `LDA [$00]`).
+  DirectIndirectLong,
+  /// A 24-bit direct page pointer, indexed by Y after dereferencing (e.g. `LDA [$00],Y`).
+  DirectIndirectLongY,
+  /// A two-byte address in the data bank (e.g. `LDA $1234`).
+  Absolute,
+  /// An address in the data bank, indexed by X (e.g. `LDA $1234,X`).
+  AbsoluteX,
+  /// An address in the data bank, indexed by Y (e.g. `LDA $1234,Y`).
+  AbsoluteY,
+  /// A full three-byte address (e.g. `LDA $123456`).
+  AbsoluteLong,
+  /// A full three-byte address, indexed by X (e.g. `LDA $123456,X`).
+  AbsoluteLongX,
+  /// A pointer in bank 0 to the target address (only used by `JMP ($1234)`).
+  AbsoluteIndirect,
+  /// A pointer in the program bank, indexed by X (e.g. `JMP ($1234,X)`).
+  AbsoluteIndirectX,
+  /// A pointer in bank 0 to a 24-bit target address (only used by `JML [$1234]`).
+  AbsoluteIndirectLong,
+  /// An offset from the stack pointer (e.g. `LDA $01,S`).
+  StackRelative,
+  /// A pointer at an offset from the stack pointer, indexed by Y after
+  /// dereferencing (e.g. `LDA ($01,S),Y`).
+  StackRelativeIndirectY,
+  /// A signed one-byte offset from the following instruction (e.g. `BNE $1234`).
+  Relative,
+  /// A signed two-byte offset from the following instruction (e.g. `BRL $1234`).
+  RelativeLong,
+  /// A destination and source bank (e.g. `MVN $01,$02`).
+  BlockMove,
+}
+
+impl Mode {
+  /// The number of operand bytes following the opcode, given whether the
+  /// accumulator and index registers are 16 bits wide.
+  pub fn operand_length(&self, wide_accumulator: bool, wide_index: bool) -> u16 {
+    match self {
+      Mode::Implied | Mode::Accumulator => 0,
+      Mode::ImmediateM => 1 + wide_accumulator as u16,
+      Mode::ImmediateX => 1 + wide_index as u16,
+      Mode::Immediate8
+      | Mode::Direct
+      | Mode::DirectX
+      | Mode::DirectY
+      | Mode::DirectIndirect
+      | Mode::DirectIndirectX
+      | Mode::DirectIndirectY
+      | Mode::DirectIndirectLong
+      | Mode::DirectIndirectLongY
+      | Mode::StackRelative
+      | Mode::StackRelativeIndirectY
+      | Mode::Relative => 1,
+      Mode::Absolute
+      | Mode::AbsoluteX
+      | Mode::AbsoluteY
+      | Mode::AbsoluteIndirect
+      | Mode::AbsoluteIndirectX
+      | Mode::AbsoluteIndirectLong
+      | Mode::RelativeLong
+      | Mode::BlockMove => 2,
+      Mode::AbsoluteLong | Mode::AbsoluteLongX => 3,
+    }
+  }
+}
+
+/// A decoded 65C816 instruction, without its operand.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Instruction {
+  pub opcode: u8,
+  pub mnemonic: &'static str,
+  pub mode: Mode,
+}
+
+impl Instruction {
+  /// Decode the given opcode. The 65C816 shares most of its opcodes with the
+  /// 65C02, so those are decoded by the 65C02 disassembler; only the opcodes
+  /// which the 65C816 adds or redefines are listed here.
+  pub fn decode(opcode: u8) -> Self {
+    let (mnemonic, mode) = match opcode {
+      0x00 => ("BRK", Mode::Immediate8),
+      0x02 => ("COP", Mode::Immediate8),
+      0x22 => ("JSL", Mode::AbsoluteLong),
+      0x42 => ("WDM", Mode::Immediate8),
+      0x62 => ("PER", Mode::RelativeLong),
+      0x82 => ("BRL", Mode::RelativeLong),
+      0xC2 => ("REP", Mode::Immediate8),
+      0xE2 => ("SEP", Mode::Immediate8),
+
+      0x0B => ("PHD", Mode::Implied),
+      0x1B => ("TCS", Mode::Implied),
+      0x2B => ("PLD", Mode::Implied),
+      0x3B => ("TSC", Mode::Implied),
+      0x4B => ("PHK", Mode::Implied),
+      0x5B => ("TCD", Mode::Implied),
+      0x6B => ("RTL", Mode::Implied),
+      0x7B => ("TDC", Mode::Implied),
+      0x8B => ("PHB", Mode::Implied),
+      0x9B => ("TXY", Mode::Implied),
+      0xAB => ("PLB", Mode::Implied),
+      0xBB => ("TYX", Mode::Implied),
+      0xCB => ("WAI", Mode::Implied),
+      0xDB => ("STP", Mode::Implied),
+      0xEB => ("XBA", Mode::Implied),
+      0xFB => ("XCE", Mode::Implied),
+
+      0x44 => ("MVP", Mode::BlockMove),
+      0x54 => ("MVN", Mode::BlockMove),
+      0xD4 => ("PEI", Mode::DirectIndirect),
+      0xF4 => ("PEA", Mode::Absolute),
+      0x5C => ("JML", Mode::AbsoluteLong),
+      0xDC => ("JML", Mode::AbsoluteIndirectLong),
+      0xFC => ("JSR", Mode::AbsoluteIndirectX),
+
+      0xA0 | 0xA2 | 0xC0 | 0xE0 => (
+        Mos6502Instruction::decode(opcode, Mos6502Variant::CMOS).mnemonic,
+        Mode::ImmediateX,
+      ),
+
+      _ => {
+        // The 65C02's RMB/SMB and BBR/BBS columns, and its unused opcodes in
+        // columns 3 and B, are replaced by new addressing modes of the
+        // accumulator operations
+        let operation =
+          ["ORA", "AND", "EOR", "ADC", "STA", "LDA", "CMP", "SBC"][opcode as usize >> 5];
+        let long = opcode & 0x10 != 0;
+
+        match opcode & 0x0F {
+          0x03 if long => (operation, Mode::StackRelativeIndirectY),
+          0x03 => (operation, Mode::StackRelative),
+          0x07 if long => (operation, Mode::DirectIndirectLongY),
+          0x07 => (operation, Mode::DirectIndirectLong),
+          0x0F if long => (operation, Mode::AbsoluteLongX),
+          0x0F => (operation, Mode::AbsoluteLong),
+          _ => {
+            let instruction = Mos6502Instruction::decode(opcode, Mos6502Variant::CMOS);
+            (instruction.mnemonic, Self::convert_mode(instruction.mode))
+          }
+        }
+      }
+    };
+
+    Self {
+      opcode,
+      mnemonic,
+      mode,
+    }
+  }
+
+  /// Convert an addressing mode of the 65C02 into its 65C816 equivalent.
+  fn convert_mode(mode: AddressingMode) -> Mode {
+    match mode {
+      AddressingMode::Implied => Mode::Implied,
+      AddressingMode::Accumulator => Mode::Accumulator,
+      AddressingMode::Immediate => Mode::ImmediateM,
+      AddressingMode::ZeroPage => Mode::Direct,
+      AddressingMode::ZeroPageX => Mode::DirectX,
+      AddressingMode::ZeroPageY => Mode::DirectY,
+      AddressingMode::Absolute => Mode::Absolute,
+      AddressingMode::AbsoluteX => Mode::AbsoluteX,
+      AddressingMode::AbsoluteY => Mode::AbsoluteY,
+      AddressingMode::Indirect => Mode::AbsoluteIndirect,
+      AddressingMode::IndirectX => Mode::DirectIndirectX,
+      AddressingMode::IndirectY => Mode::DirectIndirectY,
+      AddressingMode::ZeroPageIndirect => Mode::DirectIndirect,
+      AddressingMode::AbsoluteIndirectX => Mode::AbsoluteIndirectX,
+      AddressingMode::Relative => Mode::Relative,
+      AddressingMode::ZeroPageRelative => unreachable!("no 65C816 opcode uses this mode"),
+    }
+  }
+
+  /// The total length of this instruction in bytes, including the opcode,
+  /// given whether the accumulator and index registers are 16 bits wide.
+  pub fn length(&self, wide_accumulator: bool, wide_index: bool) -> u16 {
+    1 + self.mode.operand_length(wide_accumulator, wide_index)
+  }
+
+  /// Format this instruction in assembler syntax, given the address of the
+  /// opcode and the operand bytes following it. Missing operand bytes are
+  /// treated as zero.
+  pub fn format(&self, address: u16, operand: &[u8]) -> String {
+    let byte = |index: usize| operand.get(index).copied().unwrap_or(0);
+    let word = (byte(1) as u16) << 8 | byte(0) as u16;
+    let long = (byte(2) as u32) << 16 | word as u32;
+
+    let operand = match self.mode {
+      Mode::Implied => return self.mnemonic.to_owned(),
+      Mode::Accumulator => "A".to_owned(),
+      Mode::ImmediateM | Mode::ImmediateX if operand.len() >= 2 => format!("#${word:04X}"),
+      Mode::ImmediateM | Mode::ImmediateX | Mode::Immediate8 => format!("#${:02X}", byte(0)),
+      Mode::Direct => format!("${:02X}", byte(0)),
+      Mode::DirectX => format!("${:02X},X", byte(0)),
+      Mode::DirectY => format!("${:02X},Y", byte(0)),
+      Mode::DirectIndirect => format!("(${:02X})", byte(0)),
+      Mode::DirectIndirectX => format!("(${:02X},X)", byte(0)),
+      Mode::DirectIndirectY => format!("(${:02X}),Y", byte(0)),
+      Mode::DirectIndirectLong => format!("[${:02X}]", byte(0)),
+      Mode::DirectIndirectLongY => format!("[${:02X}],Y", byte(0)),
+      Mode::Absolute => format!("${word:04X}"),
+      Mode::AbsoluteX => format!("${word:04X},X"),
+      Mode::AbsoluteY => format!("${word:04X},Y"),
+      Mode::AbsoluteLong => format!("${long:06X}"),
+      Mode::AbsoluteLongX => format!("${long:06X},X"),
+      Mode::AbsoluteIndirect => format!("(${word:04X})"),
+      Mode::AbsoluteIndirectX => format!("(${word:04X},X)"),
+      Mode::AbsoluteIndirectLong => format!("[${word:04X}]"),
+      Mode::StackRelative => format!("${:02X},S", byte(0)),
+      Mode::StackRelativeIndirectY => format!("(${:02X},S),Y", byte(0)),
+      Mode::Relative => format!(
+        "${:04X}",
+        address.wrapping_add(2).wrapping_add(byte(0) as i8 as u16)
+      ),
+      Mode::RelativeLong => format!("${:04X}", address.wrapping_add(3).wrapping_add(word)),
+      Mode::BlockMove => format!("${:02X},${:02X}", byte(1), byte(0)),
+    };
+
+    format!("{} {}", self.mnemonic, operand)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn disassemble(bytes: &[u8]) -> String {
+    Instruction::decode(bytes[0]).format(0x1000, &bytes[1..])
+  }
+
+  #[test]
+  fn test_decode() {
+    assert_eq!("LDA #$1234", disassemble(&[0xA9, 0x34, 0x12]));
+    assert_eq!("LDX #$12", disassemble(&[0xA2, 0x12]));
+    assert_eq!("LDA $123456,X", disassemble(&[0xBF, 0x56, 0x34, 0x12]));
+    assert_eq!("STA [$10],Y", disassemble(&[0x97, 0x10]));
+    assert_eq!("SBC ($03,S),Y", disassemble(&[0xF3, 0x03]));
+    assert_eq!("MVN $01,$02", disassemble(&[0x54, 0x02, 0x01]));
+    assert_eq!("BRL $0FFD", disassemble(&[0x82, 0xFA, 0xFF]));
+    assert_eq!("JML [$FFFC]", disassemble(&[0xDC, 0xFC, 0xFF]));
+    assert_eq!("XCE", disassemble(&[0xFB]));
+  }
+
+  #[test]
+  fn test_lengths() {
+    assert_eq!(3, Instruction::decode(0xA9).length(true, false));
+    assert_eq!(2, Instruction::decode(0xA9).length(false, true));
+    assert_eq!(3, Instruction::decode(0xA0).length(false, true));
+    assert_eq!(2, Instruction::decode(0xC2).length(true, true));
+    assert_eq!(4, Instruction::decode(0x22).length(false, false));
+  }
+}
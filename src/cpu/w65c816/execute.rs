@@ -0,0 +1,802 @@
+use crate::cpu::w65c816::{
+  decode::{Instruction, Mode},
+  fetch::{Address, Fetch},
+  registers::flags,
+  Interrupt, W65C816,
+};
+use crate::cpu::CpuError;
+
+pub trait Execute {
+  /// Execute the given opcode, returning an error if the CPU has halted.
+  fn execute(&mut self, opcode: u8) -> Result<(), CpuError>;
+}
+
+/// The mask covering a value, and the mask of its sign bit, at the given width.
+fn masks(wide: bool) -> (u16, u16) {
+  if wide {
+    (0xFFFF, 0x8000)
+  } else {
+    (0x00FF, 0x0080)
+  }
+}
+
+impl W65C816 {
+  /// Add a value and the carry flag to the accumulator, in binary or decimal
+  /// depending on the decimal flag.
+  fn add(&mut self, value: u16) {
+    let wide = self.registers.wide_accumulator();
+    let (mask, sign) = masks(wide);
+    let a = self.registers.a();
+    let mut carry = self.registers.flag(flags::CARRY) as u32;
+
+    let result = if self.registers.flag(flags::DECIMAL) {
+      let mut result = 0;
+      for digit in 0..(if wide { 4 } else { 2 }) {
+        let shift = digit * 4;
+        let mut sum = ((a >> shift) & 0xF) as u32 + ((value >> shift) & 0xF) as u32 + carry;
+        if sum > 9 {
+          sum += 6;
+        }
+        carry = (sum > 0xF) as u32;
+        result |= (sum & 0xF) << shift;
+      }
+      result | carry << (if wide { 16 } else { 8 })
+    } else {
+      a as u32 + value as u32 + carry
+    };
+
+    let overflow = !(a ^ value) & (a ^ result as u16) & sign != 0;
+    self.registers.set_flag(flags::OVERFLOW, overflow);
+    self.registers.set_flag(flags::CARRY, result > mask as u32);
+    self.registers.set_a(result as u16);
+    self.registers.set_nz(result as u16, wide);
+  }
+
+  /// Subtract a value and the borrow (the inverse of the carry flag) from the
+  /// accumulator, in binary or decimal depending on the decimal flag.
+  fn subtract(&mut self, value: u16) {
+    if !self.registers.flag(flags::DECIMAL) {
+      let (mask, _) = masks(self.registers.wide_accumulator());
+      self.add(!value & mask);
+      return;
+    }
+
+    let wide = self.registers.wide_accumulator();
+    let (mask, sign) = masks(wide);
+    let a = self.registers.a();
+    let carry = self.registers.flag(flags::CARRY);
+
+    let binary = (a as u32).wrapping_sub(value as u32 + !carry as u32) as u16 & mask;
+    let overflow = (a ^ value) & (a ^ binary) & sign != 0;
+
+    let mut borrow = !carry as i32;
+    let mut result = 0;
+    for digit in 0..(if wide { 4 } else { 2 }) {
+      let shift = digit * 4;
+      let mut difference = ((a >> shift) & 0xF) as i32 - ((value >> shift) & 0xF) as i32 - borrow;
+      borrow = (difference < 0) as i32;
+      if difference < 0 {
+        difference += 10;
+      }
+      result |= (difference as u16 & 0xF) << shift;
+    }
+
+    self.registers.set_flag(flags::OVERFLOW, overflow);
+    self.registers.set_flag(flags::CARRY, borrow == 0);
+    self.registers.set_a(result);
+    self.registers.set_nz(result, wide);
+  }
+
+  /// Compare a register with a value, setting the flags as if by subtraction.
+  fn compare(&mut self, register: u16, value: u16, wide: bool) {
+    let (mask, _) = masks(wide);
+    let register = register & mask;
+    self.registers.set_flag(flags::CARRY, register >= value);
+    self
+      .registers
+      .set_nz(register.wrapping_sub(value) & mask, wide);
+  }
+
+  /// Read, modify and write back the operand of an instruction, which is
+  /// either the accumulator or memory, at the accumulator's width.
+  fn modify(
+    &mut self,
+    opcode: u8,
+    mode: Mode,
+    operation: fn(&mut Self, u16, bool) -> u16,
+  ) -> Result<(), CpuError> {
+    let wide = self.registers.wide_accumulator();
+
+    if mode == Mode::Accumulator {
+      self.idle();
+      let result = operation(self, self.registers.a(), wide);
+      self.registers.set_a(result);
+    } else {
+      let address = self.fetch_operand_address(opcode, mode)?;
+      let value = self.read_value(address, wide);
+      self.idle();
+      let result = operation(self, value, wide);
+      self.write_value(address, result, wide);
+    }
+
+    Ok(())
+  }
+
+  /// Take a branch to a one-byte relative offset if the condition holds.
+  fn branch(&mut self, condition: bool) {
+    let offset = self.fetch() as i8 as u16;
+
+    if condition {
+      self.idle();
+      let target = self.registers.pc.wrapping_add(offset);
+
+      if self.registers.emulation && (target ^ self.registers.pc) & 0xFF00 != 0 {
+        self.idle();
+      }
+
+      self.registers.pc = target;
+    }
+  }
+
+  /// Move a single byte of a block move, from the bank given by the second
+  /// operand byte to the bank given by the first. The instruction is repeated
+  /// until the accumulator underflows.
+  fn block_move(&mut self, step: u16) {
+    let destination = self.fetch();
+    let source = self.fetch();
+    self.registers.dbr = destination;
+
+    let value = self.read((source as u32) << 16 | self.registers.x as u32);
+    self.write((destination as u32) << 16 | self.registers.y as u32, value);
+    self.idle();
+    self.idle();
+
+    self.registers.set_x(self.registers.x.wrapping_add(step));
+    self.registers.set_y(self.registers.y.wrapping_add(step));
+    self.registers.c = self.registers.c.wrapping_sub(1);
+
+    if self.registers.c != 0xFFFF {
+      self.registers.pc = self.registers.pc.wrapping_sub(3);
+    }
+  }
+
+  /// Load an index register's value, setting the flags at the index width.
+  fn set_nz_index(&mut self, value: u16) {
+    let wide = self.registers.wide_index();
+    self.registers.set_nz(value, wide);
+  }
+}
+
+impl Execute for W65C816 {
+  fn execute(&mut self, opcode: u8) -> Result<(), CpuError> {
+    let mode = Instruction::decode(opcode).mode;
+    let wide_a = self.registers.wide_accumulator();
+    let wide_x = self.registers.wide_index();
+
+    match opcode {
+      // === LOAD ===
+      0xA1 | 0xA3 | 0xA5 | 0xA7 | 0xA9 | 0xAD | 0xAF | 0xB1 | 0xB2 | 0xB3 | 0xB5 | 0xB7 | 0xB9
+      | 0xBD | 0xBF => {
+        // LDA
+        let value = self.fetch_operand_value(opcode, mode, wide_a)?;
+        self.registers.set_a(value);
+        self.registers.set_nz(value, wide_a);
+      }
+
+      0xA2 | 0xA6 | 0xAE | 0xB6 | 0xBE => {
+        // LDX
+        let value = self.fetch_operand_value(opcode, mode, wide_x)?;
+        self.registers.set_x(value);
+        self.set_nz_index(value);
+      }
+
+      0xA0 | 0xA4 | 0xAC | 0xB4 | 0xBC => {
+        // LDY
+        let value = self.fetch_operand_value(opcode, mode, wide_x)?;
+        self.registers.set_y(value);
+        self.set_nz_index(value);
+      }
+
+      // === STORE ===
+      0x81 | 0x83 | 0x85 | 0x87 | 0x8D | 0x8F | 0x91 | 0x92 | 0x93 | 0x95 | 0x97 | 0x99 | 0x9D
+      | 0x9F => {
+        // STA
+        let address = self.fetch_operand_address(opcode, mode)?;
+        self.write_value(address, self.registers.a(), wide_a);
+      }
+
+      0x86 | 0x8E | 0x96 => {
+        // STX
+        let address = self.fetch_operand_address(opcode, mode)?;
+        self.write_value(address, self.registers.x, wide_x);
+      }
+
+      0x84 | 0x8C | 0x94 => {
+        // STY
+        let address = self.fetch_operand_address(opcode, mode)?;
+        self.write_value(address, self.registers.y, wide_x);
+      }
+
+      0x64 | 0x74 | 0x9C | 0x9E => {
+        // STZ
+        let address = self.fetch_operand_address(opcode, mode)?;
+        self.write_value(address, 0, wide_a);
+      }
+
+      // === TRANSFER ===
+      0xAA | 0xA8 => {
+        // TAX, TAY
+        self.idle();
+        let value = self.registers.c;
+        match opcode {
+          0xAA => self.registers.set_x(value),
+          _ => self.registers.set_y(value),
+        }
+        self.set_nz_index(value);
+      }
+
+      0x8A | 0x98 => {
+        // TXA, TYA
+        self.idle();
+        let value = match opcode {
+          0x8A => self.registers.x,
+          _ => self.registers.y,
+        };
+        self.registers.set_a(value);
+        self.registers.set_nz(value, wide_a);
+      }
+
+      0x9B | 0xBB => {
+        // TXY, TYX
+        self.idle();
+        match opcode {
+          0x9B => self.registers.set_y(self.registers.x),
+          _ => self.registers.set_x(self.registers.y),
+        }
+        let value = match opcode {
+          0x9B => self.registers.y,
+          _ => self.registers.x,
+        };
+        self.set_nz_index(value);
+      }
+
+      0xBA => {
+        // TSX
+        self.idle();
+        self.registers.set_x(self.registers.sp);
+        self.set_nz_index(self.registers.x);
+      }
+
+      0x9A => {
+        // TXS
+        self.idle();
+        self.registers.set_sp(self.registers.x);
+      }
+
+      0x1B => {
+        // TCS
+        self.idle();
+        self.registers.set_sp(self.registers.c);
+      }
+
+      0x3B => {
+        // TSC
+        self.idle();
+        self.registers.c = self.registers.sp;
+        self.registers.set_nz(self.registers.c, true);
+      }
+
+      0x5B => {
+        // TCD
+        self.idle();
+        self.registers.d = self.registers.c;
+        self.registers.set_nz(self.registers.d, true);
+      }
+
+      0x7B => {
+        // TDC
+        self.idle();
+        self.registers.c = self.registers.d;
+        self.registers.set_nz(self.registers.c, true);
+      }
+
+      0xEB => {
+        // XBA
+        self.idle();
+        self.idle();
+        self.registers.c = self.registers.c.rotate_left(8);
+        self.registers.set_nz(self.registers.c, false);
+      }
+
+      // === STACK ===
+      0x48 => {
+        // PHA
+        self.idle();
+        self.push_value(self.registers.a(), wide_a);
+      }
+
+      0xDA | 0x5A => {
+        // PHX, PHY
+        self.idle();
+        let value = match opcode {
+          0xDA => self.registers.x,
+          _ => self.registers.y,
+        };
+        self.push_value(value, wide_x);
+      }
+
+      0x08 => {
+        // PHP
+        self.idle();
+        self.push(self.registers.p);
+      }
+
+      0x8B => {
+        // PHB
+        self.idle();
+        self.push(self.registers.dbr);
+      }
+
+      0x4B => {
+        // PHK
+        self.idle();
+        self.push(self.registers.pbr);
+      }
+
+      0x0B => {
+        // PHD
+        self.idle();
+        self.push_word(self.registers.d);
+      }
+
+      0x68 => {
+        // PLA
+        self.idle();
+        self.idle();
+        let value = self.pop_value(wide_a);
+        self.registers.set_a(value);
+        self.registers.set_nz(value, wide_a);
+      }
+
+      0xFA | 0x7A => {
+        // PLX, PLY
+        self.idle();
+        self.idle();
+        let value = self.pop_value(wide_x);
+        match opcode {
+          0xFA => self.registers.set_x(value),
+          _ => self.registers.set_y(value),
+        }
+        self.set_nz_index(value);
+      }
+
+      0x28 => {
+        // PLP
+        self.idle();
+        self.idle();
+        let value = self.pop();
+        self.registers.set_p(value);
+      }
+
+      0xAB => {
+        // PLB
+        self.idle();
+        self.idle();
+        self.registers.dbr = self.pop();
+        self.registers.set_nz(self.registers.dbr as u16, false);
+      }
+
+      0x2B => {
+        // PLD
+        self.idle();
+        self.idle();
+        self.registers.d = self.pop_word();
+        self.registers.set_nz(self.registers.d, true);
+      }
+
+      0xF4 => {
+        // PEA
+        let value = self.fetch_word();
+        self.push_word(value);
+      }
+
+      0xD4 => {
+        // PEI
+        let offset = self.fetch() as u16;
+        let address = self.direct_address(offset);
+        let value = self.read_value(address, true);
+        self.push_word(value);
+      }
+
+      0x62 => {
+        // PER
+        let offset = self.fetch_word();
+        self.idle();
+        self.push_word(self.registers.pc.wrapping_add(offset));
+      }
+
+      // === INCREMENT ===
+      0x1A | 0xE6 | 0xEE | 0xF6 | 0xFE => {
+        // INC
+        self.modify(opcode, mode, |cpu, value, wide| {
+          let result = value.wrapping_add(1) & masks(wide).0;
+          cpu.registers.set_nz(result, wide);
+          result
+        })?;
+      }
+
+      0x3A | 0xC6 | 0xCE | 0xD6 | 0xDE => {
+        // DEC
+        self.modify(opcode, mode, |cpu, value, wide| {
+          let result = value.wrapping_sub(1) & masks(wide).0;
+          cpu.registers.set_nz(result, wide);
+          result
+        })?;
+      }
+
+      0xE8 | 0xC8 => {
+        // INX, INY
+        self.idle();
+        let value = match opcode {
+          0xE8 => self.registers.x,
+          _ => self.registers.y,
+        }
+        .wrapping_add(1);
+        match opcode {
+          0xE8 => self.registers.set_x(value),
+          _ => self.registers.set_y(value),
+        }
+        self.set_nz_index(value);
+      }
+
+      0xCA | 0x88 => {
+        // DEX, DEY
+        self.idle();
+        let value = match opcode {
+          0xCA => self.registers.x,
+          _ => self.registers.y,
+        }
+        .wrapping_sub(1);
+        match opcode {
+          0xCA => self.registers.set_x(value),
+          _ => self.registers.set_y(value),
+        }
+        self.set_nz_index(value);
+      }
+
+      // === ARITHMETIC ===
+      0x61 | 0x63 | 0x65 | 0x67 | 0x69 | 0x6D | 0x6F | 0x71 | 0x72 | 0x73 | 0x75 | 0x77 | 0x79
+      | 0x7D | 0x7F => {
+        // ADC
+        let value = self.fetch_operand_value(opcode, mode, wide_a)?;
+        self.add(value);
+      }
+
+      0xE1 | 0xE3 | 0xE5 | 0xE7 | 0xE9 | 0xED | 0xEF | 0xF1 | 0xF2 | 0xF3 | 0xF5 | 0xF7 | 0xF9
+      | 0xFD | 0xFF => {
+        // SBC
+        let value = self.fetch_operand_value(opcode, mode, wide_a)?;
+        self.subtract(value);
+      }
+
+      // === BITWISE ===
+      0x21 | 0x23 | 0x25 | 0x27 | 0x29 | 0x2D | 0x2F | 0x31 | 0x32 | 0x33 | 0x35 | 0x37 | 0x39
+      | 0x3D | 0x3F => {
+        // AND
+        let value = self.fetch_operand_value(opcode, mode, wide_a)? & self.registers.a();
+        self.registers.set_a(value);
+        self.registers.set_nz(value, wide_a);
+      }
+
+      0x01 | 0x03 | 0x05 | 0x07 | 0x09 | 0x0D | 0x0F | 0x11 | 0x12 | 0x13 | 0x15 | 0x17 | 0x19
+      | 0x1D | 0x1F => {
+        // ORA
+        let value = self.fetch_operand_value(opcode, mode, wide_a)? | self.registers.a();
+        self.registers.set_a(value);
+        self.registers.set_nz(value, wide_a);
+      }
+
+      0x41 | 0x43 | 0x45 | 0x47 | 0x49 | 0x4D | 0x4F | 0x51 | 0x52 | 0x53 | 0x55 | 0x57 | 0x59
+      | 0x5D | 0x5F => {
+        // EOR
+        let value = self.fetch_operand_value(opcode, mode, wide_a)? ^ self.registers.a();
+        self.registers.set_a(value);
+        self.registers.set_nz(value, wide_a);
+      }
+
+      0x24 | 0x2C | 0x34 | 0x3C | 0x89 => {
+        // BIT
+        let value = self.fetch_operand_value(opcode, mode, wide_a)?;
+        let (_, sign) = masks(wide_a);
+        self
+          .registers
+          .set_flag(flags::ZERO, value & self.registers.a() == 0);
+
+        // The immediate form only affects the zero flag
+        if mode != Mode::ImmediateM {
+          self.registers.set_flag(flags::NEGATIVE, value & sign != 0);
+          self
+            .registers
+            .set_flag(flags::OVERFLOW, value & (sign >> 1) != 0);
+        }
+      }
+
+      0x04 | 0x0C => {
+        // TSB
+        self.modify(opcode, mode, |cpu, value, _| {
+          let a = cpu.registers.a();
+          cpu.registers.set_flag(flags::ZERO, value & a == 0);
+          value | a
+        })?;
+      }
+
+      0x14 | 0x1C => {
+        // TRB
+        self.modify(opcode, mode, |cpu, value, _| {
+          let a = cpu.registers.a();
+          cpu.registers.set_flag(flags::ZERO, value & a == 0);
+          value & !a
+        })?;
+      }
+
+      // === SHIFT ===
+      0x06 | 0x0A | 0x0E | 0x16 | 0x1E => {
+        // ASL
+        self.modify(opcode, mode, |cpu, value, wide| {
+          let (mask, sign) = masks(wide);
+          let result = (value << 1) & mask;
+          cpu.registers.set_flag(flags::CARRY, value & sign != 0);
+          cpu.registers.set_nz(result, wide);
+          result
+        })?;
+      }
+
+      0x46 | 0x4A | 0x4E | 0x56 | 0x5E => {
+        // LSR
+        self.modify(opcode, mode, |cpu, value, wide| {
+          let result = value >> 1;
+          cpu.registers.set_flag(flags::CARRY, value & 1 != 0);
+          cpu.registers.set_nz(result, wide);
+          result
+        })?;
+      }
+
+      0x26 | 0x2A | 0x2E | 0x36 | 0x3E => {
+        // ROL
+        self.modify(opcode, mode, |cpu, value, wide| {
+          let (mask, sign) = masks(wide);
+          let carry = cpu.registers.flag(flags::CARRY) as u16;
+          let result = ((value << 1) | carry) & mask;
+          cpu.registers.set_flag(flags::CARRY, value & sign != 0);
+          cpu.registers.set_nz(result, wide);
+          result
+        })?;
+      }
+
+      0x66 | 0x6A | 0x6E | 0x76 | 0x7E => {
+        // ROR
+        self.modify(opcode, mode, |cpu, value, wide| {
+          let (_, sign) = masks(wide);
+          let carry = if cpu.registers.flag(flags::CARRY) {
+            sign
+          } else {
+            0
+          };
+          let result = (value >> 1) | carry;
+          cpu.registers.set_flag(flags::CARRY, value & 1 != 0);
+          cpu.registers.set_nz(result, wide);
+          result
+        })?;
+      }
+
+      // === COMPARE ===
+      0xC1 | 0xC3 | 0xC5 | 0xC7 | 0xC9 | 0xCD | 0xCF | 0xD1 | 0xD2 | 0xD3 | 0xD5 | 0xD7 | 0xD9
+      | 0xDD | 0xDF => {
+        // CMP
+        let value = self.fetch_operand_value(opcode, mode, wide_a)?;
+        self.compare(self.registers.c, value, wide_a);
+      }
+
+      0xE0 | 0xE4 | 0xEC => {
+        // CPX
+        let value = self.fetch_operand_value(opcode, mode, wide_x)?;
+        self.compare(self.registers.x, value, wide_x);
+      }
+
+      0xC0 | 0xC4 | 0xCC => {
+        // CPY
+        let value = self.fetch_operand_value(opcode, mode, wide_x)?;
+        self.compare(self.registers.y, value, wide_x);
+      }
+
+      // === BRANCH ===
+      0x10 => self.branch(!self.registers.flag(flags::NEGATIVE)), // BPL
+      0x30 => self.branch(self.registers.flag(flags::NEGATIVE)),  // BMI
+      0x50 => self.branch(!self.registers.flag(flags::OVERFLOW)), // BVC
+      0x70 => self.branch(self.registers.flag(flags::OVERFLOW)),  // BVS
+      0x90 => self.branch(!self.registers.flag(flags::CARRY)),    // BCC
+      0xB0 => self.branch(self.registers.flag(flags::CARRY)),     // BCS
+      0xD0 => self.branch(!self.registers.flag(flags::ZERO)),     // BNE
+      0xF0 => self.branch(self.registers.flag(flags::ZERO)),      // BEQ
+      0x80 => self.branch(true),                                  // BRA
+
+      0x82 => {
+        // BRL
+        let offset = self.fetch_word();
+        self.idle();
+        self.registers.pc = self.registers.pc.wrapping_add(offset);
+      }
+
+      // === JUMP ===
+      0x4C => {
+        // JMP absolute
+        self.registers.pc = self.fetch_word();
+      }
+
+      0x6C => {
+        // JMP (absolute)
+        let pointer = self.fetch_word();
+        self.registers.pc = self.read_bank_zero_word(pointer);
+      }
+
+      0x7C | 0xFC => {
+        // JMP (absolute,X), JSR (absolute,X)
+        let lo = self.fetch();
+        if opcode == 0xFC {
+          self.push_word(self.registers.pc);
+        }
+        let hi = self.fetch();
+        self.idle();
+
+        let pointer = ((hi as u16) << 8 | lo as u16).wrapping_add(self.registers.x);
+        let bank = (self.registers.pbr as u32) << 16;
+        let address = Address {
+          address: bank | pointer as u32,
+          wrap: true,
+        };
+        self.registers.pc = self.read_value(address, true);
+      }
+
+      0x5C => {
+        // JML long
+        let address = self.fetch_long();
+        self.registers.pbr = (address >> 16) as u8;
+        self.registers.pc = address as u16;
+      }
+
+      0xDC => {
+        // JML [absolute]
+        let pointer = self.fetch_word();
+        self.registers.pc = self.read_bank_zero_word(pointer);
+        self.registers.pbr = self.read(pointer.wrapping_add(2) as u32);
+      }
+
+      0x20 => {
+        // JSR absolute
+        let target = self.fetch_word();
+        self.idle();
+        self.push_word(self.registers.pc.wrapping_sub(1));
+        self.registers.pc = target;
+      }
+
+      0x22 => {
+        // JSL long
+        let target = self.fetch_word();
+        self.push(self.registers.pbr);
+        self.idle();
+        let bank = self.fetch();
+        self.push_word(self.registers.pc.wrapping_sub(1));
+        self.registers.pbr = bank;
+        self.registers.pc = target;
+      }
+
+      0x60 => {
+        // RTS
+        self.idle();
+        self.idle();
+        self.registers.pc = self.pop_word().wrapping_add(1);
+        self.idle();
+      }
+
+      0x6B => {
+        // RTL
+        self.idle();
+        self.idle();
+        self.registers.pc = self.pop_word().wrapping_add(1);
+        self.registers.pbr = self.pop();
+      }
+
+      0x40 => {
+        // RTI
+        self.idle();
+        self.idle();
+        let status = self.pop();
+        self.registers.set_p(status);
+        self.registers.pc = self.pop_word();
+        if !self.registers.emulation {
+          self.registers.pbr = self.pop();
+        }
+      }
+
+      // === INTERRUPT ===
+      0x00 => {
+        // BRK (with a signature byte)
+        self.fetch();
+        self.interrupt(Interrupt::Brk);
+      }
+
+      0x02 => {
+        // COP (with a signature byte)
+        self.fetch();
+        self.interrupt(Interrupt::Cop);
+      }
+
+      0xCB => {
+        // WAI
+        self.idle();
+        self.idle();
+        self.waiting = true;
+      }
+
+      0xDB => {
+        // STP
+        return Err(CpuError::Stopped {
+          address: self.registers.program_address().wrapping_sub(1),
+        });
+      }
+
+      // === STATUS ===
+      0x18 | 0x38 | 0x58 | 0x78 | 0xB8 | 0xD8 | 0xF8 => {
+        // CLC, SEC, CLI, SEI, CLV, CLD, SED
+        self.idle();
+        let (flag, value) = match opcode {
+          0x18 => (flags::CARRY, false),
+          0x38 => (flags::CARRY, true),
+          0x58 => (flags::INTERRUPT, false),
+          0x78 => (flags::INTERRUPT, true),
+          0xB8 => (flags::OVERFLOW, false),
+          0xD8 => (flags::DECIMAL, false),
+          _ => (flags::DECIMAL, true),
+        };
+        self.registers.set_flag(flag, value);
+      }
+
+      0xC2 => {
+        // REP
+        let mask = self.fetch();
+        self.idle();
+        self.registers.set_p(self.registers.p & !mask);
+      }
+
+      0xE2 => {
+        // SEP
+        let mask = self.fetch();
+        self.idle();
+        self.registers.set_p(self.registers.p | mask);
+      }
+
+      0xFB => {
+        // XCE
+        self.idle();
+        let carry = self.registers.flag(flags::CARRY);
+        self
+          .registers
+          .set_flag(flags::CARRY, self.registers.emulation);
+        self.registers.set_emulation(carry);
+      }
+
+      // === BLOCK MOVE ===
+      0x54 => self.block_move(1),      // MVN
+      0x44 => self.block_move(0xFFFF), // MVP
+
+      // === NOP ===
+      0xEA => self.idle(), // NOP
+      0x42 => {
+        // WDM (reserved for future expansion, with a signature byte)
+        self.fetch();
+      }
+    }
+
+    Ok(())
+  }
+}
@@ -0,0 +1,259 @@
+use crate::cpu::w65c816::{decode::Mode, W65C816};
+use crate::cpu::CpuError;
+
+/// A location in memory operated on by an instruction. Direct page and stack
+/// relative addresses always lie in bank 0, so multi-byte values there wrap
+/// around within the bank; other addresses carry into the next bank.
+#[derive(Debug, Copy, Clone)]
+pub struct Address {
+  pub address: u32,
+  pub wrap: bool,
+}
+
+impl Address {
+  /// An address which may carry into the next bank.
+  fn long(address: u32) -> Self {
+    Self {
+      address: address & 0xFF_FFFF,
+      wrap: false,
+    }
+  }
+
+  /// An address in bank 0, which wraps around within the bank.
+  fn bank_zero(address: u16) -> Self {
+    Self {
+      address: address as u32,
+      wrap: true,
+    }
+  }
+
+  /// The address of the byte at the given offset from this one.
+  pub fn offset(&self, offset: u16) -> u32 {
+    if self.wrap {
+      (self.address & 0xFF_0000) | (self.address as u16).wrapping_add(offset) as u32
+    } else {
+      (self.address + offset as u32) & 0xFF_FFFF
+    }
+  }
+}
+
+/// Fetch values or addresses from memory, based on an instruction's
+/// addressing mode.
+pub trait Fetch {
+  /// Fetch an immediate 1-byte value at the program counter, and increment the
+  /// program counter within the program bank.
+  fn fetch(&mut self) -> u8;
+
+  /// Fetch an immediate 2-byte value (little-endian).
+  fn fetch_word(&mut self) -> u16;
+
+  /// Fetch an immediate 3-byte address (little-endian).
+  fn fetch_long(&mut self) -> u32;
+
+  /// Read a value from memory, which is 16 bits wide if `wide` is set.
+  fn read_value(&mut self, address: Address, wide: bool) -> u16;
+
+  /// Write a value to memory, which is 16 bits wide if `wide` is set.
+  fn write_value(&mut self, address: Address, value: u16, wide: bool);
+
+  /// Fetch the operand address of an instruction with the given opcode and
+  /// addressing mode. Returns an error if the mode does not refer to memory.
+  fn fetch_operand_address(&mut self, opcode: u8, mode: Mode) -> Result<Address, CpuError>;
+
+  /// Fetch the operand value of an instruction with the given opcode and
+  /// addressing mode, which is 16 bits wide if `wide` is set.
+  fn fetch_operand_value(&mut self, opcode: u8, mode: Mode, wide: bool) -> Result<u16, CpuError>;
+}
+
+impl W65C816 {
+  /// The bank 0 address at the given offset into the direct page. In emulation
+  /// mode, when the direct page is aligned to a page boundary, it wraps around
+  /// within that page like the 6502's zero page.
+  pub(super) fn direct_address(&self, offset: u16) -> Address {
+    let d = self.registers.d;
+
+    if self.registers.emulation && d & 0xFF == 0 {
+      Address::bank_zero(d | (offset & 0xFF))
+    } else {
+      Address::bank_zero(d.wrapping_add(offset))
+    }
+  }
+
+  /// Read a pointer from the direct page, at the given offset. The pointer is
+  /// 24 bits wide if `long` is set; otherwise it points into the data bank.
+  fn read_direct_pointer(&mut self, offset: u16, long: bool) -> u32 {
+    let address = |offset: u16| self.direct_address(offset).address;
+    let (lo_address, hi_address, bank_address) = (
+      address(offset),
+      address(offset.wrapping_add(1)),
+      address(offset.wrapping_add(2)),
+    );
+
+    let lo = self.read(lo_address);
+    let hi = self.read(hi_address);
+
+    let bank = if long {
+      self.read(bank_address)
+    } else {
+      self.registers.dbr
+    };
+
+    (bank as u32) << 16 | (hi as u32) << 8 | lo as u32
+  }
+
+  /// Add an index register to an address in the data bank. The 65C816 takes an
+  /// extra cycle to do this when the index is 16 bits wide or a page boundary
+  /// is crossed.
+  fn index_address(&mut self, base: u32, index: u16) -> Address {
+    let address = base + index as u32;
+
+    if self.registers.wide_index() || (base ^ address) & 0xFF00 != 0 {
+      self.idle();
+    }
+
+    Address::long(address)
+  }
+}
+
+impl Fetch for W65C816 {
+  fn fetch(&mut self) -> u8 {
+    let result = self.read(self.registers.program_address());
+    self.registers.pc = self.registers.pc.wrapping_add(1);
+    result
+  }
+
+  fn fetch_word(&mut self) -> u16 {
+    let lo = self.fetch();
+    let hi = self.fetch();
+    (hi as u16) << 8 | lo as u16
+  }
+
+  fn fetch_long(&mut self) -> u32 {
+    let word = self.fetch_word();
+    let bank = self.fetch();
+    (bank as u32) << 16 | word as u32
+  }
+
+  fn read_value(&mut self, address: Address, wide: bool) -> u16 {
+    let lo = self.read(address.address);
+
+    if wide {
+      let hi = self.read(address.offset(1));
+      (hi as u16) << 8 | lo as u16
+    } else {
+      lo as u16
+    }
+  }
+
+  fn write_value(&mut self, address: Address, value: u16, wide: bool) {
+    self.write(address.address, value as u8);
+
+    if wide {
+      self.write(address.offset(1), (value >> 8) as u8);
+    }
+  }
+
+  fn fetch_operand_address(&mut self, opcode: u8, mode: Mode) -> Result<Address, CpuError> {
+    let data_bank = (self.registers.dbr as u32) << 16;
+
+    // Direct page addressing takes an extra cycle when the direct page is not
+    // aligned to a page boundary
+    let direct_penalty = |cpu: &mut Self| {
+      if cpu.registers.d & 0xFF != 0 {
+        cpu.idle();
+      }
+    };
+
+    let address = match mode {
+      Mode::Direct => {
+        let offset = self.fetch() as u16;
+        direct_penalty(self);
+        self.direct_address(offset)
+      }
+      Mode::DirectX | Mode::DirectY => {
+        let offset = self.fetch() as u16;
+        direct_penalty(self);
+        self.idle();
+
+        let index = match mode {
+          Mode::DirectX => self.registers.x,
+          _ => self.registers.y,
+        };
+        self.direct_address(offset.wrapping_add(index))
+      }
+      Mode::DirectIndirect => {
+        let offset = self.fetch() as u16;
+        direct_penalty(self);
+        Address::long(self.read_direct_pointer(offset, false))
+      }
+      Mode::DirectIndirectX => {
+        let offset = self.fetch() as u16;
+        direct_penalty(self);
+        self.idle();
+        let offset = offset.wrapping_add(self.registers.x);
+        Address::long(self.read_direct_pointer(offset, false))
+      }
+      Mode::DirectIndirectY => {
+        let offset = self.fetch() as u16;
+        direct_penalty(self);
+        let base = self.read_direct_pointer(offset, false);
+        self.index_address(base, self.registers.y)
+      }
+      Mode::DirectIndirectLong => {
+        let offset = self.fetch() as u16;
+        direct_penalty(self);
+        Address::long(self.read_direct_pointer(offset, true))
+      }
+      Mode::DirectIndirectLongY => {
+        let offset = self.fetch() as u16;
+        direct_penalty(self);
+        let base = self.read_direct_pointer(offset, true);
+        Address::long(base + self.registers.y as u32)
+      }
+      Mode::Absolute => Address::long(data_bank | self.fetch_word() as u32),
+      Mode::AbsoluteX => {
+        let base = data_bank | self.fetch_word() as u32;
+        self.index_address(base, self.registers.x)
+      }
+      Mode::AbsoluteY => {
+        let base = data_bank | self.fetch_word() as u32;
+        self.index_address(base, self.registers.y)
+      }
+      Mode::AbsoluteLong => Address::long(self.fetch_long()),
+      Mode::AbsoluteLongX => Address::long(self.fetch_long() + self.registers.x as u32),
+      Mode::StackRelative => {
+        let offset = self.fetch() as u16;
+        self.idle();
+        Address::bank_zero(self.registers.sp.wrapping_add(offset))
+      }
+      Mode::StackRelativeIndirectY => {
+        let offset = self.fetch() as u16;
+        self.idle();
+        let pointer = Address::bank_zero(self.registers.sp.wrapping_add(offset));
+        let base = data_bank | self.read_value(pointer, true) as u32;
+        self.idle();
+        Address::long(base + self.registers.y as u32)
+      }
+      _ => {
+        return Err(CpuError::InvalidAddressingMode {
+          address: self.registers.pc.wrapping_sub(1),
+          opcode,
+        })
+      }
+    };
+
+    Ok(address)
+  }
+
+  fn fetch_operand_value(&mut self, opcode: u8, mode: Mode, wide: bool) -> Result<u16, CpuError> {
+    match mode {
+      Mode::ImmediateM | Mode::ImmediateX if wide => Ok(self.fetch_word()),
+      Mode::ImmediateM | Mode::ImmediateX | Mode::Immediate8 => Ok(self.fetch() as u16),
+      Mode::Accumulator => Ok(self.registers.a()),
+      _ => {
+        let address = self.fetch_operand_address(opcode, mode)?;
+        Ok(self.read_value(address, wide))
+      }
+    }
+  }
+}
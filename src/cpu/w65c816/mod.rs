@@ -0,0 +1,390 @@
+pub mod decode;
+mod execute;
+mod fetch;
+pub mod registers;
+use crate::debugger::DebugHandler;
+use crate::memory::{ActiveInterrupt, LongMemory};
+use crate::trace::TraceHandler;
+use execute::Execute;
+use fetch::Fetch;
+use registers::{flags, Registers};
+
+use super::{Cpu, CpuError};
+
+const CLOCKS_PER_POLL: u64 = 100;
+
+/// The addresses of the interrupt vectors, all located in bank 0.
+mod vectors {
+  pub const EMULATION_COP: u16 = 0xFFF4;
+  pub const EMULATION_NMI: u16 = 0xFFFA;
+  pub const RESET: u16 = 0xFFFC;
+  pub const EMULATION_IRQ: u16 = 0xFFFE;
+
+  pub const NATIVE_COP: u16 = 0xFFE4;
+  pub const NATIVE_BRK: u16 = 0xFFE6;
+  pub const NATIVE_NMI: u16 = 0xFFEA;
+  pub const NATIVE_IRQ: u16 = 0xFFEE;
+}
+
+/// The sources of an interrupt, which determine the vector that is used.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Interrupt {
+  Brk,
+  Cop,
+  Irq,
+  Nmi,
+}
+
+/// The WDC 65C816 CPU and its associated memory. This is a 16-bit extension of
+/// the 65C02 with a 24-bit address space. It starts in emulation mode, where it
+/// behaves like a 65C02, and can be switched into native mode with `XCE`.
+///
+/// Cycles are counted as one per bus access, plus the internal operation
+/// cycles of each instruction. The debugger and trace handlers are built
+/// around the 6502's registers, so they are not supported by this CPU.
+pub struct W65C816 {
+  pub registers: Registers,
+  pub memory: Box<dyn LongMemory>,
+  cycle_count: u64,
+  cycles_since_poll: u64,
+
+  /// The number of cycles used so far by the current instruction.
+  cycles: u8,
+
+  /// Set by `WAI` until the next interrupt arrives.
+  waiting: bool,
+
+  /// Set when the CPU has stopped executing instructions (e.g. after `STP`),
+  /// until it is reset.
+  halted: Option<CpuError>,
+}
+
+impl W65C816 {
+  pub fn new(memory: impl LongMemory + 'static) -> W65C816 {
+    W65C816 {
+      registers: Registers::new(),
+      memory: Box::new(memory),
+      cycle_count: 0,
+      cycles_since_poll: 0,
+      cycles: 0,
+      waiting: false,
+      halted: None,
+    }
+  }
+
+  /// Read a byte from the given 24-bit address.
+  fn read(&mut self, address: u32) -> u8 {
+    self.cycles += 1;
+    self.memory.read(address & 0xFF_FFFF)
+  }
+
+  /// Write a byte to the given 24-bit address.
+  fn write(&mut self, address: u32, value: u8) {
+    self.cycles += 1;
+    self.memory.write(address & 0xFF_FFFF, value);
+  }
+
+  /// Spend a cycle on an internal operation, without accessing the bus.
+  fn idle(&mut self) {
+    self.cycles += 1;
+  }
+
+  /// Read a word (little-endian) from bank 0, as used for vectors and
+  /// indirect jump pointers.
+  fn read_bank_zero_word(&mut self, address: u16) -> u16 {
+    let lo = self.read(address as u32);
+    let hi = self.read(address.wrapping_add(1) as u32);
+    (hi as u16) << 8 | lo as u16
+  }
+
+  /// Push a byte onto the stack.
+  fn push(&mut self, value: u8) {
+    self.write(self.registers.sp as u32, value);
+    self.registers.set_sp(self.registers.sp.wrapping_sub(1));
+  }
+
+  /// Pop a byte from the stack.
+  fn pop(&mut self) -> u8 {
+    self.registers.set_sp(self.registers.sp.wrapping_add(1));
+    self.read(self.registers.sp as u32)
+  }
+
+  /// Push a word (little-endian) onto the stack.
+  fn push_word(&mut self, value: u16) {
+    self.push((value >> 8) as u8);
+    self.push(value as u8);
+  }
+
+  /// Pop a word (little-endian) from the stack.
+  fn pop_word(&mut self) -> u16 {
+    let lo = self.pop();
+    let hi = self.pop();
+    (hi as u16) << 8 | lo as u16
+  }
+
+  /// Push a value which is 16 bits wide if `wide` is set, or 8 bits otherwise.
+  fn push_value(&mut self, value: u16, wide: bool) {
+    if wide {
+      self.push_word(value);
+    } else {
+      self.push(value as u8);
+    }
+  }
+
+  /// Pop a value which is 16 bits wide if `wide` is set, or 8 bits otherwise.
+  fn pop_value(&mut self, wide: bool) -> u16 {
+    if wide {
+      self.pop_word()
+    } else {
+      self.pop() as u16
+    }
+  }
+
+  /// Push the program counter and status register, and jump to the vector for
+  /// the given interrupt. In native mode, the program bank is pushed as well.
+  fn interrupt(&mut self, interrupt: Interrupt) {
+    if interrupt == Interrupt::Irq && self.registers.flag(flags::INTERRUPT) {
+      return;
+    }
+
+    if !self.registers.emulation {
+      self.push(self.registers.pbr);
+    }
+
+    self.push_word(self.registers.pc);
+
+    if self.registers.emulation && interrupt != Interrupt::Brk {
+      self.push(self.registers.p & !flags::BREAK);
+    } else {
+      self.push(self.registers.p);
+    }
+
+    self.registers.set_flag(flags::INTERRUPT, true);
+    self.registers.set_flag(flags::DECIMAL, false);
+    self.registers.pbr = 0;
+
+    let vector = match (self.registers.emulation, interrupt) {
+      (true, Interrupt::Brk | Interrupt::Irq) => vectors::EMULATION_IRQ,
+      (true, Interrupt::Cop) => vectors::EMULATION_COP,
+      (true, Interrupt::Nmi) => vectors::EMULATION_NMI,
+      (false, Interrupt::Brk) => vectors::NATIVE_BRK,
+      (false, Interrupt::Cop) => vectors::NATIVE_COP,
+      (false, Interrupt::Irq) => vectors::NATIVE_IRQ,
+      (false, Interrupt::Nmi) => vectors::NATIVE_NMI,
+    };
+
+    self.registers.pc = self.read_bank_zero_word(vector);
+  }
+
+  /// Handle an interrupt raised by the memory. Any interrupt wakes the CPU
+  /// from `WAI`, even if it is masked.
+  fn handle_interrupt(&mut self, interrupt: ActiveInterrupt) {
+    match interrupt {
+      ActiveInterrupt::None => (),
+      ActiveInterrupt::NMI => {
+        self.waiting = false;
+        self.interrupt(Interrupt::Nmi);
+      }
+      ActiveInterrupt::IRQ => {
+        self.waiting = false;
+        self.interrupt(Interrupt::Irq);
+      }
+    }
+  }
+}
+
+impl Cpu for W65C816 {
+  fn reset(&mut self) {
+    self.halted = None;
+    self.waiting = false;
+    self.memory.reset();
+    self.registers.reset();
+    self.registers.pc = self.read_bank_zero_word(vectors::RESET);
+  }
+
+  fn get_cycle_count(&self) -> u64 {
+    self.cycle_count
+  }
+
+  /// Tracing is not supported by the 65C816, so the handler is discarded.
+  fn attach_trace_handler(&mut self, _trace: Box<dyn TraceHandler>) {}
+
+  /// The debugger is not supported by the 65C816, so it is discarded.
+  fn attach_debugger(&mut self, _debugger: Box<dyn DebugHandler>) {}
+
+  /// Execute a single instruction, or wait for a single cycle after `WAI`.
+  fn tick(&mut self) -> Result<u8, CpuError> {
+    if let Some(error) = self.halted {
+      return Err(error);
+    }
+
+    self.cycles = 0;
+
+    if self.waiting {
+      self.idle();
+    } else {
+      let opcode = self.fetch();
+
+      if let Err(error) = self.execute(opcode) {
+        self.halted = Some(error);
+        return Err(error);
+      }
+    }
+
+    let cycles = self.cycles;
+    self.cycle_count += cycles as u64;
+    self.cycles_since_poll += cycles as u64;
+
+    if self.cycles_since_poll >= CLOCKS_PER_POLL {
+      let total_cycle_count = self.get_cycle_count();
+      let interrupt = self.memory.poll(self.cycles_since_poll, total_cycle_count);
+      self.handle_interrupt(interrupt);
+      self.cycles_since_poll = 0;
+    }
+
+    Ok(cycles)
+  }
+
+  fn cleanup(&mut self) -> Result<(), &str> {
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::memory::{BlockMemory, LongBranchMemory, Memory};
+
+  fn cpu_with_program(program: &[u8]) -> W65C816 {
+    let mut bank_zero = BlockMemory::ram(0x10000);
+    for (i, byte) in program.iter().enumerate() {
+      bank_zero.write(0x0200 + i as u16, *byte);
+    }
+
+    let memory = LongBranchMemory::new()
+      .map(0x00, bank_zero)
+      .map(0x01, BlockMemory::ram(0x10000))
+      .map(0x02, BlockMemory::ram(0x10000));
+
+    let mut cpu = W65C816::new(memory);
+    cpu.registers.pc = 0x0200;
+    cpu
+  }
+
+  fn run(cpu: &mut W65C816, instructions: usize) {
+    for _ in 0..instructions {
+      cpu.tick().unwrap();
+    }
+  }
+
+  #[test]
+  fn test_native_mode_registers() {
+    let mut cpu = cpu_with_program(&[
+      0x18, // CLC
+      0xFB, // XCE
+      0xC2, 0x30, // REP #$30
+      0xA9, 0x34, 0x12, // LDA #$1234
+      0xA2, 0xCD, 0xAB, // LDX #$ABCD
+      0x18, // CLC
+      0x69, 0x01, 0x01, // ADC #$0101
+      0xE2, 0x20, // SEP #$20
+      0xA9, 0xFF, // LDA #$FF
+    ]);
+
+    run(&mut cpu, 7);
+    assert!(!cpu.registers.emulation);
+    assert_eq!(0x1335, cpu.registers.c);
+    assert_eq!(0xABCD, cpu.registers.x);
+
+    // with an 8-bit accumulator, B is preserved
+    run(&mut cpu, 2);
+    assert_eq!(0x13FF, cpu.registers.c);
+    assert!(cpu.registers.flag(flags::NEGATIVE));
+  }
+
+  #[test]
+  fn test_emulation_mode_forces_8_bit() {
+    let mut cpu = cpu_with_program(&[
+      0x18, // CLC
+      0xFB, // XCE
+      0xC2, 0x10, // REP #$10
+      0xA2, 0x34, 0x12, // LDX #$1234
+      0x38, // SEC
+      0xFB, // XCE
+    ]);
+
+    run(&mut cpu, 6);
+    assert!(cpu.registers.emulation);
+    assert!(!cpu.registers.wide_index());
+    assert_eq!(0x34, cpu.registers.x);
+    assert_eq!(0x01FF, cpu.registers.sp);
+  }
+
+  #[test]
+  fn test_long_addressing() {
+    let mut cpu = cpu_with_program(&[
+      0xA9, 0x42, // LDA #$42
+      0x8F, 0x00, 0x80, 0x01, // STA $018000
+      0xA9, 0x02, // LDA #$02
+      0x48, // PHA
+      0xAB, // PLB
+      0xA9, 0x99, // LDA #$99
+      0x8D, 0x34, 0x12, // STA $1234
+      0xAF, 0x00, 0x80, 0x01, // LDA $018000
+    ]);
+
+    run(&mut cpu, 8);
+    assert_eq!(0x42, cpu.memory.read(0x01_8000));
+    assert_eq!(0x99, cpu.memory.read(0x02_1234));
+    assert_eq!(0x00, cpu.memory.read(0x00_1234));
+    assert_eq!(0x42, cpu.registers.a());
+  }
+
+  #[test]
+  fn test_block_move() {
+    let mut cpu = cpu_with_program(&[
+      0x18, // CLC
+      0xFB, // XCE
+      0xC2, 0x30, // REP #$30
+      0xA9, 0x03, 0x00, // LDA #$0003
+      0xA2, 0x00, 0x10, // LDX #$1000
+      0xA0, 0x00, 0x20, // LDY #$2000
+      0x54, 0x02, 0x01, // MVN $01,$02
+      0xDB, // STP
+    ]);
+
+    for i in 0..4 {
+      cpu.memory.write(0x01_1000 + i, 0x10 + i as u8);
+    }
+
+    run(&mut cpu, 10);
+    for i in 0..4 {
+      assert_eq!(0x10 + i as u8, cpu.memory.read(0x02_2000 + i));
+    }
+    assert_eq!(0x02, cpu.registers.dbr);
+    assert_eq!(0xFFFF, cpu.registers.c);
+    assert_eq!(0x1004, cpu.registers.x);
+
+    let stopped = Err(CpuError::Stopped { address: 0x00_0210 });
+    assert_eq!(stopped, cpu.tick());
+    assert_eq!(stopped, cpu.tick());
+  }
+
+  #[test]
+  fn test_long_subroutine() {
+    let mut cpu = cpu_with_program(&[
+      0x22, 0x00, 0x00, 0x01, // JSL $010000
+      0xDB, // STP
+    ]);
+    cpu.memory.write(0x01_0000, 0xE8); // INX
+    cpu.memory.write(0x01_0001, 0x6B); // RTL
+
+    run(&mut cpu, 2);
+    assert_eq!(0x01, cpu.registers.pbr);
+    assert_eq!(1, cpu.registers.x);
+
+    run(&mut cpu, 1);
+    assert_eq!(0x0204, cpu.registers.program_address());
+    assert_eq!(0x01FF, cpu.registers.sp);
+  }
+}
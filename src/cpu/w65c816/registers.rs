@@ -0,0 +1,187 @@
+/// The bits of the processor status register.
+pub mod flags {
+  pub const CARRY: u8 = 0b00000001;
+  pub const ZERO: u8 = 0b00000010;
+  pub const INTERRUPT: u8 = 0b00000100;
+  pub const DECIMAL: u8 = 0b00001000;
+  /// In native mode: index registers are 8 bits wide when set.
+  pub const INDEX: u8 = 0b00010000;
+  /// In emulation mode: set when the status register was pushed by `BRK`.
+  pub const BREAK: u8 = 0b00010000;
+  /// In native mode: the accumulator and memory accesses are 8 bits wide when
+  /// set. Always set in emulation mode.
+  pub const MEMORY: u8 = 0b00100000;
+  pub const OVERFLOW: u8 = 0b01000000;
+  pub const NEGATIVE: u8 = 0b10000000;
+}
+
+/// The registers of the 65C816. The accumulator, index registers and stack
+/// pointer are stored at their full 16-bit width, even while the processor is
+/// in a mode where only their low bytes are visible.
+pub struct Registers {
+  /// The full 16-bit accumulator (C), made up of A (low) and B (high).
+  pub c: u16,
+  pub x: u16,
+  pub y: u16,
+  pub sp: u16,
+
+  /// The direct page register, which is the base of direct page addressing.
+  pub d: u16,
+
+  /// The data bank register, which is the bank used for absolute addressing.
+  pub dbr: u8,
+
+  /// The program bank register, which is the bank of the program counter.
+  pub pbr: u8,
+
+  pub pc: u16,
+  pub p: u8,
+
+  /// Whether the processor is in 6502 emulation mode.
+  pub emulation: bool,
+}
+
+impl Registers {
+  pub fn new() -> Self {
+    let mut registers = Self {
+      c: 0,
+      x: 0,
+      y: 0,
+      sp: 0x01FF,
+      d: 0,
+      dbr: 0,
+      pbr: 0,
+      pc: 0,
+      p: 0,
+      emulation: true,
+    };
+    registers.reset();
+    registers
+  }
+
+  /// Reset the registers as the processor does when its reset line is
+  /// asserted. This returns to emulation mode, with the direct page and banks
+  /// set to zero.
+  pub fn reset(&mut self) {
+    self.d = 0;
+    self.dbr = 0;
+    self.pbr = 0;
+    self.p = flags::MEMORY | flags::INDEX | flags::INTERRUPT;
+    self.set_emulation(true);
+  }
+
+  /// Get the value of the given flag.
+  pub fn flag(&self, flag: u8) -> bool {
+    self.p & flag != 0
+  }
+
+  /// If the given value is true, set the given flag; otherwise, clear it.
+  pub fn set_flag(&mut self, flag: u8, value: bool) {
+    if value {
+      self.p |= flag;
+    } else {
+      self.p &= !flag;
+    }
+  }
+
+  /// Load a new value into the status register. Clearing the index flag
+  /// truncates the index registers, and the mode flags can't be cleared in
+  /// emulation mode.
+  pub fn set_p(&mut self, value: u8) {
+    self.p = value;
+
+    if self.emulation {
+      self.p |= flags::MEMORY | flags::INDEX;
+    }
+
+    if self.flag(flags::INDEX) {
+      self.x &= 0x00FF;
+      self.y &= 0x00FF;
+    }
+  }
+
+  /// Switch between emulation and native mode. Entering emulation mode forces
+  /// 8-bit registers and moves the stack back to page 1.
+  pub fn set_emulation(&mut self, emulation: bool) {
+    self.emulation = emulation;
+
+    if emulation {
+      self.sp = 0x0100 | (self.sp & 0x00FF);
+      self.set_p(self.p);
+    }
+  }
+
+  /// Whether the accumulator (and memory accesses) are 16 bits wide.
+  pub fn wide_accumulator(&self) -> bool {
+    !self.flag(flags::MEMORY)
+  }
+
+  /// Whether the index registers are 16 bits wide.
+  pub fn wide_index(&self) -> bool {
+    !self.flag(flags::INDEX)
+  }
+
+  /// Set the N and Z flags based on a result, which is 16 bits wide if `wide`
+  /// is set and 8 bits wide otherwise.
+  pub fn set_nz(&mut self, value: u16, wide: bool) {
+    if wide {
+      self.set_flag(flags::NEGATIVE, value & 0x8000 != 0);
+      self.set_flag(flags::ZERO, value == 0);
+    } else {
+      self.set_flag(flags::NEGATIVE, value & 0x80 != 0);
+      self.set_flag(flags::ZERO, value & 0xFF == 0);
+    }
+  }
+
+  /// Get the accumulator, at its current width.
+  pub fn a(&self) -> u16 {
+    if self.wide_accumulator() {
+      self.c
+    } else {
+      self.c & 0x00FF
+    }
+  }
+
+  /// Set the accumulator at its current width, preserving the hidden B
+  /// register when it is 8 bits wide.
+  pub fn set_a(&mut self, value: u16) {
+    if self.wide_accumulator() {
+      self.c = value;
+    } else {
+      self.c = (self.c & 0xFF00) | (value & 0x00FF);
+    }
+  }
+
+  /// Truncate a value to the current width of the index registers.
+  fn index_width(&self, value: u16) -> u16 {
+    if self.wide_index() {
+      value
+    } else {
+      value & 0x00FF
+    }
+  }
+
+  /// Set the X register at the current index width.
+  pub fn set_x(&mut self, value: u16) {
+    self.x = self.index_width(value);
+  }
+
+  /// Set the Y register at the current index width.
+  pub fn set_y(&mut self, value: u16) {
+    self.y = self.index_width(value);
+  }
+
+  /// Set the stack pointer, which is confined to page 1 in emulation mode.
+  pub fn set_sp(&mut self, value: u16) {
+    self.sp = if self.emulation {
+      0x0100 | (value & 0x00FF)
+    } else {
+      value
+    };
+  }
+
+  /// The full 24-bit address of the program counter.
+  pub fn program_address(&self) -> u32 {
+    (self.pbr as u32) << 16 | self.pc as u32
+  }
+}
@@ -0,0 +1,897 @@
+use crate::cpu::z80::{
+  registers::{flags, InterruptMode},
+  Z80,
+};
+
+/// The register which takes the place of HL in an instruction, as selected by
+/// a `DD` (IX) or `FD` (IY) prefix.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Index {
+  HL,
+  IX,
+  IY,
+}
+
+pub trait Execute {
+  /// Execute the given (unprefixed) opcode, with HL replaced by the given index
+  /// register.
+  fn execute(&mut self, opcode: u8, index: Index);
+}
+
+/// The sign, zero and undocumented X/Y flags for a result.
+fn sz_xy(value: u8) -> u8 {
+  let zero = if value == 0 { flags::ZERO } else { 0 };
+  (value & (flags::SIGN | flags::Y | flags::X)) | zero
+}
+
+/// The sign, zero, parity and undocumented X/Y flags for a result.
+fn szp_xy(value: u8) -> u8 {
+  let parity = if value.count_ones() & 1 == 0 {
+    flags::PARITY
+  } else {
+    0
+  };
+  sz_xy(value) | parity
+}
+
+/// Return the given flag if the condition holds, or zero otherwise.
+fn flag_if(flag: u8, condition: bool) -> u8 {
+  if condition {
+    flag
+  } else {
+    0
+  }
+}
+
+impl Z80 {
+  /// Get the register pair replacing HL.
+  fn index_register(&self, index: Index) -> u16 {
+    match index {
+      Index::HL => self.registers.hl(),
+      Index::IX => self.registers.ix,
+      Index::IY => self.registers.iy,
+    }
+  }
+
+  /// Set the register pair replacing HL.
+  fn set_index_register(&mut self, index: Index, value: u16) {
+    match index {
+      Index::HL => self.registers.set_hl(value),
+      Index::IX => self.registers.ix = value,
+      Index::IY => self.registers.iy = value,
+    }
+  }
+
+  /// Get an 8-bit register by its 3-bit encoding (except 6, which refers to
+  /// memory). H and L are replaced by the halves of the index register.
+  fn get_r(&self, r: u8, index: Index) -> u8 {
+    match r {
+      0 => self.registers.b,
+      1 => self.registers.c,
+      2 => self.registers.d,
+      3 => self.registers.e,
+      4 => (self.index_register(index) >> 8) as u8,
+      5 => self.index_register(index) as u8,
+      7 => self.registers.a,
+      _ => unreachable!("register 6 refers to memory"),
+    }
+  }
+
+  /// Set an 8-bit register by its 3-bit encoding (except 6, which refers to
+  /// memory).
+  fn set_r(&mut self, r: u8, index: Index, value: u8) {
+    match r {
+      0 => self.registers.b = value,
+      1 => self.registers.c = value,
+      2 => self.registers.d = value,
+      3 => self.registers.e = value,
+      4 => {
+        let pair = self.index_register(index);
+        self.set_index_register(index, (value as u16) << 8 | (pair & 0xFF));
+      }
+      5 => {
+        let pair = self.index_register(index);
+        self.set_index_register(index, (pair & 0xFF00) | value as u16);
+      }
+      7 => self.registers.a = value,
+      _ => unreachable!("register 6 refers to memory"),
+    }
+  }
+
+  /// Get a register pair by its 2-bit encoding, with SP as the fourth pair.
+  fn get_rp(&self, p: u8, index: Index) -> u16 {
+    match p {
+      0 => self.registers.bc(),
+      1 => self.registers.de(),
+      2 => self.index_register(index),
+      _ => self.registers.sp,
+    }
+  }
+
+  /// Set a register pair by its 2-bit encoding, with SP as the fourth pair.
+  fn set_rp(&mut self, p: u8, index: Index, value: u16) {
+    match p {
+      0 => self.registers.set_bc(value),
+      1 => self.registers.set_de(value),
+      2 => self.set_index_register(index, value),
+      _ => self.registers.sp = value,
+    }
+  }
+
+  /// Get a register pair by its 2-bit encoding, with AF as the fourth pair
+  /// (as used by `PUSH` and `POP`).
+  fn get_rp2(&self, p: u8, index: Index) -> u16 {
+    match p {
+      3 => self.registers.af(),
+      _ => self.get_rp(p, index),
+    }
+  }
+
+  /// Set a register pair by its 2-bit encoding, with AF as the fourth pair.
+  fn set_rp2(&mut self, p: u8, index: Index, value: u16) {
+    match p {
+      3 => self.registers.set_af(value),
+      _ => self.set_rp(p, index, value),
+    }
+  }
+
+  /// Compute the address of an `(HL)` operand, which becomes `(IX+d)` or
+  /// `(IY+d)` with a displacement byte when prefixed.
+  fn memory_operand(&mut self, index: Index) -> u16 {
+    match index {
+      Index::HL => self.registers.hl(),
+      _ => {
+        let displacement = self.fetch() as i8 as u16;
+        self.idle(5);
+        self.index_register(index).wrapping_add(displacement)
+      }
+    }
+  }
+
+  /// Evaluate one of the eight branch conditions (NZ, Z, NC, C, PO, PE, P, M).
+  fn condition(&self, condition: u8) -> bool {
+    let flag = match condition >> 1 {
+      0 => flags::ZERO,
+      1 => flags::CARRY,
+      2 => flags::PARITY,
+      _ => flags::SIGN,
+    };
+    self.registers.flag(flag) == (condition & 1 != 0)
+  }
+
+  /// Perform one of the eight accumulator operations (ADD, ADC, SUB, SBC, AND,
+  /// XOR, OR, CP) with the given operand.
+  fn alu(&mut self, operation: u8, value: u8) {
+    let a = self.registers.a;
+    let carry = self.registers.flag(flags::CARRY) as u16;
+
+    match operation {
+      0 | 1 => {
+        let carry = if operation == 1 { carry } else { 0 };
+        let result = a as u16 + value as u16 + carry;
+        let r = result as u8;
+        self.registers.f = sz_xy(r)
+          | ((a ^ value ^ r) & flags::HALF_CARRY)
+          | flag_if(flags::PARITY, (a ^ r) & (value ^ r) & 0x80 != 0)
+          | flag_if(flags::CARRY, result > 0xFF);
+        self.registers.a = r;
+      }
+      2 | 3 | 7 => {
+        let carry = if operation == 3 { carry } else { 0 };
+        let result = (a as u16).wrapping_sub(value as u16).wrapping_sub(carry);
+        let r = result as u8;
+
+        // CP takes the undocumented flags from the operand, not the result
+        let xy = if operation == 7 { value } else { r };
+        self.registers.f = (sz_xy(r) & !(flags::X | flags::Y))
+          | (xy & (flags::X | flags::Y))
+          | ((a ^ value ^ r) & flags::HALF_CARRY)
+          | flag_if(flags::PARITY, (a ^ value) & (a ^ r) & 0x80 != 0)
+          | flags::SUBTRACT
+          | flag_if(flags::CARRY, result > 0xFF);
+
+        if operation != 7 {
+          self.registers.a = r;
+        }
+      }
+      4 => {
+        self.registers.a = a & value;
+        self.registers.f = szp_xy(self.registers.a) | flags::HALF_CARRY;
+      }
+      5 => {
+        self.registers.a = a ^ value;
+        self.registers.f = szp_xy(self.registers.a);
+      }
+      _ => {
+        self.registers.a = a | value;
+        self.registers.f = szp_xy(self.registers.a);
+      }
+    }
+  }
+
+  /// Increment an 8-bit value, setting the flags (except carry).
+  fn inc8(&mut self, value: u8) -> u8 {
+    let result = value.wrapping_add(1);
+    self.registers.f = (self.registers.f & flags::CARRY)
+      | sz_xy(result)
+      | flag_if(flags::HALF_CARRY, result & 0x0F == 0)
+      | flag_if(flags::PARITY, result == 0x80);
+    result
+  }
+
+  /// Decrement an 8-bit value, setting the flags (except carry).
+  fn dec8(&mut self, value: u8) -> u8 {
+    let result = value.wrapping_sub(1);
+    self.registers.f = (self.registers.f & flags::CARRY)
+      | sz_xy(result)
+      | flags::SUBTRACT
+      | flag_if(flags::HALF_CARRY, value & 0x0F == 0)
+      | flag_if(flags::PARITY, result == 0x7F);
+    result
+  }
+
+  /// Perform one of the eight shift and rotate operations of the `CB` prefix
+  /// (RLC, RRC, RL, RR, SLA, SRA, SLL, SRL), returning the result and the
+  /// carry out.
+  fn rotate(&self, operation: u8, value: u8) -> (u8, bool) {
+    let carry = self.registers.flag(flags::CARRY) as u8;
+
+    match operation {
+      0 => (value.rotate_left(1), value & 0x80 != 0),
+      1 => (value.rotate_right(1), value & 0x01 != 0),
+      2 => (value << 1 | carry, value & 0x80 != 0),
+      3 => (value >> 1 | carry << 7, value & 0x01 != 0),
+      4 => (value << 1, value & 0x80 != 0),
+      5 => (value >> 1 | (value & 0x80), value & 0x01 != 0),
+      6 => (value << 1 | 1, value & 0x80 != 0),
+      _ => (value >> 1, value & 0x01 != 0),
+    }
+  }
+
+  /// Add a register pair to HL (or an index register), setting only the half
+  /// carry, carry and undocumented flags.
+  fn add16(&mut self, a: u16, b: u16) -> u16 {
+    let result = a as u32 + b as u32;
+    let r = result as u16;
+    self.idle(7);
+    self.registers.f = (self.registers.f & (flags::SIGN | flags::ZERO | flags::PARITY))
+      | ((r >> 8) as u8 & (flags::X | flags::Y))
+      | (((a ^ b ^ r) >> 8) as u8 & flags::HALF_CARRY)
+      | flag_if(flags::CARRY, result > 0xFFFF);
+    r
+  }
+
+  /// Add (or subtract) a register pair and the carry flag to HL, setting all
+  /// of the flags.
+  fn adc16(&mut self, value: u16, subtract: bool) {
+    let hl = self.registers.hl();
+    let carry = self.registers.flag(flags::CARRY) as u32;
+
+    let result = if subtract {
+      (hl as u32).wrapping_sub(value as u32).wrapping_sub(carry)
+    } else {
+      hl as u32 + value as u32 + carry
+    };
+    let r = result as u16;
+
+    let overflow = if subtract {
+      (hl ^ value) & (hl ^ r) & 0x8000 != 0
+    } else {
+      (hl ^ r) & (value ^ r) & 0x8000 != 0
+    };
+
+    self.idle(7);
+    self.registers.f = (sz_xy((r >> 8) as u8) & !flags::ZERO)
+      | flag_if(flags::ZERO, r == 0)
+      | (((hl ^ value ^ r) >> 8) as u8 & flags::HALF_CARRY)
+      | flag_if(flags::PARITY, overflow)
+      | flag_if(flags::SUBTRACT, subtract)
+      | flag_if(flags::CARRY, result > 0xFFFF);
+    self.registers.set_hl(r);
+  }
+
+  /// Decimal-adjust the accumulator after a BCD addition or subtraction.
+  fn daa(&mut self) {
+    let a = self.registers.a;
+    let subtract = self.registers.flag(flags::SUBTRACT);
+    let mut correction = 0;
+    let mut carry = self.registers.flag(flags::CARRY);
+
+    if self.registers.flag(flags::HALF_CARRY) || a & 0x0F > 9 {
+      correction |= 0x06;
+    }
+
+    if carry || a > 0x99 {
+      correction |= 0x60;
+      carry = true;
+    }
+
+    let (result, half_carry) = if subtract {
+      (
+        a.wrapping_sub(correction),
+        self.registers.flag(flags::HALF_CARRY) && a & 0x0F < 6,
+      )
+    } else {
+      (a.wrapping_add(correction), a & 0x0F > 9)
+    };
+
+    self.registers.a = result;
+    self.registers.f = szp_xy(result)
+      | flag_if(flags::HALF_CARRY, half_carry)
+      | flag_if(flags::SUBTRACT, subtract)
+      | flag_if(flags::CARRY, carry);
+  }
+
+  /// Execute an instruction with the `CB` prefix.
+  fn execute_cb(&mut self, index: Index) {
+    let (address, opcode) = match index {
+      Index::HL => (self.registers.hl(), self.fetch_opcode()),
+      _ => {
+        // With an index prefix, the displacement comes before the opcode,
+        // which is read as an ordinary operand
+        let displacement = self.fetch() as i8 as u16;
+        let opcode = self.fetch();
+        self.idle(2);
+        (
+          self.index_register(index).wrapping_add(displacement),
+          opcode,
+        )
+      }
+    };
+
+    let (x, y, z) = (opcode >> 6, (opcode >> 3) & 7, opcode & 7);
+    let in_memory = z == 6 || index != Index::HL;
+
+    let value = if in_memory {
+      self.read(address)
+    } else {
+      self.get_r(z, Index::HL)
+    };
+
+    let result = match x {
+      0 => {
+        let (result, carry) = self.rotate(y, value);
+        self.registers.f = szp_xy(result) | flag_if(flags::CARRY, carry);
+        result
+      }
+      1 => {
+        // BIT: the undocumented flags come from the address for memory
+        // operands, and from the value otherwise
+        let xy = if in_memory {
+          (address >> 8) as u8
+        } else {
+          value
+        };
+        let set = value & (1 << y) != 0;
+        self.registers.f = (self.registers.f & flags::CARRY)
+          | flags::HALF_CARRY
+          | (xy & (flags::X | flags::Y))
+          | flag_if(flags::ZERO | flags::PARITY, !set)
+          | flag_if(flags::SIGN, set && y == 7);
+
+        if in_memory {
+          self.idle(1);
+        }
+        return;
+      }
+      2 => value & !(1 << y),
+      _ => value | (1 << y),
+    };
+
+    if in_memory {
+      self.idle(1);
+      self.write(address, result);
+
+      // Indexed operations also copy the result into a register
+      if z != 6 {
+        self.set_r(z, Index::HL, result);
+      }
+    } else {
+      self.set_r(z, Index::HL, result);
+    }
+  }
+
+  /// Execute one of the block transfer, compare, input or output instructions.
+  fn execute_block(&mut self, y: u8, z: u8) {
+    let increment = y & 1 == 0;
+    let repeat = y >= 6;
+    let step = |value: u16| {
+      if increment {
+        value.wrapping_add(1)
+      } else {
+        value.wrapping_sub(1)
+      }
+    };
+
+    let hl = self.registers.hl();
+    let again = match z {
+      0 => {
+        // LDI, LDD, LDIR, LDDR
+        let value = self.read(hl);
+        let de = self.registers.de();
+        self.write(de, value);
+        self.idle(2);
+
+        self.registers.set_hl(step(hl));
+        self.registers.set_de(step(de));
+        let bc = self.registers.bc().wrapping_sub(1);
+        self.registers.set_bc(bc);
+
+        let n = value.wrapping_add(self.registers.a);
+        self.registers.f = (self.registers.f & (flags::SIGN | flags::ZERO | flags::CARRY))
+          | (n & flags::X)
+          | flag_if(flags::Y, n & 0x02 != 0)
+          | flag_if(flags::PARITY, bc != 0);
+        bc != 0
+      }
+      1 => {
+        // CPI, CPD, CPIR, CPDR
+        let value = self.read(hl);
+        self.idle(5);
+        let a = self.registers.a;
+        let result = a.wrapping_sub(value);
+        let half_carry = (a ^ value ^ result) & flags::HALF_CARRY != 0;
+
+        self.registers.set_hl(step(hl));
+        let bc = self.registers.bc().wrapping_sub(1);
+        self.registers.set_bc(bc);
+
+        let n = result.wrapping_sub(half_carry as u8);
+        self.registers.f = (self.registers.f & flags::CARRY)
+          | (sz_xy(result) & !(flags::X | flags::Y))
+          | (n & flags::X)
+          | flag_if(flags::Y, n & 0x02 != 0)
+          | flag_if(flags::HALF_CARRY, half_carry)
+          | flag_if(flags::PARITY, bc != 0)
+          | flags::SUBTRACT;
+        bc != 0 && result != 0
+      }
+      2 => {
+        // INI, IND, INIR, INDR
+        self.idle(1);
+        let value = self.read_port(self.registers.bc());
+        self.write(hl, value);
+        self.registers.set_hl(step(hl));
+        self.registers.b = self.registers.b.wrapping_sub(1);
+        self.registers.f = sz_xy(self.registers.b) | flags::SUBTRACT;
+        self.registers.b != 0
+      }
+      _ => {
+        // OUTI, OUTD, OTIR, OTDR
+        self.idle(1);
+        let value = self.read(hl);
+        self.registers.b = self.registers.b.wrapping_sub(1);
+        self.write_port(self.registers.bc(), value);
+        self.registers.set_hl(step(hl));
+        self.registers.f = sz_xy(self.registers.b) | flags::SUBTRACT;
+        self.registers.b != 0
+      }
+    };
+
+    if repeat && again {
+      self.idle(5);
+      self.registers.pc = self.registers.pc.wrapping_sub(2);
+    }
+  }
+
+  /// Execute an instruction with the `ED` prefix. Index prefixes have no effect
+  /// on these instructions, and undefined opcodes act as a `NOP`.
+  fn execute_ed(&mut self) {
+    let opcode = self.fetch_opcode();
+    let (x, y, z) = (opcode >> 6, (opcode >> 3) & 7, opcode & 7);
+    let (p, q) = (y >> 1, y & 1);
+
+    match (x, z) {
+      (1, 0) => {
+        // IN r,(C), or IN (C) which only sets the flags
+        let value = self.read_port(self.registers.bc());
+        if y != 6 {
+          self.set_r(y, Index::HL, value);
+        }
+        self.registers.f = (self.registers.f & flags::CARRY) | szp_xy(value);
+      }
+      (1, 1) => {
+        // OUT (C),r, or OUT (C),0
+        let value = if y == 6 { 0 } else { self.get_r(y, Index::HL) };
+        self.write_port(self.registers.bc(), value);
+      }
+      (1, 2) => {
+        // SBC HL,rr / ADC HL,rr
+        let value = self.get_rp(p, Index::HL);
+        self.adc16(value, q == 0);
+      }
+      (1, 3) => {
+        // LD (nn),rr / LD rr,(nn)
+        let address = self.fetch_word();
+        if q == 0 {
+          let value = self.get_rp(p, Index::HL);
+          self.write_word(address, value);
+        } else {
+          let value = self.read_word(address);
+          self.set_rp(p, Index::HL, value);
+        }
+      }
+      (1, 4) => {
+        // NEG
+        let value = self.registers.a;
+        self.registers.a = 0;
+        self.alu(2, value);
+      }
+      (1, 5) => {
+        // RETN / RETI
+        self.registers.iff1 = self.registers.iff2;
+        self.registers.pc = self.pop();
+      }
+      (1, 6) => {
+        // IM 0/1/2
+        self.registers.im = match y & 3 {
+          0 | 1 => InterruptMode::Im0,
+          2 => InterruptMode::Im1,
+          _ => InterruptMode::Im2,
+        };
+      }
+      (1, 7) => match y {
+        0 => {
+          // LD I,A
+          self.idle(1);
+          self.registers.i = self.registers.a;
+        }
+        1 => {
+          // LD R,A
+          self.idle(1);
+          self.registers.r = self.registers.a;
+        }
+        2 | 3 => {
+          // LD A,I / LD A,R
+          self.idle(1);
+          let value = if y == 2 {
+            self.registers.i
+          } else {
+            self.registers.r
+          };
+          self.registers.a = value;
+          self.registers.f = (self.registers.f & flags::CARRY)
+            | sz_xy(value)
+            | flag_if(flags::PARITY, self.registers.iff2);
+        }
+        4 | 5 => {
+          // RRD / RLD
+          let hl = self.registers.hl();
+          let value = self.read(hl);
+          let a = self.registers.a;
+          self.idle(4);
+
+          let (memory, nibble) = if y == 4 {
+            ((a << 4) | (value >> 4), value & 0x0F)
+          } else {
+            ((value << 4) | (a & 0x0F), value >> 4)
+          };
+          self.write(hl, memory);
+          self.registers.a = (a & 0xF0) | nibble;
+          self.registers.f = (self.registers.f & flags::CARRY) | szp_xy(self.registers.a);
+        }
+        _ => (), // NOP
+      },
+      (2, 0..=3) if y >= 4 => self.execute_block(y, z),
+      _ => (), // NOP
+    }
+  }
+}
+
+impl Execute for Z80 {
+  fn execute(&mut self, opcode: u8, index: Index) {
+    let (x, y, z) = (opcode >> 6, (opcode >> 3) & 7, opcode & 7);
+    let (p, q) = (y >> 1, y & 1);
+
+    match (x, z) {
+      // === RELATIVE JUMPS AND ASSORTED OPERATIONS ===
+      (0, 0) => match y {
+        0 => (), // NOP
+        1 => self.registers.exchange_af(),
+        2 => {
+          // DJNZ
+          self.idle(1);
+          let offset = self.fetch() as i8 as u16;
+          self.registers.b = self.registers.b.wrapping_sub(1);
+          if self.registers.b != 0 {
+            self.idle(5);
+            self.registers.pc = self.registers.pc.wrapping_add(offset);
+          }
+        }
+        _ => {
+          // JR, JR cc
+          let offset = self.fetch() as i8 as u16;
+          if y == 3 || self.condition(y - 4) {
+            self.idle(5);
+            self.registers.pc = self.registers.pc.wrapping_add(offset);
+          }
+        }
+      },
+
+      // === 16-BIT LOAD AND ADD ===
+      (0, 1) => {
+        if q == 0 {
+          // LD rr,nn
+          let value = self.fetch_word();
+          self.set_rp(p, index, value);
+        } else {
+          // ADD HL,rr
+          let result = self.add16(self.index_register(index), self.get_rp(p, index));
+          self.set_index_register(index, result);
+        }
+      }
+
+      // === INDIRECT LOAD ===
+      (0, 2) => match (p, q) {
+        (0, 0) => self.write(self.registers.bc(), self.registers.a), // LD (BC),A
+        (1, 0) => self.write(self.registers.de(), self.registers.a), // LD (DE),A
+        (0, 1) => self.registers.a = self.read(self.registers.bc()), // LD A,(BC)
+        (1, 1) => self.registers.a = self.read(self.registers.de()), // LD A,(DE)
+        (2, 0) => {
+          // LD (nn),HL
+          let address = self.fetch_word();
+          self.write_word(address, self.index_register(index));
+        }
+        (2, _) => {
+          // LD HL,(nn)
+          let address = self.fetch_word();
+          let value = self.read_word(address);
+          self.set_index_register(index, value);
+        }
+        (_, 0) => {
+          // LD (nn),A
+          let address = self.fetch_word();
+          self.write(address, self.registers.a);
+        }
+        _ => {
+          // LD A,(nn)
+          let address = self.fetch_word();
+          self.registers.a = self.read(address);
+        }
+      },
+
+      // === 16-BIT INCREMENT AND DECREMENT ===
+      (0, 3) => {
+        self.idle(2);
+        let value = self.get_rp(p, index);
+        let value = if q == 0 {
+          value.wrapping_add(1)
+        } else {
+          value.wrapping_sub(1)
+        };
+        self.set_rp(p, index, value);
+      }
+
+      // === 8-BIT INCREMENT AND DECREMENT ===
+      (0, 4) | (0, 5) => {
+        let operation = |cpu: &mut Self, value| {
+          if z == 4 {
+            cpu.inc8(value)
+          } else {
+            cpu.dec8(value)
+          }
+        };
+
+        if y == 6 {
+          let address = self.memory_operand(index);
+          let value = self.read(address);
+          self.idle(1);
+          let result = operation(self, value);
+          self.write(address, result);
+        } else {
+          let result = operation(self, self.get_r(y, index));
+          self.set_r(y, index, result);
+        }
+      }
+
+      // === 8-BIT LOAD IMMEDIATE ===
+      (0, 6) => {
+        if y == 6 {
+          let address = match index {
+            Index::HL => self.registers.hl(),
+            _ => {
+              // The displacement and immediate value are read back to back
+              let displacement = self.fetch() as i8 as u16;
+              self.index_register(index).wrapping_add(displacement)
+            }
+          };
+          let value = self.fetch();
+          if index != Index::HL {
+            self.idle(2);
+          }
+          self.write(address, value);
+        } else {
+          let value = self.fetch();
+          self.set_r(y, index, value);
+        }
+      }
+
+      // === ACCUMULATOR AND FLAG OPERATIONS ===
+      (0, 7) => match y {
+        0..=3 => {
+          // RLCA, RRCA, RLA, RRA
+          let (result, carry) = self.rotate(y, self.registers.a);
+          self.registers.a = result;
+          self.registers.f = (self.registers.f & (flags::SIGN | flags::ZERO | flags::PARITY))
+            | (result & (flags::X | flags::Y))
+            | flag_if(flags::CARRY, carry);
+        }
+        4 => self.daa(),
+        5 => {
+          // CPL
+          self.registers.a = !self.registers.a;
+          self.registers.f = (self.registers.f
+            & (flags::SIGN | flags::ZERO | flags::PARITY | flags::CARRY))
+            | (self.registers.a & (flags::X | flags::Y))
+            | flags::HALF_CARRY
+            | flags::SUBTRACT;
+        }
+        6 => {
+          // SCF
+          self.registers.f = (self.registers.f & (flags::SIGN | flags::ZERO | flags::PARITY))
+            | (self.registers.a & (flags::X | flags::Y))
+            | flags::CARRY;
+        }
+        _ => {
+          // CCF
+          let carry = self.registers.flag(flags::CARRY);
+          self.registers.f = (self.registers.f & (flags::SIGN | flags::ZERO | flags::PARITY))
+            | (self.registers.a & (flags::X | flags::Y))
+            | flag_if(flags::HALF_CARRY, carry)
+            | flag_if(flags::CARRY, !carry);
+        }
+      },
+
+      // === HALT ===
+      (1, 6) if y == 6 => self.halted = true,
+
+      // === 8-BIT LOAD ===
+      (1, _) => {
+        if z == 6 {
+          // LD r,(HL)
+          let address = self.memory_operand(index);
+          let value = self.read(address);
+          self.set_r(y, Index::HL, value);
+        } else if y == 6 {
+          // LD (HL),r
+          let address = self.memory_operand(index);
+          self.write(address, self.get_r(z, Index::HL));
+        } else {
+          let value = self.get_r(z, index);
+          self.set_r(y, index, value);
+        }
+      }
+
+      // === 8-BIT ARITHMETIC AND LOGIC ===
+      (2, _) => {
+        let value = if z == 6 {
+          let address = self.memory_operand(index);
+          self.read(address)
+        } else {
+          self.get_r(z, index)
+        };
+        self.alu(y, value);
+      }
+
+      // === CONDITIONAL RETURN ===
+      (3, 0) => {
+        self.idle(1);
+        if self.condition(y) {
+          self.registers.pc = self.pop();
+        }
+      }
+
+      // === POP AND ASSORTED OPERATIONS ===
+      (3, 1) => match (q, p) {
+        (0, _) => {
+          // POP rr
+          let value = self.pop();
+          self.set_rp2(p, index, value);
+        }
+        (_, 0) => self.registers.pc = self.pop(), // RET
+        (_, 1) => self.registers.exchange(),      // EXX
+        (_, 2) => self.registers.pc = self.index_register(index), // JP (HL)
+        _ => {
+          // LD SP,HL
+          self.idle(2);
+          self.registers.sp = self.index_register(index);
+        }
+      },
+
+      // === CONDITIONAL JUMP ===
+      (3, 2) => {
+        let address = self.fetch_word();
+        if self.condition(y) {
+          self.registers.pc = address;
+        }
+      }
+
+      // === ASSORTED OPERATIONS ===
+      (3, 3) => match y {
+        0 => self.registers.pc = self.fetch_word(), // JP nn
+        1 => self.execute_cb(index),
+        2 => {
+          // OUT (n),A
+          let port = (self.registers.a as u16) << 8 | self.fetch() as u16;
+          self.write_port(port, self.registers.a);
+        }
+        3 => {
+          // IN A,(n)
+          let port = (self.registers.a as u16) << 8 | self.fetch() as u16;
+          self.registers.a = self.read_port(port);
+        }
+        4 => {
+          // EX (SP),HL
+          let sp = self.registers.sp;
+          let value = self.read_word(sp);
+          self.idle(1);
+          let register = self.index_register(index);
+          self.write(sp.wrapping_add(1), (register >> 8) as u8);
+          self.write(sp, register as u8);
+          self.idle(2);
+          self.set_index_register(index, value);
+        }
+        5 => {
+          // EX DE,HL (never affected by an index prefix)
+          let de = self.registers.de();
+          self.registers.set_de(self.registers.hl());
+          self.registers.set_hl(de);
+        }
+        6 => {
+          // DI
+          self.registers.iff1 = false;
+          self.registers.iff2 = false;
+        }
+        _ => {
+          // EI
+          self.registers.iff1 = true;
+          self.registers.iff2 = true;
+          self.interrupt_delay = true;
+        }
+      },
+
+      // === CONDITIONAL CALL ===
+      (3, 4) => {
+        let address = self.fetch_word();
+        if self.condition(y) {
+          self.idle(1);
+          self.push(self.registers.pc);
+          self.registers.pc = address;
+        }
+      }
+
+      // === PUSH AND CALL ===
+      (3, 5) => match (q, p) {
+        (0, _) => {
+          // PUSH rr
+          self.idle(1);
+          self.push(self.get_rp2(p, index));
+        }
+        (_, 0) => {
+          // CALL nn
+          let address = self.fetch_word();
+          self.idle(1);
+          self.push(self.registers.pc);
+          self.registers.pc = address;
+        }
+        (_, 2) => self.execute_ed(),
+        _ => (), // Index prefixes are consumed before execution
+      },
+
+      // === 8-BIT ARITHMETIC AND LOGIC IMMEDIATE ===
+      (3, 6) => {
+        let value = self.fetch();
+        self.alu(y, value);
+      }
+
+      // === RESTART ===
+      (3, 7) => {
+        self.idle(1);
+        self.push(self.registers.pc);
+        self.registers.pc = (y as u16) * 8;
+      }
+
+      _ => unreachable!(),
+    }
+  }
+}
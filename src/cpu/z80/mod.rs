@@ -0,0 +1,471 @@
+mod execute;
+pub mod registers;
+use crate::debugger::DebugHandler;
+use crate::memory::{ActiveInterrupt, IoSpace, Memory};
+use crate::trace::TraceHandler;
+use execute::{Execute, Index};
+use registers::{InterruptMode, Registers};
+
+use super::{Cpu, CpuError};
+
+const CLOCKS_PER_POLL: u64 = 100;
+
+/// The Zilog Z80 CPU, its memory, and its separate I/O port space.
+///
+/// Cycles are counted in T-states, following the timing of each machine
+/// cycle (4 for an opcode fetch, 3 for a memory access, 4 for an I/O access)
+/// plus the internal cycles of each instruction. The debugger and trace
+/// handlers are built around the 6502's registers, so they are not supported
+/// by this CPU.
+pub struct Z80 {
+  pub registers: Registers,
+  pub memory: Box<dyn Memory>,
+  pub io: Box<dyn IoSpace>,
+
+  /// The byte placed on the data bus by a device acknowledging an interrupt.
+  /// In interrupt mode 0 this is executed as an instruction, and in mode 2 it
+  /// selects the entry in the vector table. Defaults to 0xFF (`RST 38h`).
+  pub data_bus: u8,
+
+  cycle_count: u64,
+  cycles_since_poll: u64,
+
+  /// The number of T-states used so far by the current instruction.
+  cycles: u8,
+
+  /// Set by `HALT` until the next interrupt arrives.
+  halted: bool,
+
+  /// Set by `EI`, which delays accepting interrupts until after the next
+  /// instruction.
+  interrupt_delay: bool,
+}
+
+impl Z80 {
+  pub fn new(memory: impl Memory + 'static, io: impl IoSpace + 'static) -> Z80 {
+    Z80 {
+      registers: Registers::new(),
+      memory: Box::new(memory),
+      io: Box::new(io),
+      data_bus: 0xFF,
+      cycle_count: 0,
+      cycles_since_poll: 0,
+      cycles: 0,
+      halted: false,
+      interrupt_delay: false,
+    }
+  }
+
+  /// Whether the CPU is halted, waiting for an interrupt.
+  pub fn is_halted(&self) -> bool {
+    self.halted
+  }
+
+  /// Read a byte from memory.
+  fn read(&mut self, address: u16) -> u8 {
+    self.cycles += 3;
+    self.memory.read(address)
+  }
+
+  /// Write a byte to memory.
+  fn write(&mut self, address: u16, value: u8) {
+    self.cycles += 3;
+    self.memory.write(address, value);
+  }
+
+  /// Read a word (little-endian) from memory.
+  fn read_word(&mut self, address: u16) -> u16 {
+    let lo = self.read(address);
+    let hi = self.read(address.wrapping_add(1));
+    (hi as u16) << 8 | lo as u16
+  }
+
+  /// Write a word (little-endian) to memory.
+  fn write_word(&mut self, address: u16, value: u16) {
+    self.write(address, value as u8);
+    self.write(address.wrapping_add(1), (value >> 8) as u8);
+  }
+
+  /// Read a byte from the given I/O port.
+  fn read_port(&mut self, port: u16) -> u8 {
+    self.cycles += 4;
+    self.io.read_port(port)
+  }
+
+  /// Write a byte to the given I/O port.
+  fn write_port(&mut self, port: u16, value: u8) {
+    self.cycles += 4;
+    self.io.write_port(port, value);
+  }
+
+  /// Spend the given number of T-states on internal operations.
+  fn idle(&mut self, cycles: u8) {
+    self.cycles += cycles;
+  }
+
+  /// Fetch an opcode at the program counter, as the first machine cycle of an
+  /// instruction (or of a prefix).
+  fn fetch_opcode(&mut self) -> u8 {
+    let opcode = self.memory.read(self.registers.pc);
+    self.cycles += 4;
+    self.registers.pc = self.registers.pc.wrapping_add(1);
+    self.registers.increment_r();
+    opcode
+  }
+
+  /// Fetch an immediate byte at the program counter.
+  fn fetch(&mut self) -> u8 {
+    let value = self.read(self.registers.pc);
+    self.registers.pc = self.registers.pc.wrapping_add(1);
+    value
+  }
+
+  /// Fetch an immediate word (little-endian) at the program counter.
+  fn fetch_word(&mut self) -> u16 {
+    let lo = self.fetch();
+    let hi = self.fetch();
+    (hi as u16) << 8 | lo as u16
+  }
+
+  /// Push a word onto the stack.
+  fn push(&mut self, value: u16) {
+    self.registers.sp = self.registers.sp.wrapping_sub(1);
+    self.write(self.registers.sp, (value >> 8) as u8);
+    self.registers.sp = self.registers.sp.wrapping_sub(1);
+    self.write(self.registers.sp, value as u8);
+  }
+
+  /// Pop a word from the stack.
+  fn pop(&mut self) -> u16 {
+    let lo = self.read(self.registers.sp);
+    self.registers.sp = self.registers.sp.wrapping_add(1);
+    let hi = self.read(self.registers.sp);
+    self.registers.sp = self.registers.sp.wrapping_add(1);
+    (hi as u16) << 8 | lo as u16
+  }
+
+  /// Service a non-maskable interrupt by calling 0x0066.
+  fn nmi(&mut self) {
+    self.halted = false;
+    self.registers.increment_r();
+    self.registers.iff1 = false;
+    self.idle(5);
+    self.push(self.registers.pc);
+    self.registers.pc = 0x0066;
+  }
+
+  /// Service a maskable interrupt, according to the current interrupt mode.
+  fn irq(&mut self) {
+    if !self.registers.iff1 || self.interrupt_delay {
+      return;
+    }
+
+    self.halted = false;
+    self.registers.increment_r();
+    self.registers.iff1 = false;
+    self.registers.iff2 = false;
+
+    match self.registers.im {
+      InterruptMode::Im0 => {
+        // Only single-byte instructions (such as RST) are supported here, as
+        // the operand bytes would also have to come from the device
+        self.idle(6);
+        self.execute(self.data_bus, Index::HL);
+      }
+      InterruptMode::Im1 => {
+        self.idle(7);
+        self.push(self.registers.pc);
+        self.registers.pc = 0x0038;
+      }
+      InterruptMode::Im2 => {
+        self.idle(7);
+        self.push(self.registers.pc);
+        let vector = (self.registers.i as u16) << 8 | self.data_bus as u16;
+        self.registers.pc = self.read_word(vector);
+      }
+    }
+  }
+}
+
+impl Cpu for Z80 {
+  fn reset(&mut self) {
+    self.halted = false;
+    self.interrupt_delay = false;
+    self.memory.reset();
+    self.io.reset();
+    self.registers.reset();
+  }
+
+  fn get_cycle_count(&self) -> u64 {
+    self.cycle_count
+  }
+
+  /// Tracing is not supported by the Z80, so the handler is discarded.
+  fn attach_trace_handler(&mut self, _trace: Box<dyn TraceHandler>) {}
+
+  /// The debugger is not supported by the Z80, so it is discarded.
+  fn attach_debugger(&mut self, _debugger: Box<dyn DebugHandler>) {}
+
+  /// Execute a single instruction, or a single `NOP` cycle while halted. The
+  /// Z80 has no invalid opcodes, so this never fails.
+  fn tick(&mut self) -> Result<u8, CpuError> {
+    self.cycles = 0;
+    self.interrupt_delay = false;
+
+    if self.halted {
+      self.registers.increment_r();
+      self.idle(4);
+    } else {
+      let mut opcode = self.fetch_opcode();
+      let mut index = Index::HL;
+
+      // Any number of index prefixes may precede an instruction, but only the
+      // last one has an effect
+      while let 0xDD | 0xFD = opcode {
+        index = match opcode {
+          0xDD => Index::IX,
+          _ => Index::IY,
+        };
+        opcode = self.fetch_opcode();
+      }
+
+      self.execute(opcode, index);
+    }
+
+    self.cycles_since_poll += self.cycles as u64;
+
+    if self.cycles_since_poll >= CLOCKS_PER_POLL {
+      let total_cycle_count = self.cycle_count + self.cycles as u64;
+
+      match self.memory.poll(self.cycles_since_poll, total_cycle_count) {
+        ActiveInterrupt::None => (),
+        ActiveInterrupt::NMI => self.nmi(),
+        ActiveInterrupt::IRQ => self.irq(),
+      }
+
+      self.cycles_since_poll = 0;
+    }
+
+    let cycles = self.cycles;
+    self.cycle_count += cycles as u64;
+
+    Ok(cycles)
+  }
+
+  fn cleanup(&mut self) -> Result<(), &str> {
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::memory::{BlockMemory, NullMemory};
+  use registers::flags;
+
+  fn cpu_with_program(program: &[u8]) -> Z80 {
+    let mut memory = BlockMemory::ram(0x10000);
+    for (i, byte) in program.iter().enumerate() {
+      memory.write(i as u16, *byte);
+    }
+
+    Z80::new(memory, NullMemory::new())
+  }
+
+  fn run(cpu: &mut Z80, instructions: usize) -> u64 {
+    (0..instructions).map(|_| cpu.tick().unwrap() as u64).sum()
+  }
+
+  #[test]
+  fn test_arithmetic() {
+    let mut cpu = cpu_with_program(&[
+      0x3E, 0x7F, // LD A,$7F
+      0xC6, 0x01, // ADD A,$01
+      0x06, 0x15, // LD B,$15
+      0x3E, 0x27, // LD A,$27
+      0x80, // ADD A,B
+      0x27, // DAA
+    ]);
+
+    assert_eq!(14, run(&mut cpu, 2));
+    assert_eq!(0x80, cpu.registers.a);
+    assert!(cpu.registers.flag(flags::PARITY));
+    assert!(cpu.registers.flag(flags::SIGN));
+    assert!(cpu.registers.flag(flags::HALF_CARRY));
+
+    run(&mut cpu, 4);
+    assert_eq!(0x42, cpu.registers.a);
+    assert!(!cpu.registers.flag(flags::CARRY));
+  }
+
+  #[test]
+  fn test_index_registers() {
+    let mut cpu = cpu_with_program(&[
+      0xDD, 0x21, 0x00, 0x10, // LD IX,$1000
+      0xDD, 0x36, 0x05, 0x99, // LD (IX+5),$99
+      0xDD, 0x34, 0x05, // INC (IX+5)
+      0xFD, 0x21, 0x0A, 0x10, // LD IY,$100A
+      0xFD, 0x7E, 0xFB, // LD A,(IY-5)
+      0xDD, 0xCB, 0x05, 0x3E, // SRL (IX+5)
+    ]);
+
+    assert_eq!(14 + 19 + 23, run(&mut cpu, 3));
+    assert_eq!(0x9A, cpu.memory.read(0x1005));
+
+    assert_eq!(14 + 19, run(&mut cpu, 2));
+    assert_eq!(0x9A, cpu.registers.a);
+
+    assert_eq!(23, run(&mut cpu, 1));
+    assert_eq!(0x4D, cpu.memory.read(0x1005));
+  }
+
+  #[test]
+  fn test_subroutines_and_block_copy() {
+    let mut cpu = cpu_with_program(&[
+      0x31, 0x00, 0x80, // LD SP,$8000
+      0xCD, 0x10, 0x00, // CALL $0010
+      0x76, // HALT
+      0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // padding
+      0x21, 0x00, 0x00, // $0010: LD HL,$0000
+      0x11, 0x00, 0x20, // LD DE,$2000
+      0x01, 0x04, 0x00, // LD BC,$0004
+      0xED, 0xB0, // LDIR
+      0xC9, // RET
+    ]);
+
+    run(&mut cpu, 5);
+    assert_eq!(21 * 3 + 16, run(&mut cpu, 4));
+    assert_eq!(0x00, cpu.registers.bc());
+    assert_eq!(0x31, cpu.memory.read(0x2000));
+    assert_eq!(0xCD, cpu.memory.read(0x2003));
+
+    run(&mut cpu, 2);
+    assert!(cpu.is_halted());
+    assert_eq!(0x8000, cpu.registers.sp);
+    assert_eq!(0x0007, cpu.registers.pc);
+  }
+
+  /// An I/O space which echoes port writes back on the next read.
+  struct Latch(u8);
+
+  impl IoSpace for Latch {
+    fn read_port(&mut self, port: u16) -> u8 {
+      self.0.wrapping_add(port as u8)
+    }
+
+    fn write_port(&mut self, _port: u16, value: u8) {
+      self.0 = value;
+    }
+
+    fn reset(&mut self) {}
+  }
+
+  #[test]
+  fn test_io_ports() {
+    let mut memory = BlockMemory::ram(0x10000);
+    for (i, byte) in [
+      0x3E, 0x40, // LD A,$40
+      0xD3, 0x10, // OUT ($10),A
+      0x01, 0x02, 0x00, // LD BC,$0002
+      0xED, 0x58, // IN E,(C)
+    ]
+    .iter()
+    .enumerate()
+    {
+      memory.write(i as u16, *byte);
+    }
+
+    let mut cpu = Z80::new(memory, Latch(0));
+    assert_eq!(7 + 11 + 10 + 12, run(&mut cpu, 4));
+    assert_eq!(0x42, cpu.registers.e);
+  }
+
+  /// A memory which raises an interrupt on every poll.
+  struct Interrupting(BlockMemory, ActiveInterrupt);
+
+  impl Memory for Interrupting {
+    fn read(&mut self, address: u16) -> u8 {
+      self.0.read(address)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+      self.0.peek(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+      self.0.write(address, value)
+    }
+
+    fn reset(&mut self) {}
+
+    fn poll(&mut self, _cycles_since_poll: u64, _total_cycle_count: u64) -> ActiveInterrupt {
+      match self.1 {
+        ActiveInterrupt::NMI => ActiveInterrupt::NMI,
+        ActiveInterrupt::IRQ => ActiveInterrupt::IRQ,
+        ActiveInterrupt::None => ActiveInterrupt::None,
+      }
+    }
+  }
+
+  fn interrupted_cpu(mode: u8) -> Z80 {
+    let mut memory = BlockMemory::ram(0x10000);
+    for (i, byte) in [
+      0x31, 0x00, 0x80, // LD SP,$8000
+      0x3E, 0x12, // LD A,$12
+      0xED, 0x47, // LD I,A
+      0xED, mode, // IM n
+      0xFB, // EI
+      0x76, // HALT
+    ]
+    .iter()
+    .enumerate()
+    {
+      memory.write(i as u16, *byte);
+    }
+    memory.write(0x1234, 0x00);
+    memory.write(0x1235, 0x30);
+
+    let mut cpu = Z80::new(
+      Interrupting(memory, ActiveInterrupt::IRQ),
+      NullMemory::new(),
+    );
+    cpu.data_bus = 0x34;
+    cpu
+  }
+
+  #[test]
+  fn test_interrupt_modes() {
+    // IM 1 calls $0038 after the HALT
+    let mut cpu = interrupted_cpu(0x56);
+    while !cpu.is_halted() {
+      cpu.tick().unwrap();
+    }
+    while cpu.is_halted() {
+      cpu.tick().unwrap();
+    }
+    assert_eq!(0x0038, cpu.registers.pc);
+    assert_eq!(0x000B, cpu.memory.read(0x7FFE) as u16);
+    assert!(!cpu.registers.iff1);
+
+    // IM 2 uses the vector table at I * 256 + the data bus
+    let mut cpu = interrupted_cpu(0x5E);
+    while !cpu.is_halted() {
+      cpu.tick().unwrap();
+    }
+    while cpu.is_halted() {
+      cpu.tick().unwrap();
+    }
+    assert_eq!(0x3000, cpu.registers.pc);
+
+    // IM 0 executes the instruction on the data bus
+    let mut cpu = interrupted_cpu(0x46);
+    cpu.data_bus = 0xEF; // RST 28h
+    while !cpu.is_halted() {
+      cpu.tick().unwrap();
+    }
+    while cpu.is_halted() {
+      cpu.tick().unwrap();
+    }
+    assert_eq!(0x0028, cpu.registers.pc);
+  }
+}
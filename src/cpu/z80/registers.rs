@@ -0,0 +1,179 @@
+/// The bits of the flag register (F).
+pub mod flags {
+  pub const CARRY: u8 = 0b00000001;
+  pub const SUBTRACT: u8 = 0b00000010;
+  /// Parity (for logical operations) or overflow (for arithmetic).
+  pub const PARITY: u8 = 0b00000100;
+  /// Undocumented: a copy of bit 3 of a result.
+  pub const X: u8 = 0b00001000;
+  pub const HALF_CARRY: u8 = 0b00010000;
+  /// Undocumented: a copy of bit 5 of a result.
+  pub const Y: u8 = 0b00100000;
+  pub const ZERO: u8 = 0b01000000;
+  pub const SIGN: u8 = 0b10000000;
+}
+
+/// The interrupt modes selected by the `IM` instruction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InterruptMode {
+  /// Execute the instruction placed on the data bus by the interrupting
+  /// device (usually an `RST`).
+  Im0,
+  /// Call the fixed address 0x0038.
+  Im1,
+  /// Call the address in the table at `I` * 256 + the byte on the data bus.
+  Im2,
+}
+
+/// The registers of the Z80, including the alternate register set.
+pub struct Registers {
+  pub a: u8,
+  pub f: u8,
+  pub b: u8,
+  pub c: u8,
+  pub d: u8,
+  pub e: u8,
+  pub h: u8,
+  pub l: u8,
+
+  /// The alternate register set, swapped in by `EX AF,AF'` and `EXX`.
+  pub af_alt: u16,
+  pub bc_alt: u16,
+  pub de_alt: u16,
+  pub hl_alt: u16,
+
+  pub ix: u16,
+  pub iy: u16,
+  pub sp: u16,
+  pub pc: u16,
+
+  /// The interrupt vector base register, used in interrupt mode 2.
+  pub i: u8,
+
+  /// The memory refresh register, incremented on every opcode fetch.
+  pub r: u8,
+
+  /// The interrupt enable flip-flops. IFF1 masks interrupts, and IFF2 holds
+  /// its previous value while a non-maskable interrupt is serviced.
+  pub iff1: bool,
+  pub iff2: bool,
+  pub im: InterruptMode,
+}
+
+impl Registers {
+  pub fn new() -> Self {
+    let mut registers = Self {
+      a: 0,
+      f: 0,
+      b: 0,
+      c: 0,
+      d: 0,
+      e: 0,
+      h: 0,
+      l: 0,
+      af_alt: 0,
+      bc_alt: 0,
+      de_alt: 0,
+      hl_alt: 0,
+      ix: 0,
+      iy: 0,
+      sp: 0,
+      pc: 0,
+      i: 0,
+      r: 0,
+      iff1: false,
+      iff2: false,
+      im: InterruptMode::Im0,
+    };
+    registers.reset();
+    registers
+  }
+
+  /// Reset the registers as the processor does when its reset line is
+  /// asserted. Only the program counter, interrupt state, and `I` and `R` are
+  /// affected; the rest are set to 0xFF as on most real chips.
+  pub fn reset(&mut self) {
+    self.pc = 0;
+    self.i = 0;
+    self.r = 0;
+    self.iff1 = false;
+    self.iff2 = false;
+    self.im = InterruptMode::Im0;
+    self.set_af(0xFFFF);
+    self.sp = 0xFFFF;
+  }
+
+  pub fn af(&self) -> u16 {
+    (self.a as u16) << 8 | self.f as u16
+  }
+
+  pub fn bc(&self) -> u16 {
+    (self.b as u16) << 8 | self.c as u16
+  }
+
+  pub fn de(&self) -> u16 {
+    (self.d as u16) << 8 | self.e as u16
+  }
+
+  pub fn hl(&self) -> u16 {
+    (self.h as u16) << 8 | self.l as u16
+  }
+
+  pub fn set_af(&mut self, value: u16) {
+    self.a = (value >> 8) as u8;
+    self.f = value as u8;
+  }
+
+  pub fn set_bc(&mut self, value: u16) {
+    self.b = (value >> 8) as u8;
+    self.c = value as u8;
+  }
+
+  pub fn set_de(&mut self, value: u16) {
+    self.d = (value >> 8) as u8;
+    self.e = value as u8;
+  }
+
+  pub fn set_hl(&mut self, value: u16) {
+    self.h = (value >> 8) as u8;
+    self.l = value as u8;
+  }
+
+  /// Get the value of the given flag.
+  pub fn flag(&self, flag: u8) -> bool {
+    self.f & flag != 0
+  }
+
+  /// If the given value is true, set the given flag; otherwise, clear it.
+  pub fn set_flag(&mut self, flag: u8, value: bool) {
+    if value {
+      self.f |= flag;
+    } else {
+      self.f &= !flag;
+    }
+  }
+
+  /// Increment the lower 7 bits of the refresh register, as happens on each
+  /// opcode fetch.
+  pub fn increment_r(&mut self) {
+    self.r = (self.r & 0x80) | (self.r.wrapping_add(1) & 0x7F);
+  }
+
+  /// Swap BC, DE and HL with the alternate register set (`EXX`).
+  pub fn exchange(&mut self) {
+    let (bc, de, hl) = (self.bc(), self.de(), self.hl());
+    self.set_bc(self.bc_alt);
+    self.set_de(self.de_alt);
+    self.set_hl(self.hl_alt);
+    self.bc_alt = bc;
+    self.de_alt = de;
+    self.hl_alt = hl;
+  }
+
+  /// Swap AF with the alternate AF (`EX AF,AF'`).
+  pub fn exchange_af(&mut self) {
+    let af = self.af();
+    self.set_af(self.af_alt);
+    self.af_alt = af;
+  }
+}
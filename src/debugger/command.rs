@@ -0,0 +1,380 @@
+use crate::memory::{Watch, WatchKind};
+use crate::symbols::SymbolTable;
+
+/// A register of the MOS 6502 which can be displayed or edited from the monitor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Register {
+  A,
+  X,
+  Y,
+  StackPointer,
+  ProgramCounter,
+  Status,
+}
+
+impl Register {
+  fn parse(name: &str) -> Result<Self, String> {
+    match name.to_ascii_lowercase().as_str() {
+      "a" => Ok(Register::A),
+      "x" => Ok(Register::X),
+      "y" => Ok(Register::Y),
+      "s" | "sp" => Ok(Register::StackPointer),
+      "pc" => Ok(Register::ProgramCounter),
+      "p" | "sr" => Ok(Register::Status),
+      _ => Err(format!("Unknown register: {name}")),
+    }
+  }
+}
+
+/// A command entered at the machine-language monitor prompt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+  /// Show the list of available commands.
+  Help,
+
+  /// Display the current register values.
+  Registers,
+
+  /// Set the given register to a new value.
+  SetRegister(Register, u16),
+
+  /// Execute the given number of instructions, then stop.
+  Step(u32),
+
+  /// Execute one instruction, treating a subroutine call as a single step.
+  StepOver,
+
+  /// Run until the current subroutine returns.
+  StepOut,
+
+  /// Resume execution until a breakpoint is hit.
+  Continue,
+
+  /// Resume execution until the program counter reaches the given address.
+  RunUntil(u16),
+
+  /// Add an execution breakpoint at the given address.
+  Break(u16),
+
+  /// Remove the execution breakpoint at the given address.
+  Delete(u16),
+
+  /// List all execution breakpoints.
+  ListBreakpoints,
+
+  /// Add a watchpoint, which stops execution after memory is accessed.
+  Watch(Watch),
+
+  /// Remove the watchpoint with the given number.
+  Unwatch(usize),
+
+  /// List all watchpoints.
+  ListWatchpoints,
+
+  /// Display the contents of memory between two addresses (inclusive).
+  Memory(u16, u16),
+
+  /// Fill memory between two addresses (inclusive) with a repeating pattern.
+  Fill(u16, u16, Vec<u8>),
+
+  /// Disassemble instructions starting at the given address, or at the
+  /// program counter if none is given.
+  Disassemble(Option<u16>),
+
+  /// Compare memory between two addresses (inclusive) against the memory
+  /// starting at a third address.
+  Compare(u16, u16, u16),
+
+  /// Reset the CPU, clearing any halt condition.
+  Reset,
+
+  /// Exit the emulator.
+  Quit,
+}
+
+/// The number of bytes shown by the `memory` command if no end address is given.
+const DEFAULT_DUMP_LENGTH: u16 = 0x80;
+
+/// Parse a hexadecimal number, optionally prefixed with `$` or `0x`.
+fn parse_number(value: &str) -> Result<u16, String> {
+  let digits = value
+    .strip_prefix('$')
+    .or_else(|| value.strip_prefix("0x"))
+    .unwrap_or(value);
+
+  u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid number: {value}"))
+}
+
+/// Parse an address, given as a hexadecimal number or as a symbol with an
+/// optional decimal offset (e.g. `CHROUT+3`). Values prefixed with `$` or `0x`
+/// are always numbers, for when a symbol's name looks like one.
+fn parse_address(value: &str, symbols: &SymbolTable) -> Result<u16, String> {
+  if value.starts_with('$') || value.starts_with("0x") {
+    return parse_number(value);
+  }
+
+  symbols
+    .resolve(value)
+    .map_or_else(|| parse_number(value), Ok)
+    .map_err(|_| format!("Invalid address or unknown symbol: {value}"))
+}
+
+/// Parse a hexadecimal number which must fit in a single byte.
+fn parse_byte(value: &str) -> Result<u8, String> {
+  let number = parse_number(value)?;
+  u8::try_from(number).map_err(|_| format!("Value out of range: {value}"))
+}
+
+impl Command {
+  /// Parse a line of user input into a command.
+  pub fn parse(line: &str) -> Result<Command, String> {
+    Self::parse_with_symbols(line, &SymbolTable::new())
+  }
+
+  /// Parse a line of user input into a command, where addresses may also be
+  /// given as names from the symbol table.
+  pub fn parse_with_symbols(line: &str, symbols: &SymbolTable) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let name = words
+      .next()
+      .ok_or_else(|| "No command entered".to_owned())?;
+    let args: Vec<&str> = words.collect();
+
+    let address = |index: usize| -> Result<u16, String> {
+      args
+        .get(index)
+        .ok_or_else(|| format!("Missing address for {name}"))
+        .and_then(|value| parse_address(value, symbols))
+    };
+
+    match name.to_ascii_lowercase().as_str() {
+      "help" | "h" | "?" => Ok(Command::Help),
+      "registers" | "r" => match args.len() {
+        0 => Ok(Command::Registers),
+        2 => Ok(Command::SetRegister(
+          Register::parse(args[0])?,
+          parse_number(args[1])?,
+        )),
+        _ => Err("Usage: registers [<register> <value>]".to_owned()),
+      },
+      "step" | "s" | "z" => match args.first() {
+        Some(count) => count
+          .parse()
+          .map(Command::Step)
+          .map_err(|_| format!("Invalid count: {count}")),
+        None => Ok(Command::Step(1)),
+      },
+      "next" | "n" => Ok(Command::StepOver),
+      "return" | "ret" => Ok(Command::StepOut),
+      "continue" | "c" | "x" => Ok(Command::Continue),
+      "until" | "un" => Ok(Command::RunUntil(address(0)?)),
+      "break" | "b" => Ok(Command::Break(address(0)?)),
+      "delete" | "del" => Ok(Command::Delete(address(0)?)),
+      "breakpoints" | "bl" => Ok(Command::ListBreakpoints),
+      "watch" | "wa" | "rwatch" | "rw" | "awatch" | "aw" => {
+        let kind = match name.chars().next().unwrap().to_ascii_lowercase() {
+          'r' => WatchKind::Read,
+          'a' => WatchKind::Access,
+          _ => WatchKind::Write,
+        };
+        let usage = || format!("Usage: {name} <start> [<end>] [= <value>]");
+
+        let (range, value) = match args.iter().position(|&arg| arg == "=") {
+          Some(index) => (index, Some(args.get(index + 1).ok_or_else(usage)?)),
+          None => (args.len(), None),
+        };
+
+        let start = address(0)?;
+        let mut watch = match range {
+          1 => Watch::new(kind, start, start),
+          2 => Watch::new(kind, start, address(1)?),
+          _ => return Err(usage()),
+        };
+
+        if let Some(value) = value {
+          watch = watch.with_value(parse_byte(value)?);
+        }
+
+        Ok(Command::Watch(watch))
+      }
+      "unwatch" | "uw" => match args.first() {
+        Some(id) => id
+          .parse()
+          .map(Command::Unwatch)
+          .map_err(|_| format!("Invalid watchpoint number: {id}")),
+        None => Err("Usage: unwatch <number>".to_owned()),
+      },
+      "watchpoints" | "wl" => Ok(Command::ListWatchpoints),
+      "memory" | "m" => {
+        let start = address(0)?;
+        let end = match args.get(1) {
+          Some(end) => parse_address(end, symbols)?,
+          None => start.saturating_add(DEFAULT_DUMP_LENGTH - 1),
+        };
+        Ok(Command::Memory(start, end))
+      }
+      "fill" | "f" => {
+        let pattern = args
+          .iter()
+          .skip(2)
+          .map(|value| parse_byte(value))
+          .collect::<Result<Vec<u8>, String>>()?;
+
+        if pattern.is_empty() {
+          return Err("Usage: fill <start> <end> <byte> [<byte> ...]".to_owned());
+        }
+
+        Ok(Command::Fill(address(0)?, address(1)?, pattern))
+      }
+      "disassemble" | "d" => match args.first() {
+        Some(start) => Ok(Command::Disassemble(Some(parse_address(start, symbols)?))),
+        None => Ok(Command::Disassemble(None)),
+      },
+      "compare" | "cmp" => Ok(Command::Compare(address(0)?, address(1)?, address(2)?)),
+      "reset" => Ok(Command::Reset),
+      "quit" | "q" => Ok(Command::Quit),
+      _ => Err(format!(
+        "Unknown command: {name} (type \"help\" for a list)"
+      )),
+    }
+  }
+}
+
+/// Help text shown by the `help` command.
+pub const HELP: &str = "\
+Addresses and values are hexadecimal, optionally prefixed with $ or 0x.
+Addresses may also be symbols, with an optional decimal offset (e.g. CHROUT+3).
+Step counts are decimal. Address ranges are inclusive.
+  help (h, ?)                       show this message
+  registers (r) [<reg> <value>]     show registers, or set a, x, y, sp, pc or p
+  step (s, z) [<count>]             execute <count> instructions (default 1)
+  next (n)                          step over subroutine calls
+  return (ret)                      run until the current subroutine returns
+  continue (c, x)                   resume execution
+  until (un) <address>              run until the program counter reaches <address>
+  break (b) <address>               add a breakpoint
+  delete (del) <address>            remove a breakpoint
+  breakpoints (bl)                  list breakpoints
+  watch (wa) <start> [<end>] [= <value>]
+                                    stop after a write to memory (of <value>, if given)
+  rwatch (rw), awatch (aw) ...      the same, for reads or for any access
+  unwatch (uw) <number>             remove a watchpoint
+  watchpoints (wl)                  list watchpoints
+  memory (m) <start> [<end>]        display memory
+  fill (f) <start> <end> <bytes...> fill memory with a repeating pattern
+  compare (cmp) <start> <end> <dest> compare two regions of memory
+  disassemble (d) [<address>]       disassemble code (default: at the program counter)
+  reset                             reset the CPU
+  quit (q)                          exit the emulator
+";
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_numbers() {
+    assert_eq!(Ok(Command::Break(0xC000)), Command::parse("b C000"));
+    assert_eq!(Ok(Command::Break(0xC000)), Command::parse("break $c000"));
+    assert_eq!(Ok(Command::Break(0x00FF)), Command::parse("break 0xff"));
+    assert!(Command::parse("break").is_err());
+    assert!(Command::parse("break xyz").is_err());
+    assert!(Command::parse("break 12345").is_err());
+  }
+
+  #[test]
+  fn test_parse_registers() {
+    assert_eq!(Ok(Command::Registers), Command::parse("r"));
+    assert_eq!(
+      Ok(Command::SetRegister(Register::A, 0x12)),
+      Command::parse("r a 12")
+    );
+    assert_eq!(
+      Ok(Command::SetRegister(Register::ProgramCounter, 0xE000)),
+      Command::parse("registers PC $E000")
+    );
+    assert!(Command::parse("r q 12").is_err());
+    assert!(Command::parse("r a").is_err());
+  }
+
+  #[test]
+  fn test_parse_memory() {
+    assert_eq!(
+      Ok(Command::Memory(0x1000, 0x107F)),
+      Command::parse("m 1000")
+    );
+    assert_eq!(
+      Ok(Command::Memory(0xFFC0, 0xFFFF)),
+      Command::parse("m ffc0")
+    );
+    assert_eq!(
+      Ok(Command::Memory(0x1000, 0x1010)),
+      Command::parse("m 1000 1010")
+    );
+    assert_eq!(
+      Ok(Command::Fill(0x0400, 0x07E7, vec![0x20, 0x01])),
+      Command::parse("f 0400 07e7 20 01")
+    );
+    assert!(Command::parse("f 0400 07e7").is_err());
+    assert!(Command::parse("f 0400 07e7 100").is_err());
+    assert_eq!(
+      Ok(Command::Compare(0xC000, 0xC0FF, 0x2000)),
+      Command::parse("cmp c000 c0ff 2000")
+    );
+    assert_eq!(Ok(Command::Disassemble(None)), Command::parse("d"));
+    assert_eq!(
+      Ok(Command::Disassemble(Some(0xE000))),
+      Command::parse("disassemble e000")
+    );
+  }
+
+  #[test]
+  fn test_parse_execution() {
+    assert_eq!(Ok(Command::Step(1)), Command::parse("s"));
+    assert_eq!(Ok(Command::Step(10)), Command::parse("step 10"));
+    assert_eq!(Ok(Command::StepOver), Command::parse("n"));
+    assert_eq!(Ok(Command::StepOut), Command::parse("ret"));
+    assert_eq!(Ok(Command::Continue), Command::parse("c"));
+    assert_eq!(Ok(Command::RunUntil(0xE5CF)), Command::parse("until e5cf"));
+    assert!(Command::parse("").is_err());
+    assert!(Command::parse("frobnicate").is_err());
+  }
+
+  #[test]
+  fn test_parse_symbols() {
+    let mut symbols = SymbolTable::new();
+    symbols.insert("CHROUT", 0xFFD2);
+    symbols.insert("ADD", 0x0300);
+
+    let parse = |line| Command::parse_with_symbols(line, &symbols);
+    assert_eq!(Ok(Command::Break(0xFFD2)), parse("b CHROUT"));
+    assert_eq!(Ok(Command::RunUntil(0xFFD5)), parse("until chrout+3"));
+    assert_eq!(Ok(Command::Memory(0x0300, 0x0310)), parse("m ADD 0310"));
+    assert_eq!(Ok(Command::Disassemble(Some(0x0ADD))), parse("d $add"));
+    assert!(parse("b CHRIN").is_err());
+  }
+
+  #[test]
+  fn test_parse_watchpoints() {
+    assert_eq!(
+      Ok(Command::Watch(Watch::new(WatchKind::Write, 0xD020, 0xD020))),
+      Command::parse("watch d020")
+    );
+    assert_eq!(
+      Ok(Command::Watch(
+        Watch::new(WatchKind::Read, 0x00FB, 0x00FC).with_value(0)
+      )),
+      Command::parse("rw fb fc = 0")
+    );
+    assert_eq!(
+      Ok(Command::Watch(
+        Watch::new(WatchKind::Access, 0x0400, 0x0400).with_value(0x20)
+      )),
+      Command::parse("awatch 0400 = 20")
+    );
+    assert!(Command::parse("watch d020 =").is_err());
+    assert!(Command::parse("watch 1 2 3").is_err());
+    assert_eq!(Ok(Command::Unwatch(2)), Command::parse("uw 2"));
+    assert_eq!(Ok(Command::ListWatchpoints), Command::parse("wl"));
+  }
+}
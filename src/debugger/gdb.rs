@@ -0,0 +1,571 @@
+use crate::cpu::mos6502::{MemoryIO, Mos6502};
+use crate::cpu::CpuError;
+use crate::debugger::{DebugAction, DebugHandler};
+use std::io::{ErrorKind, Read, Result, Write};
+use std::net::{TcpListener, TcpStream};
+
+/// The signal reported when execution stops at a breakpoint or after a step.
+const SIGTRAP: u8 = 5;
+
+/// The signal reported when the CPU halts with an error.
+const SIGILL: u8 = 4;
+
+/// The signal reported when execution is interrupted by the client.
+const SIGINT: u8 = 2;
+
+/// The byte sent by the client to interrupt a running target.
+const INTERRUPT: u8 = 0x03;
+
+/// How many instructions to execute between checks for an interrupt request.
+const INSTRUCTIONS_PER_POLL: u32 = 1000;
+
+/// Describes the register layout used by the `g` and `p` packets:
+/// A, X, Y, P and SP are one byte each, followed by the two-byte PC.
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.noentiendo.mos6502">
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="x" bitsize="8" type="uint8"/>
+    <reg name="y" bitsize="8" type="uint8"/>
+    <reg name="p" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+/// A packet received from the client.
+enum Packet {
+  Command(String),
+  Interrupt,
+}
+
+/// A server for the GDB Remote Serial Protocol, allowing a standard remote
+/// debugger to control an emulated MOS 6502 over TCP.
+///
+/// Registers are exposed in the order A, X, Y, P, SP, PC. Memory accesses go
+/// through the CPU's view of the system's memory, so they reach any
+/// memory-mapped devices as well as RAM and ROM.
+pub struct GdbServer {
+  stream: TcpStream,
+  breakpoints: Vec<u16>,
+
+  /// Stop before the next instruction.
+  stepping: bool,
+
+  /// The client is waiting for a stop reply to a `c` or `s` packet.
+  running: bool,
+
+  /// The client has detached (or disconnected), so execution is no longer
+  /// controlled by the server.
+  detached: bool,
+
+  /// The client killed the target, which ends the session.
+  killed: bool,
+
+  last_signal: u8,
+  instructions_since_poll: u32,
+}
+
+impl GdbServer {
+  /// Listen on the given port on localhost, and block until a client
+  /// connects. Execution stops before the first instruction.
+  pub fn listen(port: u16) -> Result<Self> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("Waiting for GDB connection on port {port}");
+
+    let (stream, address) = listener.accept()?;
+    println!("GDB connected from {address}");
+
+    Self::new(stream)
+  }
+
+  /// Create a server which communicates over an existing connection.
+  pub fn new(stream: TcpStream) -> Result<Self> {
+    stream.set_nodelay(true)?;
+
+    Ok(Self {
+      stream,
+      breakpoints: Vec::new(),
+      stepping: true,
+      running: false,
+      detached: false,
+      killed: false,
+      last_signal: SIGTRAP,
+      instructions_since_poll: 0,
+    })
+  }
+
+  fn read_byte(&mut self) -> Result<u8> {
+    let mut byte = [0; 1];
+    self.stream.read_exact(&mut byte)?;
+    Ok(byte[0])
+  }
+
+  /// Block until a complete packet (or an interrupt request) is received.
+  fn read_packet(&mut self) -> Result<Packet> {
+    loop {
+      match self.read_byte()? {
+        b'$' => {
+          let mut data = Vec::new();
+          let mut checksum: u8 = 0;
+
+          loop {
+            match self.read_byte()? {
+              b'#' => break,
+              byte => {
+                checksum = checksum.wrapping_add(byte);
+                data.push(byte);
+              }
+            }
+          }
+
+          let expected = [self.read_byte()?, self.read_byte()?];
+          let expected = std::str::from_utf8(&expected)
+            .ok()
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+
+          if expected == Some(checksum) {
+            self.stream.write_all(b"+")?;
+            return Ok(Packet::Command(String::from_utf8_lossy(&data).into_owned()));
+          }
+
+          self.stream.write_all(b"-")?;
+        }
+        INTERRUPT => return Ok(Packet::Interrupt),
+        _ => {} // acknowledgements
+      }
+    }
+  }
+
+  fn write_packet(&mut self, data: &str) -> Result<()> {
+    let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    self
+      .stream
+      .write_all(format!("${data}#{checksum:02x}").as_bytes())
+  }
+
+  /// Check, without blocking, whether the client has asked to interrupt
+  /// execution.
+  fn interrupt_requested(&mut self) -> Result<bool> {
+    self.stream.set_nonblocking(true)?;
+    let mut byte = [0; 1];
+    let result = self.stream.read(&mut byte);
+    self.stream.set_nonblocking(false)?;
+
+    match result {
+      Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+      Ok(_) => Ok(byte[0] == INTERRUPT),
+      Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+      Err(e) => Err(e),
+    }
+  }
+
+  /// Report that execution has stopped, then handle packets until the client
+  /// resumes execution or kills the target.
+  fn serve(&mut self, cpu: &mut Mos6502, signal: u8) -> Result<DebugAction> {
+    self.last_signal = signal;
+
+    if self.running {
+      self.running = false;
+      self.write_packet(&format!("S{signal:02x}"))?;
+    }
+
+    loop {
+      let packet = match self.read_packet()? {
+        Packet::Command(packet) => packet,
+        Packet::Interrupt => continue,
+      };
+
+      if let Some(reply) = self.handle(cpu, &packet) {
+        self.write_packet(&reply)?;
+      }
+
+      if self.killed {
+        return Ok(DebugAction::Quit);
+      }
+
+      if self.running || self.detached {
+        return Ok(DebugAction::Continue);
+      }
+    }
+  }
+
+  /// Handle a single packet, returning the reply to send (if any).
+  fn handle(&mut self, cpu: &mut Mos6502, packet: &str) -> Option<String> {
+    let (command, args) = packet.split_at(packet.len().min(1));
+
+    let reply = match command {
+      "?" => format!("S{:02x}", self.last_signal),
+      "g" => encode(&read_registers(cpu)),
+      "G" => match decode(args) {
+        Some(values) if values.len() == 7 => {
+          write_registers(cpu, &values);
+          "OK".to_owned()
+        }
+        _ => "E01".to_owned(),
+      },
+      "p" => match usize::from_str_radix(args, 16) {
+        Ok(index @ 0..=4) => encode(&read_registers(cpu)[index..=index]),
+        Ok(5) => encode(&read_registers(cpu)[5..7]),
+        _ => "E01".to_owned(),
+      },
+      "P" => {
+        let parsed = args.split_once('=').and_then(|(index, value)| {
+          Some((usize::from_str_radix(index, 16).ok()?, decode(value)?))
+        });
+
+        match parsed {
+          Some((index @ 0..=5, value)) if value.len() == if index == 5 { 2 } else { 1 } => {
+            let mut values = read_registers(cpu);
+            values[index..index + value.len()].copy_from_slice(&value);
+            write_registers(cpu, &values);
+            "OK".to_owned()
+          }
+          _ => "E01".to_owned(),
+        }
+      }
+      "m" => match parse_range(args) {
+        Some((address, length)) => {
+          let data: Vec<u8> = (0..length)
+            .map(|offset| cpu.peek(address.wrapping_add(offset)))
+            .collect();
+          encode(&data)
+        }
+        None => "E01".to_owned(),
+      },
+      "M" => {
+        let parsed = args
+          .split_once(':')
+          .and_then(|(range, data)| Some((parse_range(range)?, decode(data)?)));
+
+        match parsed {
+          Some(((address, length), data)) if data.len() == length as usize => {
+            for (offset, value) in data.iter().enumerate() {
+              cpu.write(address.wrapping_add(offset as u16), *value);
+            }
+            "OK".to_owned()
+          }
+          _ => "E01".to_owned(),
+        }
+      }
+      "c" | "s" => {
+        if let Ok(address) = u16::from_str_radix(args, 16) {
+          cpu.registers.pc.load(address);
+        }
+        self.stepping = command == "s";
+        self.running = true;
+        return None;
+      }
+      "Z" | "z" => match parse_breakpoint(args) {
+        Some(address) => {
+          self.breakpoints.retain(|&b| b != address);
+          if command == "Z" {
+            self.breakpoints.push(address);
+          }
+          "OK".to_owned()
+        }
+        None => String::new(),
+      },
+      "D" => {
+        self.detached = true;
+        "OK".to_owned()
+      }
+      "k" => {
+        // No reply is sent to a kill request
+        self.killed = true;
+        return None;
+      }
+      "H" => "OK".to_owned(),
+      "q" => self.handle_query(packet),
+      _ => String::new(),
+    };
+
+    Some(reply)
+  }
+
+  /// Detach if the connection was lost while serving the client.
+  fn handle_result(&mut self, result: Result<DebugAction>) -> DebugAction {
+    result.unwrap_or_else(|e| {
+      println!("GDB connection lost: {e}");
+      self.detached = true;
+      DebugAction::Continue
+    })
+  }
+
+  /// Handle a general query (`q`) packet.
+  fn handle_query(&mut self, packet: &str) -> String {
+    if packet.starts_with("qSupported") {
+      "PacketSize=1000;qXfer:features:read+".to_owned()
+    } else if packet == "qAttached" {
+      "1".to_owned()
+    } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+      match range.split_once(',').and_then(|(offset, length)| {
+        Some((
+          usize::from_str_radix(offset, 16).ok()?,
+          usize::from_str_radix(length, 16).ok()?,
+        ))
+      }) {
+        Some((offset, length)) => {
+          let start = offset.min(TARGET_XML.len());
+          let end = (offset + length).min(TARGET_XML.len());
+          let prefix = if end == TARGET_XML.len() { "l" } else { "m" };
+          format!("{prefix}{}", &TARGET_XML[start..end])
+        }
+        None => "E01".to_owned(),
+      }
+    } else {
+      String::new()
+    }
+  }
+}
+
+impl DebugHandler for GdbServer {
+  fn before_instruction(&mut self, cpu: &mut Mos6502) -> DebugAction {
+    if self.detached {
+      return DebugAction::Continue;
+    }
+
+    let pc = cpu.registers.pc.address();
+
+    let signal = if self.stepping || self.breakpoints.contains(&pc) {
+      Some(Ok(SIGTRAP))
+    } else {
+      self.instructions_since_poll += 1;
+      if self.instructions_since_poll >= INSTRUCTIONS_PER_POLL {
+        self.instructions_since_poll = 0;
+        match self.interrupt_requested() {
+          Ok(true) => Some(Ok(SIGINT)),
+          Ok(false) => None,
+          Err(e) => Some(Err(e)),
+        }
+      } else {
+        None
+      }
+    };
+
+    let result = match signal {
+      Some(Ok(signal)) => self.serve(cpu, signal),
+      Some(Err(e)) => Err(e),
+      None => Ok(DebugAction::Continue),
+    };
+
+    self.handle_result(result)
+  }
+
+  fn on_error(&mut self, cpu: &mut Mos6502, error: CpuError) -> DebugAction {
+    if self.detached {
+      return DebugAction::Continue;
+    }
+
+    println!("CPU halted: {error}");
+
+    let result = self.serve(cpu, SIGILL);
+    self.handle_result(result)
+  }
+}
+
+/// Read the registers in the order used by the `g` packet.
+fn read_registers(cpu: &Mos6502) -> [u8; 7] {
+  let registers = &cpu.registers;
+  let pc = registers.pc.address();
+
+  [
+    registers.a,
+    registers.x,
+    registers.y,
+    registers.sr.get(),
+    registers.sp.get(),
+    pc as u8,
+    (pc >> 8) as u8,
+  ]
+}
+
+/// Write the registers from the order used by the `G` packet.
+fn write_registers(cpu: &mut Mos6502, values: &[u8]) {
+  let registers = &mut cpu.registers;
+
+  registers.a = values[0];
+  registers.x = values[1];
+  registers.y = values[2];
+  registers.sr.load(values[3]);
+  registers.sp.set(values[4]);
+  registers
+    .pc
+    .load((values[6] as u16) << 8 | values[5] as u16);
+}
+
+fn encode(data: &[u8]) -> String {
+  data.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode(hex: &str) -> Option<Vec<u8>> {
+  if !hex.len().is_multiple_of(2) {
+    return None;
+  }
+
+  (0..hex.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+    .collect()
+}
+
+/// Parse an `address,length` pair, as used by the `m` and `M` packets.
+fn parse_range(range: &str) -> Option<(u16, u16)> {
+  let (address, length) = range.split_once(',')?;
+  Some((
+    u16::from_str_radix(address, 16).ok()?,
+    u16::from_str_radix(length, 16).ok()?,
+  ))
+}
+
+/// Parse the arguments of a `Z` or `z` packet. Only software (0) and hardware
+/// (1) execution breakpoints are supported.
+fn parse_breakpoint(args: &str) -> Option<u16> {
+  let mut parts = args.split(',');
+  match parts.next()? {
+    "0" | "1" => u16::from_str_radix(parts.next()?, 16).ok(),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cpu::{mos6502::Mos6502Variant, Cpu};
+  use crate::memory::{BlockMemory, Memory};
+  use std::thread;
+
+  /// A minimal client, which sends a packet and returns the reply.
+  struct Client {
+    stream: TcpStream,
+  }
+
+  impl Client {
+    fn read_until(&mut self, end: u8) -> Vec<u8> {
+      let mut data = Vec::new();
+      let mut byte = [0; 1];
+      loop {
+        self.stream.read_exact(&mut byte).unwrap();
+        data.push(byte[0]);
+        if byte[0] == end {
+          return data;
+        }
+      }
+    }
+
+    fn receive(&mut self) -> String {
+      let packet = self.read_until(b'#');
+      let mut checksum = [0; 2];
+      self.stream.read_exact(&mut checksum).unwrap();
+      self.stream.write_all(b"+").unwrap();
+
+      let start = packet.iter().position(|&b| b == b'$').unwrap();
+      String::from_utf8(packet[start + 1..packet.len() - 1].to_vec()).unwrap()
+    }
+
+    fn send(&mut self, data: &str) {
+      let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+      self
+        .stream
+        .write_all(format!("${data}#{checksum:02x}").as_bytes())
+        .unwrap();
+      assert_eq!(vec![b'+'], self.read_until(b'+'));
+    }
+
+    fn request(&mut self, data: &str) -> String {
+      self.send(data);
+      self.receive()
+    }
+  }
+
+  #[test]
+  fn test_remote_session() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    // 0200: LDX #$05; DEX; BNE $0202; NOP
+    let mut memory = BlockMemory::ram(0x10000);
+    for (i, byte) in [0xA2, 0x05, 0xCA, 0xD0, 0xFD, 0xEA].iter().enumerate() {
+      memory.write(0x0200 + i as u16, *byte);
+    }
+
+    let emulator = thread::spawn(move || {
+      let mut cpu = Mos6502::new(memory, Mos6502Variant::NMOS);
+      cpu.registers.pc.load(0x0200);
+
+      let (stream, _) = listener.accept().unwrap();
+      cpu.attach_debugger(Box::new(GdbServer::new(stream).unwrap()));
+
+      while cpu.registers.pc.address() != 0x0206 {
+        cpu.tick().unwrap();
+      }
+
+      cpu.registers.x
+    });
+
+    let mut client = Client {
+      stream: TcpStream::connect(("127.0.0.1", port)).unwrap(),
+    };
+
+    assert_eq!("S05", client.request("?"));
+    assert!(client
+      .request("qSupported:multiprocess+")
+      .contains("qXfer:features:read+"));
+    assert!(client.request("g").ends_with("0002"));
+    assert_eq!("a205cad0", client.request("m200,4"));
+
+    // step over LDX
+    assert_eq!("S05", client.request("s"));
+    assert_eq!("0202", client.request("p5"));
+    assert_eq!("05", client.request("p1"));
+
+    // patch the loop counter and set a breakpoint after the loop
+    assert_eq!("OK", client.request("P1=02"));
+    assert_eq!("OK", client.request("Z0,205,1"));
+    assert_eq!("S05", client.request("c"));
+    assert_eq!("0502", client.request("p5"));
+    assert_eq!("00", client.request("p1"));
+
+    assert_eq!("OK", client.request("M300,2:beef"));
+    assert_eq!("beef", client.request("m300,2"));
+
+    assert_eq!("OK", client.request("D"));
+    assert_eq!(0, emulator.join().unwrap());
+  }
+
+  #[test]
+  fn test_kill() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let emulator = thread::spawn(move || {
+      let mut cpu = Mos6502::new(BlockMemory::ram(0x10000), Mos6502Variant::NMOS);
+
+      let (stream, _) = listener.accept().unwrap();
+      cpu.attach_debugger(Box::new(GdbServer::new(stream).unwrap()));
+
+      cpu.tick()
+    });
+
+    let mut client = Client {
+      stream: TcpStream::connect(("127.0.0.1", port)).unwrap(),
+    };
+
+    assert_eq!("S05", client.request("?"));
+    client.send("k");
+    assert_eq!(Err(CpuError::Quit), emulator.join().unwrap());
+  }
+
+  #[test]
+  fn test_hex_encoding() {
+    assert_eq!("00ff7f", encode(&[0x00, 0xFF, 0x7F]));
+    assert_eq!(Some(vec![0x00, 0xFF, 0x7F]), decode("00ff7f"));
+    assert_eq!(None, decode("0ff"));
+    assert_eq!(None, decode("zz"));
+    assert_eq!(Some((0xC000, 0x10)), parse_range("c000,10"));
+    assert_eq!(Some(0x1234), parse_breakpoint("0,1234,1"));
+    assert_eq!(None, parse_breakpoint("2,1234,1"));
+  }
+}
@@ -0,0 +1,641 @@
+use crate::cpu::mos6502::{disasm::Instruction, registers::flags, MemoryIO, Mos6502};
+use crate::cpu::{Cpu, CpuError};
+use crate::memory::{WatchKind, Watchpoints};
+use crate::platform::PlatformProvider;
+use crate::symbols::SymbolTable;
+use std::sync::Arc;
+
+mod command;
+pub use command::{Command, Register};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod gdb;
+
+/// What the CPU should do once a [`DebugHandler`] returns control to it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DebugAction {
+  /// Carry on executing (or stay halted, after an error).
+  Continue,
+
+  /// End the session. The CPU halts with [`CpuError::Quit`], so that the
+  /// platform can shut down cleanly.
+  Quit,
+}
+
+/// An item which is consulted by the CPU before each instruction, and which
+/// may pause execution to inspect or modify the state of the CPU.
+pub trait DebugHandler {
+  /// Called before each instruction is executed. If the handler decides to stop
+  /// here, this blocks until execution should resume.
+  fn before_instruction(&mut self, cpu: &mut Mos6502) -> DebugAction;
+
+  /// Called when the CPU halts with an error. The CPU remains halted after this
+  /// returns, unless the handler resets it.
+  fn on_error(&mut self, _cpu: &mut Mos6502, _error: CpuError) -> DebugAction {
+    DebugAction::Continue
+  }
+}
+
+/// Opcode of the instruction which calls a subroutine.
+const JSR: u8 = 0x20;
+
+/// Opcodes of instructions which return from a subroutine or interrupt.
+const RTS: u8 = 0x60;
+const RTI: u8 = 0x40;
+
+/// The number of instructions shown by the `disassemble` command.
+const DISASSEMBLY_LENGTH: usize = 16;
+
+/// The condition under which the debugger will next stop execution.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum RunMode {
+  /// Stop after the given number of further instructions have executed.
+  Step(u32),
+
+  /// Run until a breakpoint is hit.
+  Continue,
+
+  /// Run until the program counter reaches the given address.
+  RunUntil(u16),
+
+  /// Run until a return instruction leaves the stack pointer above the given
+  /// value, i.e. until the current subroutine returns to its caller.
+  StepOut(u8),
+}
+
+/// An interactive machine-language monitor for systems built on the MOS 6502.
+///
+/// The debugger is consulted by the CPU before every instruction. When a
+/// breakpoint is hit, a step completes, or the platform reports that the user
+/// pressed the debugger hotkey, execution pauses and the debugger reads
+/// commands from the platform (e.g. a prompt on the terminal) until the user
+/// resumes execution.
+pub struct Debugger {
+  platform: Arc<dyn PlatformProvider>,
+  breakpoints: Vec<u16>,
+  mode: RunMode,
+
+  /// The opcode of the instruction about to be executed, recorded while
+  /// stepping out of a subroutine.
+  previous_opcode: Option<u8>,
+
+  symbols: SymbolTable,
+
+  watchpoints: Watchpoints,
+
+  /// Whether the CPU's memory is checked against the watchpoints yet. If not,
+  /// it is wrapped when the first watchpoint is added.
+  watching: bool,
+
+  /// The address of the instruction executed most recently, which made any
+  /// access that hit a watchpoint.
+  previous_pc: Option<u16>,
+}
+
+impl Debugger {
+  /// Create a new debugger which communicates with the user through the given
+  /// platform. Execution stops before the first instruction.
+  pub fn new(platform: Arc<dyn PlatformProvider>) -> Self {
+    Self {
+      platform,
+      breakpoints: Vec::new(),
+      mode: RunMode::Step(0),
+      previous_opcode: None,
+      symbols: SymbolTable::new(),
+      watchpoints: Watchpoints::new(),
+      watching: false,
+      previous_pc: None,
+    }
+  }
+
+  /// Show addresses by name using the given symbols, and accept their names
+  /// in commands.
+  pub fn with_symbols(mut self, symbols: SymbolTable) -> Self {
+    self.symbols = symbols;
+    self
+  }
+
+  /// Use the given watchpoints, which the system has already placed in its
+  /// memory (e.g. around a single chip), rather than watching all of the CPU's
+  /// memory.
+  pub fn with_watchpoints(mut self, watchpoints: Watchpoints) -> Self {
+    self.watchpoints = watchpoints;
+    self.watching = true;
+    self
+  }
+
+  /// Format an address in hex, followed by its label if it has one.
+  fn describe(&self, address: u16) -> String {
+    match self.symbols.label(address) {
+      Some(label) => format!("${address:04X} ({label})"),
+      None => format!("${address:04X}"),
+    }
+  }
+
+  /// Show the current state and accept commands until execution resumes or
+  /// the user quits.
+  fn pause(&mut self, cpu: &mut Mos6502) -> DebugAction {
+    self.show_state(cpu);
+
+    loop {
+      let line = self.platform.input();
+
+      if line.trim().is_empty() {
+        continue;
+      }
+
+      match Command::parse_with_symbols(&line, &self.symbols) {
+        Ok(command) => {
+          if let Some(action) = self.execute(command, cpu) {
+            // Ignore writes made by the commands themselves (e.g. fill)
+            self.watchpoints.clear_hits();
+            return action;
+          }
+        }
+        Err(message) => self.platform.print(&format!("{message}\n")),
+      }
+    }
+  }
+
+  /// Execute a single command. Returns what to do next if the command ends the
+  /// pause, or `None` to read another command.
+  fn execute(&mut self, command: Command, cpu: &mut Mos6502) -> Option<DebugAction> {
+    match command {
+      Command::Help => self.platform.print(command::HELP),
+      Command::Registers => self.show_state(cpu),
+      Command::SetRegister(register, value) => {
+        let registers = &mut cpu.registers;
+        match register {
+          Register::A => registers.a = value as u8,
+          Register::X => registers.x = value as u8,
+          Register::Y => registers.y = value as u8,
+          Register::StackPointer => registers.sp.set(value as u8),
+          Register::ProgramCounter => registers.pc.load(value),
+          Register::Status => registers.sr.load(value as u8),
+        }
+        self.show_state(cpu);
+      }
+      Command::Step(count) => {
+        self.mode = RunMode::Step(count.saturating_sub(1));
+        return Some(DebugAction::Continue);
+      }
+      Command::StepOver => {
+        let pc = cpu.registers.pc.address();
+        self.mode = match cpu.peek(pc) {
+          JSR => RunMode::RunUntil(pc.wrapping_add(3)),
+          _ => RunMode::Step(0),
+        };
+        return Some(DebugAction::Continue);
+      }
+      Command::StepOut => {
+        self.mode = RunMode::StepOut(cpu.registers.sp.get());
+        self.previous_opcode = None;
+        return Some(DebugAction::Continue);
+      }
+      Command::Continue => {
+        self.mode = RunMode::Continue;
+        return Some(DebugAction::Continue);
+      }
+      Command::RunUntil(address) => {
+        self.mode = RunMode::RunUntil(address);
+        return Some(DebugAction::Continue);
+      }
+      Command::Break(address) => {
+        if !self.breakpoints.contains(&address) {
+          self.breakpoints.push(address);
+        }
+      }
+      Command::Delete(address) => {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|&b| b != address);
+        if self.breakpoints.len() == count {
+          self
+            .platform
+            .print(&format!("No breakpoint at ${address:04X}\n"));
+        }
+      }
+      Command::ListBreakpoints => {
+        if self.breakpoints.is_empty() {
+          self.platform.print("No breakpoints set\n");
+        }
+        for address in &self.breakpoints {
+          self
+            .platform
+            .print(&format!("{}\n", self.describe(*address)));
+        }
+      }
+      Command::Watch(watch) => {
+        if !self.watching {
+          cpu.watch_memory(self.watchpoints.clone());
+          self.watching = true;
+        }
+
+        let id = self.watchpoints.add(watch);
+        self.platform.print(&format!("Watchpoint {id} set\n"));
+      }
+      Command::Unwatch(id) => {
+        if !self.watchpoints.remove(id) {
+          self.platform.print(&format!("No watchpoint {id}\n"));
+        }
+      }
+      Command::ListWatchpoints => {
+        let watchpoints = self.watchpoints.list();
+        if watchpoints.is_empty() {
+          self.platform.print("No watchpoints set\n");
+        }
+        for (id, watch) in watchpoints {
+          let kind = match watch.kind {
+            WatchKind::Read => "read",
+            WatchKind::Write => "write",
+            WatchKind::Access => "access",
+          };
+          let mut line = format!("{id}: {kind} {}", self.describe(watch.start));
+          if watch.end != watch.start {
+            line += &format!(" to {}", self.describe(watch.end));
+          }
+          if let Some(value) = watch.value {
+            line += &format!(" = ${value:02X}");
+          }
+          self.platform.print(&format!("{line}\n"));
+        }
+      }
+      Command::Memory(start, end) => self.dump_memory(cpu, start, end),
+      Command::Fill(start, end, pattern) => {
+        for (address, value) in (start..=end).zip(pattern.iter().cycle()) {
+          cpu.write(address, *value);
+        }
+      }
+      Command::Disassemble(start) => {
+        let mut address = start.unwrap_or_else(|| cpu.registers.pc.address());
+        for _ in 0..DISASSEMBLY_LENGTH {
+          let (line, length) = self.disassemble(cpu, address);
+          self.platform.print(&line);
+          address = address.wrapping_add(length);
+        }
+      }
+      Command::Compare(start, end, destination) => {
+        let mut differences = 0;
+        for address in start..=end {
+          let other = destination.wrapping_add(address - start);
+          let (a, b) = (cpu.peek(address), cpu.peek(other));
+          if a != b {
+            self
+              .platform
+              .print(&format!("${address:04X}: {a:02X}  ${other:04X}: {b:02X}\n"));
+            differences += 1;
+          }
+        }
+        self
+          .platform
+          .print(&format!("{differences} difference(s) found\n"));
+      }
+      Command::Reset => {
+        cpu.reset();
+        self.mode = RunMode::Step(0);
+        self.show_state(cpu);
+      }
+      Command::Quit => return Some(DebugAction::Quit),
+    }
+
+    None
+  }
+
+  /// Print the registers and a disassembly of the next instruction.
+  fn show_state(&mut self, cpu: &mut Mos6502) {
+    let registers = &cpu.registers;
+    let status = registers.sr.get();
+
+    let flag_names = [
+      (flags::NEGATIVE, 'N'),
+      (flags::OVERFLOW, 'V'),
+      (flags::UNUSED, '-'),
+      (flags::BREAK, 'B'),
+      (flags::DECIMAL, 'D'),
+      (flags::INTERRUPT, 'I'),
+      (flags::ZERO, 'Z'),
+      (flags::CARRY, 'C'),
+    ];
+    let flag_string: String = flag_names
+      .iter()
+      .map(|&(flag, name)| if status & flag != 0 { name } else { '.' })
+      .collect();
+
+    let pc = registers.pc.address();
+    let summary = format!(
+      "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} SP:{:02X} P:{:02X} {}\n",
+      pc,
+      registers.a,
+      registers.x,
+      registers.y,
+      registers.sp.get(),
+      status,
+      flag_string
+    );
+
+    self.platform.print(&summary);
+    let (line, _) = self.disassemble(cpu, pc);
+    self.platform.print(&line);
+  }
+
+  /// Format a line of disassembly for the instruction at the given address,
+  /// preceded by a line with its symbol if it has one. Returns the text and
+  /// the length of the instruction.
+  fn disassemble(&self, cpu: &Mos6502, address: u16) -> (String, u16) {
+    let instruction = Instruction::decode(cpu.peek(address), cpu.get_variant());
+    let bytes: Vec<u8> = (0..instruction.length())
+      .map(|i| cpu.peek(address.wrapping_add(i)))
+      .collect();
+
+    let hex: Vec<String> = bytes.iter().map(|v| format!("{v:02X}")).collect();
+    let mut text = match self.symbols.name(address) {
+      Some(name) => format!("{name}:\n"),
+      None => String::new(),
+    };
+    text += &format!(
+      "${address:04X}: {:<8}  {}\n",
+      hex.join(" "),
+      instruction.format_with_symbols(address, &bytes[1..], &self.symbols)
+    );
+
+    (text, instruction.length())
+  }
+
+  /// Print a hex and ASCII dump of memory between two addresses (inclusive).
+  fn dump_memory(&mut self, cpu: &Mos6502, start: u16, end: u16) {
+    let mut address = start as u32;
+
+    while address <= end as u32 {
+      let line_end = (address + 15).min(end as u32);
+      let values: Vec<u8> = (address..=line_end).map(|a| cpu.peek(a as u16)).collect();
+
+      let hex: Vec<String> = values.iter().map(|v| format!("{v:02X}")).collect();
+      let ascii: String = values
+        .iter()
+        .map(|&v| match v {
+          0x20..=0x7E => v as char,
+          _ => '.',
+        })
+        .collect();
+
+      self
+        .platform
+        .print(&format!("${address:04X}: {:<47}  {ascii}\n", hex.join(" ")));
+      address += 16;
+    }
+  }
+}
+
+impl DebugHandler for Debugger {
+  fn before_instruction(&mut self, cpu: &mut Mos6502) -> DebugAction {
+    let pc = cpu.registers.pc.address();
+
+    let stop = match self.mode {
+      RunMode::Step(0) => true,
+      RunMode::Step(remaining) => {
+        self.mode = RunMode::Step(remaining - 1);
+        false
+      }
+      RunMode::Continue => false,
+      RunMode::RunUntil(address) => pc == address,
+      RunMode::StepOut(stack_pointer) => {
+        let returned = matches!(self.previous_opcode, Some(RTS) | Some(RTI))
+          && cpu.registers.sp.get() > stack_pointer;
+        self.previous_opcode = Some(cpu.peek(pc));
+        returned
+      }
+    };
+
+    let previous_pc = self.previous_pc.replace(pc);
+
+    if let Some(hit) = self.watchpoints.take_hit() {
+      let access = match hit.kind {
+        WatchKind::Read => format!("read ${:02X} from", hit.value),
+        _ => format!("wrote ${:02X} to", hit.value),
+      };
+      let by = previous_pc.map_or(String::new(), |address| {
+        format!(" at {}", self.describe(address))
+      });
+      self.platform.print(&format!(
+        "Watchpoint {}: {access} {}{by}\n",
+        hit.id,
+        self.describe(hit.address)
+      ));
+
+      // Any further hits from the same instruction are not shown
+      self.watchpoints.clear_hits();
+      self.pause(cpu)
+    } else if self.breakpoints.contains(&pc) {
+      self
+        .platform
+        .print(&format!("Breakpoint at {}\n", self.describe(pc)));
+      self.pause(cpu)
+    } else if stop || self.platform.debug_break_requested() {
+      self.pause(cpu)
+    } else {
+      DebugAction::Continue
+    }
+  }
+
+  fn on_error(&mut self, cpu: &mut Mos6502, error: CpuError) -> DebugAction {
+    self.platform.print(&format!("CPU halted: {error}\n"));
+    self.pause(cpu)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cpu::{mos6502::Mos6502Variant, Cpu};
+  use crate::keyboard::{KeyPosition, KeyState, VirtualKey};
+  use crate::memory::{BlockMemory, Memory};
+  use crate::platform::{JoystickState, WindowConfig};
+  use std::collections::VecDeque;
+  use std::sync::Mutex;
+
+  /// A platform which feeds a fixed script of commands to the debugger.
+  struct ScriptedProvider {
+    commands: Mutex<VecDeque<&'static str>>,
+    output: Mutex<String>,
+  }
+
+  impl ScriptedProvider {
+    fn new(commands: &[&'static str]) -> Arc<Self> {
+      Arc::new(Self {
+        commands: Mutex::new(commands.iter().copied().collect()),
+        output: Mutex::new(String::new()),
+      })
+    }
+  }
+
+  impl PlatformProvider for ScriptedProvider {
+    fn request_window(&self, _config: WindowConfig) {}
+
+    fn get_key_state(&self) -> KeyState<KeyPosition> {
+      KeyState::new()
+    }
+
+    fn get_virtual_key_state(&self) -> KeyState<VirtualKey> {
+      KeyState::new()
+    }
+
+    fn get_joystick_state(&self) -> JoystickState {
+      JoystickState::empty()
+    }
+
+    fn debug_break_requested(&self) -> bool {
+      false
+    }
+
+    fn print(&self, text: &str) {
+      self.output.lock().unwrap().push_str(text);
+    }
+
+    fn input(&self) -> String {
+      self
+        .commands
+        .lock()
+        .unwrap()
+        .pop_front()
+        .expect("debugger asked for more input than scripted")
+        .to_owned()
+    }
+
+    fn random(&self) -> u8 {
+      0
+    }
+  }
+
+  /// Build a CPU running the given program at 0x0200, with a debugger
+  /// attached which executes the given commands.
+  fn setup(program: &[u8], commands: &[&'static str]) -> Mos6502 {
+    let mut memory = BlockMemory::ram(0x10000);
+    for (i, byte) in program.iter().enumerate() {
+      memory.write(0x0200 + i as u16, *byte);
+    }
+
+    let mut cpu = Mos6502::new(memory, Mos6502Variant::NMOS);
+    cpu.registers.pc.load(0x0200);
+
+    cpu.attach_debugger(Box::new(Debugger::new(ScriptedProvider::new(commands))));
+    cpu
+  }
+
+  #[test]
+  fn test_step_and_registers() {
+    // LDA #$42; TAX; INX
+    let mut cpu = setup(&[0xA9, 0x42, 0xAA, 0xE8], &["r y 7", "s 2", "s"]);
+
+    cpu.tick().unwrap();
+    cpu.tick().unwrap();
+    assert_eq!(0x07, cpu.registers.y);
+    assert_eq!(0x42, cpu.registers.x);
+
+    cpu.tick().unwrap();
+    assert_eq!(0x43, cpu.registers.x);
+  }
+
+  #[test]
+  fn test_breakpoints_and_subroutines() {
+    // 0200: JSR $0210; JSR $0210; NOP
+    // 0210: INX; INX; RTS
+    let mut program = vec![0xEA; 0x20];
+    program[0..6].copy_from_slice(&[0x20, 0x10, 0x02, 0x20, 0x10, 0x02]);
+    program[0x10..0x13].copy_from_slice(&[0xE8, 0xE8, 0x60]);
+
+    let script = ["b 0211", "c", "ret", "del 0211", "n", "s"];
+    let mut cpu = setup(&program, &script);
+
+    // continue until the breakpoint inside the subroutine
+    cpu.tick().unwrap();
+    cpu.tick().unwrap();
+    assert_eq!(0x0211, cpu.registers.pc.address());
+
+    // step out of the subroutine, back to the caller
+    cpu.tick().unwrap();
+    cpu.tick().unwrap();
+    assert_eq!(0x0203, cpu.registers.pc.address());
+    assert_eq!(2, cpu.registers.x);
+
+    // step over the second call in one go
+    for _ in 0..4 {
+      cpu.tick().unwrap();
+    }
+    assert_eq!(0x0206, cpu.registers.pc.address());
+    assert_eq!(4, cpu.registers.x);
+
+    // then stop before the following instruction
+    cpu.tick().unwrap();
+    assert_eq!(0x0207, cpu.registers.pc.address());
+  }
+
+  #[test]
+  fn test_fill_and_compare() {
+    let mut cpu = setup(&[0xEA], &["f 1000 1007 aa 55", "c"]);
+    cpu.tick().unwrap();
+
+    assert_eq!(0xAA, cpu.read(0x1000));
+    assert_eq!(0x55, cpu.read(0x1001));
+    assert_eq!(0x55, cpu.read(0x1007));
+    assert_eq!(0x00, cpu.read(0x1008));
+  }
+
+  #[test]
+  fn test_quit() {
+    // INX; INX
+    let mut cpu = setup(&[0xE8, 0xE8], &["s", "q"]);
+
+    cpu.tick().unwrap();
+    assert_eq!(Err(CpuError::Quit), cpu.tick());
+    assert_eq!(1, cpu.registers.x);
+
+    // the CPU stays halted, without asking the debugger again
+    assert_eq!(Err(CpuError::Quit), cpu.tick());
+  }
+
+  #[test]
+  fn test_symbols() {
+    // JSR sub
+    let cpu = setup(&[0x20, 0x10, 0x02], &[]);
+
+    let mut symbols = SymbolTable::new();
+    symbols.insert("main", 0x0200);
+    symbols.insert("sub", 0x0210);
+    let debugger = Debugger::new(ScriptedProvider::new(&[])).with_symbols(symbols);
+
+    assert_eq!(
+      ("main:\n$0200: 20 10 02  JSR sub\n".to_owned(), 3),
+      debugger.disassemble(&cpu, 0x0200)
+    );
+    assert_eq!("$0212 (sub+2)", debugger.describe(0x0212));
+  }
+
+  #[test]
+  fn test_watchpoints() {
+    // LDA #$05; STA $10; INC $10; NOP
+    let program = [0xA9, 0x05, 0x85, 0x10, 0xE6, 0x10, 0xEA];
+    let mut memory = BlockMemory::ram(0x10000);
+    for (i, byte) in program.iter().enumerate() {
+      memory.write(0x0200 + i as u16, *byte);
+    }
+
+    let mut cpu = Mos6502::new(memory, Mos6502Variant::NMOS);
+    cpu.registers.pc.load(0x0200);
+
+    let platform = ScriptedProvider::new(&["watch 10 = 6", "c", "c"]);
+    cpu.attach_debugger(Box::new(Debugger::new(platform.clone())));
+
+    // the first write doesn't match the value, but the second does
+    for _ in 0..3 {
+      cpu.tick().unwrap();
+    }
+    assert_eq!(1, platform.commands.lock().unwrap().len());
+
+    cpu.tick().unwrap();
+    assert!(platform.commands.lock().unwrap().is_empty());
+    assert!(platform
+      .output
+      .lock()
+      .unwrap()
+      .contains("Watchpoint 1: wrote $06 to $0010 at $0204\n"));
+  }
+}
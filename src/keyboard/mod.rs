@@ -10,6 +10,7 @@ mod symbols;
 /// Keys which can be pressed on a virtual / emulated keyboard.
 mod virtualkey;
 
+use serde::{Deserialize, Serialize};
 use std::ops::BitOr;
 
 pub use positions::KeyPosition;
@@ -18,7 +19,7 @@ pub use virtualkey::VirtualKey;
 
 /// A set of keys that are currently pressed.
 /// Parameter `T` is the type of the key symbols.
-#[derive(Default, Debug, Clone, PartialEq)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct KeyState<T: PartialEq + Clone> {
   pressed: Vec<T>,
 }
@@ -88,10 +89,12 @@ pub trait KeyAdapter<F: PartialEq + Clone, T: PartialEq + Clone> {
 
 /// Represents different approaches to mapping key states, to allow the user to
 /// indicate their preference.
+#[derive(Default)]
 pub enum KeyMappingStrategy {
   /// Preserve physical keys one-to-one. This is most compatible, but the
   /// resulting mapping may be less intuitive. For instance, symbols may
   /// not be mapped as expected.
+  #[default]
   Physical,
 
   /// Preserve symbols one-to-one. This is more intuitive, but may cause issues
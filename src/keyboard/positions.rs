@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 /// A representation for a position on a modern keyboard.
 /// Source: <https://en.wikipedia.org/wiki/Keyboard_layout#/media/File:Qwerty.svg>
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum KeyPosition {
   Escape,
 
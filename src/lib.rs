@@ -1,10 +1,13 @@
 #![doc = include_str!("../README.md")]
 #![allow(clippy::new_without_default)]
 
-/// A [`cpu::Cpu`] represents a processor and associated memory. Specific implementations include the [`cpu::mos6502::Mos6502`], which represents the MOS 6502 or its variants (e.g. 65C02).
+/// A [`cpu::Cpu`] represents a processor and associated memory. Specific implementations include the [`cpu::mos6502::Mos6502`], which represents the MOS 6502 or its variants (e.g. 65C02), and the [`cpu::w65c816::W65C816`], which represents the WDC 65C816 with its 24-bit address space (see [`memory::LongMemory`]), and the [`cpu::z80::Z80`], which has a separate I/O port space (see [`memory::IoSpace`]).
 pub mod cpu;
 
-/// A [`memory::Memory`] implementation can be read from and written to, but it can also be polled for interrupts. This is used for the PIA, VIA, and other chips that interface over memory but also trigger interrupts. The [`memory`] module provides implementations for various types of memory and other memory-mapped devices. Mappings are handled using [`memory::BranchMemory`].
+/// An interactive machine-language monitor is provided by [`debugger::Debugger`]. Once attached to a system's CPU, it can pause execution at breakpoints, when watched memory is accessed, or when the user presses a hotkey, and then accepts commands to step through code, inspect and edit registers, and dump, fill, or compare memory. It communicates with the user through the [`platform::PlatformProvider`]'s `print` and `input` methods. Alternatively, a `debugger::gdb::GdbServer` allows an external debugger to control the CPU using the GDB Remote Serial Protocol. Both implement the [`debugger::DebugHandler`] trait, which the CPU consults before each instruction.
+pub mod debugger;
+
+/// A [`memory::Memory`] implementation can be read from and written to, but it can also be polled for interrupts. This is used for the PIA, VIA, and other chips that interface over memory but also trigger interrupts. Since reading a chip's registers can have side effects, every implementation also provides `peek`, which returns the same value without them, for use by the debugger, trace handlers, and renderers. The [`memory`] module provides implementations for various types of memory and other memory-mapped devices. Mappings are handled using [`memory::BranchMemory`]. Chips which only decode some of their address lines are wrapped in a [`memory::MirroredMemory`], which repeats their registers across the region they are mapped to. Addresses which nothing is connected to can read back the last value on the data bus, as on real hardware (see [`memory::DataBus`]); the Commodore systems enable this with the `open_bus` option of their configuration. RAM starts out holding a [`memory::RamPattern`], which is zero by default but can be set to the pattern of the real machine or to seeded noise, and is restored whenever the system is reset. Any region can be wrapped in a [`memory::WatchMemory`] to check its accesses against a set of [`memory::Watchpoints`], which the debugger uses to stop when memory is read or written.
 ///
 pub mod memory;
 
@@ -21,17 +24,25 @@ pub mod memory;
 pub mod keyboard;
 
 /// A [`platform::Platform`] consumes a system and runs it. Platforms provide access to the video output, keyboard input, system random number generator, and other details via a [`platform::PlatformProvider`]. Some platforms run synchronously (taking over the thread) while others run asynchronously with the help of an event loop (such as when compiling to WASM). Platforms are defined in the [`platform`] module.
-
-/// Currently, available platforms include `TextPlatform` for simple headless text-based operation, `WinitPlatform` for a graphical window on a desktop environment, and `CanvasPlatform` for drawing to a `<canvas>` element on the web. In the future, platforms for mobile apps are planned, in addition to a platform for running on a microcontroller (e.g. the RP2040).
+///
+/// Currently, available platforms include `TextPlatform` for simple headless text-based operation, `HeadlessPlatform` for running graphical systems in automated tests (rendering to an in-memory framebuffer, with scripted input and PNG screenshots), `WinitPlatform` for a graphical window on a desktop environment, and `CanvasPlatform` for drawing to a `<canvas>` element on the web. The desktop platforms can record what the system shows to a GIF or Y4M video with a `VideoRecorder`. In the future, platforms for mobile apps are planned, in addition to a platform for running on a microcontroller (e.g. the RP2040).
 pub mod platform;
 
 /// ROM file loading and unloading is different on different platforms: desktop platforms typically load ROMs from a file, while WebAssembly platforms need to load ROMs from a `Uint8Array`. ROM file definition and loading is handled in the [`roms`] module, with specific [`roms::DiskLoadable`] and `roms::JsValueLoadable` traits for these two cases. Loaded ROMs are represented with a [`roms::RomFile`] object, which can be passed to [`memory::BlockMemory::from_file`].
 pub mod roms;
 
+/// A running system can be saved to a snapshot and later restored with [`systems::System::save_state`] and [`systems::System::load_state`]. Each CPU, memory, and chip writes its state in order to a [`state::StateWriter`] and reads it back from a [`state::StateReader`], using `serde` with a compact binary format. Stateless memory such as ROM writes nothing, so save states stay small and do not contain copies of the system's ROMs. A [`state::RewindBuffer`] keeps a bounded history of recent save states, storing each as the changes from the next, to step a system back in time.
+pub mod state;
+
+/// Addresses can be given names with a [`symbols::SymbolTable`], read from the symbol files written by VICE, ca65/ld65, or vasm. Tables for the KERNAL and BASIC ROMs of the PET, VIC-20, and C64 are built in. Traces, the profiler, and the debugger use them to show `CHROUT+3` in place of `$FFD5`, and the debugger accepts symbol names wherever it takes an address.
+pub mod symbols;
+
 /// A system is created with some roms, configuration, and platform. System instantiation is handled with the [`systems::BuildableSystem`] trait, which is generic over these parameters. For instance, the `build` implementation on [`systems::pet::PetSystem`] takes in [`systems::pet::PetSystemRoms`], [`systems::pet::PetSystemConfig`], and an `Arc<dyn PlatformProvider>`.
 pub mod systems;
 
 /// Tools to trace the log the state of the system as it runs (e.g., to a file). This is useful for debugging.
+///
+/// The [`trace::profile::ProfileTraceHandler`] uses these traces to measure the cycles spent at each address and in each subroutine, producing a hotspot report and call stacks for flamegraphs, and the [`trace::coverage::CoverageTraceHandler`] records which instructions and branches executed, as an lcov report on the assembly source.
 pub mod trace;
 
 mod time;
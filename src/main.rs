@@ -1,33 +1,41 @@
 #[cfg(not(target_arch = "wasm32"))]
 use libnoentiendo::{
   keyboard::KeyMappingStrategy,
-  platform::{SyncPlatform, TextPlatform, WinitPlatform},
+  platform::{
+    HeadlessPlatform, InputMovie, InputScript, MovieSystem, PlatformProvider, RecordingProvider,
+    ReplayProvider, SyncPlatform, TextPlatform, WinitPlatform,
+  },
   roms::DiskLoadable,
   systems::{
-    basic::BasicSystem, c64::C64System, c64::C64SystemConfig, c64::C64SystemRoms,
-    easy::Easy6502System, klaus::KlausSystem, pet::PetSystem, pet::PetSystemConfig,
-    pet::PetSystemRoms, vic::Vic20System, vic::Vic20SystemConfig, vic::Vic20SystemRoms,
-    BuildableSystem,
+    basic::BasicSystem, basic816::Basic816System, c64::C64System, c64::C64SystemConfig,
+    c64::C64SystemRoms, easy::Easy6502System, klaus::KlausSystem, pet::PetSystem,
+    pet::PetSystemConfig, pet::PetSystemRoms, vic::Vic20RamExpansion, vic::Vic20System,
+    vic::Vic20SystemConfig, vic::Vic20SystemRoms, zex::ZexSystem, BuildableSystem,
   },
 };
 
 #[cfg(not(target_arch = "wasm32"))]
-use clap::{Parser, ValueEnum};
+use clap::{CommandFactory, ErrorKind, Parser, Subcommand, ValueEnum};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::{atomic::AtomicU64, Arc};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum SystemArg {
   Basic,
+  Basic816,
   Easy,
   Klaus,
   Pet,
   Vic,
   C64,
+  Zex,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum PlatformArg {
   Text,
   Winit,
+  Headless,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -36,15 +44,115 @@ enum KeyMappingArg {
   Physical,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum RamExpansionArg {
+  None,
+  #[clap(name = "3k")]
+  Ram3K,
+  #[clap(name = "8k")]
+  Ram8K,
+  #[clap(name = "16k")]
+  Ram16K,
+  #[clap(name = "24k")]
+  Ram24K,
+  #[clap(name = "35k")]
+  Ram35K,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum TraceFormatArg {
+  Compact,
+  Vice,
+  Nestest,
+  Binary,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum VariantArg {
+  Nmos,
+  Cmos,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_address(value: &str) -> Result<u16, String> {
+  let digits = value
+    .strip_prefix('$')
+    .or_else(|| value.strip_prefix("0x"))
+    .unwrap_or(value);
+
+  u16::from_str_radix(digits, 16).map_err(|_| format!("invalid hexadecimal address: {value}"))
+}
+
+/// The contents of RAM at power-on, as given on the command line.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum RamPatternArg {
+  /// Every byte is zero.
+  Zero,
+
+  /// The pattern of the real machine.
+  System,
+
+  /// Noise from the given seed, or from a new seed if none was given.
+  Random(Option<u64>),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_ram_pattern(value: &str) -> Result<RamPatternArg, String> {
+  match value.split_once(':') {
+    None if value == "zero" => Ok(RamPatternArg::Zero),
+    None if value == "system" => Ok(RamPatternArg::System),
+    None if value == "random" => Ok(RamPatternArg::Random(None)),
+    Some(("random", seed)) => seed
+      .parse()
+      .map(|seed| RamPatternArg::Random(Some(seed)))
+      .map_err(|_| format!("invalid seed: {seed}")),
+    _ => Err(format!(
+      "expected zero, system, random or random:SEED, got {value}"
+    )),
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_screenshot(value: &str) -> Result<(u64, String), String> {
+  let (frame, path) = value
+    .split_once(':')
+    .ok_or_else(|| format!("expected FRAME:PATH, got {value}"))?;
+  let frame = frame
+    .parse()
+    .map_err(|_| format!("invalid frame number: {frame}"))?;
+
+  Ok((frame, path.to_owned()))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Subcommand, Debug)]
+enum Command {
+  /// Disassemble a ROM file, as if it were loaded at the given address.
+  Disasm {
+    #[clap(value_parser)]
+    rom_path: String,
+
+    #[clap(short, long, value_parser = parse_address)]
+    address: u16,
+
+    #[clap(short, long, value_parser, default_value = "nmos")]
+    variant: VariantArg,
+  },
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+  #[clap(subcommand)]
+  command: Option<Command>,
+
   #[clap(short, long, value_parser, default_value = "")]
   rom_path: String,
 
-  #[clap(short, long, value_parser)]
-  system: SystemArg,
+  #[clap(short, long, value_parser, required = true)]
+  system: Option<SystemArg>,
 
   #[clap(short, long, value_parser, default_value = "text")]
   platform: PlatformArg,
@@ -52,21 +160,204 @@ struct Args {
   #[clap(short, long, value_parser, default_value = "symbolic")]
   key_mapping: KeyMappingArg,
 
+  /// The RAM cartridge plugged into the VIC-20.
+  #[clap(long, value_parser, default_value = "none")]
+  ram_expansion: RamExpansionArg,
+
   #[clap(short, long, value_parser, default_value = "false")]
   trace: bool,
+
+  #[clap(long, value_parser, default_value = "compact")]
+  trace_format: TraceFormatArg,
+
+  /// Profile the CPU, writing a report to ./cpu.profile and folded call
+  /// stacks (for flamegraphs) to ./cpu.folded when the system exits.
+  #[clap(long, value_parser, default_value = "false", conflicts_with = "trace")]
+  profile: bool,
+
+  /// Record which instructions and branches execute, and write them to
+  /// ./lcov.info when the system exits, mapped to source lines with the given
+  /// vasm listing or ca65 debug info file.
+  #[clap(long, value_parser, conflicts_with_all = &["trace", "profile"])]
+  coverage: Option<String>,
+
+  #[clap(short, long, value_parser, default_value = "false")]
+  debug: bool,
+
+  #[clap(long, value_parser, conflicts_with = "debug")]
+  gdb_port: Option<u16>,
+
+  #[clap(long, value_parser, default_value = "false")]
+  cycle_stepped: bool,
+
+  /// Read the last value on the data bus from unconnected addresses on the
+  /// PET, VIC-20 and C64, rather than zero.
+  #[clap(long, value_parser, default_value = "false")]
+  open_bus: bool,
+
+  /// The contents of RAM at power-on on the PET, VIC-20 and C64: zero, the
+  /// pattern of the real machine (system), or noise (random or random:SEED).
+  #[clap(long, value_parser = parse_ram_pattern, default_value = "zero")]
+  ram_pattern: RamPatternArg,
+
+  #[clap(long, value_parser)]
+  load_state: Option<String>,
+
+  #[clap(long, value_parser, conflicts_with = "replay")]
+  record: Option<String>,
+
+  #[clap(long, value_parser)]
+  replay: Option<String>,
+
+  /// Stop the headless platform after this many frames.
+  #[clap(long, value_parser, conflicts_with = "cycles")]
+  frames: Option<u64>,
+
+  /// Stop the headless platform after this many CPU cycles.
+  #[clap(long, value_parser)]
+  cycles: Option<u64>,
+
+  /// Play back the input script in this file on the headless platform.
+  #[clap(long, value_parser)]
+  script: Option<String>,
+
+  /// Save a screenshot on the headless platform, given as FRAME:PATH.
+  #[clap(long, value_parser = parse_screenshot)]
+  screenshot: Vec<(u64, String)>,
+
+  /// Record a video (.gif or .y4m) of the system from when it starts.
+  #[clap(long, value_parser)]
+  record_video: Option<String>,
+
+  /// Name addresses in traces, profiles and the debugger with the symbols in
+  /// this VICE label file, ca65 debug info file or vasm listing. May be given
+  /// more than once. The ROM symbols of the PET, VIC-20 and C64 are included.
+  #[clap(long, value_parser)]
+  symbols: Vec<String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn disassemble(rom_path: &str, load_address: u16, variant: VariantArg) {
+  use libnoentiendo::cpu::mos6502::{disasm::disassemble, Mos6502Variant};
+
+  let variant = match variant {
+    VariantArg::Nmos => Mos6502Variant::NMOS,
+    VariantArg::Cmos => Mos6502Variant::CMOS,
+  };
+
+  let data = libnoentiendo::roms::RomFile::from_file(rom_path).get_data();
+  let mut offset = 0;
+
+  while offset < data.len() {
+    let address = load_address.wrapping_add(offset as u16);
+    let (text, length) = disassemble(address, &data[offset..], variant);
+    let end = (offset + length as usize).min(data.len());
+
+    let hex: Vec<String> = data[offset..end]
+      .iter()
+      .map(|v| format!("{v:02X}"))
+      .collect();
+    println!("{:04X}  {:<8}  {}", address, hex.join(" "), text);
+
+    offset += length as usize;
+  }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 fn main() {
   use libnoentiendo::{
-    cpu::mos6502::Mos6502Variant, systems::klaus::KlausSystemConfig, trace::file::FileTraceHandler,
+    cpu::mos6502::Mos6502Variant,
+    debugger::{gdb::GdbServer, Debugger},
+    memory::RamPattern,
+    symbols::{c64_symbols, pet_symbols, vic20_symbols, SymbolTable},
+    systems::{klaus::KlausSystemConfig, zex::ZexSystemConfig},
+    trace::coverage::{CoverageTraceHandler, SourceMap},
+    trace::file::{FileTraceHandler, TraceFormat},
+    trace::profile::ProfileTraceHandler,
   };
 
   let args = Args::parse();
 
+  if let Some(Command::Disasm {
+    rom_path,
+    address,
+    variant,
+  }) = args.command
+  {
+    disassemble(&rom_path, address, variant);
+    return;
+  }
+
+  // Tracing, debugging and cycle stepping are only supported by the 6502 systems
+  if let Some(system @ (SystemArg::Basic816 | SystemArg::Zex)) = args.system {
+    let flags = [
+      ("--cycle-stepped", args.cycle_stepped),
+      ("--trace", args.trace),
+      ("--profile", args.profile),
+      ("--coverage", args.coverage.is_some()),
+      ("--debug", args.debug),
+      ("--gdb-port", args.gdb_port.is_some()),
+    ];
+
+    if let Some((flag, _)) = flags.iter().find(|(_, given)| *given) {
+      Args::command()
+        .error(
+          ErrorKind::ArgumentConflict,
+          format!(
+            "{flag} is not supported by the {} system",
+            system.to_possible_value().unwrap().get_name()
+          ),
+        )
+        .exit();
+    }
+  }
+
   let mut platform: Box<dyn SyncPlatform> = match args.platform {
     PlatformArg::Text => Box::new(TextPlatform::new()),
-    PlatformArg::Winit => Box::new(WinitPlatform::new()),
+    PlatformArg::Winit => {
+      let mut winit = WinitPlatform::new();
+
+      if let Some(path) = &args.load_state {
+        winit = winit.with_state_file(path);
+      }
+
+      if let Some(path) = &args.record_video {
+        winit = winit.with_video_file(path);
+      }
+
+      Box::new(winit)
+    }
+    PlatformArg::Headless => {
+      let mut headless = HeadlessPlatform::new();
+
+      if let Some(frames) = args.frames {
+        headless = headless.with_frames(frames);
+      }
+
+      if let Some(cycles) = args.cycles {
+        headless = headless.with_cycles(cycles);
+      }
+
+      if let Some(path) = &args.script {
+        let script = InputScript::load(path)
+          .unwrap_or_else(|error| panic!("Failed to load input script: {error}"));
+        headless = headless.with_script(script);
+      }
+
+      for (frame, path) in &args.screenshot {
+        headless = headless.with_screenshot(*frame, path);
+      }
+
+      if let Some(path) = &args.load_state {
+        headless = headless.with_state_file(path);
+      }
+
+      if let Some(path) = &args.record_video {
+        headless = headless.with_video_file(path);
+      }
+
+      Box::new(headless)
+    }
   };
 
   let romfile = match args.rom_path.as_str() {
@@ -79,39 +370,172 @@ fn main() {
     KeyMappingArg::Physical => KeyMappingStrategy::Physical,
   };
 
-  let mut system = match args.system {
-    SystemArg::Basic => BasicSystem::build(romfile.unwrap(), (), platform.provider()),
-    SystemArg::Easy => Easy6502System::build(romfile.unwrap(), (), platform.provider()),
+  // When recording or replaying, the system's inputs pass through a provider
+  // which tags them with the cycle count in this clock
+  let clock = Arc::new(AtomicU64::new(0));
+  let mut recorder = None;
+
+  // Providers are shared with the system through an Arc, even on one thread
+  #[allow(clippy::arc_with_non_send_sync)]
+  let provider: Arc<dyn PlatformProvider> = match (&args.record, &args.replay) {
+    (Some(_), _) => {
+      let provider = Arc::new(RecordingProvider::new(platform.provider(), clock.clone()));
+      recorder = Some(provider.clone());
+      provider
+    }
+    (None, Some(path)) => Arc::new(ReplayProvider::new(
+      platform.provider(),
+      InputMovie::load(path).unwrap_or_else(|error| panic!("Failed to load input movie: {error}")),
+      clock.clone(),
+    )),
+    (None, None) => platform.provider(),
+  };
+
+  // The pattern for every system, or None to use each system's own
+  let ram_pattern = match args.ram_pattern {
+    RamPatternArg::Zero => Some(RamPattern::Zero),
+    RamPatternArg::System => None,
+    RamPatternArg::Random(Some(seed)) => Some(RamPattern::Random(seed)),
+    RamPatternArg::Random(None) => {
+      // Report the seed, so that the run can be repeated
+      let seed = rand::random();
+      println!("RAM pattern seed: {seed} (repeat with --ram-pattern random:{seed})");
+      Some(RamPattern::Random(seed))
+    }
+  };
+  let ram_pattern = |system: RamPattern| ram_pattern.unwrap_or(system);
+
+  let mut system = match args.system.unwrap() {
+    SystemArg::Basic => BasicSystem::build(romfile.unwrap(), (), provider.clone()),
+    SystemArg::Basic816 => Basic816System::build(romfile.unwrap(), (), provider.clone()),
+    SystemArg::Easy => Easy6502System::build(romfile.unwrap(), (), provider.clone()),
     SystemArg::Klaus => KlausSystem::build(
       romfile.unwrap(),
       KlausSystemConfig {
         pc_report: None,
         variant: Mos6502Variant::NMOS,
       },
-      platform.provider(),
+      provider.clone(),
     ),
     SystemArg::Pet => PetSystem::build(
       PetSystemRoms::from_disk(),
-      PetSystemConfig { mapping },
-      platform.provider(),
+      PetSystemConfig {
+        mapping,
+        open_bus: args.open_bus,
+        ram_pattern: ram_pattern(PetSystem::RAM_PATTERN),
+      },
+      provider.clone(),
     ),
     SystemArg::Vic => Vic20System::build(
       Vic20SystemRoms::from_disk(match romfile {
         Some(_) => Some(args.rom_path.as_str()),
         None => None,
       }),
-      Vic20SystemConfig { mapping },
-      platform.provider(),
+      Vic20SystemConfig {
+        mapping,
+        ram_expansion: match args.ram_expansion {
+          RamExpansionArg::None => Vic20RamExpansion::None,
+          RamExpansionArg::Ram3K => Vic20RamExpansion::Ram3K,
+          RamExpansionArg::Ram8K => Vic20RamExpansion::Ram8K,
+          RamExpansionArg::Ram16K => Vic20RamExpansion::Ram16K,
+          RamExpansionArg::Ram24K => Vic20RamExpansion::Ram24K,
+          RamExpansionArg::Ram35K => Vic20RamExpansion::Ram35K,
+        },
+        open_bus: args.open_bus,
+        ram_pattern: ram_pattern(Vic20System::RAM_PATTERN),
+      },
+      provider.clone(),
     ),
     SystemArg::C64 => C64System::build(
       C64SystemRoms::from_disk(),
-      C64SystemConfig { mapping },
-      platform.provider(),
+      C64SystemConfig {
+        mapping,
+        open_bus: args.open_bus,
+        ram_pattern: ram_pattern(C64System::RAM_PATTERN),
+      },
+      provider.clone(),
+    ),
+    SystemArg::Zex => ZexSystem::build(
+      romfile.unwrap(),
+      ZexSystemConfig { output: None },
+      provider.clone(),
     ),
   };
 
+  if args.record.is_some() || args.replay.is_some() {
+    let mut movie_system = MovieSystem::new(system, clock);
+
+    if let (Some(recorder), Some(path)) = (recorder, &args.record) {
+      movie_system = movie_system.with_recording(recorder, path);
+    }
+
+    system = Box::new(movie_system);
+  }
+
+  system.set_cycle_stepped(args.cycle_stepped);
+
+  // The winit and headless platforms reset the system when they start, so
+  // they load the state themselves afterwards
+  if let (Some(path), PlatformArg::Text) = (&args.load_state, args.platform) {
+    let data = std::fs::read(path).expect("Failed to read save state");
+    system
+      .load_state(&data)
+      .unwrap_or_else(|error| panic!("Failed to load save state: {error}"));
+  }
+
+  // Symbol files are loaded after the ROM symbols, so their names take
+  // precedence
+  let mut symbols = match args.system.unwrap() {
+    SystemArg::Pet => pet_symbols(),
+    SystemArg::Vic => vic20_symbols(),
+    SystemArg::C64 => c64_symbols(),
+    _ => SymbolTable::new(),
+  };
+
+  for path in &args.symbols {
+    let table =
+      SymbolTable::load(path).unwrap_or_else(|error| panic!("Failed to load {path}: {error}"));
+    symbols.merge(table);
+  }
+
   if args.trace {
-    system.attach_trace_handler(Box::new(FileTraceHandler::new("./cpu.trace".to_owned())));
+    let format = match args.trace_format {
+      TraceFormatArg::Compact => TraceFormat::Compact,
+      TraceFormatArg::Vice => TraceFormat::Vice,
+      TraceFormatArg::Nestest => TraceFormat::Nestest,
+      TraceFormatArg::Binary => TraceFormat::Binary,
+    };
+
+    system.attach_trace_handler(Box::new(
+      FileTraceHandler::new("./cpu.trace".to_owned(), format).with_symbols(symbols.clone()),
+    ));
+  }
+
+  if args.profile {
+    system.attach_trace_handler(Box::new(
+      ProfileTraceHandler::new("./cpu.profile".to_owned(), "./cpu.folded".to_owned())
+        .with_symbols(symbols.clone()),
+    ));
+  }
+
+  if let Some(path) = &args.coverage {
+    let sources =
+      SourceMap::load(path).unwrap_or_else(|error| panic!("Failed to load {path}: {error}"));
+    system.attach_trace_handler(Box::new(CoverageTraceHandler::new(
+      sources,
+      "./lcov.info".to_owned(),
+    )));
+  }
+
+  if args.debug {
+    system.attach_debugger(Box::new(
+      Debugger::new(platform.provider()).with_symbols(symbols),
+    ));
+  }
+
+  if let Some(port) = args.gdb_port {
+    let server = GdbServer::listen(port).expect("Failed to start GDB server");
+    system.attach_debugger(Box::new(server));
   }
 
   platform.run(system);
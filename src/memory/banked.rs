@@ -1,6 +1,7 @@
 use std::{cell::Cell, rc::Rc};
 
 use super::{ActiveInterrupt, Memory};
+use crate::state::{StateError, StateReader, StateWriter};
 
 /// Represents the memory banking features found in the Commodore 64 and other
 /// devices. Multiple memory implementations are all mapped to the same
@@ -35,6 +36,13 @@ impl Memory for BankedMemory {
     }
   }
 
+  fn peek(&self, address: u16) -> u8 {
+    match self.banks.get(self.active.get()) {
+      Some(memory) => memory.peek(address),
+      None => panic!("Invalid bank {} selected", self.active.get()),
+    }
+  }
+
   fn write(&mut self, address: u16, value: u8) {
     match self.banks.get_mut(self.active.get()) {
       Some(memory) => memory.write(address, value),
@@ -67,4 +75,32 @@ impl Memory for BankedMemory {
 
     highest
   }
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    state.write(&self.active.get())?;
+
+    for memory in &self.banks {
+      memory.save_state(state)?;
+    }
+
+    Ok(())
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    let active: usize = state.read()?;
+
+    if active >= self.banks.len() {
+      return Err(StateError::Mismatch(format!(
+        "invalid bank {active} selected"
+      )));
+    }
+
+    self.active.set(active);
+
+    for memory in &mut self.banks {
+      memory.load_state(state)?;
+    }
+
+    Ok(())
+  }
 }
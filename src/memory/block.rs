@@ -1,5 +1,50 @@
 use crate::memory::{ActiveInterrupt, Memory};
 use crate::roms::RomFile;
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// The contents of RAM when it is powered on (or reset).
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum RamPattern {
+  /// Every byte is zero.
+  #[default]
+  Zero,
+
+  /// Alternating stripes of $00 and $FF bytes, each stripe the given number of
+  /// bytes wide (which must not be zero), starting with $00 at address zero.
+  /// The C64's RAM powers up this way, with 64-byte stripes.
+  Stripes(usize),
+
+  /// Noise, which is the same for every run with the same seed.
+  Random(u64),
+}
+
+/// A step of the SplitMix64 generator, used to hash a seed and address into a
+/// random byte.
+fn splitmix64(value: u64) -> u64 {
+  let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+  z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+  z ^ (z >> 31)
+}
+
+impl RamPattern {
+  /// Return the byte at the given address when powered on. The pattern
+  /// depends on the address seen by the CPU, so that separate blocks of RAM
+  /// line up (and are not filled with the same noise).
+  pub fn byte(&self, address: usize) -> u8 {
+    match *self {
+      RamPattern::Zero => 0x00,
+      RamPattern::Stripes(width) => {
+        if (address / width).is_multiple_of(2) {
+          0x00
+        } else {
+          0xFF
+        }
+      }
+      RamPattern::Random(seed) => splitmix64(splitmix64(seed) ^ address as u64) as u8,
+    }
+  }
+}
 
 /// Represents a simple block of contiguous memory, with no additional hardware.
 /// This can be used to represent both RAM and ROM.
@@ -9,6 +54,10 @@ pub struct BlockMemory {
   data: Vec<u8>,
   persistent: bool,
   writeable: bool,
+
+  /// The contents of RAM after a reset, and the address it is mapped to.
+  pattern: RamPattern,
+  offset: u16,
 }
 
 impl BlockMemory {
@@ -20,6 +69,8 @@ impl BlockMemory {
       data: vec![0; size],
       persistent: false,
       writeable: true,
+      pattern: RamPattern::Zero,
+      offset: 0,
     }
   }
 
@@ -31,6 +82,8 @@ impl BlockMemory {
       data: vec![0; size],
       persistent: true,
       writeable: false,
+      pattern: RamPattern::Zero,
+      offset: 0,
     }
   }
 
@@ -55,6 +108,8 @@ impl BlockMemory {
       data,
       persistent: true,
       writeable: false,
+      pattern: RamPattern::Zero,
+      offset: 0,
     }
   }
 
@@ -64,10 +119,32 @@ impl BlockMemory {
 
     self
   }
+
+  /// Fill this RAM with the given pattern, now and whenever it is reset. The
+  /// pattern is computed from the addresses the RAM is mapped to, starting at
+  /// the given offset.
+  pub fn with_pattern(mut self, pattern: RamPattern, offset: u16) -> Self {
+    self.pattern = pattern;
+    self.offset = offset;
+    self.fill();
+
+    self
+  }
+
+  /// Overwrite the contents of this memory with its pattern.
+  fn fill(&mut self) {
+    for (i, byte) in self.data.iter_mut().enumerate() {
+      *byte = self.pattern.byte(self.offset as usize + i);
+    }
+  }
 }
 
 impl Memory for BlockMemory {
   fn read(&mut self, address: u16) -> u8 {
+    self.peek(address)
+  }
+
+  fn peek(&self, address: u16) -> u8 {
     self.data[(address as usize) % self.size]
   }
 
@@ -79,15 +156,40 @@ impl Memory for BlockMemory {
 
   fn reset(&mut self) {
     if !self.persistent {
-      for i in 0..self.data.len() {
-        self.data[i] = 0;
-      }
+      self.fill();
     }
   }
 
   fn poll(&mut self, _cycles_since_poll: u64, _total_cycle_count: u64) -> ActiveInterrupt {
     ActiveInterrupt::None
   }
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    // The contents of read-only memory always come from a ROM file
+    if self.writeable {
+      state.write(&self.data)?;
+    }
+
+    Ok(())
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    if self.writeable {
+      let data: Vec<u8> = state.read()?;
+
+      if data.len() != self.size {
+        return Err(StateError::Mismatch(format!(
+          "expected {} bytes of memory, found {}",
+          self.size,
+          data.len()
+        )));
+      }
+
+      self.data = data;
+    }
+
+    Ok(())
+  }
 }
 
 #[cfg(test)]
@@ -110,6 +212,34 @@ mod tests {
     assert_eq!(0x00, mem.read(0x123));
   }
 
+  #[test]
+  fn test_patterns() {
+    let mut mem = BlockMemory::ram(0x1000).with_pattern(RamPattern::Stripes(64), 0x0800);
+    assert_eq!(0x00, mem.read(0x000));
+    assert_eq!(0x00, mem.read(0x03F));
+    assert_eq!(0xFF, mem.read(0x040));
+    assert_eq!(0x00, mem.read(0x080));
+
+    // the pattern is restored on reset
+    mem.write(0x040, 0x12);
+    mem.reset();
+    assert_eq!(0xFF, mem.read(0x040));
+
+    // stripes follow the address the memory is mapped to
+    let mut mem = BlockMemory::ram(0x100).with_pattern(RamPattern::Stripes(64), 0x0002);
+    assert_eq!(0xFF, mem.read(0x03E));
+
+    // noise depends on the seed and address, and nothing else
+    let noise = |seed, offset| {
+      let mut mem = BlockMemory::ram(0x100).with_pattern(RamPattern::Random(seed), offset);
+      (0..0x100).map(|i| mem.read(i)).collect::<Vec<_>>()
+    };
+    assert_eq!(noise(1, 0x1000), noise(1, 0x1000));
+    assert_ne!(noise(1, 0x1000), noise(2, 0x1000));
+    assert_ne!(noise(1, 0x1000), noise(1, 0x1100));
+    assert!(noise(1, 0x1000).iter().any(|&byte| byte != 0));
+  }
+
   #[test]
   fn test_rom() {
     let mut mem = BlockMemory::rom(0x1000);
@@ -144,6 +274,31 @@ mod tests {
     assert_eq!(0x00, mem.read(0xFFF));
   }
 
+  #[test]
+  fn test_save_state() {
+    let mut mem = BlockMemory::ram(0x100);
+    mem.write(0x12, 0x34);
+
+    let mut writer = StateWriter::new();
+    mem.save_state(&mut writer).unwrap();
+    let data = writer.finish();
+
+    mem.reset();
+    assert_eq!(0x00, mem.read(0x12));
+
+    let mut reader = StateReader::new(&data).unwrap();
+    mem.load_state(&mut reader).unwrap();
+    reader.finish().unwrap();
+    assert_eq!(0x34, mem.read(0x12));
+
+    // the state doesn't fit in a block of a different size
+    let mut reader = StateReader::new(&data).unwrap();
+    assert!(matches!(
+      BlockMemory::ram(0x200).load_state(&mut reader),
+      Err(StateError::Mismatch(_))
+    ));
+  }
+
   #[test]
   #[should_panic]
   fn test_from_file_too_large() {
@@ -1,12 +1,42 @@
-use crate::memory::{ActiveInterrupt, Memory};
+use crate::memory::{ActiveInterrupt, Memory, MirroredMemory};
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// The number of addresses covered by each entry in the page table.
+const PAGE_SIZE: usize = 0x100;
+
+/// Which of the mapped Memory objects handles each address in a page.
+enum Page {
+  /// The whole page is handled by one mapping (or none).
+  Whole(Option<usize>),
+
+  /// The page is split between several mappings, such as the I/O chips at
+  /// $E810, $E820 and $E840 on the PET, so each address has its own entry.
+  Split(Box<[Option<usize>; PAGE_SIZE]>),
+}
 
 /// Maps several Memory objects into a single contiguous address space.
 /// Each mapped object is assigned a starting address, and reads and writes
 /// will have the starting address subtracted from them before being passed
 /// to the underlying Memory object.
-#[derive(Default)]
+///
+/// An address is handled by the most recently added mapping which starts at
+/// or below it, so mappings are normally added in order of address. Rather
+/// than searching the mappings on every access, this is precomputed for each
+/// 256-byte page whenever a mapping is added.
 pub struct BranchMemory {
   mapping: Vec<(usize, Box<dyn Memory>)>,
+  pages: Vec<Page>,
+}
+
+impl Default for BranchMemory {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Find the index of the mapping which handles the given address, if any.
+fn find_mapping(mapping: &[(usize, Box<dyn Memory>)], address: usize) -> Option<usize> {
+  mapping.iter().rposition(|(start, _)| *start <= address)
 }
 
 impl BranchMemory {
@@ -14,6 +44,9 @@ impl BranchMemory {
   pub fn new() -> Self {
     Self {
       mapping: Vec::new(),
+      pages: (0..0x10000 / PAGE_SIZE)
+        .map(|_| Page::Whole(None))
+        .collect(),
     }
   }
 
@@ -21,42 +54,73 @@ impl BranchMemory {
   /// Returns this BranchMemory for chaining.
   pub fn map(mut self, address: usize, memory: impl Memory + 'static) -> Self {
     self.mapping.push((address, Box::new(memory)));
+    self.build_pages();
 
     self
   }
+
+  /// Map a Memory object with the given number of registers (a power of two)
+  /// to the given starting address, repeating it up to the start of the next
+  /// mapping. Returns this BranchMemory for chaining.
+  pub fn map_mirrored(self, address: usize, registers: u16, memory: impl Memory + 'static) -> Self {
+    self.map(address, MirroredMemory::new(memory, registers))
+  }
+
+  /// Recompute the page table from the list of mappings.
+  fn build_pages(&mut self) {
+    let mapping = &self.mapping;
+
+    for (index, page) in self.pages.iter_mut().enumerate() {
+      let base = index * PAGE_SIZE;
+      let split = mapping
+        .iter()
+        .any(|(start, _)| *start > base && *start < base + PAGE_SIZE);
+
+      *page = if split {
+        Page::Split(Box::new(std::array::from_fn(|offset| {
+          find_mapping(mapping, base + offset)
+        })))
+      } else {
+        Page::Whole(find_mapping(mapping, base))
+      };
+    }
+  }
+
+  /// Return the index of the mapping which handles the given address, if any.
+  #[inline]
+  fn lookup(&self, address: u16) -> Option<usize> {
+    match &self.pages[address as usize / PAGE_SIZE] {
+      Page::Whole(index) => *index,
+      Page::Split(indices) => indices[address as usize % PAGE_SIZE],
+    }
+  }
 }
 
 impl Memory for BranchMemory {
   fn read(&mut self, address: u16) -> u8 {
-    let mut memory = None;
-    let mut offset = 0;
-
-    for (start, mapped) in &mut self.mapping {
-      if address as usize >= *start {
-        memory = Some(mapped);
-        offset = *start as u16;
+    match self.lookup(address) {
+      Some(index) => {
+        let (start, memory) = &mut self.mapping[index];
+        memory.read(address - *start as u16)
       }
-    }
-
-    match memory {
-      Some(memory) => memory.read(address - offset),
       None => 0,
     }
   }
 
-  fn write(&mut self, address: u16, value: u8) {
-    let mut memory = None;
-    let mut offset = 0;
-
-    for (start, mapped) in &mut self.mapping {
-      if address as usize >= *start {
-        memory = Some(mapped);
-        offset = *start as u16;
+  fn peek(&self, address: u16) -> u8 {
+    match self.lookup(address) {
+      Some(index) => {
+        let (start, memory) = &self.mapping[index];
+        memory.peek(address - *start as u16)
       }
+      None => 0,
     }
+  }
 
-    if let Some(memory) = memory {
-      memory.write(address - offset, value);
+  fn write(&mut self, address: u16, value: u8) {
+    if let Some(index) = self.lookup(address) {
+      let (start, memory) = &mut self.mapping[index];
+      memory.write(address - *start as u16, value);
     }
   }
 
@@ -85,11 +149,27 @@ impl Memory for BranchMemory {
 
     highest
   }
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    for (_, mapped) in &self.mapping {
+      mapped.save_state(state)?;
+    }
+
+    Ok(())
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    for (_, mapped) in &mut self.mapping {
+      mapped.load_state(state)?;
+    }
+
+    Ok(())
+  }
 }
 
 #[cfg(test)]
 mod tests {
-  use crate::memory::BlockMemory;
+  use crate::memory::{BlockMemory, NullMemory};
 
   use super::*;
 
@@ -209,4 +289,36 @@ mod tests {
     assert_eq!(0x78, memory.read(0x0100));
     assert_eq!(0xFF, memory.read(0x0234));
   }
+
+  #[test]
+  fn test_sub_page() {
+    let mut pia = BlockMemory::ram(0x10);
+    pia.write(0x02, 0x34);
+
+    // I/O chips mapped partway through a page, as on the PET
+    let mut memory = BranchMemory::new()
+      .map(0xE000, BlockMemory::rom(0x0800))
+      .map(0xE810, pia)
+      .map(0xE820, BlockMemory::ram(0x10))
+      .map(0xE840, NullMemory::new())
+      .map(0xF000, BlockMemory::ram(0x1000));
+
+    memory.write(0xE800, 0x12);
+    memory.write(0xE820, 0x56);
+    memory.write(0xF000, 0x78);
+
+    assert_eq!(0x00, memory.read(0xE800));
+    assert_eq!(0x34, memory.read(0xE812));
+    assert_eq!(0x56, memory.read(0xE820));
+    assert_eq!(0x56, memory.peek(0xE820));
+    assert_eq!(0x00, memory.read(0xE841));
+    assert_eq!(0x78, memory.read(0xF000));
+
+    // the last mapping at an address takes precedence
+    let mut memory = BranchMemory::new()
+      .map(0x0000, BlockMemory::ram(0x100))
+      .map(0x0000, NullMemory::new());
+    memory.write(0x0010, 0x12);
+    assert_eq!(0x00, memory.read(0x0010));
+  }
 }
@@ -0,0 +1,138 @@
+use super::{ActiveInterrupt, Memory};
+use crate::state::{StateError, StateReader, StateWriter};
+use std::{cell::Cell, rc::Rc};
+
+/// The value last driven onto the data bus, shared between a
+/// [`DataBusMemory`] which records it and the memory which reads it back.
+///
+/// When the CPU reads from an address which nothing drives, the bus keeps the
+/// value of the previous access (usually the high byte of the address just
+/// fetched), which some programs depend on. A [`super::NullMemory`] given a
+/// `DataBus` returns this value for unmapped addresses, rather than zero.
+#[derive(Clone, Default)]
+pub struct DataBus {
+  value: Rc<Cell<u8>>,
+}
+
+impl DataBus {
+  /// Create a data bus, which initially holds zero.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Return the value last driven onto the bus.
+  pub fn get(&self) -> u8 {
+    self.value.get()
+  }
+
+  /// Drive a new value onto the bus.
+  pub fn set(&self, value: u8) {
+    self.value.set(value);
+  }
+}
+
+/// Memory which passes every access through to the memory it wraps, recording
+/// the value read or written on a [`DataBus`]. This wraps the whole address
+/// space of a system, so that every access the CPU makes is seen.
+pub struct DataBusMemory {
+  backing: Box<dyn Memory>,
+  bus: DataBus,
+}
+
+impl DataBusMemory {
+  /// Record the accesses to the given memory on the given bus.
+  pub fn new(backing: impl Memory + 'static, bus: DataBus) -> Self {
+    Self {
+      backing: Box::new(backing),
+      bus,
+    }
+  }
+}
+
+impl Memory for DataBusMemory {
+  fn read(&mut self, address: u16) -> u8 {
+    let value = self.backing.read(address);
+    self.bus.set(value);
+    value
+  }
+
+  fn peek(&self, address: u16) -> u8 {
+    self.backing.peek(address)
+  }
+
+  fn write(&mut self, address: u16, value: u8) {
+    self.bus.set(value);
+    self.backing.write(address, value);
+  }
+
+  fn reset(&mut self) {
+    self.bus.set(0);
+    self.backing.reset();
+  }
+
+  fn poll(&mut self, cycles_since_poll: u64, total_cycle_count: u64) -> ActiveInterrupt {
+    self.backing.poll(cycles_since_poll, total_cycle_count)
+  }
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    state.write(&self.bus.get())?;
+    self.backing.save_state(state)
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    self.bus.set(state.read()?);
+    self.backing.load_state(state)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cpu::mos6502::{Mos6502, Mos6502Variant};
+  use crate::cpu::Cpu;
+  use crate::memory::{BlockMemory, BranchMemory, NullMemory};
+
+  #[test]
+  fn test_open_bus() {
+    let bus = DataBus::new();
+    let memory = BranchMemory::new()
+      .map(0x0000, BlockMemory::ram(0x1000))
+      .map(0x1000, NullMemory::open_bus(bus.clone()))
+      .map(0x2000, BlockMemory::ram(0x1000));
+    let mut memory = DataBusMemory::new(memory, bus.clone());
+
+    memory.write(0x0010, 0x42);
+    assert_eq!(0x42, memory.read(0x1234));
+    assert_eq!(0x42, memory.peek(0x1234));
+
+    memory.read(0x0011);
+    assert_eq!(0x00, memory.read(0x1234));
+  }
+
+  #[test]
+  fn test_high_byte() {
+    let bus = DataBus::new();
+
+    // LDA $9234, with the reset vector pointing to it
+    let mut rom = BlockMemory::rom(0x1000).set_writeable(true);
+    for (i, byte) in [0xAD, 0x34, 0x92].iter().enumerate() {
+      rom.write(i as u16, *byte);
+    }
+    rom.write(0x0FFD, 0xF0);
+
+    let memory = BranchMemory::new()
+      .map(0x0000, BlockMemory::ram(0x8000))
+      .map(0x8000, NullMemory::open_bus(bus.clone()))
+      .map(0xF000, rom);
+    let mut cpu = Mos6502::new(
+      DataBusMemory::new(memory, bus.clone()),
+      Mos6502Variant::NMOS,
+    );
+
+    cpu.reset();
+    cpu.tick().unwrap();
+
+    // the last value on the bus was the high byte of the address
+    assert_eq!(0x92, cpu.registers.a);
+  }
+}
@@ -1,4 +1,5 @@
 use super::{ActiveInterrupt, Memory};
+use crate::state::{StateError, StateReader, StateWriter};
 
 pub struct LoggingMemory {
   backing: Box<dyn Memory>,
@@ -28,6 +29,10 @@ impl Memory for LoggingMemory {
     value
   }
 
+  fn peek(&self, address: u16) -> u8 {
+    self.backing.peek(address)
+  }
+
   fn write(&mut self, address: u16, value: u8) {
     self.backing.write(address, value);
     println!(
@@ -47,4 +52,12 @@ impl Memory for LoggingMemory {
     // println!("[Memory Poll]: {}", self.message);
     self.backing.poll(cycles_since_poll, total_cycle_count)
   }
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    self.backing.save_state(state)
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    self.backing.load_state(state)
+  }
 }
@@ -0,0 +1,86 @@
+use crate::memory::{ActiveInterrupt, LongMemory, Memory};
+
+/// Maps Memory objects into banks of a 24-bit address space. Each bank is 64K
+/// in size, and reads and writes within it are passed to the mapped Memory
+/// object with the bank number removed. Unmapped banks read as zero and ignore
+/// writes.
+pub struct LongBranchMemory {
+  banks: Vec<Option<Box<dyn Memory>>>,
+}
+
+impl LongBranchMemory {
+  /// Create a new LongBranchMemory with no banks mapped.
+  pub fn new() -> Self {
+    Self {
+      banks: (0..=0xFF).map(|_| None).collect(),
+    }
+  }
+
+  /// Map a new Memory object to the given bank, replacing any previous mapping.
+  /// Returns this LongBranchMemory for chaining.
+  pub fn map(mut self, bank: u8, memory: impl Memory + 'static) -> Self {
+    self.banks[bank as usize] = Some(Box::new(memory));
+
+    self
+  }
+}
+
+impl LongMemory for LongBranchMemory {
+  fn read(&mut self, address: u32) -> u8 {
+    match &mut self.banks[(address >> 16) as u8 as usize] {
+      Some(memory) => memory.read(address as u16),
+      None => 0,
+    }
+  }
+
+  fn write(&mut self, address: u32, value: u8) {
+    if let Some(memory) = &mut self.banks[(address >> 16) as u8 as usize] {
+      memory.write(address as u16, value);
+    }
+  }
+
+  fn reset(&mut self) {
+    for memory in self.banks.iter_mut().flatten() {
+      memory.reset();
+    }
+  }
+
+  fn poll(&mut self, cycles_since_poll: u64, total_cycle_count: u64) -> ActiveInterrupt {
+    let mut highest = ActiveInterrupt::None;
+
+    for memory in self.banks.iter_mut().flatten() {
+      match memory.poll(cycles_since_poll, total_cycle_count) {
+        ActiveInterrupt::None => (),
+        ActiveInterrupt::NMI => highest = ActiveInterrupt::NMI,
+        ActiveInterrupt::IRQ => {
+          if highest == ActiveInterrupt::None {
+            highest = ActiveInterrupt::IRQ;
+          }
+        }
+      }
+    }
+
+    highest
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::memory::BlockMemory;
+
+  #[test]
+  fn test_banks() {
+    let mut memory = LongBranchMemory::new()
+      .map(0x00, BlockMemory::ram(0x10000))
+      .map(0x12, BlockMemory::ram(0x10000));
+
+    memory.write(0x00_1234, 0x56);
+    memory.write(0x12_1234, 0x78);
+    memory.write(0x13_1234, 0x9A);
+
+    assert_eq!(0x56, memory.read(0x00_1234));
+    assert_eq!(0x78, memory.read(0x12_1234));
+    assert_eq!(0x00, memory.read(0x13_1234));
+  }
+}
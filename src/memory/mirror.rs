@@ -0,0 +1,108 @@
+use super::{ActiveInterrupt, Memory};
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Memory which only decodes some of the address lines, so that its contents
+/// repeat across the region it is mapped to. This is how most chips are wired:
+/// the VIC-II's 64 registers, for instance, repeat every $40 bytes across
+/// $D000-$D3FF, since it only looks at the lowest six address lines.
+///
+/// Addresses are masked before being passed to the wrapped memory, so it only
+/// ever sees addresses with the decoded lines set.
+pub struct MirroredMemory {
+  backing: Box<dyn Memory>,
+  mask: u16,
+}
+
+impl MirroredMemory {
+  /// Repeat the given memory every `size` bytes. The size must be a power of
+  /// two, as with any memory decoded by its lowest address lines.
+  pub fn new(backing: impl Memory + 'static, size: u16) -> Self {
+    if !size.is_power_of_two() {
+      panic!("Mirrored memory size {size:#X} is not a power of two");
+    }
+
+    Self::with_mask(backing, size - 1)
+  }
+
+  /// Pass only the address lines set in the given mask to the memory. Lines
+  /// which are not decoded read as zero.
+  pub fn with_mask(backing: impl Memory + 'static, mask: u16) -> Self {
+    Self {
+      backing: Box::new(backing),
+      mask,
+    }
+  }
+}
+
+impl Memory for MirroredMemory {
+  fn read(&mut self, address: u16) -> u8 {
+    self.backing.read(address & self.mask)
+  }
+
+  fn peek(&self, address: u16) -> u8 {
+    self.backing.peek(address & self.mask)
+  }
+
+  fn write(&mut self, address: u16, value: u8) {
+    self.backing.write(address & self.mask, value);
+  }
+
+  fn reset(&mut self) {
+    self.backing.reset();
+  }
+
+  fn poll(&mut self, cycles_since_poll: u64, total_cycle_count: u64) -> ActiveInterrupt {
+    self.backing.poll(cycles_since_poll, total_cycle_count)
+  }
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    self.backing.save_state(state)
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    self.backing.load_state(state)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::memory::{BlockMemory, BranchMemory};
+
+  #[test]
+  fn test_mirrored() {
+    let mut memory = BranchMemory::new()
+      .map(0x0000, BlockMemory::ram(0x1000))
+      .map_mirrored(0xD000, 0x40, BlockMemory::ram(0x40))
+      .map(0xD400, BlockMemory::ram(0x0400));
+
+    memory.write(0xD020, 0x0E);
+    assert_eq!(0x0E, memory.read(0xD020));
+    assert_eq!(0x0E, memory.read(0xD060));
+    assert_eq!(0x0E, memory.peek(0xD3E0));
+    assert_eq!(0x00, memory.read(0xD420));
+
+    memory.write(0xD3E1, 0x06);
+    assert_eq!(0x06, memory.read(0xD021));
+  }
+
+  #[test]
+  fn test_mask() {
+    // a chip which ignores address line 2
+    let mut memory = MirroredMemory::with_mask(BlockMemory::ram(0x10), 0x0B);
+
+    memory.write(0x01, 0x12);
+    assert_eq!(0x12, memory.read(0x05));
+    assert_eq!(0x12, memory.read(0x15));
+
+    memory.write(0x0D, 0x34);
+    assert_eq!(0x34, memory.read(0x09));
+    assert_eq!(0x12, memory.read(0x01));
+  }
+
+  #[test]
+  #[should_panic]
+  fn test_size() {
+    MirroredMemory::new(BlockMemory::ram(0x30), 0x30);
+  }
+}
@@ -1,20 +1,30 @@
 mod banked;
 mod block;
 mod branch;
+mod bus;
 mod logging;
+mod long;
+mod mirror;
 mod mos6510;
 /// The various interface adapters (6520, 6522, 6526) for the MOS 6502 CPU.
 pub mod mos652x;
 mod null;
 mod ports;
+mod watch;
 
 pub use banked::BankedMemory;
-pub use block::BlockMemory;
+pub use block::{BlockMemory, RamPattern};
 pub use branch::BranchMemory;
+pub use bus::{DataBus, DataBusMemory};
 pub use logging::LoggingMemory;
+pub use long::LongBranchMemory;
+pub use mirror::MirroredMemory;
 pub use mos6510::Mos6510Port;
 pub use null::NullMemory;
 pub use ports::{NullPort, Port};
+pub use watch::{Watch, WatchHit, WatchKind, WatchMemory, Watchpoints};
+
+use crate::state::{StateError, StateReader, StateWriter};
 
 /// Represents the state of the interrupts on the system.
 #[derive(Debug, PartialEq, Eq)]
@@ -34,6 +44,12 @@ pub trait Memory {
   /// Implementations may trigger side effects as a result of this read.
   fn read(&mut self, address: u16) -> u8;
 
+  /// Return the byte which a read at the given address would return, without
+  /// any of its side effects (such as clearing a chip's interrupt flags or
+  /// waiting for input). This lets tools such as the debugger inspect memory
+  /// without disturbing the running system.
+  fn peek(&self, address: u16) -> u8;
+
   /// Write a byte to this memory at the given address.
   fn write(&mut self, address: u16, value: u8);
 
@@ -46,4 +62,50 @@ pub trait Memory {
   /// Implementations may trigger an NMI or IRQ for any
   /// implementation-dependent reason.
   fn poll(&mut self, cycles_since_poll: u64, total_cycle_count: u64) -> ActiveInterrupt;
+
+  /// Write the state of this memory (e.g. the contents of RAM, or the
+  /// registers of a chip) to the given save state. Memory which has no state
+  /// of its own, such as ROM, writes nothing.
+  fn save_state(&self, _state: &mut StateWriter) -> Result<(), StateError> {
+    Ok(())
+  }
+
+  /// Restore the state of this memory from the given save state, reading back
+  /// exactly what [`Memory::save_state`] wrote.
+  fn load_state(&mut self, _state: &mut StateReader) -> Result<(), StateError> {
+    Ok(())
+  }
+}
+
+/// Represents a 24-bit address space, divided into 256 banks of 64K, for
+/// processors such as the 65C816 which can address more than 64K of memory.
+/// Like [`Memory`], it can be read, written, reset, and polled for interrupts.
+pub trait LongMemory {
+  /// Read a byte from the given 24-bit address.
+  /// Implementations may trigger side effects as a result of this read.
+  fn read(&mut self, address: u32) -> u8;
+
+  /// Write a byte to the given 24-bit address.
+  fn write(&mut self, address: u32, value: u8);
+
+  /// Reset this memory to its initial state, e.g. after a system reboot.
+  fn reset(&mut self);
+
+  /// Poll this memory to see if an interrupt has been triggered.
+  fn poll(&mut self, cycles_since_poll: u64, total_cycle_count: u64) -> ActiveInterrupt;
+}
+
+/// Represents the separate I/O address space of processors such as the Z80,
+/// which is accessed by dedicated `IN` and `OUT` instructions rather than
+/// ordinary memory reads and writes.
+pub trait IoSpace {
+  /// Read a byte from the given port.
+  /// Implementations may trigger side effects as a result of this read.
+  fn read_port(&mut self, port: u16) -> u8;
+
+  /// Write a byte to the given port.
+  fn write_port(&mut self, port: u16, value: u8);
+
+  /// Reset the devices in this I/O space to their initial state.
+  fn reset(&mut self);
 }
@@ -1,4 +1,5 @@
 use super::{ActiveInterrupt, Memory, Port};
+use crate::state::{StateError, StateReader, StateWriter};
 
 /// Represents the port built into a MOS 6510 processor, mapped to memory addresses 0x0000 (for the DDR) and 0x0001 (for the port itself).
 pub struct Mos6510Port {
@@ -32,6 +33,14 @@ impl Memory for Mos6510Port {
     }
   }
 
+  fn peek(&self, address: u16) -> u8 {
+    match address % 2 {
+      0 => self.ddr,
+      1 => (self.port.peek() & !self.ddr) | (self.writes & self.ddr),
+      _ => unreachable!(),
+    }
+  }
+
   fn write(&mut self, address: u16, value: u8) {
     match address % 2 {
       0 => {
@@ -56,4 +65,14 @@ impl Memory for Mos6510Port {
       false => ActiveInterrupt::None,
     }
   }
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    state.write(&(self.writes, self.ddr))?;
+    self.port.save_state(state)
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    (self.writes, self.ddr) = state.read()?;
+    self.port.load_state(state)
+  }
 }
@@ -2,7 +2,10 @@ use crate::memory::{
   mos652x::{InterruptRegister, PortRegisters, ShiftRegister, Timer},
   ActiveInterrupt, Memory, Port,
 };
+use crate::state::{StateError, StateReader, StateWriter};
+use serde::{Deserialize, Serialize};
 
+#[derive(Serialize, Deserialize)]
 struct TimeRegisters {
   tenth_seconds: u8,
   seconds: u8,
@@ -29,6 +32,7 @@ impl TimeRegisters {
   }
 }
 
+#[derive(Serialize, Deserialize)]
 struct TimeClock {
   time: TimeRegisters,
   alarm: TimeRegisters,
@@ -77,6 +81,11 @@ pub struct Cia {
 }
 
 impl Cia {
+  /// The number of registers. The CIA only sees the register index, so map it
+  /// with [`BranchMemory::map_mirrored`](crate::memory::BranchMemory::map_mirrored)
+  /// to repeat these across the region it occupies.
+  pub const REGISTERS: u16 = 0x10;
+
   pub fn new(port_a: Box<dyn Port>, port_b: Box<dyn Port>) -> Self {
     Self {
       a: PortRegisters::new(port_a),
@@ -92,9 +101,26 @@ impl Cia {
 
 impl Memory for Cia {
   fn read(&mut self, address: u16) -> u8 {
-    match address % 0x10 {
+    match address {
       0x00 => self.a.read(),
       0x01 => self.b.read(),
+      0x0D => {
+        let value = self.peek(address);
+
+        // Reading the interrupt flags clears them
+        self.timer_a.interrupt = false;
+        self.timer_b.interrupt = false;
+
+        value
+      }
+      _ => self.peek(address),
+    }
+  }
+
+  fn peek(&self, address: u16) -> u8 {
+    match address {
+      0x00 => self.a.peek(),
+      0x01 => self.b.peek(),
       0x02 => self.a.ddr,
       0x03 => self.b.ddr,
       0x04 => self.timer_a.counter as u8,
@@ -112,14 +138,9 @@ impl Memory for Cia {
       0x0C => self.shift_register.data,
       0x0D => {
         // TODO: alarm and shift register flags
-        let value = self
+        self
           .interrupts
-          .read_flags((self.timer_a.interrupt as u8) | (self.timer_b.interrupt as u8) << 1);
-
-        self.timer_a.interrupt = false;
-        self.timer_b.interrupt = false;
-
-        value
+          .read_flags((self.timer_a.interrupt as u8) | (self.timer_b.interrupt as u8) << 1)
       }
       0x0E => {
         (self.timer_a.read_cia() & 0b0011_1111)
@@ -132,7 +153,7 @@ impl Memory for Cia {
   }
 
   fn write(&mut self, address: u16, value: u8) {
-    match address % 0x10 {
+    match address {
       0x00 => self.a.write(value),
       0x01 => self.b.write(value),
       0x02 => self.a.ddr = value,
@@ -229,14 +250,50 @@ impl Memory for Cia {
 
     ActiveInterrupt::None
   }
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    self.a.save_state(state)?;
+    self.b.save_state(state)?;
+    state.write(&self.timer_a)?;
+    state.write(&self.timer_b)?;
+    state.write(&self.time_clock)?;
+    state.write(&self.shift_register)?;
+    state.write(&self.interrupts)
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    self.a.load_state(state)?;
+    self.b.load_state(state)?;
+    self.timer_a = state.read()?;
+    self.timer_b = state.read()?;
+    self.time_clock = state.read()?;
+    self.shift_register = state.read()?;
+    self.interrupts = state.read()?;
+
+    Ok(())
+  }
 }
 
 #[cfg(test)]
 mod tests {
-  use crate::memory::NullPort;
+  use crate::memory::{BlockMemory, BranchMemory, NullPort};
 
   use super::*;
 
+  #[test]
+  fn test_mirrored() {
+    let cia = Cia::new(Box::new(NullPort::new()), Box::new(NullPort::new()));
+    let mut memory = BranchMemory::new()
+      .map_mirrored(0x00, Cia::REGISTERS, cia)
+      .map(0x100, BlockMemory::ram(0x100));
+
+    // the registers repeat every 16 bytes, up to the next chip
+    memory.write(0x12, 0b11110000);
+    assert_eq!(0b11110000, memory.read(0x02));
+    assert_eq!(0b11110000, memory.read(0xF2));
+    assert_eq!(0, memory.read(0x102));
+  }
+
   #[test]
   fn test_read_write() {
     let mut cia = Cia::new(Box::new(NullPort::new()), Box::new(NullPort::new()));
@@ -360,6 +417,26 @@ mod tests {
     assert_eq!(ActiveInterrupt::IRQ, cia.poll(1, 0));
   }
 
+  #[test]
+  fn test_peek() {
+    let mut cia = Cia::new(Box::new(NullPort::new()), Box::new(NullPort::new()));
+
+    // let timer B run out, without interrupts enabled
+    cia.write(0x06, 0x08);
+    cia.write(0x07, 0x00);
+    for _ in 0..0x08 {
+      cia.poll(1, 0);
+    }
+
+    // peeking at the flag register shouldn't clear it...
+    assert_eq!(interrupt_bits::TIMER_B, cia.peek(0x0D));
+    assert_eq!(interrupt_bits::TIMER_B, cia.peek(0x0D));
+
+    // ...but reading it should
+    assert_eq!(interrupt_bits::TIMER_B, cia.read(0x0D));
+    assert_eq!(0x00, cia.peek(0x0D));
+  }
+
   #[test]
   fn test_interrupt_flags() {
     let mut cia = Cia::new(Box::new(NullPort::new()), Box::new(NullPort::new()));
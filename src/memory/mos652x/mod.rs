@@ -7,6 +7,8 @@ pub use pia::Pia;
 pub use via::Via;
 
 use crate::memory::Port;
+use crate::state::{StateError, StateReader, StateWriter};
+use serde::{Deserialize, Serialize};
 
 /// A port and its associated registers on the MOS 6522 VIA or MOS 6526 CIA.
 pub struct PortRegisters {
@@ -38,6 +40,11 @@ impl PortRegisters {
     (self.port.read() & !self.ddr) | (self.writes & self.ddr)
   }
 
+  /// Return what a read from the port would, without its side effects.
+  pub fn peek(&self) -> u8 {
+    (self.port.peek() & !self.ddr) | (self.writes & self.ddr)
+  }
+
   /// Write to the port, respecting the DDR.
   pub fn write(&mut self, value: u8) {
     self.writes = value;
@@ -55,9 +62,22 @@ impl PortRegisters {
 
     self.port.reset();
   }
+
+  /// Write the registers and the underlying port's state to a save state.
+  pub fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    state.write(&(self.writes, self.ddr, self.latch_enabled))?;
+    self.port.save_state(state)
+  }
+
+  /// Restore the registers and the underlying port's state from a save state.
+  pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    (self.writes, self.ddr, self.latch_enabled) = state.read()?;
+    self.port.load_state(state)
+  }
 }
 
 /// The manner in which the timer will output signals to the port, if at all.
+#[derive(Serialize, Deserialize)]
 pub enum TimerOutput {
   /// The timer will not output to the port.
   None,
@@ -73,6 +93,7 @@ pub enum TimerOutput {
 }
 
 /// The source of the timer's clock, which controls the rate at which its clock decrements.
+#[derive(Serialize, Deserialize)]
 pub enum TimerClockSource {
   /// Use the internal system clock.
   Phi2,
@@ -88,6 +109,7 @@ pub enum TimerClockSource {
 }
 
 /// A timer circuit on the MOS 6522 VIA or MOS 6526 CIA.
+#[derive(Serialize, Deserialize)]
 pub struct Timer {
   /// The latched value that the counter is reloaded from.
   latch: u16,
@@ -206,6 +228,7 @@ impl Timer {
 }
 
 /// The shift register used by the MOS 6522 VIA and MOS 6526 CIA.
+#[derive(Serialize, Deserialize)]
 pub struct ShiftRegister {
   /// The data currently in the shift register.
   data: u8,
@@ -237,6 +260,7 @@ impl ShiftRegister {
 
 /// Registers for interrupt flags and interrupt enable bits.
 /// Each bit from 0 to 6 corresponds to an interrupt source.
+#[derive(Serialize, Deserialize)]
 pub struct InterruptRegister {
   /// The current state of which interrupts are enabled.
   /// If a bit is set, the corresponding interrupt is enabled.
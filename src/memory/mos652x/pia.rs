@@ -1,4 +1,5 @@
 use crate::memory::{ActiveInterrupt, Memory, Port};
+use crate::state::{StateError, StateReader, StateWriter};
 
 // MOS 6520
 
@@ -41,6 +42,15 @@ impl PiaPortRegisters {
     }
   }
 
+  /// Return what a read would, without any side effects of reading the port.
+  pub fn peek(&self) -> u8 {
+    if self.control & pia_control_bits::DDR_SELECT != 0 {
+      (self.port.peek() & !self.ddr) | (self.writes & self.ddr)
+    } else {
+      self.ddr
+    }
+  }
+
   /// Write to either the port or the DDR, depending on the DDR_SELECT bit in
   /// the control register.
   /// Respects the DDR, so if a bit in the DDR is set to read, then that bit
@@ -66,6 +76,18 @@ impl PiaPortRegisters {
 
     self.port.reset();
   }
+
+  /// Write the registers and the underlying port's state to a save state.
+  pub fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    state.write(&(self.writes, self.ddr, self.control))?;
+    self.port.save_state(state)
+  }
+
+  /// Restore the registers and the underlying port's state from a save state.
+  pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    (self.writes, self.ddr, self.control) = state.read()?;
+    self.port.load_state(state)
+  }
 }
 
 #[allow(dead_code)]
@@ -87,6 +109,12 @@ pub struct Pia {
 }
 
 impl Pia {
+  /// The number of registers. The PIA only decodes the two lowest address
+  /// lines, so map it with
+  /// [`BranchMemory::map_mirrored`](crate::memory::BranchMemory::map_mirrored)
+  /// to repeat these across the region it occupies.
+  pub const REGISTERS: u16 = 0x04;
+
   /// Create a new PIA with the two given port implementations.
   pub fn new(a: Box<dyn Port>, b: Box<dyn Port>) -> Self {
     Self {
@@ -98,7 +126,7 @@ impl Pia {
 
 impl Memory for Pia {
   fn read(&mut self, address: u16) -> u8 {
-    match address % 0x04 {
+    match address {
       0x00 => self.a.read(),
       0x01 => self.a.control,
       0x02 => self.b.read(),
@@ -107,8 +135,18 @@ impl Memory for Pia {
     }
   }
 
+  fn peek(&self, address: u16) -> u8 {
+    match address {
+      0x00 => self.a.peek(),
+      0x01 => self.a.control,
+      0x02 => self.b.peek(),
+      0x03 => self.b.control,
+      _ => unreachable!(),
+    }
+  }
+
   fn write(&mut self, address: u16, value: u8) {
-    match address % 0x04 {
+    match address {
       0x00 => self.a.write(value),
       0x01 => self.a.control = value,
       0x02 => self.b.write(value),
@@ -132,17 +170,28 @@ impl Memory for Pia {
       ActiveInterrupt::None
     }
   }
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    self.a.save_state(state)?;
+    self.b.save_state(state)
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    self.a.load_state(state)?;
+    self.b.load_state(state)
+  }
 }
 
 #[cfg(test)]
 mod tests {
-  use crate::memory::NullPort;
+  use crate::memory::{MirroredMemory, NullPort};
 
   use super::*;
 
   #[test]
   fn test_read() {
-    let mut pia = Pia::new(Box::new(NullPort::new()), Box::new(NullPort::new()));
+    let pia = Pia::new(Box::new(NullPort::new()), Box::new(NullPort::new()));
+    let mut pia = MirroredMemory::new(pia, Pia::REGISTERS);
 
     // deselect the DDR
     pia.write(0x01, pia_control_bits::DDR_SELECT);
@@ -154,6 +203,8 @@ mod tests {
 
     // wraps around
     assert_eq!(0, pia.read(0x04));
+    assert_eq!(pia_control_bits::DDR_SELECT, pia.read(0x05));
+    assert_eq!(pia_control_bits::DDR_SELECT, pia.read(0xFD));
 
     // select the DDR
     pia.write(0x01, 0);
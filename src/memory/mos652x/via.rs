@@ -2,6 +2,7 @@ use crate::memory::{
   mos652x::{InterruptRegister, PortRegisters, ShiftRegister, Timer, TimerOutput},
   ActiveInterrupt, Memory, Port,
 };
+use crate::state::{StateError, StateReader, StateWriter};
 
 #[allow(dead_code)]
 pub mod sr_control_bits {
@@ -49,6 +50,11 @@ pub mod interrupt_bits {
 }
 
 impl Via {
+  /// The number of registers. The VIA only sees the register index, so map it
+  /// with [`BranchMemory::map_mirrored`](crate::memory::BranchMemory::map_mirrored)
+  /// to repeat these across the region it occupies.
+  pub const REGISTERS: u16 = 0x10;
+
   pub fn new(a: Box<dyn Port>, b: Box<dyn Port>) -> Self {
     Self {
       a: PortRegisters::new(a),
@@ -64,22 +70,32 @@ impl Via {
 
 impl Memory for Via {
   fn read(&mut self, address: u16) -> u8 {
-    match address % 0x10 {
+    match address {
       0x00 => self.b.read(),
-      0x01 => self.a.read(), // TODO: controls handshake?
-      0x02 => self.b.ddr,
-      0x03 => self.a.ddr,
+      0x01 | 0x0f => self.a.read(), // TODO: controls handshake?
       0x04 => {
         self.t1.interrupt = false;
         (self.t1.counter & 0xff) as u8
       }
-      0x05 => ((self.t1.counter >> 8) & 0xff) as u8,
-      0x06 => (self.t1.latch & 0xff) as u8,
-      0x07 => ((self.t1.latch >> 8) & 0xff) as u8,
       0x08 => {
         self.t2.interrupt = false;
         (self.t2.counter & 0xff) as u8
       }
+      _ => self.peek(address),
+    }
+  }
+
+  fn peek(&self, address: u16) -> u8 {
+    match address {
+      0x00 => self.b.peek(),
+      0x01 => self.a.peek(), // TODO: controls handshake?
+      0x02 => self.b.ddr,
+      0x03 => self.a.ddr,
+      0x04 => (self.t1.counter & 0xff) as u8,
+      0x05 => ((self.t1.counter >> 8) & 0xff) as u8,
+      0x06 => (self.t1.latch & 0xff) as u8,
+      0x07 => ((self.t1.latch >> 8) & 0xff) as u8,
+      0x08 => (self.t2.counter & 0xff) as u8,
       0x09 => ((self.t2.counter >> 8) & 0xff) as u8,
       0x0a => self.sr.data,
       0x0b => {
@@ -115,13 +131,13 @@ impl Memory for Via {
         self.interrupts.read_flags(value)
       }
       0x0e => self.interrupts.read_enable(),
-      0x0f => self.a.read(),
+      0x0f => self.a.peek(),
       _ => unreachable!(),
     }
   }
 
   fn write(&mut self, address: u16, value: u8) {
-    match address % 0x10 {
+    match address {
       0x00 => self.b.write(value),
       0x01 => self.a.write(value), // TODO: controls handshake?
       0x02 => self.b.ddr = value,
@@ -204,14 +220,50 @@ impl Memory for Via {
 
     ActiveInterrupt::None
   }
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    self.a.save_state(state)?;
+    self.b.save_state(state)?;
+    state.write(&self.t1)?;
+    state.write(&self.t2)?;
+    state.write(&self.sr)?;
+    state.write(&self.interrupts)?;
+    state.write(&self.pcr)
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    self.a.load_state(state)?;
+    self.b.load_state(state)?;
+    self.t1 = state.read()?;
+    self.t2 = state.read()?;
+    self.sr = state.read()?;
+    self.interrupts = state.read()?;
+    self.pcr = state.read()?;
+
+    Ok(())
+  }
 }
 
 #[cfg(test)]
 mod tests {
-  use crate::memory::NullPort;
+  use crate::memory::{BlockMemory, BranchMemory, NullPort};
 
   use super::*;
 
+  #[test]
+  fn test_mirrored() {
+    let via = Via::new(Box::new(NullPort::new()), Box::new(NullPort::new()));
+    let mut memory = BranchMemory::new()
+      .map_mirrored(0x00, Via::REGISTERS, via)
+      .map(0x100, BlockMemory::ram(0x100));
+
+    // the registers repeat every 16 bytes, up to the next chip
+    memory.write(0x12, 0b11110000);
+    assert_eq!(0b11110000, memory.read(0x02));
+    assert_eq!(0b11110000, memory.read(0xF2));
+    assert_eq!(0, memory.read(0x102));
+  }
+
   #[test]
   fn test_read_write() {
     let mut via = Via::new(Box::new(NullPort::new()), Box::new(NullPort::new()));
@@ -238,6 +290,36 @@ mod tests {
     assert_eq!(0b01010000, via.read(0x00));
   }
 
+  #[test]
+  fn test_save_state() {
+    let mut via = Via::new(Box::new(NullPort::new()), Box::new(NullPort::new()));
+
+    via.write(0x02, 0b11110000);
+    via.write(0x00, 0b10100000);
+    via.write(0x0e, interrupt_bits::MASTER | interrupt_bits::T1_ENABLE);
+    via.write(0x04, 0x10);
+    via.write(0x05, 0x00);
+    via.poll(0x08, 0);
+
+    let mut writer = StateWriter::new();
+    via.save_state(&mut writer).unwrap();
+    let data = writer.finish();
+
+    let mut restored = Via::new(Box::new(NullPort::new()), Box::new(NullPort::new()));
+    let mut reader = StateReader::new(&data).unwrap();
+    restored.load_state(&mut reader).unwrap();
+    reader.finish().unwrap();
+
+    assert_eq!(0b10100000, restored.read(0x00));
+    assert_eq!(0b11110000, restored.read(0x02));
+
+    // the timer should carry on from where it was saved
+    for _ in 0..0x07 {
+      assert_eq!(ActiveInterrupt::None, restored.poll(1, 0));
+    }
+    assert_eq!(ActiveInterrupt::IRQ, restored.poll(1, 0));
+  }
+
   #[test]
   fn test_timer_1() {
     let mut via = Via::new(Box::new(NullPort::new()), Box::new(NullPort::new()));
@@ -261,6 +343,28 @@ mod tests {
     }
   }
 
+  #[test]
+  fn test_peek() {
+    let mut via = Via::new(Box::new(NullPort::new()), Box::new(NullPort::new()));
+
+    // enable timer 1 interrupts, and let the timer run out
+    via.write(0x0e, interrupt_bits::MASTER | interrupt_bits::T1_ENABLE);
+    via.write(0x04, 0x10);
+    via.write(0x05, 0x00);
+    for _ in 0..0x10 {
+      via.poll(1, 0);
+    }
+
+    // peeking at the counter shouldn't clear the flag...
+    let flags = interrupt_bits::MASTER | interrupt_bits::T1_ENABLE;
+    assert_eq!(via.peek(0x04), via.peek(0x04));
+    assert_eq!(flags, via.peek(0x0d));
+
+    // ...but reading it should
+    via.read(0x04);
+    assert_eq!(0, via.peek(0x0d));
+  }
+
   #[test]
   fn test_timer_2() {
     let mut via = Via::new(Box::new(NullPort::new()), Box::new(NullPort::new()));
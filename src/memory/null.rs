@@ -1,21 +1,48 @@
-use crate::memory::{ActiveInterrupt, Memory};
+use crate::memory::{ActiveInterrupt, DataBus, IoSpace, Memory};
 
-/// Memory that does nothing when read or written to.
+/// Memory that does nothing when read or written to. Reads return zero, or
+/// the last value on the data bus if the memory was created with
+/// [`NullMemory::open_bus`].
 #[derive(Default)]
 pub struct NullMemory {
   warn: Option<&'static str>,
+  bus: Option<DataBus>,
 }
 
 impl NullMemory {
   /// Create a new NullMemory that will not warn when read or written to.
   pub fn new() -> Self {
-    Self { warn: None }
+    Self {
+      warn: None,
+      bus: None,
+    }
   }
 
   /// Create a new NullMemory that will warn when read or written to.
   pub fn with_warnings(message: &'static str) -> Self {
     Self {
       warn: Some(message),
+      bus: None,
+    }
+  }
+
+  /// Create a new NullMemory which returns the last value on the given data
+  /// bus when read, as unconnected addresses do on real hardware.
+  pub fn open_bus(bus: DataBus) -> Self {
+    Self {
+      warn: None,
+      bus: Some(bus),
+    }
+  }
+
+  /// Create a new NullMemory for addresses which nothing is connected to.
+  /// With `open_bus`, these return the last value on the data bus when read,
+  /// as the real machine does, rather than zero.
+  pub fn unmapped(bus: &DataBus, open_bus: bool) -> Self {
+    if open_bus {
+      Self::open_bus(bus.clone())
+    } else {
+      Self::new()
     }
   }
 }
@@ -25,7 +52,11 @@ impl Memory for NullMemory {
     if let Some(message) = self.warn {
       println!("attempted to read from {message} at address {address:04x}",);
     }
-    0
+    self.peek(address)
+  }
+
+  fn peek(&self, _address: u16) -> u8 {
+    self.bus.as_ref().map_or(0, DataBus::get)
   }
 
   fn write(&mut self, address: u16, _value: u8) {
@@ -41,6 +72,23 @@ impl Memory for NullMemory {
   }
 }
 
+impl IoSpace for NullMemory {
+  fn read_port(&mut self, port: u16) -> u8 {
+    if let Some(message) = self.warn {
+      println!("attempted to read from {message} at port {port:04x}",);
+    }
+    0
+  }
+
+  fn write_port(&mut self, port: u16, _value: u8) {
+    if let Some(message) = self.warn {
+      println!("attempted to write to {message} at port {port:04x}",);
+    }
+  }
+
+  fn reset(&mut self) {}
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
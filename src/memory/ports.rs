@@ -1,3 +1,5 @@
+use crate::state::{StateError, StateReader, StateWriter};
+
 /// A Port that can be read from, written to, reset, or polled for interrupts.
 /// Used in the MOS 6520 PIA and the 6522 VIA.
 pub trait Port {
@@ -5,6 +7,10 @@ pub trait Port {
   /// side effects.
   fn read(&mut self) -> u8;
 
+  /// Return the byte which a read from the port would return, without any of
+  /// its side effects.
+  fn peek(&self) -> u8;
+
   /// Write a byte to the port. This is implementation-defined.
   fn write(&mut self, value: u8);
 
@@ -14,6 +20,18 @@ pub trait Port {
 
   /// Reset the port to its initial state, analogous to a system reboot.
   fn reset(&mut self);
+
+  /// Write any state held by the port itself (such as a latched output) to
+  /// the given save state.
+  fn save_state(&self, _state: &mut StateWriter) -> Result<(), StateError> {
+    Ok(())
+  }
+
+  /// Restore the port's state from the given save state, reading back exactly
+  /// what [`Port::save_state`] wrote.
+  fn load_state(&mut self, _state: &mut StateReader) -> Result<(), StateError> {
+    Ok(())
+  }
 }
 
 /// A Port that does nothing.
@@ -44,6 +62,10 @@ impl Port for NullPort {
     0
   }
 
+  fn peek(&self) -> u8 {
+    0
+  }
+
   fn write(&mut self, _value: u8) {
     if let Some(message) = self.warn {
       println!("attempted to write to {} at address {:04x}", message, 0);
@@ -0,0 +1,334 @@
+use super::{ActiveInterrupt, Memory};
+use crate::state::{StateError, StateReader, StateWriter};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The kind of memory access which a watchpoint looks for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WatchKind {
+  Read,
+  Write,
+
+  /// Either a read or a write.
+  Access,
+}
+
+/// A condition on memory accesses: which addresses, which kind of access, and
+/// optionally which value must be read or written.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Watch {
+  pub kind: WatchKind,
+
+  /// The first and last addresses watched (inclusive).
+  pub start: u16,
+  pub end: u16,
+
+  /// If set, only accesses which read or write this value are matched.
+  pub value: Option<u8>,
+}
+
+impl Watch {
+  /// Watch for the given kind of access to any address in the range
+  /// (inclusive).
+  pub fn new(kind: WatchKind, start: u16, end: u16) -> Self {
+    Self {
+      kind,
+      start,
+      end,
+      value: None,
+    }
+  }
+
+  /// Only match accesses which read or write the given value.
+  pub fn with_value(mut self, value: u8) -> Self {
+    self.value = Some(value);
+    self
+  }
+
+  /// Return true if the given access meets this condition.
+  pub fn matches(&self, hit: &WatchHit) -> bool {
+    let kind = self.kind == WatchKind::Access || self.kind == hit.kind;
+    let value = self.value.is_none_or(|value| value == hit.value);
+    kind && value && (self.start..=self.end).contains(&hit.address)
+  }
+}
+
+/// A memory access which met the condition of a watchpoint.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WatchHit {
+  /// The number of the watchpoint, as returned when it was added.
+  pub id: usize,
+
+  /// Whether the access was a read or a write.
+  pub kind: WatchKind,
+  pub address: u16,
+  pub value: u8,
+}
+
+/// A function called when a watchpoint is hit.
+type WatchCallback = Rc<RefCell<dyn FnMut(&WatchHit)>>;
+
+/// A watchpoint, and what happens when it is hit.
+struct Watchpoint {
+  id: usize,
+  watch: Watch,
+
+  /// The function to call, or `None` to stop at the next instruction.
+  callback: Option<WatchCallback>,
+}
+
+#[derive(Default)]
+struct WatchState {
+  watchpoints: Vec<Watchpoint>,
+  next_id: usize,
+
+  /// Hits of watchpoints which stop execution, not yet handled.
+  hits: Vec<WatchHit>,
+}
+
+/// A shared list of watchpoints, checked by every [`WatchMemory`] created with
+/// it. Each watchpoint either calls a function when it is hit, or records the
+/// hit so that a debugger can stop before the next instruction (see
+/// [`Watchpoints::take_hit`]).
+#[derive(Clone, Default)]
+pub struct Watchpoints {
+  state: Rc<RefCell<WatchState>>,
+}
+
+impl Watchpoints {
+  /// Create an empty list of watchpoints.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn insert(&self, watch: Watch, callback: Option<WatchCallback>) -> usize {
+    let mut state = self.state.borrow_mut();
+    state.next_id += 1;
+    let id = state.next_id;
+    state.watchpoints.push(Watchpoint {
+      id,
+      watch,
+      callback,
+    });
+    id
+  }
+
+  /// Add a watchpoint which stops execution when it is hit. Returns the
+  /// number of the new watchpoint.
+  pub fn add(&self, watch: Watch) -> usize {
+    self.insert(watch, None)
+  }
+
+  /// Add a watchpoint which calls the given function when it is hit, during
+  /// the access. Returns the number of the new watchpoint.
+  pub fn add_callback(&self, watch: Watch, callback: impl FnMut(&WatchHit) + 'static) -> usize {
+    self.insert(watch, Some(Rc::new(RefCell::new(callback))))
+  }
+
+  /// Remove the watchpoint with the given number. Returns false if there was
+  /// no such watchpoint.
+  pub fn remove(&self, id: usize) -> bool {
+    let mut state = self.state.borrow_mut();
+    let count = state.watchpoints.len();
+    state.watchpoints.retain(|watchpoint| watchpoint.id != id);
+    state.watchpoints.len() != count
+  }
+
+  /// Return the number and condition of every watchpoint.
+  pub fn list(&self) -> Vec<(usize, Watch)> {
+    let state = self.state.borrow();
+    state
+      .watchpoints
+      .iter()
+      .map(|watchpoint| (watchpoint.id, watchpoint.watch))
+      .collect()
+  }
+
+  /// Return the earliest hit of a watchpoint which stops execution, if any
+  /// have been hit since the last call.
+  pub fn take_hit(&self) -> Option<WatchHit> {
+    let mut state = self.state.borrow_mut();
+    if state.hits.is_empty() {
+      None
+    } else {
+      Some(state.hits.remove(0))
+    }
+  }
+
+  /// Forget any hits not yet taken, e.g. those caused by a debugger's own
+  /// accesses to memory.
+  pub fn clear_hits(&self) {
+    self.state.borrow_mut().hits.clear();
+  }
+
+  /// Check an access against every watchpoint.
+  fn check(&self, kind: WatchKind, address: u16, value: u8) {
+    let mut callbacks = Vec::new();
+
+    {
+      let mut state = self.state.borrow_mut();
+      let state = &mut *state;
+      if state.watchpoints.is_empty() {
+        return;
+      }
+
+      for watchpoint in &state.watchpoints {
+        let hit = WatchHit {
+          id: watchpoint.id,
+          kind,
+          address,
+          value,
+        };
+
+        if watchpoint.watch.matches(&hit) {
+          match &watchpoint.callback {
+            Some(callback) => callbacks.push((callback.clone(), hit)),
+            None => state.hits.push(hit),
+          }
+        }
+      }
+    }
+
+    // Called once the list is released, so the callbacks may change it
+    for (callback, hit) in callbacks {
+      (callback.borrow_mut())(&hit);
+    }
+  }
+}
+
+/// Memory which passes every access through to the memory it wraps, checking
+/// each against a list of [`Watchpoints`]. It can wrap a single region within
+/// a [`super::BranchMemory`], with the offset of the region given so that
+/// watchpoints use the addresses seen by the CPU, or the whole address space.
+pub struct WatchMemory {
+  backing: Box<dyn Memory>,
+  watchpoints: Watchpoints,
+  offset: u16,
+}
+
+impl WatchMemory {
+  /// Watch the given memory, which is mapped at the given offset.
+  pub fn new(backing: impl Memory + 'static, watchpoints: Watchpoints, offset: u16) -> Self {
+    Self::from_boxed(Box::new(backing), watchpoints, offset)
+  }
+
+  /// Watch memory which has already been boxed, such as a CPU's memory.
+  pub fn from_boxed(backing: Box<dyn Memory>, watchpoints: Watchpoints, offset: u16) -> Self {
+    Self {
+      backing,
+      watchpoints,
+      offset,
+    }
+  }
+}
+
+impl Memory for WatchMemory {
+  fn read(&mut self, address: u16) -> u8 {
+    let value = self.backing.read(address);
+    self
+      .watchpoints
+      .check(WatchKind::Read, address.wrapping_add(self.offset), value);
+    value
+  }
+
+  fn peek(&self, address: u16) -> u8 {
+    self.backing.peek(address)
+  }
+
+  fn write(&mut self, address: u16, value: u8) {
+    self.backing.write(address, value);
+    self
+      .watchpoints
+      .check(WatchKind::Write, address.wrapping_add(self.offset), value);
+  }
+
+  fn reset(&mut self) {
+    self.backing.reset();
+  }
+
+  fn poll(&mut self, cycles_since_poll: u64, total_cycle_count: u64) -> ActiveInterrupt {
+    self.backing.poll(cycles_since_poll, total_cycle_count)
+  }
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    self.backing.save_state(state)
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    self.backing.load_state(state)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::memory::{BlockMemory, BranchMemory};
+
+  #[test]
+  fn test_watch_region() {
+    let watchpoints = Watchpoints::new();
+    let mut memory = BranchMemory::new()
+      .map(0x0000, BlockMemory::ram(0x1000))
+      .map(
+        0xD000,
+        WatchMemory::new(BlockMemory::ram(0x0400), watchpoints.clone(), 0xD000),
+      );
+
+    let write = watchpoints.add(Watch::new(WatchKind::Write, 0xD020, 0xD021));
+    let zero = watchpoints.add(Watch::new(WatchKind::Access, 0xD000, 0xD3FF).with_value(0));
+
+    memory.write(0x0020, 0x05);
+    assert_eq!(None, watchpoints.take_hit());
+
+    memory.write(0xD020, 0x05);
+    assert_eq!(
+      Some(WatchHit {
+        id: write,
+        kind: WatchKind::Write,
+        address: 0xD020,
+        value: 0x05,
+      }),
+      watchpoints.take_hit()
+    );
+    assert_eq!(None, watchpoints.take_hit());
+
+    memory.read(0xD021);
+    assert_eq!(Some(zero), watchpoints.take_hit().map(|hit| hit.id));
+
+    assert!(watchpoints.remove(zero));
+    assert!(!watchpoints.remove(zero));
+    memory.read(0xD021);
+    assert_eq!(None, watchpoints.take_hit());
+    assert_eq!(
+      vec![write],
+      watchpoints
+        .list()
+        .iter()
+        .map(|(id, _)| *id)
+        .collect::<Vec<_>>()
+    );
+  }
+
+  #[test]
+  fn test_callback() {
+    let watchpoints = Watchpoints::new();
+    let mut memory = WatchMemory::new(BlockMemory::ram(0x100), watchpoints.clone(), 0);
+
+    let reads = Rc::new(RefCell::new(Vec::new()));
+    let log = reads.clone();
+    let list = watchpoints.clone();
+    watchpoints.add_callback(Watch::new(WatchKind::Read, 0x10, 0x10), move |hit| {
+      log.borrow_mut().push(hit.value);
+
+      // a callback can remove its own watchpoint
+      list.remove(hit.id);
+    });
+
+    memory.write(0x10, 0x42);
+    memory.read(0x10);
+    memory.read(0x10);
+
+    assert_eq!(vec![0x42], *reads.borrow());
+    assert_eq!(None, watchpoints.take_hit());
+  }
+}
@@ -35,6 +35,7 @@ pub struct CanvasPlatform {
   provider: Arc<CanvasPlatformProvider>,
   key_state: Arc<Mutex<KeyState<String>>>,
   joystick_state: Arc<Mutex<JoystickState>>,
+  halted: bool,
 }
 
 impl CanvasPlatform {
@@ -62,6 +63,7 @@ impl CanvasPlatform {
       resize_requested,
       key_state,
       joystick_state,
+      halted: false,
     }
   }
 
@@ -138,8 +140,14 @@ impl AsyncPlatform for CanvasPlatform {
   async fn tick(&mut self, system: &mut Box<dyn System>) {
     let mut duration = Duration::ZERO;
 
-    while duration < Duration::from_millis(20) {
-      duration += system.tick();
+    while !self.halted && duration < Duration::from_millis(20) {
+      match system.tick() {
+        Ok(elapsed) => duration += elapsed,
+        Err(error) => {
+          alert(&format!("CPU halted: {error}"));
+          self.halted = true;
+        }
+      }
     }
 
     let pixels = self.pixels.as_mut().unwrap();
@@ -255,6 +263,10 @@ impl PlatformProvider for CanvasPlatformProvider {
     *self.joystick_state.lock().unwrap()
   }
 
+  fn debug_break_requested(&self) -> bool {
+    false
+  }
+
   fn print(&self, text: &str) {
     alert(text);
   }
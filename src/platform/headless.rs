@@ -0,0 +1,570 @@
+use crate::cpu::CpuError;
+use crate::keyboard::{KeyPosition, KeyState, VirtualKey};
+use crate::platform::{
+  JoystickState, Platform, PlatformProvider, SyncPlatform, VideoRecorder, WindowConfig,
+};
+use crate::systems::System;
+use instant::Duration;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{de::IntoDeserializer, Deserialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex};
+
+/// The length of one frame of emulated time. Script events and screenshots
+/// are scheduled in frames.
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// The seed for the random numbers given to the system, so that every run of
+/// the same script behaves identically.
+const RANDOM_SEED: u64 = 0x6502;
+
+/// Something that happens at a given frame of a headless run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptEvent {
+  /// Press the given key, and hold it until it is released.
+  Press(KeyPosition),
+
+  /// Release the given key.
+  Release(KeyPosition),
+
+  /// Set the state of the joystick.
+  Joystick(JoystickState),
+
+  /// Save the current contents of the framebuffer as a PNG to the given path.
+  Screenshot(String),
+}
+
+/// A list of events to play back during a headless run, each happening once
+/// the given number of frames of emulated time have passed.
+///
+/// Scripts can also be written as text, with one event per line:
+///
+/// ```text
+/// # comments start with a hash
+/// 120 press A
+/// 125 release A
+/// 200 joystick up fire
+/// 230 joystick
+/// 300 screenshot ready.png
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputScript {
+  events: Vec<(u64, ScriptEvent)>,
+}
+
+impl InputScript {
+  pub fn new() -> Self {
+    Self { events: Vec::new() }
+  }
+
+  /// Add an event happening at the given frame.
+  pub fn at(mut self, frame: u64, event: ScriptEvent) -> Self {
+    // Keep events in order, with events at the same frame in the order added
+    let index = self.events.partition_point(|(other, _)| *other <= frame);
+    self.events.insert(index, (frame, event));
+
+    self
+  }
+
+  /// Press the given key at one frame, and release it at another.
+  pub fn tap(self, frame: u64, length: u64, key: KeyPosition) -> Self {
+    self
+      .at(frame, ScriptEvent::Press(key))
+      .at(frame + length, ScriptEvent::Release(key))
+  }
+
+  /// Return the events in this script, in the order they happen.
+  pub fn events(&self) -> &[(u64, ScriptEvent)] {
+    &self.events
+  }
+
+  /// Parse a script from its text form.
+  pub fn parse(text: &str) -> Result<Self, String> {
+    let mut script = Self::new();
+
+    for (number, line) in text.lines().enumerate() {
+      let line = line.split('#').next().unwrap().trim();
+      if line.is_empty() {
+        continue;
+      }
+
+      let error = |message: String| format!("line {}: {}", number + 1, message);
+      let mut words = line.split_whitespace();
+
+      let frame = words.next().unwrap();
+      let frame = frame
+        .parse::<u64>()
+        .map_err(|_| error(format!("invalid frame number: {frame}")))?;
+
+      let event = match words.next() {
+        Some("press") => ScriptEvent::Press(parse_key(words.next()).map_err(error)?),
+        Some("release") => ScriptEvent::Release(parse_key(words.next()).map_err(error)?),
+        Some("joystick") => {
+          let mut joystick = JoystickState::empty();
+          for word in words.by_ref() {
+            match word {
+              "up" => joystick.up = true,
+              "down" => joystick.down = true,
+              "left" => joystick.left = true,
+              "right" => joystick.right = true,
+              "fire" => joystick.fire = true,
+              _ => return Err(error(format!("unknown joystick input: {word}"))),
+            }
+          }
+          ScriptEvent::Joystick(joystick)
+        }
+        Some("screenshot") => match words.next() {
+          Some(path) => ScriptEvent::Screenshot(path.to_owned()),
+          None => return Err(error("missing screenshot path".to_owned())),
+        },
+        Some(action) => return Err(error(format!("unknown action: {action}"))),
+        None => return Err(error("missing action".to_owned())),
+      };
+
+      if let Some(word) = words.next() {
+        return Err(error(format!("unexpected argument: {word}")));
+      }
+
+      script = script.at(frame, event);
+    }
+
+    Ok(script)
+  }
+
+  /// Load a script in its text form from the given file.
+  pub fn load(path: &str) -> Result<Self, String> {
+    let text = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+    Self::parse(&text)
+  }
+}
+
+/// Parse the name of a [`KeyPosition`], e.g. `A`, `Digit1` or `Enter`.
+fn parse_key(name: Option<&str>) -> Result<KeyPosition, String> {
+  let name = name.ok_or_else(|| "missing key name".to_owned())?;
+
+  KeyPosition::deserialize(IntoDeserializer::<serde::de::value::Error>::into_deserializer(name))
+    .map_err(|_| format!("unknown key: {name}"))
+}
+
+/// When a headless run should stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunLength {
+  /// Run until the CPU halts.
+  UntilHalted,
+
+  /// Run for the given number of frames of emulated time.
+  Frames(u64),
+
+  /// Run until the CPU has executed the given number of cycles.
+  Cycles(u64),
+}
+
+/// A platform which runs a system without any window or terminal input,
+/// rendering into an in-memory framebuffer instead. Keyboard and joystick
+/// input comes from an [`InputScript`], and screenshots of the framebuffer can
+/// be saved as PNGs at any frame. Random numbers come from a fixed seed, so
+/// runs are repeatable, which makes this platform suitable for automated tests.
+/// The whole run can also be recorded to a video file.
+/// This platform runs synchronously, as fast as possible.
+pub struct HeadlessPlatform {
+  config: Arc<Mutex<Option<WindowConfig>>>,
+  provider: Arc<HeadlessPlatformProvider>,
+  key_state: Arc<Mutex<KeyState<KeyPosition>>>,
+  joystick_state: Arc<Mutex<JoystickState>>,
+  length: RunLength,
+  script: InputScript,
+  state_file: Option<String>,
+  video_file: Option<String>,
+  framebuffer: Vec<u8>,
+  frame: u64,
+}
+
+impl HeadlessPlatform {
+  pub fn new() -> Self {
+    let config = Arc::new(Mutex::new(None));
+    let key_state = Arc::new(Mutex::new(KeyState::new()));
+    let joystick_state = Arc::new(Mutex::new(JoystickState::empty()));
+
+    Self {
+      provider: Arc::new(HeadlessPlatformProvider::new(
+        config.clone(),
+        key_state.clone(),
+        joystick_state.clone(),
+      )),
+      config,
+      key_state,
+      joystick_state,
+      length: RunLength::UntilHalted,
+      script: InputScript::new(),
+      state_file: None,
+      video_file: None,
+      framebuffer: Vec::new(),
+      frame: 0,
+    }
+  }
+
+  /// Stop the run after the given number of frames of emulated time.
+  pub fn with_frames(mut self, frames: u64) -> Self {
+    self.length = RunLength::Frames(frames);
+
+    self
+  }
+
+  /// Stop the run once the CPU has executed the given number of cycles.
+  pub fn with_cycles(mut self, cycles: u64) -> Self {
+    self.length = RunLength::Cycles(cycles);
+
+    self
+  }
+
+  /// Play back the events in the given script during the run.
+  pub fn with_script(mut self, script: InputScript) -> Self {
+    for (frame, event) in script.events {
+      self.script = self.script.at(frame, event);
+    }
+
+    self
+  }
+
+  /// Save a screenshot to the given path once the given number of frames have
+  /// passed.
+  pub fn with_screenshot(mut self, frame: u64, path: &str) -> Self {
+    self.script = self
+      .script
+      .at(frame, ScriptEvent::Screenshot(path.to_owned()));
+
+    self
+  }
+
+  /// Load the save state in the given file once the system has been reset.
+  pub fn with_state_file(mut self, path: &str) -> Self {
+    self.state_file = Some(path.to_owned());
+
+    self
+  }
+
+  /// Record the whole run to the given video file (see [`VideoRecorder`]).
+  pub fn with_video_file(mut self, path: &str) -> Self {
+    self.video_file = Some(path.to_owned());
+
+    self
+  }
+
+  /// Return the window requested by the system, if any.
+  pub fn window(&self) -> Option<WindowConfig> {
+    *self.config.lock().unwrap()
+  }
+
+  /// Return the most recently rendered frame, as RGBA pixels.
+  pub fn framebuffer(&self) -> &[u8] {
+    &self.framebuffer
+  }
+
+  /// Return the number of frames of emulated time that have passed.
+  pub fn frame(&self) -> u64 {
+    self.frame
+  }
+
+  /// Render the current state of the system into the framebuffer, resizing it
+  /// to match the requested window.
+  fn render(&mut self, system: &mut Box<dyn System>) {
+    if let Some(config) = self.window() {
+      let size = (config.width * config.height * 4) as usize;
+      self.framebuffer.resize(size, 0);
+      system.render(&mut self.framebuffer, config);
+    }
+  }
+
+  /// Apply the script events for the current frame.
+  fn run_script(&mut self, system: &mut Box<dyn System>) {
+    let count = self
+      .script
+      .events
+      .partition_point(|(frame, _)| *frame <= self.frame);
+    let mut rendered = false;
+
+    for (_, event) in self.script.events.drain(..count).collect::<Vec<_>>() {
+      match event {
+        ScriptEvent::Press(key) => self.key_state.lock().unwrap().press(key),
+        ScriptEvent::Release(key) => self.key_state.lock().unwrap().release(key),
+        ScriptEvent::Joystick(state) => *self.joystick_state.lock().unwrap() = state,
+        ScriptEvent::Screenshot(path) => {
+          if !rendered {
+            self.render(system);
+            rendered = true;
+          }
+
+          match self.screenshot(&path) {
+            Ok(_) => println!("Saved screenshot to {path}"),
+            Err(error) => println!("Failed to save screenshot to {path}: {error}"),
+          }
+        }
+      }
+    }
+  }
+
+  /// Save the contents of the framebuffer as a PNG.
+  pub fn screenshot(&self, path: &str) -> Result<(), String> {
+    let config = self
+      .window()
+      .ok_or_else(|| "the system has not requested a window".to_owned())?;
+
+    let file = File::create(path).map_err(|error| error.to_string())?;
+    write_png(BufWriter::new(file), config, &self.framebuffer)
+  }
+}
+
+/// Encode the given RGBA framebuffer as a PNG.
+pub fn write_png<W: Write>(
+  writer: W,
+  config: WindowConfig,
+  framebuffer: &[u8],
+) -> Result<(), String> {
+  let mut encoder = png::Encoder::new(writer, config.width, config.height);
+  encoder.set_color(png::ColorType::Rgba);
+  encoder.set_depth(png::BitDepth::Eight);
+
+  let mut writer = encoder.write_header().map_err(|error| error.to_string())?;
+  writer
+    .write_image_data(framebuffer)
+    .map_err(|error| error.to_string())
+}
+
+impl Platform for HeadlessPlatform {
+  fn provider(&self) -> Arc<dyn PlatformProvider> {
+    self.provider.clone()
+  }
+}
+
+impl SyncPlatform for HeadlessPlatform {
+  fn run(&mut self, mut system: Box<dyn System>) {
+    system.reset();
+
+    if let Some(path) = &self.state_file {
+      let result = std::fs::read(path)
+        .map_err(|error| error.to_string())
+        .and_then(|data| system.load_state(&data).map_err(|error| error.to_string()));
+
+      if let Err(error) = result {
+        println!("Failed to load state from {path}: {error}");
+      }
+    }
+
+    let mut video = match (&self.video_file, self.window()) {
+      (Some(path), Some(config)) => match VideoRecorder::create(path, config) {
+        Ok(recorder) => Some(recorder),
+        Err(error) => {
+          println!("Failed to record video to {path}: {error}");
+          None
+        }
+      },
+      (Some(path), None) => {
+        println!("Cannot record video to {path}: the system has not requested a window");
+        None
+      }
+      (None, _) => None,
+    };
+
+    self.frame = 0;
+    let mut elapsed = Duration::ZERO;
+    self.run_script(&mut system);
+
+    loop {
+      let done = match self.length {
+        RunLength::UntilHalted => false,
+        RunLength::Frames(frames) => self.frame >= frames,
+        RunLength::Cycles(cycles) => system.get_cpu_mut().get_cycle_count() >= cycles,
+      };
+
+      if done {
+        break;
+      }
+
+      match system.tick() {
+        Ok(duration) => {
+          elapsed += duration;
+
+          if let (Some(recorder), Some(config)) = (&mut video, self.window()) {
+            if let Err(error) = recorder.capture(&mut system, config, duration) {
+              println!("Failed to record video: {error}");
+              video = None;
+            }
+          }
+        }
+        Err(CpuError::Quit) => break,
+        Err(error) => {
+          println!("CPU halted: {error}");
+          break;
+        }
+      }
+
+      while elapsed >= FRAME {
+        elapsed -= FRAME;
+        self.frame += 1;
+        self.run_script(&mut system);
+      }
+    }
+
+    self.render(&mut system);
+
+    if let Some(recorder) = video {
+      let (path, frames) = (recorder.path().to_owned(), recorder.frames());
+      match recorder.finish() {
+        Ok(_) => println!("Saved {frames} frames of video to {path}"),
+        Err(error) => println!("Failed to save video to {path}: {error}"),
+      }
+    }
+
+    if let Err(msg) = system.cleanup() {
+      println!("Error during cleanup: {}", msg);
+    }
+  }
+}
+
+pub struct HeadlessPlatformProvider {
+  config: Arc<Mutex<Option<WindowConfig>>>,
+  key_state: Arc<Mutex<KeyState<KeyPosition>>>,
+  joystick_state: Arc<Mutex<JoystickState>>,
+  rng: Mutex<StdRng>,
+}
+
+impl HeadlessPlatformProvider {
+  pub fn new(
+    config: Arc<Mutex<Option<WindowConfig>>>,
+    key_state: Arc<Mutex<KeyState<KeyPosition>>>,
+    joystick_state: Arc<Mutex<JoystickState>>,
+  ) -> Self {
+    Self {
+      config,
+      key_state,
+      joystick_state,
+      rng: Mutex::new(StdRng::seed_from_u64(RANDOM_SEED)),
+    }
+  }
+}
+
+impl PlatformProvider for HeadlessPlatformProvider {
+  fn request_window(&self, config: WindowConfig) {
+    *self.config.lock().unwrap() = Some(config);
+  }
+
+  fn get_key_state(&self) -> KeyState<KeyPosition> {
+    self.key_state.lock().unwrap().clone()
+  }
+
+  fn get_virtual_key_state(&self) -> KeyState<VirtualKey> {
+    KeyState::new()
+  }
+
+  fn get_joystick_state(&self) -> JoystickState {
+    *self.joystick_state.lock().unwrap()
+  }
+
+  fn debug_break_requested(&self) -> bool {
+    false
+  }
+
+  fn print(&self, text: &str) {
+    print!("{text}");
+  }
+
+  fn input(&self) -> String {
+    // There is no one to type anything
+    String::new()
+  }
+
+  fn random(&self) -> u8 {
+    self.rng.lock().unwrap().gen()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::roms::RomFile;
+  use crate::systems::{easy::Easy6502System, BuildableSystem};
+
+  /// An Easy6502 program which copies the last key pressed to the first pixel.
+  fn key_to_pixel(platform: &HeadlessPlatform) -> Box<dyn System> {
+    let mut rom = vec![0; 0x8000];
+    // loop: LDA $FF; STA $0200; JMP loop
+    rom[..8].copy_from_slice(&[0xA5, 0xFF, 0x8D, 0x00, 0x02, 0x4C, 0x00, 0x80]);
+    rom[0x7FFC..].copy_from_slice(&[0x00, 0x80, 0x00, 0x80]);
+
+    Easy6502System::build(RomFile::new(rom), (), platform.provider())
+  }
+
+  #[test]
+  fn test_parse_script() {
+    let script = InputScript::parse(
+      "# boot\n\
+       300 screenshot out.png\n\
+       120 press A\n\
+       125 release A # done\n\
+       \n\
+       200 joystick up fire\n",
+    )
+    .unwrap();
+
+    let mut joystick = JoystickState::empty();
+    joystick.up = true;
+    joystick.fire = true;
+
+    assert_eq!(
+      InputScript::new()
+        .tap(120, 5, KeyPosition::A)
+        .at(200, ScriptEvent::Joystick(joystick))
+        .at(300, ScriptEvent::Screenshot("out.png".to_owned())),
+      script
+    );
+
+    assert!(InputScript::parse("10 press Nope").is_err());
+    assert!(InputScript::parse("ten press A").is_err());
+    assert!(InputScript::parse("10 jump").is_err());
+  }
+
+  #[test]
+  fn test_framebuffer() {
+    let mut platform = HeadlessPlatform::new()
+      .with_frames(10)
+      .with_script(InputScript::new().at(5, ScriptEvent::Press(KeyPosition::A)));
+    let system = key_to_pixel(&platform);
+
+    platform.run(system);
+
+    assert_eq!(10, platform.frame());
+    assert_eq!(Some(WindowConfig::new(32, 32, 8.0)), platform.window());
+    assert_eq!(32 * 32 * 4, platform.framebuffer().len());
+
+    // 'A' is 0x41, which is white
+    assert_eq!(&[0xFF, 0xFF, 0xFF, 0xFF], &platform.framebuffer()[..4]);
+    assert_eq!(&[0x00, 0x00, 0x00, 0xFF], &platform.framebuffer()[4..8]);
+  }
+
+  #[test]
+  fn test_screenshot() {
+    let path = std::env::temp_dir().join("noentiendo_headless_test.png");
+    let path = path.to_str().unwrap();
+
+    let mut platform = HeadlessPlatform::new()
+      .with_cycles(20_000)
+      .with_script(InputScript::new().tap(2, 10, KeyPosition::A))
+      .with_screenshot(5, path);
+    let system = key_to_pixel(&platform);
+
+    platform.run(system);
+
+    let decoder = png::Decoder::new(File::open(path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut image = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut image).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!((32, 32), (info.width, info.height));
+    assert_eq!(&[0xFF, 0xFF, 0xFF, 0xFF], &image[..4]);
+
+    // the key was released before the run finished
+    assert_eq!(&[0x00, 0x00, 0x00, 0xFF], &platform.framebuffer()[..4]);
+  }
+}
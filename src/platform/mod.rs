@@ -1,22 +1,40 @@
 use crate::keyboard::{KeyPosition, KeyState, VirtualKey};
 use crate::systems::System;
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 #[cfg(target_arch = "wasm32")]
 mod canvas;
 
+#[cfg(not(target_arch = "wasm32"))]
+mod headless;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod movie;
+
 #[cfg(not(target_arch = "wasm32"))]
 mod text;
 
+#[cfg(not(target_arch = "wasm32"))]
+mod video;
+
 #[cfg(not(target_arch = "wasm32"))]
 mod winit;
 
 #[cfg(target_arch = "wasm32")]
 pub use self::canvas::{CanvasPlatform, CanvasPlatformProvider};
 #[cfg(not(target_arch = "wasm32"))]
+pub use self::headless::{
+  write_png, HeadlessPlatform, HeadlessPlatformProvider, InputScript, ScriptEvent,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::movie::{InputMovie, MovieSystem, RecordingProvider, ReplayProvider};
+#[cfg(not(target_arch = "wasm32"))]
 pub use self::text::{TextPlatform, TextPlatformProvider};
 #[cfg(not(target_arch = "wasm32"))]
+pub use self::video::{VideoFormat, VideoRecorder, VIDEO_FRAME_RATE};
+#[cfg(not(target_arch = "wasm32"))]
 pub use self::winit::{WinitPlatform, WinitPlatformProvider};
 
 /// A Platform provides platform-specific functionality to the emulator.
@@ -71,7 +89,7 @@ impl Color {
 }
 
 /// Represents the current state of the connected joystick.
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct JoystickState {
   pub up: bool,
   pub down: bool,
@@ -129,6 +147,10 @@ pub trait PlatformProvider {
   /// If no joystick is connected, this should return a default state.
   fn get_joystick_state(&self) -> JoystickState;
 
+  /// Return true if the user has asked to break into the debugger (e.g. by
+  /// pressing a hotkey) since this was last called.
+  fn debug_break_requested(&self) -> bool;
+
   /// Display the given string to the user, "out-of-band" from any other
   /// graphics. This is used for text-mode systems. Implementations may choose
   /// various ways to display this, such as a terminal message or a pop-up.
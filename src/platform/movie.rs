@@ -0,0 +1,468 @@
+use crate::cpu::{Cpu, CpuError};
+use crate::debugger::DebugHandler;
+use crate::keyboard::{KeyPosition, KeyState, VirtualKey};
+use crate::platform::{JoystickState, PlatformProvider, WindowConfig};
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::systems::System;
+use crate::trace::TraceHandler;
+use instant::Duration;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Stored at the start of every movie, to tell it apart from a save state.
+const MOVIE_TAG: &str = "noentiendo input movie";
+
+/// A single sample of one of the host's inputs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum InputSample {
+  Keys(KeyState<KeyPosition>),
+  VirtualKeys(KeyState<VirtualKey>),
+  Joystick(JoystickState),
+  Random(u8),
+}
+
+/// A recording of the host inputs read by a system, each tagged with the CPU
+/// cycle count at which it was read. The state of the keyboard and joystick
+/// is only recorded when it changes, while every random number is recorded.
+#[derive(Default, Serialize, Deserialize)]
+pub struct InputMovie {
+  samples: Vec<(u64, InputSample)>,
+}
+
+impl InputMovie {
+  /// Create an empty movie.
+  pub fn new() -> Self {
+    Self {
+      samples: Vec::new(),
+    }
+  }
+
+  /// Return the number of samples in this movie.
+  pub fn len(&self) -> usize {
+    self.samples.len()
+  }
+
+  /// Return true if this movie contains no samples.
+  pub fn is_empty(&self) -> bool {
+    self.samples.is_empty()
+  }
+
+  /// Encode this movie in the same binary format as save states.
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut writer = StateWriter::new();
+    writer
+      .write(&(MOVIE_TAG, self))
+      .expect("Failed to encode movie");
+    writer.finish()
+  }
+
+  /// Decode a movie written by [`InputMovie::to_bytes`].
+  pub fn from_bytes(data: &[u8]) -> Result<Self, StateError> {
+    let mut reader = StateReader::new(data)?;
+    let (tag, movie): (String, InputMovie) = reader.read()?;
+
+    if tag != MOVIE_TAG {
+      return Err(StateError::Mismatch("not an input movie".to_owned()));
+    }
+
+    reader.finish()?;
+    Ok(movie)
+  }
+
+  /// Save this movie to the given file.
+  pub fn save(&self, path: &str) -> std::io::Result<()> {
+    std::fs::write(path, self.to_bytes())
+  }
+
+  /// Load a movie from the given file.
+  pub fn load(path: &str) -> Result<Self, String> {
+    let data = std::fs::read(path).map_err(|error| error.to_string())?;
+    Self::from_bytes(&data).map_err(|error| error.to_string())
+  }
+}
+
+/// The movie being recorded, along with the last recorded state of each input.
+struct Recording {
+  movie: InputMovie,
+  keys: Option<KeyState<KeyPosition>>,
+  virtual_keys: Option<KeyState<VirtualKey>>,
+  joystick: Option<JoystickState>,
+}
+
+/// Record a sample if the input it came from has changed since last recorded.
+fn record_change<T: Clone + PartialEq>(
+  last: &mut Option<T>,
+  value: &T,
+  samples: &mut Vec<(u64, InputSample)>,
+  cycle: u64,
+  sample: fn(T) -> InputSample,
+) {
+  if last.as_ref() != Some(value) {
+    *last = Some(value.clone());
+    samples.push((cycle, sample(value.clone())));
+  }
+}
+
+/// A PlatformProvider which passes through another provider, recording every
+/// keyboard, joystick, and random number input into an [`InputMovie`]. Each
+/// input is tagged with the cycle count in the given clock, which should be
+/// kept up to date by a [`MovieSystem`].
+pub struct RecordingProvider {
+  inner: Arc<dyn PlatformProvider>,
+  clock: Arc<AtomicU64>,
+  recording: Mutex<Recording>,
+}
+
+impl RecordingProvider {
+  pub fn new(inner: Arc<dyn PlatformProvider>, clock: Arc<AtomicU64>) -> Self {
+    Self {
+      inner,
+      clock,
+      recording: Mutex::new(Recording {
+        movie: InputMovie::new(),
+        keys: None,
+        virtual_keys: None,
+        joystick: None,
+      }),
+    }
+  }
+
+  /// Save the inputs recorded so far to the given file.
+  pub fn save(&self, path: &str) -> std::io::Result<()> {
+    self.recording.lock().unwrap().movie.save(path)
+  }
+
+  fn cycle(&self) -> u64 {
+    self.clock.load(Ordering::Relaxed)
+  }
+}
+
+impl PlatformProvider for RecordingProvider {
+  fn request_window(&self, config: WindowConfig) {
+    self.inner.request_window(config);
+  }
+
+  fn get_key_state(&self) -> KeyState<KeyPosition> {
+    let state = self.inner.get_key_state();
+    let recording = &mut *self.recording.lock().unwrap();
+    record_change(
+      &mut recording.keys,
+      &state,
+      &mut recording.movie.samples,
+      self.cycle(),
+      InputSample::Keys,
+    );
+    state
+  }
+
+  fn get_virtual_key_state(&self) -> KeyState<VirtualKey> {
+    let state = self.inner.get_virtual_key_state();
+    let recording = &mut *self.recording.lock().unwrap();
+    record_change(
+      &mut recording.virtual_keys,
+      &state,
+      &mut recording.movie.samples,
+      self.cycle(),
+      InputSample::VirtualKeys,
+    );
+    state
+  }
+
+  fn get_joystick_state(&self) -> JoystickState {
+    let state = self.inner.get_joystick_state();
+    let recording = &mut *self.recording.lock().unwrap();
+    record_change(
+      &mut recording.joystick,
+      &state,
+      &mut recording.movie.samples,
+      self.cycle(),
+      InputSample::Joystick,
+    );
+    state
+  }
+
+  fn debug_break_requested(&self) -> bool {
+    self.inner.debug_break_requested()
+  }
+
+  fn print(&self, text: &str) {
+    self.inner.print(text);
+  }
+
+  fn input(&self) -> String {
+    self.inner.input()
+  }
+
+  fn random(&self) -> u8 {
+    let value = self.inner.random();
+    let mut recording = self.recording.lock().unwrap();
+    let cycle = self.cycle();
+    recording
+      .movie
+      .samples
+      .push((cycle, InputSample::Random(value)));
+    value
+  }
+}
+
+/// The recorded states of one input, played back in order of cycle count.
+struct Track<T> {
+  samples: VecDeque<(u64, T)>,
+  current: T,
+}
+
+impl<T: Clone> Track<T> {
+  fn new(initial: T) -> Self {
+    Self {
+      samples: VecDeque::new(),
+      current: initial,
+    }
+  }
+
+  /// Return the most recent state recorded at or before the given cycle.
+  fn at(&mut self, cycle: u64) -> T {
+    while let Some((_, state)) = self.samples.front().filter(|(at, _)| *at <= cycle) {
+      self.current = state.clone();
+      self.samples.pop_front();
+    }
+
+    self.current.clone()
+  }
+}
+
+/// The inputs remaining to be played back.
+struct Replay {
+  keys: Track<KeyState<KeyPosition>>,
+  virtual_keys: Track<KeyState<VirtualKey>>,
+  joystick: Track<JoystickState>,
+  random: VecDeque<u8>,
+}
+
+/// A PlatformProvider which plays back the inputs in an [`InputMovie`] instead
+/// of reading the host's keyboard, joystick, and random number generator, so
+/// that a recorded session runs exactly the same way again. Each input is
+/// played back at the cycle count it was recorded at, according to the given
+/// clock. Everything else is passed through to another provider.
+pub struct ReplayProvider {
+  inner: Arc<dyn PlatformProvider>,
+  clock: Arc<AtomicU64>,
+  replay: Mutex<Replay>,
+}
+
+impl ReplayProvider {
+  pub fn new(inner: Arc<dyn PlatformProvider>, movie: InputMovie, clock: Arc<AtomicU64>) -> Self {
+    let mut replay = Replay {
+      keys: Track::new(KeyState::new()),
+      virtual_keys: Track::new(KeyState::new()),
+      joystick: Track::new(JoystickState::empty()),
+      random: VecDeque::new(),
+    };
+
+    for (cycle, sample) in movie.samples {
+      match sample {
+        InputSample::Keys(state) => replay.keys.samples.push_back((cycle, state)),
+        InputSample::VirtualKeys(state) => replay.virtual_keys.samples.push_back((cycle, state)),
+        InputSample::Joystick(state) => replay.joystick.samples.push_back((cycle, state)),
+        InputSample::Random(value) => replay.random.push_back(value),
+      }
+    }
+
+    Self {
+      inner,
+      clock,
+      replay: Mutex::new(replay),
+    }
+  }
+
+  fn cycle(&self) -> u64 {
+    self.clock.load(Ordering::Relaxed)
+  }
+}
+
+impl PlatformProvider for ReplayProvider {
+  fn request_window(&self, config: WindowConfig) {
+    self.inner.request_window(config);
+  }
+
+  fn get_key_state(&self) -> KeyState<KeyPosition> {
+    self.replay.lock().unwrap().keys.at(self.cycle())
+  }
+
+  fn get_virtual_key_state(&self) -> KeyState<VirtualKey> {
+    self.replay.lock().unwrap().virtual_keys.at(self.cycle())
+  }
+
+  fn get_joystick_state(&self) -> JoystickState {
+    self.replay.lock().unwrap().joystick.at(self.cycle())
+  }
+
+  fn debug_break_requested(&self) -> bool {
+    self.inner.debug_break_requested()
+  }
+
+  fn print(&self, text: &str) {
+    self.inner.print(text);
+  }
+
+  fn input(&self) -> String {
+    self.inner.input()
+  }
+
+  fn random(&self) -> u8 {
+    match self.replay.lock().unwrap().random.pop_front() {
+      Some(value) => value,
+      None => {
+        println!("WARNING: Input movie has run out of random numbers");
+        self.inner.random()
+      }
+    }
+  }
+}
+
+/// Wraps a system whose inputs are being recorded or replayed, keeping the
+/// clock used to tag each input in step with the system's CPU. When recording,
+/// the movie is saved once the system is cleaned up.
+pub struct MovieSystem {
+  system: Box<dyn System>,
+  clock: Arc<AtomicU64>,
+  recording: Option<(Arc<RecordingProvider>, String)>,
+}
+
+impl MovieSystem {
+  pub fn new(system: Box<dyn System>, clock: Arc<AtomicU64>) -> Self {
+    Self {
+      system,
+      clock,
+      recording: None,
+    }
+  }
+
+  /// Save the inputs recorded by the given provider to the given file when the
+  /// system is cleaned up.
+  pub fn with_recording(mut self, provider: Arc<RecordingProvider>, path: &str) -> Self {
+    self.recording = Some((provider, path.to_owned()));
+
+    self
+  }
+}
+
+impl System for MovieSystem {
+  fn get_cpu_mut(&mut self) -> Box<&mut dyn Cpu> {
+    self.system.get_cpu_mut()
+  }
+
+  fn attach_trace_handler(&mut self, handler: Box<dyn TraceHandler>) {
+    self.system.attach_trace_handler(handler);
+  }
+
+  fn attach_debugger(&mut self, debugger: Box<dyn DebugHandler>) {
+    self.system.attach_debugger(debugger);
+  }
+
+  fn set_cycle_stepped(&mut self, enabled: bool) {
+    self.system.set_cycle_stepped(enabled);
+  }
+
+  fn tick(&mut self) -> Result<Duration, CpuError> {
+    let cycle = self.system.get_cpu_mut().get_cycle_count();
+    self.clock.store(cycle, Ordering::Relaxed);
+    self.system.tick()
+  }
+
+  fn reset(&mut self) {
+    self.system.reset();
+  }
+
+  fn render(&mut self, framebuffer: &mut [u8], window: WindowConfig) {
+    self.system.render(framebuffer, window);
+  }
+
+  fn cleanup(&mut self) -> Result<(), &str> {
+    if let Some((provider, path)) = &self.recording {
+      match provider.save(path) {
+        Ok(_) => println!("Saved input movie to {path}"),
+        Err(error) => println!("Failed to save input movie to {path}: {error}"),
+      }
+    }
+
+    self.system.cleanup()
+  }
+
+  fn save_state(&mut self) -> Result<Vec<u8>, StateError> {
+    self.system.save_state()
+  }
+
+  fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+    self.system.load_state(data)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::platform::TextPlatformProvider;
+
+  #[test]
+  fn test_record_replay() {
+    let clock = Arc::new(AtomicU64::new(0));
+    let recorder = RecordingProvider::new(Arc::new(TextPlatformProvider::new()), clock.clone());
+
+    let mut recorded = Vec::new();
+    for cycle in 0..100 {
+      clock.store(cycle * 10, Ordering::Relaxed);
+      recorded.push(recorder.random());
+      recorder.get_key_state();
+      recorder.get_joystick_state();
+    }
+
+    let movie = recorder.recording.lock().unwrap().movie.to_bytes();
+    let movie = InputMovie::from_bytes(&movie).unwrap();
+
+    // the key and joystick states never changed
+    assert_eq!(102, movie.len());
+
+    let clock = Arc::new(AtomicU64::new(0));
+    let replayer = ReplayProvider::new(Arc::new(TextPlatformProvider::new()), movie, clock.clone());
+
+    for (cycle, expected) in recorded.into_iter().enumerate() {
+      clock.store(cycle as u64 * 10, Ordering::Relaxed);
+      assert_eq!(expected, replayer.random());
+      assert_eq!(KeyState::new(), replayer.get_key_state());
+    }
+  }
+
+  #[test]
+  fn test_replay_timing() {
+    let mut pressed = KeyState::new();
+    pressed.press(KeyPosition::A);
+
+    let movie = InputMovie {
+      samples: vec![
+        (0, InputSample::Keys(KeyState::new())),
+        (100, InputSample::Keys(pressed.clone())),
+        (200, InputSample::Keys(KeyState::new())),
+      ],
+    };
+
+    let clock = Arc::new(AtomicU64::new(0));
+    let replayer = ReplayProvider::new(Arc::new(TextPlatformProvider::new()), movie, clock.clone());
+
+    assert_eq!(KeyState::new(), replayer.get_key_state());
+    clock.store(99, Ordering::Relaxed);
+    assert_eq!(KeyState::new(), replayer.get_key_state());
+    clock.store(100, Ordering::Relaxed);
+    assert_eq!(pressed, replayer.get_key_state());
+    clock.store(150, Ordering::Relaxed);
+    assert_eq!(pressed, replayer.get_key_state());
+    clock.store(250, Ordering::Relaxed);
+    assert_eq!(KeyState::new(), replayer.get_key_state());
+  }
+
+  #[test]
+  fn test_not_a_movie() {
+    let state = StateWriter::new().finish();
+    assert!(InputMovie::from_bytes(&state).is_err());
+  }
+}
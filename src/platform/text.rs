@@ -1,3 +1,4 @@
+use crate::cpu::CpuError;
 use crate::keyboard::{KeyPosition, KeyState, VirtualKey};
 use crate::platform::{Platform, PlatformProvider, SyncPlatform, WindowConfig};
 use crate::systems::System;
@@ -32,7 +33,18 @@ impl SyncPlatform for TextPlatform {
     let mut timer = FixedTimeStep::new(60.0, Duration::from_secs_f64(1.0 / 60.0));
 
     loop {
-      timer.do_update(&mut || system.tick());
+      match timer.do_update(&mut || system.tick()) {
+        Ok(_) => {}
+        Err(CpuError::Quit) => break,
+        Err(error) => {
+          println!("CPU halted: {error}");
+          break;
+        }
+      }
+    }
+
+    if let Err(msg) = system.cleanup() {
+      println!("Error during cleanup: {}", msg);
     }
   }
 }
@@ -60,6 +72,10 @@ impl PlatformProvider for TextPlatformProvider {
     JoystickState::empty()
   }
 
+  fn debug_break_requested(&self) -> bool {
+    false
+  }
+
   fn print(&self, text: &str) {
     print!("{text}");
   }
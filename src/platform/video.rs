@@ -0,0 +1,421 @@
+use crate::platform::WindowConfig;
+use crate::systems::System;
+use instant::Duration;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// The number of frames recorded per second of emulated time.
+pub const VIDEO_FRAME_RATE: u32 = 60;
+
+/// The format of a video file, chosen by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoFormat {
+  /// Uncompressed YUV4MPEG2 (`.y4m`), which most video tools (e.g. ffmpeg)
+  /// can convert to other formats without any loss.
+  Y4m,
+
+  /// An animated GIF (`.gif`), which can be attached directly to a bug report.
+  Gif,
+}
+
+impl VideoFormat {
+  /// Choose the format for the given path from its extension.
+  pub fn from_path(path: &str) -> Option<Self> {
+    let extension = path.rsplit_once('.')?.1.to_ascii_lowercase();
+
+    match extension.as_str() {
+      "y4m" => Some(VideoFormat::Y4m),
+      "gif" => Some(VideoFormat::Gif),
+      _ => None,
+    }
+  }
+}
+
+/// Writes frames to a YUV4MPEG2 file, with full resolution color (4:4:4).
+struct Y4mEncoder {
+  writer: BufWriter<File>,
+  planes: Vec<u8>,
+}
+
+impl Y4mEncoder {
+  fn new(mut writer: BufWriter<File>, width: u32, height: u32) -> Result<Self, String> {
+    writeln!(
+      writer,
+      "YUV4MPEG2 W{width} H{height} F{VIDEO_FRAME_RATE}:1 Ip A1:1 C444"
+    )
+    .map_err(|error| error.to_string())?;
+
+    Ok(Self {
+      writer,
+      planes: Vec::new(),
+    })
+  }
+
+  fn write_frame(&mut self, frame: &[u8]) -> Result<(), String> {
+    let pixels = frame.len() / 4;
+    self.planes.resize(pixels * 3, 0);
+    let (y, uv) = self.planes.split_at_mut(pixels);
+    let (u, v) = uv.split_at_mut(pixels);
+
+    // BT.601, limited range
+    for (i, pixel) in frame.chunks_exact(4).enumerate() {
+      let (r, g, b) = (pixel[0] as i32, pixel[1] as i32, pixel[2] as i32);
+      y[i] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+      u[i] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+      v[i] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+    }
+
+    self
+      .writer
+      .write_all(b"FRAME\n")
+      .and_then(|_| self.writer.write_all(&self.planes))
+      .map_err(|error| error.to_string())
+  }
+
+  fn finish(mut self) -> Result<(), String> {
+    self.writer.flush().map_err(|error| error.to_string())
+  }
+}
+
+/// Writes frames to an animated GIF. GIF delays are in hundredths of a second,
+/// so identical frames are merged into one, and delays are rounded such that
+/// the video as a whole keeps to the emulated frame rate.
+struct GifEncoder {
+  encoder: gif::Encoder<BufWriter<File>>,
+  width: u16,
+  height: u16,
+
+  /// The most recent frame, which has not been written yet in case the next
+  /// frame is the same.
+  pending: Option<Vec<u8>>,
+
+  /// The index of the first frame that the pending frame stands in for.
+  pending_start: u64,
+
+  /// The number of frames received so far.
+  frames: u64,
+}
+
+impl GifEncoder {
+  fn new(writer: BufWriter<File>, width: u32, height: u32) -> Result<Self, String> {
+    let (width, height) = (
+      u16::try_from(width).map_err(|_| "frame too wide for a GIF".to_owned())?,
+      u16::try_from(height).map_err(|_| "frame too tall for a GIF".to_owned())?,
+    );
+
+    let mut encoder = gif::Encoder::new(writer, width, height, &[]).map_err(|e| e.to_string())?;
+    encoder
+      .set_repeat(gif::Repeat::Infinite)
+      .map_err(|error| error.to_string())?;
+
+    Ok(Self {
+      encoder,
+      width,
+      height,
+      pending: None,
+      pending_start: 0,
+      frames: 0,
+    })
+  }
+
+  fn write_frame(&mut self, frame: &[u8]) -> Result<(), String> {
+    if self.pending.as_deref() != Some(frame) {
+      self.flush()?;
+      self.pending = Some(frame.to_vec());
+      self.pending_start = self.frames;
+    }
+
+    self.frames += 1;
+    Ok(())
+  }
+
+  /// Write out the pending frame, lasting until the current frame.
+  fn flush(&mut self) -> Result<(), String> {
+    let mut pixels = match self.pending.take() {
+      Some(pixels) => pixels,
+      None => return Ok(()),
+    };
+
+    let centiseconds =
+      |frame: u64| (frame * 100 + VIDEO_FRAME_RATE as u64 / 2) / VIDEO_FRAME_RATE as u64;
+    let delay = centiseconds(self.frames) - centiseconds(self.pending_start);
+
+    let mut frame = indexed_frame(self.width, self.height, &pixels)
+      .unwrap_or_else(|| gif::Frame::from_rgba_speed(self.width, self.height, &mut pixels, 10));
+    frame.delay = delay.min(u16::MAX as u64) as u16;
+
+    self
+      .encoder
+      .write_frame(&frame)
+      .map_err(|error| error.to_string())
+  }
+
+  fn finish(mut self) -> Result<(), String> {
+    self.flush()?;
+    self
+      .encoder
+      .into_inner()
+      .and_then(|mut writer| writer.flush())
+      .map_err(|error| error.to_string())
+  }
+}
+
+/// Build a GIF frame using exactly the colors in the given RGBA pixels, if
+/// there are few enough of them to fit in a palette. Retro systems rarely
+/// have more than a handful of colors, so this avoids any quantization.
+fn indexed_frame(width: u16, height: u16, pixels: &[u8]) -> Option<gif::Frame<'static>> {
+  let mut colors: HashMap<[u8; 3], u8> = HashMap::new();
+  let mut palette = Vec::new();
+  let mut indices = Vec::with_capacity(pixels.len() / 4);
+
+  for pixel in pixels.chunks_exact(4) {
+    let color = [pixel[0], pixel[1], pixel[2]];
+
+    let index = match colors.get(&color) {
+      Some(index) => *index,
+      None => {
+        let index = u8::try_from(colors.len()).ok()?;
+        colors.insert(color, index);
+        palette.extend_from_slice(&color);
+        index
+      }
+    };
+
+    indices.push(index);
+  }
+
+  Some(gif::Frame::from_palette_pixels(
+    width, height, &indices, &palette, None,
+  ))
+}
+
+enum Encoder {
+  Y4m(Y4mEncoder),
+  Gif(GifEncoder),
+}
+
+/// Records the frames rendered by a system to a video file. Frames are taken
+/// at a fixed rate of emulated (not real) time, so the video plays back at the
+/// speed of the original system even if the host could not keep up. The size
+/// of the video is fixed by the window requested when recording starts; if the
+/// system requests a different window later, its frames are cropped or padded
+/// to fit.
+///
+/// The emulated systems do not produce audio yet, so only video is recorded.
+pub struct VideoRecorder {
+  path: String,
+  encoder: Encoder,
+  config: WindowConfig,
+  elapsed: Duration,
+  frames: u64,
+  framebuffer: Vec<u8>,
+  frame: Vec<u8>,
+}
+
+impl VideoRecorder {
+  /// Start recording to the given file, with frames of the given size. The
+  /// format is chosen from the file's extension.
+  pub fn create(path: &str, config: WindowConfig) -> Result<Self, String> {
+    let format = VideoFormat::from_path(path)
+      .ok_or_else(|| format!("unknown video format for {path}, expected .y4m or .gif"))?;
+    let writer = BufWriter::new(File::create(path).map_err(|error| error.to_string())?);
+
+    let encoder = match format {
+      VideoFormat::Y4m => Encoder::Y4m(Y4mEncoder::new(writer, config.width, config.height)?),
+      VideoFormat::Gif => Encoder::Gif(GifEncoder::new(writer, config.width, config.height)?),
+    };
+
+    Ok(Self {
+      path: path.to_owned(),
+      encoder,
+      config,
+      elapsed: Duration::ZERO,
+      frames: 0,
+      framebuffer: Vec::new(),
+      frame: vec![0; (config.width * config.height * 4) as usize],
+    })
+  }
+
+  /// Return the path of the file being recorded to.
+  pub fn path(&self) -> &str {
+    &self.path
+  }
+
+  /// Return the number of frames recorded so far.
+  pub fn frames(&self) -> u64 {
+    self.frames
+  }
+
+  /// Add a frame to the video, given as RGBA pixels in a window of the given
+  /// size.
+  pub fn push_frame(&mut self, framebuffer: &[u8], config: WindowConfig) -> Result<(), String> {
+    let frame = if (config.width, config.height) == (self.config.width, self.config.height) {
+      framebuffer
+    } else {
+      fit(framebuffer, config, &mut self.frame, self.config);
+      &self.frame
+    };
+
+    match &mut self.encoder {
+      Encoder::Y4m(encoder) => encoder.write_frame(frame)?,
+      Encoder::Gif(encoder) => encoder.write_frame(frame)?,
+    }
+
+    self.frames += 1;
+    Ok(())
+  }
+
+  /// Advance the recording by the given amount of emulated time, rendering
+  /// the system into the video for each frame that has passed.
+  pub fn capture(
+    &mut self,
+    system: &mut Box<dyn System>,
+    config: WindowConfig,
+    elapsed: Duration,
+  ) -> Result<(), String> {
+    let frame_length = Duration::from_secs(1) / VIDEO_FRAME_RATE;
+    self.elapsed += elapsed;
+
+    if self.elapsed < frame_length {
+      return Ok(());
+    }
+
+    // If several frames have passed at once, they all show the current state
+    self
+      .framebuffer
+      .resize((config.width * config.height * 4) as usize, 0);
+    system.render(&mut self.framebuffer, config);
+    let framebuffer = std::mem::take(&mut self.framebuffer);
+
+    let mut result = Ok(());
+    while self.elapsed >= frame_length && result.is_ok() {
+      self.elapsed -= frame_length;
+      result = self.push_frame(&framebuffer, config);
+    }
+
+    self.framebuffer = framebuffer;
+    result
+  }
+
+  /// Finish writing the video file.
+  pub fn finish(self) -> Result<(), String> {
+    match self.encoder {
+      Encoder::Y4m(encoder) => encoder.finish(),
+      Encoder::Gif(encoder) => encoder.finish(),
+    }
+  }
+}
+
+/// Copy a frame of one size into a frame of another, cropping off anything
+/// outside of it and filling any remaining space with black.
+fn fit(source: &[u8], source_config: WindowConfig, dest: &mut [u8], dest_config: WindowConfig) {
+  let width = source_config.width.min(dest_config.width) as usize * 4;
+  let height = source_config.height.min(dest_config.height) as usize;
+
+  for pixel in dest.chunks_exact_mut(4) {
+    pixel.copy_from_slice(&[0, 0, 0, 255]);
+  }
+
+  for y in 0..height {
+    let source_row = y * source_config.width as usize * 4;
+    let dest_row = y * dest_config.width as usize * 4;
+    dest[dest_row..dest_row + width].copy_from_slice(&source[source_row..source_row + width]);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn solid(config: WindowConfig, color: [u8; 4]) -> Vec<u8> {
+    color.repeat((config.width * config.height) as usize)
+  }
+
+  #[test]
+  fn test_format() {
+    assert_eq!(Some(VideoFormat::Gif), VideoFormat::from_path("bug.GIF"));
+    assert_eq!(
+      Some(VideoFormat::Y4m),
+      VideoFormat::from_path("./out/demo.y4m")
+    );
+    assert_eq!(None, VideoFormat::from_path("video.mp4"));
+    assert_eq!(None, VideoFormat::from_path("video"));
+    assert!(VideoRecorder::create("video.mp4", WindowConfig::new(4, 4, 1.0)).is_err());
+  }
+
+  #[test]
+  fn test_y4m() {
+    let path = std::env::temp_dir().join("noentiendo_video_test.y4m");
+    let path = path.to_str().unwrap();
+    let config = WindowConfig::new(4, 2, 1.0);
+
+    let mut recorder = VideoRecorder::create(path, config).unwrap();
+    recorder
+      .push_frame(&solid(config, [0, 0, 0, 255]), config)
+      .unwrap();
+    recorder
+      .push_frame(&solid(config, [255, 255, 255, 255]), config)
+      .unwrap();
+    recorder.finish().unwrap();
+
+    let data = std::fs::read(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    let header = b"YUV4MPEG2 W4 H2 F60:1 Ip A1:1 C444\n";
+    assert_eq!(header, &data[..header.len()]);
+
+    let frames: Vec<&[u8]> = data[header.len()..].chunks(6 + 4 * 2 * 3).collect();
+    assert_eq!(2, frames.len());
+    assert_eq!(b"FRAME\n", &frames[0][..6]);
+    assert_eq!((16, 128, 128), (frames[0][6], frames[0][14], frames[0][22]));
+    assert_eq!(
+      (235, 128, 128),
+      (frames[1][6], frames[1][14], frames[1][22])
+    );
+  }
+
+  #[test]
+  fn test_gif() {
+    let path = std::env::temp_dir().join("noentiendo_video_test.gif");
+    let path = path.to_str().unwrap();
+    let config = WindowConfig::new(8, 8, 1.0);
+    let red = solid(config, [255, 0, 0, 255]);
+    let blue = solid(config, [0, 0, 255, 255]);
+
+    let mut recorder = VideoRecorder::create(path, config).unwrap();
+    for _ in 0..30 {
+      recorder.push_frame(&red, config).unwrap();
+    }
+    for _ in 0..30 {
+      recorder.push_frame(&blue, config).unwrap();
+    }
+    // a smaller frame is padded with black
+    recorder
+      .push_frame(
+        &solid(WindowConfig::new(4, 4, 1.0), [0, 0, 255, 255]),
+        WindowConfig::new(4, 4, 1.0),
+      )
+      .unwrap();
+    assert_eq!(61, recorder.frames());
+    recorder.finish().unwrap();
+
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(File::open(path).unwrap()).unwrap();
+
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+      frames.push((frame.delay, frame.buffer.to_vec()));
+    }
+    std::fs::remove_file(path).unwrap();
+
+    // identical frames are merged
+    assert_eq!(3, frames.len());
+    assert_eq!((50, &red), (frames[0].0, &frames[0].1));
+    assert_eq!((50, &blue), (frames[1].0, &frames[1].1));
+    assert_eq!(2, frames[2].0);
+    assert_eq!(&[0, 0, 255, 255], &frames[2].1[..4]);
+    assert_eq!(&[0, 0, 0, 255], &frames[2].1[4 * 4..4 * 5]);
+  }
+}
@@ -1,13 +1,18 @@
+use crate::cpu::CpuError;
 use crate::keyboard::{KeyAdapter, KeyPosition, KeyState, VirtualKey};
 mod keyboard;
-use crate::platform::{JoystickState, Platform, PlatformProvider, SyncPlatform, WindowConfig};
+use crate::platform::{
+  JoystickState, Platform, PlatformProvider, SyncPlatform, VideoRecorder, WindowConfig,
+};
+use crate::state::RewindBuffer;
 use crate::systems::System;
 use crate::time::VariableTimeStep;
 use gilrs::{Button, EventType, Gilrs};
-use instant::Duration;
+use instant::{Duration, Instant};
 use keyboard::WinitAdapter;
 use pixels::{Pixels, SurfaceTexture};
 use rand;
+use std::collections::HashSet;
 use std::io::Write;
 use std::sync::{Arc, Mutex};
 use winit::dpi::LogicalSize;
@@ -16,13 +21,41 @@ use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::WindowBuilder;
 use winit_input_helper::WinitInputHelper;
 
+/// The file used for save states, unless another is chosen.
+const DEFAULT_STATE_FILE: &str = "./noentiendo.state";
+
+/// The file used for video recordings, unless another is chosen.
+const DEFAULT_VIDEO_FILE: &str = "./noentiendo.gif";
+
+/// How often the state of the system is captured for rewinding.
+const REWIND_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The number of states kept for rewinding (30 seconds' worth).
+const REWIND_CAPACITY: usize = 300;
+
+/// How often a state is restored while rewinding. This is shorter than the
+/// capture interval, so rewinding runs faster than real time.
+const REWIND_STEP: Duration = Duration::from_millis(50);
+
 /// A platform implementation for desktop platforms using Winit and Pixels.
 /// This platform runs synchronously.
+/// Pressing Ctrl+F5 saves the state of the system to a file, and Ctrl+F9
+/// loads it again. These hotkeys are never passed on to the system, whose
+/// keyboard may have its own F5 key. Holding Ctrl+Backspace rewinds the system
+/// through its recent history, and Backspace alone is still the system's
+/// delete key.
+/// Pressing F10 starts recording a video of the system, and pressing it again
+/// stops the recording.
 pub struct WinitPlatform {
   config: Arc<Mutex<Option<WindowConfig>>>,
   provider: Arc<WinitPlatformProvider>,
   key_state: Arc<Mutex<KeyState<VirtualKeyCode>>>,
   joystick_state: Arc<Mutex<JoystickState>>,
+  debug_requested: Arc<Mutex<bool>>,
+  state_file: String,
+  load_state_on_start: bool,
+  video_file: String,
+  record_video_on_start: bool,
 }
 
 impl WinitPlatform {
@@ -30,19 +63,44 @@ impl WinitPlatform {
     let config = Arc::new(Mutex::new(None));
     let key_state = Arc::new(Mutex::new(KeyState::new()));
     let joystick_state = Arc::new(Mutex::new(JoystickState::empty()));
+    let debug_requested = Arc::new(Mutex::new(false));
 
     Self {
       provider: Arc::new(WinitPlatformProvider::new(
         config.clone(),
         key_state.clone(),
         joystick_state.clone(),
+        debug_requested.clone(),
       )),
       config,
       key_state,
       joystick_state,
+      debug_requested,
+      state_file: DEFAULT_STATE_FILE.to_owned(),
+      load_state_on_start: false,
+      video_file: DEFAULT_VIDEO_FILE.to_owned(),
+      record_video_on_start: false,
     }
   }
 
+  /// Load the save state in the given file once the system starts, and use
+  /// the same file for the save and load hotkeys.
+  pub fn with_state_file(mut self, path: &str) -> Self {
+    self.state_file = path.to_owned();
+    self.load_state_on_start = true;
+
+    self
+  }
+
+  /// Start recording a video to the given file once the system starts, and
+  /// use the same file for the recording hotkey.
+  pub fn with_video_file(mut self, path: &str) -> Self {
+    self.video_file = path.to_owned();
+    self.record_video_on_start = true;
+
+    self
+  }
+
   fn get_config(&self) -> WindowConfig {
     let config = self.config.lock().unwrap();
     config.expect("WindowConfig not set")
@@ -80,22 +138,78 @@ impl SyncPlatform for WinitPlatform {
     let mut input = WinitInputHelper::new();
     let key_state = self.key_state.clone();
     let config = self.config.clone();
+    let debug_requested = self.debug_requested.clone();
+    let state_file = self.state_file.clone();
+    let video_file = self.video_file.clone();
+    let mut halted = false;
+
+    // Keys pressed as hotkeys, whose releases are also kept from the system
+    let mut hotkeys_held = HashSet::new();
 
     system.reset();
 
+    if self.load_state_on_start {
+      load_state(&mut system, &state_file);
+    }
+
+    let mut video = None;
+    if self.record_video_on_start {
+      video = start_video(&video_file, current_config);
+    }
+
     let mut timer = VariableTimeStep::new(Duration::from_secs_f64(1.0 / 60.0));
 
     let mut gilrs = Gilrs::new().unwrap();
     let joystick_state = self.joystick_state.clone();
 
+    let mut rewind = RewindBuffer::new(REWIND_CAPACITY);
+    let mut last_rewind = Instant::now();
+    let mut rewinding = false;
+
     event_loop.run(move |event, _, control_flow| {
       *control_flow = ControlFlow::Poll;
 
       if input.update(&event) {
         if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+          shut_down(&mut system, video.take());
           *control_flow = ControlFlow::Exit;
         }
 
+        if input.key_pressed(VirtualKeyCode::F12) {
+          *debug_requested.lock().unwrap() = true;
+        }
+
+        if input.key_pressed(VirtualKeyCode::F5) && input.held_control() {
+          match system.save_state() {
+            Ok(data) => match std::fs::write(&state_file, data) {
+              Ok(_) => println!("Saved state to {state_file}"),
+              Err(error) => println!("Failed to write {state_file}: {error}"),
+            },
+            Err(error) => println!("Failed to save state: {error}"),
+          }
+        }
+
+        if input.key_pressed(VirtualKeyCode::F9)
+          && input.held_control()
+          && load_state(&mut system, &state_file)
+        {
+          // Loading a state may un-halt the CPU
+          halted = false;
+          window.set_title("noentiendo");
+          rewind.clear();
+        }
+
+        if input.key_pressed(VirtualKeyCode::F10) {
+          match video.take() {
+            Some(recorder) => finish_video(Some(recorder)),
+            None => video = start_video(&video_file, *config.lock().unwrap().as_ref().unwrap()),
+          }
+        }
+
+        // Rewinding continues until Backspace is released, even if Control
+        // is released first
+        rewinding = hotkeys_held.contains(&VirtualKeyCode::Back);
+
         if let Some(size) = input.window_resized() {
           // Winit bug, sometimes we get window_resized with -1
           if size.width != u32::MAX && size.height != u32::MAX {
@@ -106,7 +220,60 @@ impl SyncPlatform for WinitPlatform {
 
       match event {
         Event::MainEventsCleared => {
-          timer.do_update(&mut || system.tick());
+          if rewinding {
+            if last_rewind.elapsed() >= REWIND_STEP {
+              last_rewind = Instant::now();
+
+              if let Some(state) = rewind.pop() {
+                // Stay at the oldest state once the history runs out
+                if rewind.is_empty() {
+                  rewind.push(state.clone());
+                }
+
+                match system.load_state(&state) {
+                  Ok(_) => {
+                    halted = false;
+                    window.set_title("noentiendo - rewinding");
+                  }
+                  Err(error) => println!("Failed to rewind: {error}"),
+                }
+              }
+            }
+
+            // Don't try to catch up on the time spent rewinding
+            timer.next_update_interval();
+          } else if !halted {
+            let mut emulated = Duration::ZERO;
+            let result = timer.do_update(&mut || {
+              let elapsed = system.tick()?;
+              emulated += elapsed;
+              Ok(elapsed)
+            });
+
+            if let Some(recorder) = &mut video {
+              let config = config.lock().unwrap().unwrap();
+              if let Err(error) = recorder.capture(&mut system, config, emulated) {
+                println!("Failed to record video: {error}");
+                video = None;
+              }
+            }
+
+            if let Err(CpuError::Quit) = result {
+              shut_down(&mut system, video.take());
+              *control_flow = ControlFlow::Exit;
+            } else if let Err(error) = result {
+              println!("CPU halted: {error}");
+              window.set_title(&format!("noentiendo - {error}"));
+              halted = true;
+            } else if last_rewind.elapsed() >= REWIND_INTERVAL {
+              last_rewind = Instant::now();
+
+              // Systems without save states can't be rewound
+              if let Ok(state) = system.save_state() {
+                rewind.push(state);
+              }
+            }
+          }
 
           {
             let mut joystick_state = joystick_state.lock().unwrap();
@@ -177,16 +344,20 @@ impl SyncPlatform for WinitPlatform {
             ..
           } => match state {
             ElementState::Pressed => {
-              key_state.lock().unwrap().press(key);
+              if hotkeys_held.contains(&key) || is_hotkey(key, input.held_control()) {
+                hotkeys_held.insert(key);
+              } else {
+                key_state.lock().unwrap().press(key);
+              }
             }
             ElementState::Released => {
-              key_state.lock().unwrap().release(key);
+              if !hotkeys_held.remove(&key) {
+                key_state.lock().unwrap().release(key);
+              }
             }
           },
           WindowEvent::CloseRequested => {
-            if let Err(msg) = system.cleanup() {
-              println!("Error during cleanup: {}", msg);
-            }
+            shut_down(&mut system, video.take());
             *control_flow = ControlFlow::Exit;
           }
           _ => (),
@@ -197,10 +368,76 @@ impl SyncPlatform for WinitPlatform {
   }
 }
 
+/// Return true if pressing the given key, with or without Control held, is one
+/// of the platform's hotkeys rather than a key for the system.
+fn is_hotkey(key: VirtualKeyCode, control: bool) -> bool {
+  control
+    && matches!(
+      key,
+      VirtualKeyCode::F5 | VirtualKeyCode::F9 | VirtualKeyCode::Back
+    )
+}
+
+/// Finish any video being recorded and clean up the system, before the event
+/// loop exits.
+fn shut_down(system: &mut Box<dyn System>, video: Option<VideoRecorder>) {
+  finish_video(video);
+
+  if let Err(msg) = system.cleanup() {
+    println!("Error during cleanup: {}", msg);
+  }
+}
+
+/// Load the save state in the given file into the system, reporting any error.
+/// Return true if the state was loaded.
+fn load_state(system: &mut Box<dyn System>, path: &str) -> bool {
+  let result = std::fs::read(path)
+    .map_err(|error| error.to_string())
+    .and_then(|data| system.load_state(&data).map_err(|error| error.to_string()));
+
+  match result {
+    Ok(_) => {
+      println!("Loaded state from {path}");
+      true
+    }
+    Err(error) => {
+      println!("Failed to load state from {path}: {error}");
+      false
+    }
+  }
+}
+
+/// Start recording a video to the given file, reporting any error.
+fn start_video(path: &str, config: WindowConfig) -> Option<VideoRecorder> {
+  match VideoRecorder::create(path, config) {
+    Ok(recorder) => {
+      println!("Recording video to {path}");
+      Some(recorder)
+    }
+    Err(error) => {
+      println!("Failed to record video to {path}: {error}");
+      None
+    }
+  }
+}
+
+/// Finish the video being recorded, if any, reporting any error.
+fn finish_video(recorder: Option<VideoRecorder>) {
+  if let Some(recorder) = recorder {
+    let (path, frames) = (recorder.path().to_owned(), recorder.frames());
+
+    match recorder.finish() {
+      Ok(_) => println!("Saved {frames} frames of video to {path}"),
+      Err(error) => println!("Failed to save video to {path}: {error}"),
+    }
+  }
+}
+
 pub struct WinitPlatformProvider {
   config: Arc<Mutex<Option<WindowConfig>>>,
   key_state: Arc<Mutex<KeyState<VirtualKeyCode>>>,
   joystick_state: Arc<Mutex<JoystickState>>,
+  debug_requested: Arc<Mutex<bool>>,
 }
 
 impl WinitPlatformProvider {
@@ -208,11 +445,13 @@ impl WinitPlatformProvider {
     config: Arc<Mutex<Option<WindowConfig>>>,
     key_state: Arc<Mutex<KeyState<VirtualKeyCode>>>,
     joystick_state: Arc<Mutex<JoystickState>>,
+    debug_requested: Arc<Mutex<bool>>,
   ) -> Self {
     Self {
       config,
       key_state,
       joystick_state,
+      debug_requested,
     }
   }
 }
@@ -234,6 +473,13 @@ impl PlatformProvider for WinitPlatformProvider {
     *self.joystick_state.lock().unwrap()
   }
 
+  fn debug_break_requested(&self) -> bool {
+    let mut requested = self.debug_requested.lock().unwrap();
+    let result = *requested;
+    *requested = false;
+    result
+  }
+
   fn print(&self, text: &str) {
     print!("{text}");
   }
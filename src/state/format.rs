@@ -0,0 +1,621 @@
+use crate::state::{StateError, StateReader, StateWriter};
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+
+// The save state format is a compact binary encoding of the serde data model.
+// Integers are stored little-endian at their full width, sequences, maps and
+// strings are prefixed with their length as a u64, options are prefixed with a
+// tag byte, and enum variants are stored as their u32 index. Structs and tuples
+// are stored as their fields in order, with no names or lengths, so the format
+// is not self-describing: values must be read back as the same types that were
+// written.
+
+impl ser::Error for StateError {
+  fn custom<T: std::fmt::Display>(msg: T) -> Self {
+    StateError::Custom(msg.to_string())
+  }
+}
+
+impl de::Error for StateError {
+  fn custom<T: std::fmt::Display>(msg: T) -> Self {
+    StateError::Custom(msg.to_string())
+  }
+}
+
+impl StateWriter {
+  fn write_length(&mut self, len: Option<usize>) -> Result<(), StateError> {
+    match len {
+      Some(len) => {
+        self.data.extend_from_slice(&(len as u64).to_le_bytes());
+        Ok(())
+      }
+      None => Err(StateError::Custom(
+        "sequences must have a known length".to_owned(),
+      )),
+    }
+  }
+}
+
+impl ser::Serializer for &mut StateWriter {
+  type Ok = ();
+  type Error = StateError;
+
+  type SerializeSeq = Self;
+  type SerializeTuple = Self;
+  type SerializeTupleStruct = Self;
+  type SerializeTupleVariant = Self;
+  type SerializeMap = Self;
+  type SerializeStruct = Self;
+  type SerializeStructVariant = Self;
+
+  fn serialize_bool(self, v: bool) -> Result<(), StateError> {
+    self.data.push(v as u8);
+    Ok(())
+  }
+
+  fn serialize_i8(self, v: i8) -> Result<(), StateError> {
+    self.data.extend_from_slice(&v.to_le_bytes());
+    Ok(())
+  }
+
+  fn serialize_i16(self, v: i16) -> Result<(), StateError> {
+    self.data.extend_from_slice(&v.to_le_bytes());
+    Ok(())
+  }
+
+  fn serialize_i32(self, v: i32) -> Result<(), StateError> {
+    self.data.extend_from_slice(&v.to_le_bytes());
+    Ok(())
+  }
+
+  fn serialize_i64(self, v: i64) -> Result<(), StateError> {
+    self.data.extend_from_slice(&v.to_le_bytes());
+    Ok(())
+  }
+
+  fn serialize_u8(self, v: u8) -> Result<(), StateError> {
+    self.data.push(v);
+    Ok(())
+  }
+
+  fn serialize_u16(self, v: u16) -> Result<(), StateError> {
+    self.data.extend_from_slice(&v.to_le_bytes());
+    Ok(())
+  }
+
+  fn serialize_u32(self, v: u32) -> Result<(), StateError> {
+    self.data.extend_from_slice(&v.to_le_bytes());
+    Ok(())
+  }
+
+  fn serialize_u64(self, v: u64) -> Result<(), StateError> {
+    self.data.extend_from_slice(&v.to_le_bytes());
+    Ok(())
+  }
+
+  fn serialize_f32(self, v: f32) -> Result<(), StateError> {
+    self.data.extend_from_slice(&v.to_le_bytes());
+    Ok(())
+  }
+
+  fn serialize_f64(self, v: f64) -> Result<(), StateError> {
+    self.data.extend_from_slice(&v.to_le_bytes());
+    Ok(())
+  }
+
+  fn serialize_char(self, v: char) -> Result<(), StateError> {
+    self.serialize_u32(v as u32)
+  }
+
+  fn serialize_str(self, v: &str) -> Result<(), StateError> {
+    self.serialize_bytes(v.as_bytes())
+  }
+
+  fn serialize_bytes(self, v: &[u8]) -> Result<(), StateError> {
+    self.write_length(Some(v.len()))?;
+    self.data.extend_from_slice(v);
+    Ok(())
+  }
+
+  fn serialize_none(self) -> Result<(), StateError> {
+    self.serialize_u8(0)
+  }
+
+  fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), StateError> {
+    self.serialize_u8(1)?;
+    value.serialize(self)
+  }
+
+  fn serialize_unit(self) -> Result<(), StateError> {
+    Ok(())
+  }
+
+  fn serialize_unit_struct(self, _name: &'static str) -> Result<(), StateError> {
+    Ok(())
+  }
+
+  fn serialize_unit_variant(
+    self,
+    _name: &'static str,
+    variant_index: u32,
+    _variant: &'static str,
+  ) -> Result<(), StateError> {
+    self.serialize_u32(variant_index)
+  }
+
+  fn serialize_newtype_struct<T: ?Sized + Serialize>(
+    self,
+    _name: &'static str,
+    value: &T,
+  ) -> Result<(), StateError> {
+    value.serialize(self)
+  }
+
+  fn serialize_newtype_variant<T: ?Sized + Serialize>(
+    self,
+    _name: &'static str,
+    variant_index: u32,
+    _variant: &'static str,
+    value: &T,
+  ) -> Result<(), StateError> {
+    self.serialize_u32(variant_index)?;
+    value.serialize(self)
+  }
+
+  fn serialize_seq(self, len: Option<usize>) -> Result<Self, StateError> {
+    self.write_length(len)?;
+    Ok(self)
+  }
+
+  fn serialize_tuple(self, _len: usize) -> Result<Self, StateError> {
+    Ok(self)
+  }
+
+  fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self, StateError> {
+    Ok(self)
+  }
+
+  fn serialize_tuple_variant(
+    self,
+    _name: &'static str,
+    variant_index: u32,
+    _variant: &'static str,
+    _len: usize,
+  ) -> Result<Self, StateError> {
+    self.serialize_u32(variant_index)?;
+    Ok(self)
+  }
+
+  fn serialize_map(self, len: Option<usize>) -> Result<Self, StateError> {
+    self.write_length(len)?;
+    Ok(self)
+  }
+
+  fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, StateError> {
+    Ok(self)
+  }
+
+  fn serialize_struct_variant(
+    self,
+    _name: &'static str,
+    variant_index: u32,
+    _variant: &'static str,
+    _len: usize,
+  ) -> Result<Self, StateError> {
+    self.serialize_u32(variant_index)?;
+    Ok(self)
+  }
+
+  fn is_human_readable(&self) -> bool {
+    false
+  }
+}
+
+impl ser::SerializeSeq for &mut StateWriter {
+  type Ok = ();
+  type Error = StateError;
+
+  fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), StateError> {
+    value.serialize(&mut **self)
+  }
+
+  fn end(self) -> Result<(), StateError> {
+    Ok(())
+  }
+}
+
+impl ser::SerializeTuple for &mut StateWriter {
+  type Ok = ();
+  type Error = StateError;
+
+  fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), StateError> {
+    value.serialize(&mut **self)
+  }
+
+  fn end(self) -> Result<(), StateError> {
+    Ok(())
+  }
+}
+
+impl ser::SerializeTupleStruct for &mut StateWriter {
+  type Ok = ();
+  type Error = StateError;
+
+  fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), StateError> {
+    value.serialize(&mut **self)
+  }
+
+  fn end(self) -> Result<(), StateError> {
+    Ok(())
+  }
+}
+
+impl ser::SerializeTupleVariant for &mut StateWriter {
+  type Ok = ();
+  type Error = StateError;
+
+  fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), StateError> {
+    value.serialize(&mut **self)
+  }
+
+  fn end(self) -> Result<(), StateError> {
+    Ok(())
+  }
+}
+
+impl ser::SerializeMap for &mut StateWriter {
+  type Ok = ();
+  type Error = StateError;
+
+  fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), StateError> {
+    key.serialize(&mut **self)
+  }
+
+  fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), StateError> {
+    value.serialize(&mut **self)
+  }
+
+  fn end(self) -> Result<(), StateError> {
+    Ok(())
+  }
+}
+
+impl ser::SerializeStruct for &mut StateWriter {
+  type Ok = ();
+  type Error = StateError;
+
+  fn serialize_field<T: ?Sized + Serialize>(
+    &mut self,
+    _key: &'static str,
+    value: &T,
+  ) -> Result<(), StateError> {
+    value.serialize(&mut **self)
+  }
+
+  fn end(self) -> Result<(), StateError> {
+    Ok(())
+  }
+}
+
+impl ser::SerializeStructVariant for &mut StateWriter {
+  type Ok = ();
+  type Error = StateError;
+
+  fn serialize_field<T: ?Sized + Serialize>(
+    &mut self,
+    _key: &'static str,
+    value: &T,
+  ) -> Result<(), StateError> {
+    value.serialize(&mut **self)
+  }
+
+  fn end(self) -> Result<(), StateError> {
+    Ok(())
+  }
+}
+
+impl<'de> StateReader<'de> {
+  /// Take the next `len` bytes from the save state.
+  fn take(&mut self, len: usize) -> Result<&'de [u8], StateError> {
+    if self.data.len() - self.position < len {
+      return Err(StateError::UnexpectedEnd);
+    }
+
+    let bytes = &self.data[self.position..self.position + len];
+    self.position += len;
+    Ok(bytes)
+  }
+
+  fn take_array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+    let mut array = [0; N];
+    array.copy_from_slice(self.take(N)?);
+    Ok(array)
+  }
+
+  fn read_length(&mut self) -> Result<usize, StateError> {
+    let len = u64::from_le_bytes(self.take_array()?);
+
+    // Every element takes at least one byte, so a longer length is corrupt
+    if len > (self.data.len() - self.position) as u64 {
+      return Err(StateError::UnexpectedEnd);
+    }
+
+    Ok(len as usize)
+  }
+
+  fn read_u32(&mut self) -> Result<u32, StateError> {
+    Ok(u32::from_le_bytes(self.take_array()?))
+  }
+}
+
+impl<'de> de::Deserializer<'de> for &mut StateReader<'de> {
+  type Error = StateError;
+
+  fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, StateError> {
+    Err(StateError::Custom(
+      "save states can only be read as known types".to_owned(),
+    ))
+  }
+
+  fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    match self.take(1)?[0] {
+      0 => visitor.visit_bool(false),
+      1 => visitor.visit_bool(true),
+      value => Err(StateError::Custom(format!("invalid bool {value}"))),
+    }
+  }
+
+  fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    visitor.visit_i8(i8::from_le_bytes(self.take_array()?))
+  }
+
+  fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    visitor.visit_i16(i16::from_le_bytes(self.take_array()?))
+  }
+
+  fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    visitor.visit_i32(i32::from_le_bytes(self.take_array()?))
+  }
+
+  fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    visitor.visit_i64(i64::from_le_bytes(self.take_array()?))
+  }
+
+  fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    visitor.visit_u8(self.take(1)?[0])
+  }
+
+  fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    visitor.visit_u16(u16::from_le_bytes(self.take_array()?))
+  }
+
+  fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    visitor.visit_u32(self.read_u32()?)
+  }
+
+  fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    visitor.visit_u64(u64::from_le_bytes(self.take_array()?))
+  }
+
+  fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    visitor.visit_f32(f32::from_le_bytes(self.take_array()?))
+  }
+
+  fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    visitor.visit_f64(f64::from_le_bytes(self.take_array()?))
+  }
+
+  fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    let value = self.read_u32()?;
+
+    match char::from_u32(value) {
+      Some(c) => visitor.visit_char(c),
+      None => Err(StateError::Custom(format!("invalid char {value:#X}"))),
+    }
+  }
+
+  fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    let len = self.read_length()?;
+
+    match std::str::from_utf8(self.take(len)?) {
+      Ok(s) => visitor.visit_borrowed_str(s),
+      Err(error) => Err(StateError::Custom(error.to_string())),
+    }
+  }
+
+  fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    self.deserialize_str(visitor)
+  }
+
+  fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    let len = self.read_length()?;
+    visitor.visit_borrowed_bytes(self.take(len)?)
+  }
+
+  fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    self.deserialize_bytes(visitor)
+  }
+
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    match self.take(1)?[0] {
+      0 => visitor.visit_none(),
+      1 => visitor.visit_some(self),
+      tag => Err(StateError::Custom(format!("invalid option tag {tag}"))),
+    }
+  }
+
+  fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    visitor.visit_unit()
+  }
+
+  fn deserialize_unit_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    visitor: V,
+  ) -> Result<V::Value, StateError> {
+    visitor.visit_unit()
+  }
+
+  fn deserialize_newtype_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    visitor: V,
+  ) -> Result<V::Value, StateError> {
+    visitor.visit_newtype_struct(self)
+  }
+
+  fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    let len = self.read_length()?;
+    visitor.visit_seq(Elements {
+      reader: self,
+      remaining: len,
+    })
+  }
+
+  fn deserialize_tuple<V: Visitor<'de>>(
+    self,
+    len: usize,
+    visitor: V,
+  ) -> Result<V::Value, StateError> {
+    visitor.visit_seq(Elements {
+      reader: self,
+      remaining: len,
+    })
+  }
+
+  fn deserialize_tuple_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    len: usize,
+    visitor: V,
+  ) -> Result<V::Value, StateError> {
+    self.deserialize_tuple(len, visitor)
+  }
+
+  fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, StateError> {
+    let len = self.read_length()?;
+    visitor.visit_map(Elements {
+      reader: self,
+      remaining: len,
+    })
+  }
+
+  fn deserialize_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, StateError> {
+    self.deserialize_tuple(fields.len(), visitor)
+  }
+
+  fn deserialize_enum<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, StateError> {
+    visitor.visit_enum(self)
+  }
+
+  fn deserialize_identifier<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, StateError> {
+    Err(StateError::Custom(
+      "save states do not store identifiers".to_owned(),
+    ))
+  }
+
+  fn deserialize_ignored_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, StateError> {
+    Err(StateError::Custom(
+      "save states cannot skip unknown values".to_owned(),
+    ))
+  }
+
+  fn is_human_readable(&self) -> bool {
+    false
+  }
+}
+
+/// The elements of a sequence, tuple or map in a save state.
+struct Elements<'a, 'de> {
+  reader: &'a mut StateReader<'de>,
+  remaining: usize,
+}
+
+impl<'de> de::SeqAccess<'de> for Elements<'_, 'de> {
+  type Error = StateError;
+
+  fn next_element_seed<T: DeserializeSeed<'de>>(
+    &mut self,
+    seed: T,
+  ) -> Result<Option<T::Value>, StateError> {
+    if self.remaining == 0 {
+      return Ok(None);
+    }
+
+    self.remaining -= 1;
+    seed.deserialize(&mut *self.reader).map(Some)
+  }
+
+  fn size_hint(&self) -> Option<usize> {
+    Some(self.remaining)
+  }
+}
+
+impl<'de> de::MapAccess<'de> for Elements<'_, 'de> {
+  type Error = StateError;
+
+  fn next_key_seed<K: DeserializeSeed<'de>>(
+    &mut self,
+    seed: K,
+  ) -> Result<Option<K::Value>, StateError> {
+    if self.remaining == 0 {
+      return Ok(None);
+    }
+
+    self.remaining -= 1;
+    seed.deserialize(&mut *self.reader).map(Some)
+  }
+
+  fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, StateError> {
+    seed.deserialize(&mut *self.reader)
+  }
+
+  fn size_hint(&self) -> Option<usize> {
+    Some(self.remaining)
+  }
+}
+
+impl<'de> de::EnumAccess<'de> for &mut StateReader<'de> {
+  type Error = StateError;
+  type Variant = Self;
+
+  fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), StateError> {
+    let index = self.read_u32()?;
+    let variant = seed.deserialize(IntoDeserializer::<StateError>::into_deserializer(index))?;
+    Ok((variant, self))
+  }
+}
+
+impl<'de> de::VariantAccess<'de> for &mut StateReader<'de> {
+  type Error = StateError;
+
+  fn unit_variant(self) -> Result<(), StateError> {
+    Ok(())
+  }
+
+  fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, StateError> {
+    seed.deserialize(self)
+  }
+
+  fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, StateError> {
+    de::Deserializer::deserialize_tuple(self, len, visitor)
+  }
+
+  fn struct_variant<V: Visitor<'de>>(
+    self,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, StateError> {
+    de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+  }
+}
@@ -0,0 +1,191 @@
+mod format;
+mod rewind;
+
+pub use rewind::RewindBuffer;
+
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt;
+
+/// The bytes at the start of every save state.
+const MAGIC: &[u8; 8] = b"NOENSTAT";
+
+/// The version of the save state format. This should be incremented whenever
+/// the state stored by any component changes, since states are read back
+/// positionally and an older state cannot be interpreted by newer code.
+const VERSION: u16 = 1;
+
+/// An error encountered while saving or loading a save state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+  /// The system (or its CPU) does not support save states.
+  Unsupported,
+
+  /// The data does not begin with a save state header, or was written by an
+  /// incompatible version of the emulator.
+  InvalidHeader,
+
+  /// The save state ended before all of the system's state was read.
+  UnexpectedEnd,
+
+  /// The save state contains more data than the system's state. This usually
+  /// means it was saved from a different system.
+  TrailingData,
+
+  /// The save state does not match the shape of this system, e.g. a block of
+  /// memory has a different size.
+  Mismatch(String),
+
+  /// Any other error reported while serializing or deserializing a value.
+  Custom(String),
+}
+
+impl fmt::Display for StateError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      StateError::Unsupported => write!(f, "save states are not supported by this system"),
+      StateError::InvalidHeader => write!(f, "not a save state, or from an incompatible version"),
+      StateError::UnexpectedEnd => write!(f, "save state ended unexpectedly"),
+      StateError::TrailingData => write!(f, "save state contains unexpected trailing data"),
+      StateError::Mismatch(message) => write!(f, "save state does not match system: {message}"),
+      StateError::Custom(message) => write!(f, "{message}"),
+    }
+  }
+}
+
+impl std::error::Error for StateError {}
+
+/// Collects the state of each component of a system, in order, into a save
+/// state. Each component writes its values with serde.
+pub struct StateWriter {
+  data: Vec<u8>,
+}
+
+impl StateWriter {
+  /// Create a new save state, containing only the header.
+  pub fn new() -> Self {
+    let mut data = MAGIC.to_vec();
+    data.extend_from_slice(&VERSION.to_le_bytes());
+
+    Self { data }
+  }
+
+  /// Append the given value to the save state.
+  pub fn write<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), StateError> {
+    value.serialize(self)
+  }
+
+  /// Return the bytes of the completed save state.
+  pub fn finish(self) -> Vec<u8> {
+    self.data
+  }
+}
+
+/// Reads back the values in a save state, in the same order as they were
+/// written by a [`StateWriter`].
+pub struct StateReader<'a> {
+  data: &'a [u8],
+  position: usize,
+}
+
+impl<'a> StateReader<'a> {
+  /// Begin reading the given save state, checking its header.
+  pub fn new(data: &'a [u8]) -> Result<Self, StateError> {
+    let header_length = MAGIC.len() + 2;
+
+    if data.len() < header_length || &data[..MAGIC.len()] != MAGIC {
+      return Err(StateError::InvalidHeader);
+    }
+
+    if data[MAGIC.len()..header_length] != VERSION.to_le_bytes() {
+      return Err(StateError::InvalidHeader);
+    }
+
+    Ok(Self {
+      data,
+      position: header_length,
+    })
+  }
+
+  /// Read the next value from the save state.
+  pub fn read<T: DeserializeOwned>(&mut self) -> Result<T, StateError> {
+    T::deserialize(self)
+  }
+
+  /// Check that the whole save state has been read.
+  pub fn finish(self) -> Result<(), StateError> {
+    if self.position == self.data.len() {
+      Ok(())
+    } else {
+      Err(StateError::TrailingData)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde::Deserialize;
+
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  enum Mode {
+    Idle,
+    Counting(u16),
+    Shifting { data: u8, count: i32 },
+  }
+
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  struct Chip {
+    enabled: bool,
+    modes: [Mode; 3],
+    memory: Vec<u8>,
+    pending: Option<u64>,
+    name: String,
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let chip = Chip {
+      enabled: true,
+      modes: [
+        Mode::Idle,
+        Mode::Counting(0x1234),
+        Mode::Shifting {
+          data: 0xAA,
+          count: -3,
+        },
+      ],
+      memory: vec![1, 2, 3, 4],
+      pending: Some(u64::MAX),
+      name: "VIA".to_owned(),
+    };
+
+    let mut writer = StateWriter::new();
+    writer.write(&chip).unwrap();
+    writer.write(&0x56u8).unwrap();
+    let data = writer.finish();
+
+    let mut reader = StateReader::new(&data).unwrap();
+    assert_eq!(chip, reader.read::<Chip>().unwrap());
+    assert_eq!(0x56, reader.read::<u8>().unwrap());
+    reader.finish().unwrap();
+  }
+
+  #[test]
+  fn test_errors() {
+    assert_eq!(
+      StateError::InvalidHeader,
+      StateReader::new(b"not a state").err().unwrap()
+    );
+
+    let mut writer = StateWriter::new();
+    writer.write(&0x1234u16).unwrap();
+    let data = writer.finish();
+
+    let mut reader = StateReader::new(&data).unwrap();
+    assert_eq!(StateError::UnexpectedEnd, reader.read::<u32>().unwrap_err());
+
+    let mut reader = StateReader::new(&data).unwrap();
+    reader.read::<u8>().unwrap();
+    assert_eq!(StateError::TrailingData, reader.finish().unwrap_err());
+  }
+}
@@ -0,0 +1,216 @@
+use std::collections::VecDeque;
+
+/// Differences smaller than this many bytes apart are stored in the same
+/// patch, since each patch has some overhead of its own.
+const PATCH_GAP: usize = 16;
+
+/// The changes needed to turn one save state into another.
+struct Delta {
+  /// The length of the resulting state.
+  length: usize,
+
+  /// Runs of bytes which differ, and the offsets at which they start.
+  patches: Vec<(usize, Vec<u8>)>,
+}
+
+impl Delta {
+  /// Compute the changes needed to turn `from` into `to`.
+  fn between(from: &[u8], to: &[u8]) -> Self {
+    let differs = |i: usize| from.get(i) != to.get(i);
+    let mut patches = Vec::new();
+    let mut i = 0;
+
+    while i < to.len() {
+      if !differs(i) {
+        i += 1;
+        continue;
+      }
+
+      // Extend the patch until there is a long enough run of equal bytes
+      let start = i;
+      let mut end = i + 1;
+      while end < to.len() && (end - i) <= PATCH_GAP {
+        if differs(end) {
+          i = end;
+        }
+        end += 1;
+      }
+
+      patches.push((start, to[start..=i].to_vec()));
+      i += 1;
+    }
+
+    Self {
+      length: to.len(),
+      patches,
+    }
+  }
+
+  /// Apply these changes to the given state.
+  fn apply(&self, state: &mut Vec<u8>) {
+    state.resize(self.length, 0);
+
+    for (offset, data) in &self.patches {
+      state[*offset..*offset + data.len()].copy_from_slice(data);
+    }
+  }
+
+  /// The approximate number of bytes used to store these changes.
+  fn size(&self) -> usize {
+    self
+      .patches
+      .iter()
+      .map(|(_, data)| data.len() + std::mem::size_of::<(usize, Vec<u8>)>())
+      .sum()
+  }
+}
+
+/// A bounded history of save states, used to step a system back in time.
+/// Only the most recent state is kept in full. Each older state is stored as
+/// the changes from the state after it, so states of a system whose memory
+/// mostly stays the same take up little space. Once the buffer is full, the
+/// oldest state is discarded whenever a new one is added.
+pub struct RewindBuffer {
+  capacity: usize,
+  latest: Option<Vec<u8>>,
+  history: VecDeque<Delta>,
+}
+
+impl RewindBuffer {
+  /// Create an empty buffer which holds at most `capacity` states.
+  pub fn new(capacity: usize) -> Self {
+    Self {
+      capacity: capacity.max(1),
+      latest: None,
+      history: VecDeque::new(),
+    }
+  }
+
+  /// Add a state (e.g. from [`crate::systems::System::save_state`]) to the
+  /// buffer, as the most recent one.
+  pub fn push(&mut self, state: Vec<u8>) {
+    if let Some(previous) = self.latest.take() {
+      self.history.push_back(Delta::between(&state, &previous));
+
+      if self.history.len() >= self.capacity {
+        self.history.pop_front();
+      }
+    }
+
+    self.latest = Some(state);
+  }
+
+  /// Remove and return the most recent state in the buffer.
+  pub fn pop(&mut self) -> Option<Vec<u8>> {
+    let latest = self.latest.take()?;
+
+    if let Some(delta) = self.history.pop_back() {
+      let mut previous = latest.clone();
+      delta.apply(&mut previous);
+      self.latest = Some(previous);
+    }
+
+    Some(latest)
+  }
+
+  /// Return the number of states in the buffer.
+  pub fn len(&self) -> usize {
+    match self.latest {
+      Some(_) => self.history.len() + 1,
+      None => 0,
+    }
+  }
+
+  /// Return true if there are no states in the buffer.
+  pub fn is_empty(&self) -> bool {
+    self.latest.is_none()
+  }
+
+  /// Discard every state in the buffer.
+  pub fn clear(&mut self) {
+    self.latest = None;
+    self.history.clear();
+  }
+
+  /// Return the approximate number of bytes used to store the states.
+  pub fn size(&self) -> usize {
+    let latest = self.latest.as_ref().map_or(0, |state| state.len());
+    latest + self.history.iter().map(Delta::size).sum::<usize>()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn state(seed: u8, length: usize) -> Vec<u8> {
+    let mut state = vec![0; length];
+    state[seed as usize % length] = seed;
+    state[(seed as usize * 7) % length] = seed.wrapping_mul(3);
+    state
+  }
+
+  #[test]
+  fn test_delta() {
+    let from = vec![1, 2, 3, 4, 5, 6, 7, 8];
+    let to = vec![1, 9, 3, 4, 5, 6, 7, 8, 10, 11];
+    let mut state = from.clone();
+    Delta::between(&from, &to).apply(&mut state);
+    assert_eq!(to, state);
+
+    // shrinking
+    let mut state = to.clone();
+    Delta::between(&to, &from).apply(&mut state);
+    assert_eq!(from, state);
+
+    // distant changes are stored separately
+    let from = vec![0; 1000];
+    let mut to = from.clone();
+    to[10] = 1;
+    to[900] = 2;
+    let delta = Delta::between(&from, &to);
+    assert_eq!(vec![(10, vec![1]), (900, vec![2])], delta.patches);
+  }
+
+  #[test]
+  fn test_push_pop() {
+    let mut buffer = RewindBuffer::new(10);
+    assert!(buffer.is_empty());
+    assert_eq!(None, buffer.pop());
+
+    for i in 0..5 {
+      buffer.push(state(i, 0x1000));
+    }
+    assert_eq!(5, buffer.len());
+
+    for i in (0..5).rev() {
+      assert_eq!(Some(state(i, 0x1000)), buffer.pop());
+    }
+    assert!(buffer.is_empty());
+  }
+
+  #[test]
+  fn test_capacity() {
+    let mut buffer = RewindBuffer::new(4);
+
+    for i in 0..10 {
+      buffer.push(state(i, 0x1000));
+    }
+    assert_eq!(4, buffer.len());
+
+    // only the most recent states are kept
+    for i in (6..10).rev() {
+      assert_eq!(Some(state(i, 0x1000)), buffer.pop());
+    }
+    assert_eq!(None, buffer.pop());
+
+    // similar states are compressed
+    for i in 0..4 {
+      buffer.push(state(i, 0x1000));
+    }
+    assert!(buffer.size() < 0x1000 + 4 * 100);
+
+    buffer.clear();
+    assert!(buffer.is_empty());
+  }
+}
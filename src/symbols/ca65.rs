@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+
+/// Split a line of a ca65 debug info file into its type and its fields.
+pub(crate) fn record(line: &str) -> Option<(&str, HashMap<&str, &str>)> {
+  let (kind, rest) = line.split_once('\t')?;
+  let mut fields = HashMap::new();
+  let mut rest = rest;
+
+  while !rest.is_empty() {
+    let (key, value) = rest.split_once('=')?;
+
+    // Quoted values may contain commas
+    let end = if let Some(quoted) = value.strip_prefix('"') {
+      quoted.find('"')? + 2
+    } else {
+      value.find(',').unwrap_or(value.len())
+    };
+
+    fields.insert(key, value[..end].trim_matches('"'));
+    rest = value[end..].strip_prefix(',').unwrap_or(&value[end..]);
+  }
+
+  Some((kind, fields))
+}
+
+/// Parse a number from a ca65 debug info file, in decimal or `0x` hex.
+pub(crate) fn number(value: Option<&&str>) -> Result<u32, String> {
+  let value = value.ok_or_else(|| "missing field in debug info".to_owned())?;
+
+  match value.strip_prefix("0x") {
+    Some(hex) => u32::from_str_radix(hex, 16),
+    None => value.parse(),
+  }
+  .map_err(|_| format!("invalid number in debug info: {value}"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_record() {
+    let (kind, fields) = record("file\tid=0,name=\"a,b.s\",size=12,mtime=0x5F").unwrap();
+    assert_eq!("file", kind);
+    assert_eq!(Some(&"a,b.s"), fields.get("name"));
+    assert_eq!(Ok(12), number(fields.get("size")));
+    assert_eq!(Ok(0x5F), number(fields.get("mtime")));
+    assert!(number(fields.get("line")).is_err());
+
+    assert_eq!(None, record("version"));
+  }
+}
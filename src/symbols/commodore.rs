@@ -0,0 +1,148 @@
+use super::SymbolTable;
+
+/// The KERNAL jump table of the VIC-20 and C64. The C64 adds the first three
+/// entries, which the VIC-20 does not have.
+const KERNAL_JUMP_TABLE: &[(&str, u16)] = &[
+  ("CINT", 0xFF81),
+  ("IOINIT", 0xFF84),
+  ("RAMTAS", 0xFF87),
+  ("RESTOR", 0xFF8A),
+  ("VECTOR", 0xFF8D),
+  ("SETMSG", 0xFF90),
+  ("SECOND", 0xFF93),
+  ("TKSA", 0xFF96),
+  ("MEMTOP", 0xFF99),
+  ("MEMBOT", 0xFF9C),
+  ("SCNKEY", 0xFF9F),
+  ("SETTMO", 0xFFA2),
+  ("ACPTR", 0xFFA5),
+  ("CIOUT", 0xFFA8),
+  ("UNTLK", 0xFFAB),
+  ("UNLSN", 0xFFAE),
+  ("LISTEN", 0xFFB1),
+  ("TALK", 0xFFB4),
+  ("READST", 0xFFB7),
+  ("SETLFS", 0xFFBA),
+  ("SETNAM", 0xFFBD),
+  ("OPEN", 0xFFC0),
+  ("CLOSE", 0xFFC3),
+  ("CHKIN", 0xFFC6),
+  ("CHKOUT", 0xFFC9),
+  ("CLRCHN", 0xFFCC),
+  ("CHRIN", 0xFFCF),
+  ("CHROUT", 0xFFD2),
+  ("LOAD", 0xFFD5),
+  ("SAVE", 0xFFD8),
+  ("SETTIM", 0xFFDB),
+  ("RDTIM", 0xFFDE),
+  ("STOP", 0xFFE1),
+  ("GETIN", 0xFFE4),
+  ("CLALL", 0xFFE7),
+  ("UDTIM", 0xFFEA),
+  ("SCREEN", 0xFFED),
+  ("PLOT", 0xFFF0),
+  ("IOBASE", 0xFFF3),
+];
+
+/// The jump table of the PET's BASIC 2 KERNAL, which predates the one shared
+/// by the later machines.
+const PET_JUMP_TABLE: &[(&str, u16)] = &[
+  ("OPEN", 0xFFC0),
+  ("CLOSE", 0xFFC3),
+  ("CHKIN", 0xFFC6),
+  ("CHKOUT", 0xFFC9),
+  ("CLRCHN", 0xFFCC),
+  ("CHRIN", 0xFFCF),
+  ("CHROUT", 0xFFD2),
+  ("LOAD", 0xFFD5),
+  ("SAVE", 0xFFD8),
+  ("VERIFY", 0xFFDB),
+  ("SYS", 0xFFDE),
+  ("STOP", 0xFFE1),
+  ("GETIN", 0xFFE4),
+  ("CLALL", 0xFFE7),
+  ("UDTIM", 0xFFEA),
+];
+
+/// Add each of the given symbols to the table.
+fn insert_all(table: &mut SymbolTable, symbols: &[(&str, u16)]) {
+  for (name, address) in symbols {
+    table.insert(name, *address);
+  }
+}
+
+/// Return the symbols of the PET's BASIC 2 and KERNAL ROMs.
+pub fn pet_symbols() -> SymbolTable {
+  let mut table = SymbolTable::new();
+
+  insert_all(&mut table, PET_JUMP_TABLE);
+
+  // The hardware vectors and the main entry points of BASIC
+  insert_all(
+    &mut table,
+    &[
+      ("CHRGET", 0x0070),
+      ("READY", 0xC38B),
+      ("MAIN", 0xC392),
+      ("INLIN", 0xC46F),
+      ("STROUT", 0xCA1C),
+      ("IRQ", 0xE61B),
+      ("RESET", 0xFCD1),
+      ("NMI", 0xFCFE),
+    ],
+  );
+
+  table
+}
+
+/// Return the symbols of the VIC-20's BASIC and KERNAL ROMs.
+pub fn vic20_symbols() -> SymbolTable {
+  let mut table = SymbolTable::new();
+
+  insert_all(&mut table, &KERNAL_JUMP_TABLE[3..]);
+
+  // The hardware vectors and the main entry points of BASIC
+  insert_all(
+    &mut table,
+    &[
+      ("CHRGET", 0x0073),
+      ("READY", 0xC474),
+      ("MAIN", 0xC480),
+      ("INLIN", 0xC560),
+      ("STROUT", 0xCB1E),
+      ("WARMST", 0xE467),
+      ("COLDST", 0xE378),
+      ("RESET", 0xFD22),
+      ("NMI", 0xFEA9),
+      ("IRQ", 0xFF72),
+    ],
+  );
+
+  table
+}
+
+/// Return the symbols of the C64's BASIC and KERNAL ROMs.
+pub fn c64_symbols() -> SymbolTable {
+  let mut table = SymbolTable::new();
+
+  insert_all(&mut table, KERNAL_JUMP_TABLE);
+
+  // The hardware vectors and the main entry points of BASIC
+  insert_all(
+    &mut table,
+    &[
+      ("CHRGET", 0x0073),
+      ("READY", 0xA474),
+      ("MAIN", 0xA480),
+      ("INLIN", 0xA560),
+      ("STROUT", 0xAB1E),
+      ("WARMST", 0xE37B),
+      ("COLDST", 0xE394),
+      ("RESET", 0xFCE2),
+      ("NMI", 0xFE43),
+      ("IRQ", 0xFF48),
+    ],
+  );
+
+  table
+}
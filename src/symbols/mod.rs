@@ -0,0 +1,356 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// Parsing for the debug info files written by ld65's `--dbgfile` option,
+/// shared with the coverage trace handler.
+pub(crate) mod ca65;
+mod commodore;
+pub use commodore::{c64_symbols, pet_symbols, vic20_symbols};
+
+/// The furthest past a symbol that an address is still shown relative to it,
+/// e.g. `CHROUT+3`. Further than this, the address is shown in hex.
+const MAX_OFFSET: u16 = 0xFF;
+
+/// A set of names for addresses, used to show `CHROUT+3` in place of `$FFD5`
+/// in traces and the debugger, and to let the user refer to addresses by name.
+///
+/// Symbols can be read from the label files written by VICE (and by ld65's
+/// `-Ln` option), from ca65 debug info files, and from vasm listings. Tables
+/// for the Commodore ROMs are built in (see [`pet_symbols`], [`vic20_symbols`]
+/// and [`c64_symbols`]). An address can have several names; the one inserted
+/// last is the one shown.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+  /// The name shown for each address which has one.
+  names: BTreeMap<u16, String>,
+
+  /// The address of every symbol, by name.
+  addresses: HashMap<String, u16>,
+}
+
+/// Parse a hexadecimal address from a symbol file, ignoring any bank above the
+/// low 16 bits (ld65 writes 24-bit addresses).
+fn parse_hex(value: &str) -> Result<u16, String> {
+  u32::from_str_radix(value, 16)
+    .map(|address| address as u16)
+    .map_err(|_| format!("invalid address in symbol file: {value}"))
+}
+
+impl SymbolTable {
+  /// Create an empty symbol table.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Add a symbol, which becomes the name shown for its address. If the name
+  /// was already given to another address, it is moved from there.
+  pub fn insert(&mut self, name: &str, address: u16) {
+    self.unbind(name, address);
+    self.addresses.insert(name.to_owned(), address);
+    self.names.insert(address, name.to_owned());
+  }
+
+  /// Add every symbol in another table, whose names take precedence over the
+  /// ones already here.
+  pub fn merge(&mut self, other: SymbolTable) {
+    for (name, address) in &other.addresses {
+      self.unbind(name, *address);
+    }
+
+    self.addresses.extend(other.addresses);
+    self.names.extend(other.names);
+  }
+
+  /// Stop showing a symbol at its old address, if it is being given a new one.
+  fn unbind(&mut self, name: &str, address: u16) {
+    if let Some(old) = self.addresses.get(name).copied() {
+      if old != address && self.name(old) == Some(name) {
+        self.names.remove(&old);
+      }
+    }
+  }
+
+  /// Return the number of symbols in the table.
+  pub fn len(&self) -> usize {
+    self.addresses.len()
+  }
+
+  /// Return true if the table has no symbols.
+  pub fn is_empty(&self) -> bool {
+    self.addresses.is_empty()
+  }
+
+  /// Return the address of the symbol with the given name. If there is no
+  /// exact match, a match ignoring case is accepted.
+  pub fn address(&self, name: &str) -> Option<u16> {
+    self.addresses.get(name).copied().or_else(|| {
+      self
+        .addresses
+        .iter()
+        .find(|(symbol, _)| symbol.eq_ignore_ascii_case(name))
+        .map(|(_, address)| *address)
+    })
+  }
+
+  /// Return the name shown for exactly the given address, if any.
+  pub fn name(&self, address: u16) -> Option<&str> {
+    self.names.get(&address).map(String::as_str)
+  }
+
+  /// Return the given address relative to the nearest symbol at or before it
+  /// (e.g. `CHROUT` or `CHROUT+3`), if there is one close enough.
+  pub fn label(&self, address: u16) -> Option<String> {
+    let (start, name) = self.names.range(..=address).next_back()?;
+
+    match address - start {
+      0 => Some(name.clone()),
+      offset if offset <= MAX_OFFSET => Some(format!("{name}+{offset}")),
+      _ => None,
+    }
+  }
+
+  /// Return the label for the given address, or the address in hex (e.g.
+  /// `$FFD5`) if it has none.
+  pub fn format(&self, address: u16) -> String {
+    self
+      .label(address)
+      .unwrap_or_else(|| format!("${address:04X}"))
+  }
+
+  /// Find the address named by a label in the form returned by
+  /// [`SymbolTable::label`]: a symbol, optionally followed by a decimal offset
+  /// (e.g. `CHROUT+3` or `loop-1`).
+  pub fn resolve(&self, label: &str) -> Option<u16> {
+    if let Some(address) = self.address(label) {
+      return Some(address);
+    }
+
+    let split = label.rfind(['+', '-']).filter(|&index| index > 0)?;
+    let base = self.address(&label[..split])?;
+    let offset: u16 = label[split + 1..].parse().ok()?;
+
+    match &label[split..=split] {
+      "+" => Some(base.wrapping_add(offset)),
+      _ => Some(base.wrapping_sub(offset)),
+    }
+  }
+
+  /// Read a VICE label file, with lines like `al C:ffd2 .CHROUT`. These are
+  /// also written by ld65's `-Ln` option, as `al 00FFD2 .CHROUT`.
+  pub fn parse_vice(text: &str) -> Result<Self, String> {
+    let mut table = SymbolTable::new();
+
+    for line in text.lines() {
+      let mut words = line.split_whitespace();
+      if words.next() != Some("al") {
+        continue;
+      }
+
+      let (address, name) = match (words.next(), words.next()) {
+        (Some(address), Some(name)) => (address, name),
+        _ => return Err(format!("invalid label: {line}")),
+      };
+
+      // The address may be prefixed with a memory space, e.g. `C:` for the CPU
+      let address = address.rsplit(':').next().unwrap();
+      table.insert(name.trim_start_matches('.'), parse_hex(address)?);
+    }
+
+    if table.is_empty() {
+      return Err("not a VICE label file".to_owned());
+    }
+
+    Ok(table)
+  }
+
+  /// Read the labels from a debug info file produced by ld65's `--dbgfile`
+  /// option. Constants defined with `=` are left out, since most of them are
+  /// not addresses.
+  pub fn parse_ca65_dbg(text: &str) -> Result<Self, String> {
+    let mut table = SymbolTable::new();
+
+    if !text.starts_with("version\t") {
+      return Err("not a ca65 debug info file".to_owned());
+    }
+
+    for line in text.lines() {
+      match ca65::record(line) {
+        Some(("sym", fields)) if fields.get("type") == Some(&"lab") => {
+          let name = fields.get("name").ok_or("symbol without a name")?;
+          table.insert(name, ca65::number(fields.get("val"))? as u16);
+        }
+        _ => {}
+      }
+    }
+
+    Ok(table)
+  }
+
+  /// Read the symbols from the end of a listing produced by vasm's `-L`
+  /// option, where each line under `Symbols by name:` gives a symbol and its
+  /// value (e.g. `loop    A:0202`).
+  pub fn parse_vasm_listing(text: &str) -> Result<Self, String> {
+    let mut table = SymbolTable::new();
+
+    let (_, symbols) = text
+      .split_once("Symbols by name:")
+      .ok_or("not a vasm listing with symbols")?;
+
+    for line in symbols.lines().skip(1) {
+      let mut words = line.split_whitespace();
+      let (name, value) = match (words.next(), words.next()) {
+        (Some(name), Some(value)) => (name, value),
+        // The table ends at the first blank line
+        (None, _) => break,
+        (Some(_), None) => return Err(format!("invalid symbol: {line}")),
+      };
+
+      let value = value.split_once(':').map_or(value, |(_, value)| value);
+      table.insert(name, parse_hex(value)?);
+    }
+
+    Ok(table)
+  }
+
+  /// Load a VICE label file, ca65 debug info file or vasm listing, detecting
+  /// which it is from its contents.
+  pub fn load(path: &str) -> Result<Self, String> {
+    let text = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+
+    if text.starts_with("version\t") {
+      Self::parse_ca65_dbg(&text)
+    } else if text.contains("Symbols by name:") {
+      Self::parse_vasm_listing(&text)
+    } else {
+      Self::parse_vice(&text)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_labels() {
+    let mut table = SymbolTable::new();
+    table.insert("CHROUT", 0xFFD2);
+    table.insert("LOAD", 0xFFD5);
+    table.insert("start", 0x0200);
+
+    assert_eq!(Some("CHROUT".to_owned()), table.label(0xFFD2));
+    assert_eq!(Some("CHROUT+2".to_owned()), table.label(0xFFD4));
+    assert_eq!(Some("LOAD".to_owned()), table.label(0xFFD5));
+    assert_eq!(Some("start+255".to_owned()), table.label(0x02FF));
+    assert_eq!(None, table.label(0x0300));
+    assert_eq!(None, table.label(0x01FF));
+    assert_eq!("$0300", table.format(0x0300));
+
+    assert_eq!(Some(0xFFD4), table.resolve("CHROUT+2"));
+    assert_eq!(Some(0xFFD4), table.resolve("chrout+2"));
+    assert_eq!(Some(0x01FF), table.resolve("start-1"));
+    assert_eq!(None, table.resolve("CHROUT+x"));
+    assert_eq!(None, table.resolve("nowhere"));
+
+    // the last name given to an address is the one shown
+    table.insert("BSOUT", 0xFFD2);
+    assert_eq!(Some("BSOUT"), table.name(0xFFD2));
+    assert_eq!(Some(0xFFD2), table.address("CHROUT"));
+
+    // a name given to a new address is no longer shown at the old one
+    table.insert("LOAD", 0xC000);
+    assert_eq!(None, table.name(0xFFD5));
+    assert_eq!(Some("LOAD"), table.name(0xC000));
+  }
+
+  #[test]
+  fn test_merge() {
+    let mut table = c64_symbols();
+    let mut labels = SymbolTable::new();
+    labels.insert("IRQ", 0xC000);
+    table.merge(labels);
+
+    assert_eq!(Some(0xC000), table.address("IRQ"));
+    assert_eq!(Some("IRQ"), table.name(0xC000));
+    assert_eq!(None, table.name(0xFF48));
+  }
+
+  #[test]
+  fn test_vice() {
+    let table =
+      SymbolTable::parse_vice("al C:ffd2 .CHROUT\nal 00C000 .main\nbreak 1234\nal C:c010 .@loop\n")
+        .unwrap();
+
+    assert_eq!(3, table.len());
+    assert_eq!(Some(0xFFD2), table.address("CHROUT"));
+    assert_eq!(Some(0xC000), table.address("main"));
+    assert_eq!(Some("@loop"), table.name(0xC010));
+
+    assert!(SymbolTable::parse_vice("al C:xyz .CHROUT").is_err());
+    assert!(SymbolTable::parse_vice("nothing here").is_err());
+  }
+
+  #[test]
+  fn test_ca65_dbg() {
+    let dbg = "version\tmajor=2,minor=0
+sym\tid=0,name=\"main\",addrsize=absolute,size=3,scope=0,def=1,ref=4,val=0xC000,seg=0,type=lab
+sym\tid=1,name=\"COLUMNS\",addrsize=zeropage,scope=0,def=2,val=0x28,type=equ
+sym\tid=2,name=\"CHROUT\",addrsize=absolute,scope=0,def=3,type=imp,exp=3
+sym\tid=3,name=\"@done\",addrsize=absolute,scope=0,def=5,val=49159,seg=0,type=lab
+";
+
+    let table = SymbolTable::parse_ca65_dbg(dbg).unwrap();
+    assert_eq!(2, table.len());
+    assert_eq!(Some(0xC000), table.address("main"));
+    assert_eq!(Some(0xC007), table.address("@done"));
+
+    assert!(SymbolTable::parse_ca65_dbg("al C:ffd2 .CHROUT").is_err());
+  }
+
+  #[test]
+  fn test_vasm_listing() {
+    let listing = "Source: \"count.s\"
+00:0200 A203            \t     3: start: ldx #3
+
+
+Symbols by name:
+done                             A:020B
+loop                             A:0202
+start                            A:0200
+
+Symbols by value:
+0200 start
+";
+
+    let table = SymbolTable::parse_vasm_listing(listing).unwrap();
+    assert_eq!(3, table.len());
+    assert_eq!(Some("loop+3"), table.label(0x0205).as_deref());
+    assert_eq!(Some(0x020B), table.address("done"));
+
+    assert!(SymbolTable::parse_vasm_listing("Source: \"count.s\"").is_err());
+  }
+
+  #[test]
+  fn test_builtin() {
+    let c64 = c64_symbols();
+    assert_eq!("CHROUT", c64.format(0xFFD2));
+    assert_eq!("CHROUT+2", c64.format(0xFFD4));
+    assert_eq!(Some(0xA474), c64.address("READY"));
+
+    // Every KERNAL jump table entry should be a JMP in the bundled ROMs
+    for (table, path, base) in [
+      (pet_symbols(), "pet/kernal.bin", 0xF000),
+      (vic20_symbols(), "vic/kernal.bin", 0xE000),
+      (c64, "c64/kernal.bin", 0xE000),
+    ] {
+      let rom = std::fs::read(path).unwrap();
+      for address in (0xFF81..0xFFF6).filter(|&a| table.name(a).is_some()) {
+        let opcode = rom[(address - base) as usize];
+        assert!(
+          opcode == 0x4C || opcode == 0x6C,
+          "{path}: {} is not a jump",
+          table.format(address)
+        );
+      }
+    }
+  }
+}
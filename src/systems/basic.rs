@@ -2,7 +2,7 @@ use instant::Duration;
 
 use crate::cpu::{
   mos6502::{Mos6502, Mos6502Variant},
-  Cpu,
+  Cpu, CpuError,
 };
 use crate::memory::{ActiveInterrupt, Memory};
 use crate::memory::{BlockMemory, BranchMemory};
@@ -14,7 +14,7 @@ use std::sync::Arc;
 
 /// A Memory implementation that can be used to read from or write to
 /// STDIN/STDOUT.
-struct MappedStdIO {
+pub(crate) struct MappedStdIO {
   provider: Arc<dyn PlatformProvider>,
 }
 
@@ -44,6 +44,11 @@ impl Memory for MappedStdIO {
     }
   }
 
+  /// Input can't be seen without waiting for it, so nothing is read.
+  fn peek(&self, _address: u16) -> u8 {
+    0
+  }
+
   /// Write to STDOUT. The mode is controlled by the address.
   /// 0x00: u8 as dec
   /// 0x01: char
@@ -95,8 +100,8 @@ impl System for BasicSystem {
     Box::new(&mut self.cpu)
   }
 
-  fn tick(&mut self) -> Duration {
-    Duration::from_secs_f64(1.0 / 20_000.0) * self.cpu.tick().into()
+  fn tick(&mut self) -> Result<Duration, CpuError> {
+    Ok(Duration::from_secs_f64(1.0 / 20_000.0) * self.cpu.tick()?.into())
   }
 
   fn reset(&mut self) {
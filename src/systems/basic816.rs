@@ -0,0 +1,56 @@
+use instant::Duration;
+
+use crate::cpu::{w65c816::W65C816, Cpu, CpuError};
+use crate::memory::{BlockMemory, BranchMemory, LongBranchMemory};
+use crate::platform::{PlatformProvider, WindowConfig};
+use crate::roms::RomFile;
+use crate::systems::{basic::MappedStdIO, BuildableSystem, System};
+use std::sync::Arc;
+
+/// The number of 64K banks of RAM mapped above bank 0.
+const EXTRA_RAM_BANKS: u8 = 7;
+
+impl BuildableSystem<RomFile, ()> for Basic816System {
+  fn build(rom: RomFile, _config: (), platform: Arc<dyn PlatformProvider>) -> Box<dyn System> {
+    let ram = BlockMemory::ram(0x4000);
+    let io = MappedStdIO::new(platform);
+    let rom = BlockMemory::from_file(0x8000, rom);
+
+    let bank_zero = BranchMemory::new()
+      .map(0x0000, ram)
+      .map(0x4000, io)
+      .map(0x8000, rom);
+
+    let memory = (1..=EXTRA_RAM_BANKS).fold(
+      LongBranchMemory::new().map(0x00, bank_zero),
+      |memory, bank| memory.map(bank, BlockMemory::ram(0x10000)),
+    );
+
+    let cpu = W65C816::new(memory);
+
+    Box::new(Basic816System { cpu })
+  }
+}
+
+/// A text-mode system with the same memory map as the
+/// [`BasicSystem`](crate::systems::basic::BasicSystem) in bank 0, but driven
+/// by a 65C816 with an additional 448K of RAM in banks 1 through 7.
+pub struct Basic816System {
+  cpu: W65C816,
+}
+
+impl System for Basic816System {
+  fn get_cpu_mut(&mut self) -> Box<&mut dyn Cpu> {
+    Box::new(&mut self.cpu)
+  }
+
+  fn tick(&mut self) -> Result<Duration, CpuError> {
+    Ok(Duration::from_secs_f64(1.0 / 20_000.0) * self.cpu.tick()?.into())
+  }
+
+  fn reset(&mut self) {
+    self.cpu.reset();
+  }
+
+  fn render(&mut self, _framebuffer: &mut [u8], _config: WindowConfig) {}
+}
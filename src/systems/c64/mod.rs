@@ -6,15 +6,17 @@ use std::{
 
 use crate::{
   cpu::mos6502::{Mos6502, Mos6502Variant},
-  cpu::Cpu,
+  cpu::{Cpu, CpuError},
   keyboard::{
     commodore::{C64KeyboardAdapter, C64SymbolAdapter, C64VirtualAdapter},
     KeyAdapter, KeyMappingStrategy, SymbolAdapter,
   },
   memory::{
-    mos652x::Cia, BankedMemory, BlockMemory, BranchMemory, Mos6510Port, NullMemory, NullPort, Port,
+    mos652x::Cia, BankedMemory, BlockMemory, BranchMemory, DataBus, DataBusMemory, Mos6510Port,
+    NullMemory, NullPort, Port, RamPattern,
   },
   platform::{PlatformProvider, WindowConfig},
+  state::{StateError, StateReader, StateWriter},
   systems::System,
 };
 
@@ -52,6 +54,10 @@ impl C64Cia1PortA {
 
 impl Port for C64Cia1PortA {
   fn read(&mut self) -> u8 {
+    self.peek()
+  }
+
+  fn peek(&self) -> u8 {
     self.keyboard_row.get()
   }
 
@@ -64,6 +70,15 @@ impl Port for C64Cia1PortA {
   }
 
   fn reset(&mut self) {}
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    state.write(&self.keyboard_row.get())
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    self.keyboard_row.set(state.read()?);
+    Ok(())
+  }
 }
 
 /// Port B on the first CIA chip on the C64 deals with reading columns of the keyboard matrix.
@@ -91,6 +106,10 @@ impl C64Cia1PortB {
 
 impl Port for C64Cia1PortB {
   fn read(&mut self) -> u8 {
+    self.peek()
+  }
+
+  fn peek(&self) -> u8 {
     let row_mask = self.keyboard_row.get();
 
     let mut value = 0b1111_1111;
@@ -152,6 +171,10 @@ impl C64BankSwitching {
 
 impl Port for C64BankSwitching {
   fn read(&mut self) -> u8 {
+    self.peek()
+  }
+
+  fn peek(&self) -> u8 {
     (self.loram as u8) | (self.hiram as u8) << 1 | (self.charen as u8) << 2
   }
 
@@ -197,11 +220,30 @@ impl Port for C64BankSwitching {
     self.loram = true;
     self.charen = true;
   }
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    state.write(&(self.hiram, self.loram, self.charen))
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    // The selectors themselves are restored by the banked memory
+    (self.hiram, self.loram, self.charen) = state.read()?;
+    Ok(())
+  }
 }
 
-/// Configuration for a Commodore 64 system.
+/// Configuration for a Commodore 64 system. The default maps physical keys,
+/// reads zero from unmapped addresses and clears RAM at power-on.
+#[derive(Default)]
 pub struct C64SystemConfig {
   pub mapping: KeyMappingStrategy,
+
+  /// Whether unmapped addresses read back open bus; see [`NullMemory::unmapped`].
+  pub open_bus: bool,
+
+  /// The contents of RAM when the system is powered on or reset. The pattern
+  /// of the real machine is [`C64System::RAM_PATTERN`].
+  pub ram_pattern: RamPattern,
 }
 
 impl BuildableSystem<C64SystemRoms, C64SystemConfig> for C64System {
@@ -216,34 +258,42 @@ impl BuildableSystem<C64SystemRoms, C64SystemConfig> for C64System {
       2.0,
     ));
 
+    // Addresses which nothing is connected to
+    let bus = DataBus::new();
+    let unmapped = || NullMemory::unmapped(&bus, config.open_bus);
+
+    // RAM, at the given address
+    let ram_pattern = config.ram_pattern;
+    let ram = |size, address| BlockMemory::ram(size).with_pattern(ram_pattern, address);
+
     // Region 1: 0x0000 - 0x0FFF
-    let region1 = BlockMemory::ram(0x1000);
+    let region1 = ram(0x1000, 0x0002);
 
     // Region 2: 0x1000 - 0x7FFF
     let selector2 = Rc::new(Cell::new(0));
     let region2 = BankedMemory::new(selector2.clone())
-      .bank(BlockMemory::ram(0x7000))
-      .bank(NullMemory::new());
+      .bank(ram(0x7000, 0x1000))
+      .bank(unmapped());
 
     // Region 3: 0x8000 - 0x9FFF
     let selector3 = Rc::new(Cell::new(0));
     let region3 = BankedMemory::new(selector3.clone())
-      .bank(BlockMemory::ram(0x2000))
-      .bank(NullMemory::new()); // TODO: Cartridge Rom Low
+      .bank(ram(0x2000, 0x8000))
+      .bank(unmapped()); // TODO: Cartridge Rom Low
 
     // Region 4: 0xA000 - 0xBFFF
     let selector4 = Rc::new(Cell::new(0));
     let region4 = BankedMemory::new(selector4.clone())
       .bank(BlockMemory::from_file(0x2000, roms.basic))
-      .bank(BlockMemory::ram(0x2000))
-      .bank(NullMemory::new()) // TODO: Cartridge Rom High
-      .bank(NullMemory::new());
+      .bank(ram(0x2000, 0xA000))
+      .bank(unmapped()) // TODO: Cartridge Rom High
+      .bank(unmapped());
 
     // Region 5: 0xC000 - 0xCFFF
     let selector5 = Rc::new(Cell::new(0));
     let region5 = BankedMemory::new(selector5.clone())
-      .bank(BlockMemory::ram(0x1000))
-      .bank(NullMemory::new());
+      .bank(ram(0x1000, 0xC000))
+      .bank(unmapped());
 
     // Region 6: 0xD000 - 0xDFFF
     let selector6 = Rc::new(Cell::new(0));
@@ -268,23 +318,23 @@ impl BuildableSystem<C64SystemRoms, C64SystemConfig> for C64System {
     let region6 = BankedMemory::new(selector6.clone())
       .bank(
         BranchMemory::new()
-          .map(0x000, vic_io)
-          .map(0x400, NullMemory::new()) // TODO: SID
-          .map(0x800, BlockMemory::ram(0x0400))
-          .map(0xC00, cia_1)
-          .map(0xD00, cia_2)
-          .map(0xE00, NullMemory::new()) // TODO: Expansion card
-          .map(0xF00, NullMemory::new()), // TODO: Expansion card
+          .map_mirrored(0x000, VicIIChipIO::REGISTERS, vic_io)
+          .map_mirrored(0x400, 0x20, unmapped()) // TODO: SID
+          .map(0x800, ram(0x0400, 0xD800))
+          .map_mirrored(0xC00, Cia::REGISTERS, cia_1)
+          .map_mirrored(0xD00, Cia::REGISTERS, cia_2)
+          .map(0xE00, unmapped()) // TODO: Expansion card
+          .map(0xF00, unmapped()), // TODO: Expansion card
       )
-      .bank(BlockMemory::ram(0x1000))
+      .bank(ram(0x1000, 0xD000))
       .bank(BlockMemory::from_file(0x1000, roms.character));
 
     // Region 7: 0xE000 - 0xFFFF
     let selector7 = Rc::new(Cell::new(0));
     let region7 = BankedMemory::new(selector7.clone())
       .bank(BlockMemory::from_file(0x2000, roms.kernal))
-      .bank(BlockMemory::ram(0x2000))
-      .bank(NullMemory::new()); // TODO: Cartidge Rom High
+      .bank(ram(0x2000, 0xE000))
+      .bank(unmapped()); // TODO: Cartidge Rom High
 
     let bank_switching = C64BankSwitching::new([
       selector2, selector3, selector4, selector5, selector6, selector7,
@@ -300,7 +350,7 @@ impl BuildableSystem<C64SystemRoms, C64SystemConfig> for C64System {
       .map(0xD000, region6)
       .map(0xE000, region7);
 
-    let cpu = Mos6502::new(memory, Mos6502Variant::NMOS);
+    let cpu = Mos6502::new(DataBusMemory::new(memory, bus), Mos6502Variant::NMOS);
 
     Box::new(C64System { cpu, vic: vic_ii })
   }
@@ -312,13 +362,19 @@ pub struct C64System {
   vic: Rc<RefCell<VicIIChip>>,
 }
 
+impl C64System {
+  /// The contents of the C64's RAM when it is powered on: alternating stripes
+  /// of 64 $00 bytes and 64 $FF bytes.
+  pub const RAM_PATTERN: RamPattern = RamPattern::Stripes(64);
+}
+
 impl System for C64System {
   fn get_cpu_mut(&mut self) -> Box<&mut dyn Cpu> {
     Box::new(&mut self.cpu)
   }
 
-  fn tick(&mut self) -> Duration {
-    Duration::from_secs_f64(1.0 / 1_000_000.0) * self.cpu.tick() as u32
+  fn tick(&mut self) -> Result<Duration, CpuError> {
+    Ok(Duration::from_secs_f64(1.0 / 1_000_000.0) * self.cpu.tick()? as u32)
   }
 
   fn reset(&mut self) {
@@ -1,5 +1,7 @@
-use crate::memory::{ActiveInterrupt, Memory};
+use crate::memory::{ActiveInterrupt, Memory, NullMemory};
 use crate::platform::{Color, WindowConfig};
+use crate::state::{StateError, StateReader, StateWriter};
+use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use std::rc::Rc;
 
@@ -15,7 +17,7 @@ pub const FULL_WIDTH: u32 = WIDTH * CHAR_WIDTH + BORDER_WIDTH * 2;
 pub const FULL_HEIGHT: u32 = HEIGHT * CHAR_HEIGHT + BORDER_HEIGHT * 2;
 const SPRITE_MEMORY_SIZE: u16 = (SPRITE_WIDTH * SPRITE_HEIGHT / 8) as u16;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 struct Sprite {
   pub x: u16,
   pub y: u8,
@@ -50,7 +52,10 @@ mod interrupt_bits {
   pub const LIGHT_PEN: u8 = 1 << 3;
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct VicIIChip {
+  // The character ROM never changes, so it isn't part of the save state
+  #[serde(skip, default = "no_character_rom")]
   character_rom: Box<dyn Memory>,
 
   sprites: [Sprite; 8],
@@ -79,6 +84,10 @@ pub struct VicIIChip {
   y_scroll: u8,
 }
 
+fn no_character_rom() -> Box<dyn Memory> {
+  Box::new(NullMemory::new())
+}
+
 impl VicIIChip {
   pub fn new(character_rom: Box<dyn Memory>) -> Self {
     Self {
@@ -127,7 +136,7 @@ impl VicIIChip {
   fn read_vram(&self, address: u16, memory: &mut Box<dyn Memory>) -> u8 {
     let offset = 0x0400;
 
-    memory.read(address + offset)
+    memory.peek(address + offset)
   }
 
   /// Read the value of the color memory at the given address,
@@ -135,7 +144,7 @@ impl VicIIChip {
   fn read_color(&self, address: u16, memory: &mut Box<dyn Memory>) -> u8 {
     let offset = 0xD800;
 
-    memory.read(address + offset)
+    memory.peek(address + offset)
   }
 
   /// Get the bits in the character at the given value.
@@ -196,10 +205,10 @@ impl VicIIChip {
     }
 
     let data_pointer = 0x07F8 + index as u16;
-    let data_address = memory.read(data_pointer) as u16 * 64;
+    let data_address = memory.peek(data_pointer) as u16 * 64;
 
     for byte_index in 0..SPRITE_MEMORY_SIZE {
-      let data_byte = memory.read(data_address + byte_index);
+      let data_byte = memory.peek(data_address + byte_index);
 
       for bit_index in 0..8 {
         let color = if data_byte & (1 << (7 - bit_index)) != 0 {
@@ -293,6 +302,11 @@ pub struct VicIIChipIO {
 }
 
 impl VicIIChipIO {
+  /// The number of registers, which repeat every $40 bytes across $D000-$D3FF.
+  /// It only sees the register index, so map it with
+  /// [`BranchMemory::map_mirrored`](crate::memory::BranchMemory::map_mirrored).
+  pub const REGISTERS: u16 = 0x40;
+
   pub fn new(chip: Rc<RefCell<VicIIChip>>) -> Self {
     Self { chip }
   }
@@ -300,11 +314,15 @@ impl VicIIChipIO {
 
 impl Memory for VicIIChipIO {
   fn read(&mut self, address: u16) -> u8 {
+    self.peek(address)
+  }
+
+  fn peek(&self, address: u16) -> u8 {
     let chip = self.chip.borrow();
 
-    match address % 0x40 {
+    match address {
       0x00..=0x0F => {
-        let sprite_index = (address % 0x40 / 2) as usize;
+        let sprite_index = (address / 2) as usize;
 
         match sprite_index % 2 {
           0 => chip.sprites[sprite_index].x as u8,
@@ -360,9 +378,9 @@ impl Memory for VicIIChipIO {
       0x1E => 0, // TODO: sprite-sprite collision
       0x1F => 0, // TODO: sprite-data collision
       0x20 => 0xF0 | chip.border_color,
-      0x21..=0x24 => 0xF0 | chip.background_color[(address % 0x40) as usize - 0x21],
-      0x25..=0x26 => 0xF0 | chip.sprite_multicolor[(address % 0x40) as usize - 0x25],
-      0x27..=0x2E => 0xF0 | chip.sprites[(address % 0x40) as usize - 0x27].color,
+      0x21..=0x24 => 0xF0 | chip.background_color[address as usize - 0x21],
+      0x25..=0x26 => 0xF0 | chip.sprite_multicolor[address as usize - 0x25],
+      0x27..=0x2E => 0xF0 | chip.sprites[address as usize - 0x27].color,
       0x2F..=0x3F => 0xFF,
       _ => unreachable!(),
     }
@@ -371,9 +389,9 @@ impl Memory for VicIIChipIO {
   fn write(&mut self, address: u16, value: u8) {
     let mut chip = self.chip.borrow_mut();
 
-    match address % 0x40 {
+    match address {
       0x00..=0x0F => {
-        let sprite_index = (address % 0x40 / 2) as usize;
+        let sprite_index = (address / 2) as usize;
 
         match sprite_index % 2 {
           0 => {
@@ -438,9 +456,9 @@ impl Memory for VicIIChipIO {
       0x1E => {}
       0x1F => {}
       0x20 => chip.border_color = value & 0x0F,
-      0x21..=0x24 => chip.background_color[(address % 0x40) as usize - 0x21] = value & 0x0F,
-      0x25..=0x26 => chip.sprite_multicolor[(address % 0x40) as usize - 0x25] = value & 0x0F,
-      0x27..=0x2E => chip.sprites[(address % 0x40) as usize - 0x27].color = value & 0x0F,
+      0x21..=0x24 => chip.background_color[address as usize - 0x21] = value & 0x0F,
+      0x25..=0x26 => chip.sprite_multicolor[address as usize - 0x25] = value & 0x0F,
+      0x27..=0x2E => chip.sprites[address as usize - 0x27].color = value & 0x0F,
       0x2F..=0x3F => {} // no-op
       _ => unreachable!(),
     }
@@ -455,4 +473,18 @@ impl Memory for VicIIChipIO {
 
     ActiveInterrupt::None
   }
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    state.write(&*self.chip.borrow())
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    let mut restored: VicIIChip = state.read()?;
+    let mut chip = self.chip.borrow_mut();
+
+    std::mem::swap(&mut restored.character_rom, &mut chip.character_rom);
+    *chip = restored;
+
+    Ok(())
+  }
 }
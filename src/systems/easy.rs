@@ -1,8 +1,8 @@
 use instant::Duration;
 
 use crate::cpu::{
-  mos6502::{MemoryIO, Mos6502, Mos6502Variant},
-  Cpu,
+  mos6502::{Mos6502, Mos6502Variant},
+  Cpu, CpuError,
 };
 use crate::keyboard::KeyPosition;
 use crate::memory::{ActiveInterrupt, BlockMemory, BranchMemory, Memory};
@@ -33,6 +33,14 @@ impl Memory for EasyIO {
   fn read(&mut self, address: u16) -> u8 {
     match address % 2 {
       0 => self.platform.random(),
+      _ => self.peek(address),
+    }
+  }
+
+  /// The random number is left out, since generating it changes the next one.
+  fn peek(&self, address: u16) -> u8 {
+    match address % 2 {
+      0 => 0,
       _ => {
         let state = self.platform.get_key_state();
 
@@ -97,8 +105,8 @@ impl System for Easy6502System {
     Box::new(&mut self.cpu)
   }
 
-  fn tick(&mut self) -> Duration {
-    Duration::from_secs_f64(1.0 / 20_000.0) * self.cpu.tick().into()
+  fn tick(&mut self) -> Result<Duration, CpuError> {
+    Ok(Duration::from_secs_f64(1.0 / 20_000.0) * self.cpu.tick()?.into())
   }
 
   fn reset(&mut self) {
@@ -109,7 +117,7 @@ impl System for Easy6502System {
     for y in 0..WIDTH {
       for x in 0..WIDTH {
         let index = (y * WIDTH + x) as u16;
-        let color = self.cpu.read(0x0200 + index);
+        let color = self.cpu.peek(0x0200 + index);
 
         let color = match color & 0x0F {
           0 => Color::new(0x00, 0x00, 0x00),
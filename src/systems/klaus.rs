@@ -2,7 +2,7 @@ use instant::Duration;
 
 use crate::cpu::{
   mos6502::{Mos6502, Mos6502Variant},
-  Cpu,
+  Cpu, CpuError,
 };
 use crate::memory::BlockMemory;
 use crate::platform::{PlatformProvider, WindowConfig};
@@ -49,12 +49,12 @@ impl System for KlausSystem {
     Box::new(&mut self.cpu)
   }
 
-  fn tick(&mut self) -> Duration {
-    self.cpu.tick();
+  fn tick(&mut self) -> Result<Duration, CpuError> {
+    self.cpu.tick()?;
     if let Some(pc) = &self.pc {
       pc.set(self.cpu.registers.pc.address());
     }
-    Duration::ZERO
+    Ok(Duration::ZERO)
   }
 
   fn reset(&mut self) {
@@ -69,6 +69,7 @@ mod tests {
   use crate::{
     platform::{Platform, TextPlatform},
     roms::DiskLoadable,
+    state::StateWriter,
   };
 
   use super::*;
@@ -89,7 +90,7 @@ mod tests {
     );
 
     for _ in 0..=100000000 {
-      system.tick();
+      system.tick().unwrap();
     }
 
     assert_eq!(pc.get(), 0x3469);
@@ -111,9 +112,54 @@ mod tests {
     );
 
     for _ in 0..=100000000 {
-      system.tick();
+      system.tick().unwrap();
     }
 
     assert_eq!(pc.get(), 0x24f1);
   }
+
+  #[test]
+  fn test_save_state() {
+    let roms = RomFile::from_file("bin/klaus_6502.bin");
+    let platform = TextPlatform::new();
+    let pc = Rc::new(Cell::new(0));
+
+    let mut system = KlausSystem::build(
+      roms,
+      KlausSystemConfig {
+        pc_report: Some(pc.clone()),
+        variant: Mos6502Variant::NMOS,
+      },
+      platform.provider(),
+    );
+
+    for _ in 0..10000 {
+      system.tick().unwrap();
+    }
+
+    let state = system.save_state().unwrap();
+
+    let mut trace = Vec::new();
+    for _ in 0..10000 {
+      system.tick().unwrap();
+      trace.push(pc.get());
+    }
+    let cycles = system.get_cpu_mut().get_cycle_count();
+
+    // resuming from the save state should retrace the same steps
+    system.reset();
+    system.load_state(&state).unwrap();
+
+    for expected in trace {
+      system.tick().unwrap();
+      assert_eq!(expected, pc.get());
+    }
+    assert_eq!(cycles, system.get_cpu_mut().get_cycle_count());
+
+    // a save state from a different system is rejected
+    let cpu = Mos6502::new(BlockMemory::ram(0x100), Mos6502Variant::NMOS);
+    let mut writer = StateWriter::new();
+    cpu.save_state(&mut writer).unwrap();
+    assert!(system.load_state(&writer.finish()).is_err());
+  }
 }
@@ -1,17 +1,21 @@
 use crate::{
-  cpu::Cpu,
+  cpu::{Cpu, CpuError},
+  debugger::DebugHandler,
   platform::{PlatformProvider, WindowConfig},
+  state::{StateError, StateReader, StateWriter},
   trace::TraceHandler,
 };
 use instant::Duration;
 use std::sync::Arc;
 
 pub mod basic;
+pub mod basic816;
 pub mod c64;
 pub mod easy;
 pub mod klaus;
 pub mod pet;
 pub mod vic;
+pub mod zex;
 
 pub trait BuildableSystem<RomRegistry, SystemConfig> {
   /// Instantiate this system from the given roms, configuration, and with I/O provided by the given
@@ -32,8 +36,20 @@ pub trait System {
     self.get_cpu_mut().attach_trace_handler(handler);
   }
 
+  /// Attach an interactive debugger to this system's CPU.
+  fn attach_debugger(&mut self, debugger: Box<dyn DebugHandler>) {
+    self.get_cpu_mut().attach_debugger(debugger);
+  }
+
+  /// Choose whether this system's CPU executes in cycle-stepped mode.
+  fn set_cycle_stepped(&mut self, enabled: bool) {
+    self.get_cpu_mut().set_cycle_stepped(enabled);
+  }
+
   /// Advance the system by one tick.
-  fn tick(&mut self) -> Duration;
+  /// Return the amount of emulated time that has passed, or an error if the
+  /// CPU has halted.
+  fn tick(&mut self) -> Result<Duration, CpuError>;
 
   /// Reset the system's state.
   fn reset(&mut self);
@@ -45,4 +61,21 @@ pub trait System {
   fn cleanup(&mut self) -> Result<(), &str> {
     self.get_cpu_mut().cleanup()
   }
+
+  /// Snapshot the complete state of this system (its CPU, memory, and chips),
+  /// so that it can be resumed later with [`System::load_state`].
+  fn save_state(&mut self) -> Result<Vec<u8>, StateError> {
+    let mut state = StateWriter::new();
+    self.get_cpu_mut().save_state(&mut state)?;
+    Ok(state.finish())
+  }
+
+  /// Restore a snapshot taken by [`System::save_state`] on a system built with
+  /// the same ROMs and configuration. If this fails, the system may be left
+  /// partially restored, and should be reset.
+  fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+    let mut state = StateReader::new(data)?;
+    self.get_cpu_mut().load_state(&mut state)?;
+    state.finish()
+  }
 }
@@ -1,13 +1,15 @@
 use crate::cpu::{
-  mos6502::{MemoryIO, Mos6502, Mos6502Variant},
-  Cpu,
+  mos6502::{Mos6502, Mos6502Variant},
+  Cpu, CpuError,
 };
 use crate::keyboard::{KeyAdapter, KeyMappingStrategy, SymbolAdapter};
 use crate::memory::mos652x::{Pia, Via};
-use crate::memory::{BlockMemory, BranchMemory, NullMemory, NullPort, Port};
+use crate::memory::{
+  BlockMemory, BranchMemory, DataBus, DataBusMemory, NullMemory, NullPort, Port, RamPattern,
+};
 use crate::platform::{Color, PlatformProvider, WindowConfig};
+use crate::state::{StateError, StateReader, StateWriter};
 use crate::systems::{BuildableSystem, System};
-use instant::Instant;
 use std::cell::Cell;
 use std::rc::Rc;
 use std::sync::Arc;
@@ -26,13 +28,16 @@ const CHAR_WIDTH: u32 = 8;
 const CHAR_HEIGHT: u32 = 8;
 const VRAM_SIZE: usize = 1024; // 24 extra bytes to make mapping easier
 
+/// The number of cycles between the interrupts fired at the end of each frame,
+/// 60 times per second at the PET's 1 MHz clock.
+const CYCLES_PER_FRAME: u64 = 1_000_000 / 60;
+
 /// Port A on the first PIA.
 /// This is used for generating the 60Hz interrupt (which is fired when the
 /// screen drawing reaches the last line), and for setting the active
 /// row of the keyboard matrix.
 pub struct PetPia1PortA {
   keyboard_row: Rc<Cell<u8>>,
-  last_draw_instant: Option<Instant>,
   last_draw_cycle: u64,
 }
 
@@ -40,7 +45,6 @@ impl PetPia1PortA {
   pub fn new() -> Self {
     Self {
       keyboard_row: Rc::new(Cell::new(0)),
-      last_draw_instant: None,
       last_draw_cycle: 0,
     }
   }
@@ -52,6 +56,10 @@ impl PetPia1PortA {
 
 impl Port for PetPia1PortA {
   fn read(&mut self) -> u8 {
+    self.peek()
+  }
+
+  fn peek(&self) -> u8 {
     0b1000_0000 | self.keyboard_row.get()
     //^         diagnostic mode off
     // ^        IEEE488 (not implemented)
@@ -64,32 +72,28 @@ impl Port for PetPia1PortA {
   }
 
   fn poll(&mut self, _cycles_since_poll: u64, total_cycle_count: u64) -> bool {
-    // let min_elapsed = ((info.cycles_per_second as f64 / 60.0) * (2.0 / 3.0)) as u64;
-    let min_elapsed = 0; // TODO: fix
-
-    match self.last_draw_instant {
-      Some(last_draw) => {
-        if (last_draw.elapsed() > Duration::from_millis(17))
-          && (total_cycle_count > self.last_draw_cycle + min_elapsed)
-        {
-          self.last_draw_cycle = total_cycle_count;
-          self.last_draw_instant = Some(Instant::now());
-          true
-          // false
-        } else {
-          false
-        }
-      }
-      None => {
-        self.last_draw_instant = Some(Instant::now());
-        false
-      }
+    if total_cycle_count >= self.last_draw_cycle + CYCLES_PER_FRAME {
+      self.last_draw_cycle = total_cycle_count;
+      true
+    } else {
+      false
     }
   }
 
   fn reset(&mut self) {
     self.keyboard_row.set(0);
   }
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    state.write(&(self.keyboard_row.get(), self.last_draw_cycle))
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    let (keyboard_row, last_draw_cycle) = state.read()?;
+    self.keyboard_row.set(keyboard_row);
+    self.last_draw_cycle = last_draw_cycle;
+    Ok(())
+  }
 }
 
 /// Port B on the first PIA.
@@ -116,6 +120,10 @@ impl PetPia1PortB {
 
 impl Port for PetPia1PortB {
   fn read(&mut self) -> u8 {
+    self.peek()
+  }
+
+  fn peek(&self) -> u8 {
     let row = self.keyboard_row.get();
     let row = KEYBOARD_MAPPING[row as usize % 10];
     let mut value = 0b1111_1111;
@@ -146,9 +154,18 @@ impl Port for PetPia1PortB {
   fn reset(&mut self) {}
 }
 
-/// Configuration for a Commodore PET system.
+/// Configuration for a Commodore PET system. The default maps physical keys,
+/// reads zero from unmapped addresses and clears RAM at power-on.
+#[derive(Default)]
 pub struct PetSystemConfig {
   pub mapping: KeyMappingStrategy,
+
+  /// Whether unmapped addresses read back open bus; see [`NullMemory::unmapped`].
+  pub open_bus: bool,
+
+  /// The contents of RAM when the system is powered on or reset. The pattern
+  /// of the real machine is [`PetSystem::RAM_PATTERN`].
+  pub ram_pattern: RamPattern,
 }
 
 impl BuildableSystem<PetSystemRoms, PetSystemConfig> for PetSystem {
@@ -163,12 +180,20 @@ impl BuildableSystem<PetSystemRoms, PetSystemConfig> for PetSystem {
       2.0,
     ));
 
-    let ram = BlockMemory::ram(0x8000);
-    let vram = BlockMemory::ram(VRAM_SIZE);
+    // Addresses which nothing is connected to
+    let bus = DataBus::new();
+    let unmapped = || NullMemory::unmapped(&bus, config.open_bus);
 
-    let expansion_rom_9 = NullMemory::new();
-    let expansion_rom_a = NullMemory::new();
-    let expansion_rom_b = NullMemory::new();
+    // RAM, at the given address
+    let ram_pattern = config.ram_pattern;
+    let ram = |size, address| BlockMemory::ram(size).with_pattern(ram_pattern, address);
+
+    let main_ram = ram(0x8000, 0x0000);
+    let vram = ram(VRAM_SIZE, 0x8000);
+
+    let expansion_rom_9 = unmapped();
+    let expansion_rom_a = unmapped();
+    let expansion_rom_b = unmapped();
 
     let basic_rom = BlockMemory::from_file(0x2000, roms.basic);
     let editor_rom = BlockMemory::from_file(0x1000, roms.editor);
@@ -182,19 +207,20 @@ impl BuildableSystem<PetSystemRoms, PetSystemConfig> for PetSystem {
     let kernel_rom = BlockMemory::from_file(0x1000, roms.kernal);
 
     let memory = BranchMemory::new()
-      .map(0x0000, ram)
+      .map(0x0000, main_ram)
       .map(0x8000, vram)
       .map(0x9000, expansion_rom_9)
       .map(0xA000, expansion_rom_a)
       .map(0xB000, expansion_rom_b)
       .map(0xC000, basic_rom)
       .map(0xE000, editor_rom)
-      .map(0xE810, pia1)
-      .map(0xE820, pia2)
-      .map(0xE840, via)
+      .map_mirrored(0xE810, Pia::REGISTERS, pia1)
+      .map_mirrored(0xE820, Pia::REGISTERS, pia2)
+      .map_mirrored(0xE840, Via::REGISTERS, via)
+      .map(0xE900, unmapped())
       .map(0xF000, kernel_rom);
 
-    let cpu = Mos6502::new(memory, Mos6502Variant::NMOS);
+    let cpu = Mos6502::new(DataBusMemory::new(memory, bus), Mos6502Variant::NMOS);
 
     Box::new(PetSystem {
       cpu,
@@ -209,13 +235,19 @@ pub struct PetSystem {
   characters: Vec<u8>,
 }
 
+impl PetSystem {
+  /// The contents of the PET's RAM when it is powered on: noise, here with a
+  /// fixed seed.
+  pub const RAM_PATTERN: RamPattern = RamPattern::Random(0);
+}
+
 impl System for PetSystem {
   fn get_cpu_mut(&mut self) -> Box<&mut dyn Cpu> {
     Box::new(&mut self.cpu)
   }
 
-  fn tick(&mut self) -> Duration {
-    Duration::from_secs_f64(1.0 / 1_000_000.0) * self.cpu.tick() as u32
+  fn tick(&mut self) -> Result<Duration, CpuError> {
+    Ok(Duration::from_secs_f64(1.0 / 1_000_000.0) * self.cpu.tick()? as u32)
   }
 
   fn reset(&mut self) {
@@ -226,7 +258,7 @@ impl System for PetSystem {
     for y in 0..HEIGHT {
       for x in 0..WIDTH {
         let index = (y * WIDTH + x) as u16;
-        let value = self.cpu.read(0x8000 + index);
+        let value = self.cpu.peek(0x8000 + index);
 
         let character_index = (value as usize) * 8;
 
@@ -256,3 +288,22 @@ impl System for PetSystem {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_interrupt_rate() {
+    // One second at 1 MHz, however often the port is polled. Each interrupt
+    // fires at the first poll after it is due, so coarse polling loses one.
+    for step in [1, 7, 100] {
+      let mut port = PetPia1PortA::new();
+      let fired = (step..=1_000_000)
+        .step_by(step as usize)
+        .filter(|&cycle| port.poll(step, cycle))
+        .count();
+      assert!((59..=60).contains(&fired), "{fired} interrupts");
+    }
+  }
+}
@@ -1,10 +1,13 @@
 use crate::memory::{ActiveInterrupt, Memory};
 use crate::platform::{Color, PlatformProvider, WindowConfig};
+use crate::state::{StateError, StateReader, StateWriter};
+use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::sync::Arc;
 
 /// One of the speakers available on the MOS 6560 VIC.
+#[derive(Serialize, Deserialize)]
 struct VicChipSpeaker {
   on: bool,
   note: u8,
@@ -31,6 +34,7 @@ impl VicChipSpeaker {
 }
 
 /// The light pen input available on the MOS 6560 VIC.
+#[derive(Serialize, Deserialize)]
 struct VicChipLightPen {
   x: u8,
   y: u8,
@@ -67,8 +71,8 @@ impl VicChipLightPen {
 /// Uses VRAM memory, character memory, and color memory to draw the screen.
 /// Also handles the speakers and light pen.
 /// Source: <http://tinyvga.com/6561>
+#[derive(Serialize, Deserialize)]
 pub struct VicChip {
-  platform: Arc<dyn PlatformProvider>,
   // Registers
 
   // TV scan settings
@@ -113,15 +117,11 @@ pub struct VicChip {
 }
 
 impl VicChip {
-  pub fn new(platform: Arc<dyn PlatformProvider>) -> Self {
+  pub fn new() -> Self {
     let width: u8 = 22;
     let height: u8 = 23;
 
-    platform.request_window(WindowConfig::new(width as u32 * 8, height as u32 * 8, 2.0));
-
     Self {
-      platform,
-
       scan_mode: false,
       left_draw_offset: 12,
       top_draw_offset: 38,
@@ -147,6 +147,12 @@ impl VicChip {
     }
   }
 
+  /// The window needed to display the screen with the current row and column
+  /// counts.
+  fn window_config(&self) -> WindowConfig {
+    WindowConfig::new(self.column_count as u32 * 8, self.row_count as u32 * 8, 2.0)
+  }
+
   pub fn reset(&mut self) {
     self.scan_mode = false;
     self.left_draw_offset = 12;
@@ -195,7 +201,7 @@ impl VicChip {
     let mut offset = (self.vram_address_top as u16) << 10;
     offset += (self.color_ram_mapping as u16) << 9;
 
-    memory.read(VicChip::vic_to_cpu_address(address + offset))
+    memory.peek(VicChip::vic_to_cpu_address(address + offset))
   }
 
   /// Read the value of the color memory at the given address,
@@ -207,7 +213,7 @@ impl VicChip {
       0x1400
     };
 
-    memory.read(VicChip::vic_to_cpu_address(address + offset))
+    memory.peek(VicChip::vic_to_cpu_address(address + offset))
   }
 
   /// Read the value of the character memory at the given address,
@@ -215,7 +221,7 @@ impl VicChip {
   fn read_character(&self, address: u16, memory: &mut Box<dyn Memory>) -> u8 {
     let offset = (self.character_address_top as u16) << 10;
 
-    memory.read(VicChip::vic_to_cpu_address(address + offset))
+    memory.peek(VicChip::vic_to_cpu_address(address + offset))
   }
 
   /// Get the bits in the character at the given value.
@@ -386,21 +392,33 @@ impl VicChip {
 }
 
 /// Represents the I/O mapping for the MOS 6560 VIC.
+/// Changes to the screen size are passed on to the platform.
 pub struct VicChipIO {
   chip: Rc<RefCell<VicChip>>,
+  platform: Arc<dyn PlatformProvider>,
 }
 
 impl VicChipIO {
-  pub fn new(chip: Rc<RefCell<VicChip>>) -> Self {
-    Self { chip }
+  /// The number of registers of the VIC. It only sees the register index, so
+  /// map it with [`BranchMemory::map_mirrored`](crate::memory::BranchMemory::map_mirrored).
+  pub const REGISTERS: u16 = 0x10;
+
+  pub fn new(chip: Rc<RefCell<VicChip>>, platform: Arc<dyn PlatformProvider>) -> Self {
+    platform.request_window(chip.borrow().window_config());
+
+    Self { chip, platform }
   }
 }
 
 impl Memory for VicChipIO {
   fn read(&mut self, address: u16) -> u8 {
+    self.peek(address)
+  }
+
+  fn peek(&self, address: u16) -> u8 {
     let chip = self.chip.borrow();
 
-    match address & 0x0F {
+    match address {
       0x0 => chip.left_draw_offset | (chip.scan_mode as u8) << 7,
       0x1 => chip.top_draw_offset,
       0x2 => chip.column_count | (chip.color_ram_mapping as u8) << 7,
@@ -427,7 +445,7 @@ impl Memory for VicChipIO {
 
   fn write(&mut self, address: u16, value: u8) {
     let mut chip = self.chip.borrow_mut();
-    match address & 0x0F {
+    match address {
       0x0 => {
         chip.scan_mode = (value & 0x80) != 0;
         chip.left_draw_offset = value & 0x7F;
@@ -435,7 +453,7 @@ impl Memory for VicChipIO {
       0x1 => chip.top_draw_offset = value,
       0x2 => {
         if value & 0x7F != chip.column_count {
-          chip.platform.request_window(WindowConfig::new(
+          self.platform.request_window(WindowConfig::new(
             (value & 0x7F) as u32 * 8,
             chip.row_count as u32 * 8,
             2.0,
@@ -449,7 +467,7 @@ impl Memory for VicChipIO {
         chip.raster_counter = (chip.raster_counter & 0x1FE) | ((value & 0x80) as u16) >> 7;
 
         if ((value >> 1) & 0x3F) != chip.row_count {
-          chip.platform.request_window(WindowConfig::new(
+          self.platform.request_window(WindowConfig::new(
             chip.column_count as u32 * 8,
             ((value >> 1) & 0x3F) as u32 * 8,
             2.0,
@@ -496,6 +514,18 @@ impl Memory for VicChipIO {
   fn poll(&mut self, _cycles_since_poll: u64, _total_cycle_count: u64) -> ActiveInterrupt {
     ActiveInterrupt::None
   }
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    state.write(&*self.chip.borrow())
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    let chip: VicChip = state.read()?;
+    self.platform.request_window(chip.window_config());
+    *self.chip.borrow_mut() = chip;
+
+    Ok(())
+  }
 }
 
 #[cfg(test)]
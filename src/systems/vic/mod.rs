@@ -1,6 +1,6 @@
 use crate::cpu::{
   mos6502::{Mos6502, Mos6502Variant},
-  Cpu,
+  Cpu, CpuError,
 };
 use crate::keyboard::commodore::C64VirtualAdapter;
 use crate::keyboard::{
@@ -8,9 +8,12 @@ use crate::keyboard::{
   KeyAdapter, KeyMappingStrategy, SymbolAdapter,
 };
 use crate::memory::mos652x::Via;
-use crate::memory::{BlockMemory, BranchMemory, NullMemory, NullPort, Port};
+use crate::memory::{
+  BlockMemory, BranchMemory, DataBus, DataBusMemory, NullMemory, NullPort, Port, RamPattern,
+};
 use crate::platform::{PlatformProvider, WindowConfig};
 use crate::roms::RomFile;
+use crate::state::{StateError, StateReader, StateWriter};
 use crate::systems::System;
 use std::cell::{Cell, RefCell};
 use std::rc::Rc;
@@ -138,6 +141,19 @@ impl Port for VicVia1PortA {
     (pin_0 as u8) << 2 | (pin_1 as u8) << 3 | (pin_2 as u8) << 4 | (lightpen_fire as u8) << 5
   }
 
+  /// The same as a read, but without latching the joystick's pin 3 for the
+  /// other VIA to read.
+  fn peek(&self) -> u8 {
+    let joystick = self.platform.get_joystick_state();
+
+    let pin_0 = !joystick.up;
+    let pin_1 = !joystick.down;
+    let pin_2 = !joystick.left;
+    let lightpen_fire = !joystick.fire;
+
+    (pin_0 as u8) << 2 | (pin_1 as u8) << 3 | (pin_2 as u8) << 4 | (lightpen_fire as u8) << 5
+  }
+
   fn write(&mut self, _value: u8) {}
 
   fn poll(&mut self, _cycles_since_poll: u64, _total_cycle_count: u64) -> bool {
@@ -171,6 +187,10 @@ impl VicVia2PortB {
 
 impl Port for VicVia2PortB {
   fn read(&mut self) -> u8 {
+    self.peek()
+  }
+
+  fn peek(&self) -> u8 {
     self.keyboard_col.get() | (self.joy_pin_3.get() as u8) << 7
   }
 
@@ -183,6 +203,15 @@ impl Port for VicVia2PortB {
   }
 
   fn reset(&mut self) {}
+
+  fn save_state(&self, state: &mut StateWriter) -> Result<(), StateError> {
+    state.write(&self.keyboard_col.get())
+  }
+
+  fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+    self.keyboard_col.set(state.read()?);
+    Ok(())
+  }
 }
 
 /// Port A on the second VIA chip.
@@ -211,6 +240,10 @@ impl VicVia2PortA {
 
 impl Port for VicVia2PortA {
   fn read(&mut self) -> u8 {
+    self.peek()
+  }
+
+  fn peek(&self) -> u8 {
     let col_mask = self.keyboard_col.get();
 
     let mut value = 0b1111_1111;
@@ -244,9 +277,70 @@ impl Port for VicVia2PortA {
   fn reset(&mut self) {}
 }
 
-/// Configuration for a VIC-20 system.
+/// RAM cartridges which can be plugged into the VIC-20's expansion port. Each
+/// is named by how much RAM it adds to the 5K built in.
+///
+/// With 8K or more of expansion RAM, the KERNAL moves the screen from $1E00 to
+/// $1000 and the color RAM from $9600 to $9400, so that BASIC programs can use
+/// contiguous memory from $1200 up. The VIC chip follows the registers the
+/// KERNAL sets, so nothing else needs to change.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Vic20RamExpansion {
+  /// The unexpanded machine.
+  #[default]
+  None,
+
+  /// 3K at $0400-$0FFF.
+  Ram3K,
+
+  /// 8K at $2000-$3FFF (block 1).
+  Ram8K,
+
+  /// 16K at $2000-$5FFF (blocks 1 and 2).
+  Ram16K,
+
+  /// 24K at $2000-$7FFF (blocks 1 to 3).
+  Ram24K,
+
+  /// Every block: 3K at $0400-$0FFF, 24K at $2000-$7FFF, and 8K at
+  /// $A000-$BFFF (block 5) unless a cartridge is inserted there. BASIC does
+  /// not use block 5, but machine code programs can.
+  Ram35K,
+}
+
+impl Vic20RamExpansion {
+  /// Return true if RAM is added at $0400-$0FFF.
+  fn has_3k(&self) -> bool {
+    matches!(self, Vic20RamExpansion::Ram3K | Vic20RamExpansion::Ram35K)
+  }
+
+  /// Return how many 8K blocks of RAM are added from $2000 up.
+  fn blocks(&self) -> usize {
+    match self {
+      Vic20RamExpansion::None | Vic20RamExpansion::Ram3K => 0,
+      Vic20RamExpansion::Ram8K => 1,
+      Vic20RamExpansion::Ram16K => 2,
+      Vic20RamExpansion::Ram24K | Vic20RamExpansion::Ram35K => 3,
+    }
+  }
+}
+
+/// Configuration for a VIC-20 system. The default is unexpanded, maps
+/// physical keys, reads zero from unmapped addresses and clears RAM at
+/// power-on.
+#[derive(Default)]
 pub struct Vic20SystemConfig {
   pub mapping: KeyMappingStrategy,
+
+  /// The RAM cartridge plugged into the expansion port, if any.
+  pub ram_expansion: Vic20RamExpansion,
+
+  /// Whether unmapped addresses read back open bus; see [`NullMemory::unmapped`].
+  pub open_bus: bool,
+
+  /// The contents of RAM when the system is powered on or reset. The pattern
+  /// of the real machine is [`Vic20System::RAM_PATTERN`].
+  pub ram_pattern: RamPattern,
 }
 
 impl BuildableSystem<Vic20SystemRoms, Vic20SystemConfig> for Vic20System {
@@ -255,14 +349,22 @@ impl BuildableSystem<Vic20SystemRoms, Vic20SystemConfig> for Vic20System {
     config: Vic20SystemConfig,
     platform: Arc<dyn PlatformProvider>,
   ) -> Box<dyn System> {
-    let low_ram = BlockMemory::ram(0x0400);
-    let main_ram = BlockMemory::ram(0x0E00);
+    // Addresses which nothing is connected to
+    let bus = DataBus::new();
+    let unmapped = || NullMemory::unmapped(&bus, config.open_bus);
+
+    // RAM, at the given address
+    let ram_pattern = config.ram_pattern;
+    let ram = |size, address| BlockMemory::ram(size).with_pattern(ram_pattern, address);
 
-    let vic_chip = Rc::new(RefCell::new(VicChip::new(platform.clone())));
+    let low_ram = ram(0x0400, 0x0000);
+    let main_ram = ram(0x0E00, 0x1000);
+
+    let vic_chip = Rc::new(RefCell::new(VicChip::new()));
 
     let v1a = VicVia1PortA::new(platform.clone());
     let v2b = VicVia2PortB::new(v1a.get_joy_pin_3());
-    let v2a = VicVia2PortA::new(v2b.get_keyboard_col(), config.mapping, platform);
+    let v2a = VicVia2PortA::new(v2b.get_keyboard_col(), config.mapping, platform.clone());
 
     let via1 = Via::new(Box::new(v1a), Box::new(NullPort::new()));
     let via2 = Via::new(Box::new(v2a), Box::new(v2b));
@@ -270,35 +372,54 @@ impl BuildableSystem<Vic20SystemRoms, Vic20SystemConfig> for Vic20System {
     let basic_rom = BlockMemory::from_file(0x2000, roms.basic);
     let kernel_rom = BlockMemory::from_file(0x2000, roms.kernal);
 
-    let vram = BlockMemory::ram(0x0200);
+    let vram = ram(0x0200, 0x1E00);
     let characters = BlockMemory::from_file(0x1000, roms.character);
-    let colors = BlockMemory::ram(0x0200);
-    let chip_io = VicChipIO::new(vic_chip.clone());
 
-    let cartridge = match roms.cartridge {
-      Some(rom) => BlockMemory::from_file(0x4000, rom),
-      None => BlockMemory::ram(0x4000),
+    // The screen's colors are at $9600 or, with 8K or more of expansion RAM,
+    // at $9400
+    let colors = ram(0x0400, 0x9400);
+    let chip_io = VicChipIO::new(vic_chip.clone(), platform);
+
+    let mut memory = BranchMemory::new().map(0x0000, low_ram);
+
+    memory = if config.ram_expansion.has_3k() {
+      memory.map(0x0400, ram(0x0C00, 0x0400))
+    } else {
+      memory.map(0x0400, unmapped())
     };
 
-    let memory = BranchMemory::new()
-      .map(0x0000, low_ram)
-      .map(0x0400, NullMemory::new())
-      .map(0x1000, main_ram)
-      .map(0x1E00, vram)
-      .map(0x2000, NullMemory::new())
-      // .map(0x2000, Box::new(expansion_ram))
+    memory = memory.map(0x1000, main_ram).map(0x1E00, vram);
+
+    // Blocks 1 to 3, from $2000 up
+    for block in 1..=3 {
+      let address = block * 0x2000;
+      memory = if block <= config.ram_expansion.blocks() {
+        memory.map(address, ram(0x2000, address as u16))
+      } else {
+        memory.map(address, unmapped())
+      };
+    }
+
+    let memory = memory
       .map(0x8000, characters)
-      .map(0x9000, chip_io)
-      .map(0x9010, NullMemory::new())
-      .map(0x9110, via1)
-      .map(0x9120, via2)
-      .map(0x9130, NullMemory::new())
-      .map(0x9600, colors)
-      .map(0xA000, cartridge)
-      .map(0xC000, basic_rom)
-      .map(0xE000, kernel_rom);
-
-    let cpu = Mos6502::new(memory, Mos6502Variant::NMOS);
+      .map_mirrored(0x9000, VicChipIO::REGISTERS, chip_io)
+      .map(0x9010, unmapped())
+      .map_mirrored(0x9110, Via::REGISTERS, via1)
+      .map_mirrored(0x9120, Via::REGISTERS, via2)
+      .map(0x9130, unmapped())
+      .map(0x9400, colors);
+
+    let memory = match roms.cartridge {
+      Some(rom) => memory.map(0xA000, BlockMemory::from_file(0x4000, rom)),
+      None if config.ram_expansion == Vic20RamExpansion::Ram35K => {
+        memory.map(0xA000, ram(0x2000, 0xA000))
+      }
+      None => memory.map(0xA000, unmapped()),
+    };
+
+    let memory = memory.map(0xC000, basic_rom).map(0xE000, kernel_rom);
+
+    let cpu = Mos6502::new(DataBusMemory::new(memory, bus), Mos6502Variant::NMOS);
 
     Box::new(Vic20System { cpu, vic: vic_chip })
   }
@@ -310,13 +431,19 @@ pub struct Vic20System {
   vic: Rc<RefCell<VicChip>>,
 }
 
+impl Vic20System {
+  /// The contents of the VIC-20's RAM when it is powered on: noise, here with
+  /// a fixed seed.
+  pub const RAM_PATTERN: RamPattern = RamPattern::Random(0);
+}
+
 impl System for Vic20System {
   fn get_cpu_mut(&mut self) -> Box<&mut dyn Cpu> {
     Box::new(&mut self.cpu)
   }
 
-  fn tick(&mut self) -> instant::Duration {
-    Duration::from_secs_f64(1.0 / 1_000_000.0) * self.cpu.tick() as u32
+  fn tick(&mut self) -> Result<Duration, CpuError> {
+    Ok(Duration::from_secs_f64(1.0 / 1_000_000.0) * self.cpu.tick()? as u32)
   }
 
   fn reset(&mut self) {
@@ -0,0 +1,209 @@
+use instant::Duration;
+
+use crate::cpu::{z80::Z80, Cpu, CpuError};
+use crate::memory::{BlockMemory, NullMemory};
+use crate::platform::{PlatformProvider, WindowConfig};
+use crate::roms::RomFile;
+use crate::systems::System;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use super::BuildableSystem;
+
+/// The address at which CP/M loads and starts a `.COM` program.
+const PROGRAM_START: u16 = 0x0100;
+
+/// The address of the CP/M BDOS entry point, which programs `CALL` to request
+/// system services. It is intercepted here rather than implemented in Z80 code.
+const BDOS_ENTRY: u16 = 0x0005;
+
+/// The address which programs jump to when they exit (the CP/M warm boot).
+const WARM_BOOT: u16 = 0x0000;
+
+/// The top of the transient program area, read by programs to set up their
+/// stack.
+const BDOS_BASE: u16 = 0xFE00;
+
+pub struct ZexSystemConfig {
+  /// If set, the text printed by the program is also collected here.
+  pub output: Option<Rc<RefCell<String>>>,
+}
+
+/// A factory for creating a system that runs Frank Cringle's zexdoc and zexall
+/// Z80 instruction exercisers (or any other CP/M program which only prints to
+/// the console).
+impl BuildableSystem<RomFile, ZexSystemConfig> for ZexSystem {
+  fn build(
+    rom: RomFile,
+    config: ZexSystemConfig,
+    platform: Arc<dyn PlatformProvider>,
+  ) -> Box<dyn System> {
+    // Page zero holds a HALT at the warm boot address and a jump to the BDOS,
+    // whose target programs use to find the top of memory
+    let mut data = vec![0; PROGRAM_START as usize];
+    data[WARM_BOOT as usize] = 0x76;
+    data[BDOS_ENTRY as usize..BDOS_ENTRY as usize + 3].copy_from_slice(&[
+      0xC3,
+      BDOS_BASE as u8,
+      (BDOS_BASE >> 8) as u8,
+    ]);
+    data.extend(rom.get_data());
+
+    let memory = BlockMemory::from_file(0x10000, RomFile::new(data)).set_writeable(true);
+    let mut cpu = Z80::new(memory, NullMemory::new());
+    cpu.registers.pc = PROGRAM_START;
+
+    Box::new(ZexSystem {
+      cpu,
+      platform,
+      output: config.output,
+    })
+  }
+}
+
+/// A system which runs CP/M console programs, used for the zexdoc and zexall
+/// Z80 instruction exercisers.
+pub struct ZexSystem {
+  cpu: Z80,
+  platform: Arc<dyn PlatformProvider>,
+  output: Option<Rc<RefCell<String>>>,
+}
+
+impl ZexSystem {
+  /// Print a character to the console.
+  fn print(&mut self, character: u8) {
+    let text = (character as char).to_string();
+    self.platform.print(&text);
+
+    if let Some(output) = &self.output {
+      output.borrow_mut().push_str(&text);
+    }
+  }
+
+  /// Handle a BDOS call, as selected by the C register, then return to the
+  /// caller. Only the console output functions are supported.
+  fn bdos(&mut self) {
+    match self.cpu.registers.c {
+      0x02 => self.print(self.cpu.registers.e),
+      0x09 => {
+        let mut address = self.cpu.registers.de();
+        loop {
+          let character = self.cpu.memory.read(address);
+          if character == b'$' {
+            break;
+          }
+          self.print(character);
+          address = address.wrapping_add(1);
+        }
+      }
+      function => self
+        .platform
+        .print(&format!("unsupported BDOS function {function}\n")),
+    }
+
+    let sp = self.cpu.registers.sp;
+    let lo = self.cpu.memory.read(sp);
+    let hi = self.cpu.memory.read(sp.wrapping_add(1));
+    self.cpu.registers.sp = sp.wrapping_add(2);
+    self.cpu.registers.pc = (hi as u16) << 8 | lo as u16;
+  }
+
+  /// Whether the program has exited to CP/M.
+  pub fn is_finished(&self) -> bool {
+    self.cpu.is_halted() && self.cpu.registers.pc == WARM_BOOT + 1
+  }
+}
+
+impl System for ZexSystem {
+  fn get_cpu_mut(&mut self) -> Box<&mut dyn Cpu> {
+    Box::new(&mut self.cpu)
+  }
+
+  fn tick(&mut self) -> Result<Duration, CpuError> {
+    if self.cpu.registers.pc == BDOS_ENTRY {
+      self.bdos();
+    }
+
+    self.cpu.tick()?;
+    Ok(Duration::ZERO)
+  }
+
+  fn reset(&mut self) {
+    self.cpu.reset();
+    self.cpu.registers.pc = PROGRAM_START;
+  }
+
+  fn render(&mut self, _framebuffer: &mut [u8], _window: WindowConfig) {}
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    platform::{Platform, TextPlatform},
+    roms::DiskLoadable,
+  };
+
+  use super::*;
+
+  /// Run the given program until it prints the given text, or until the
+  /// given number of instructions have executed, returning its output.
+  fn run(rom: RomFile, until: &str, ticks: usize) -> String {
+    let platform = TextPlatform::new();
+    let output = Rc::new(RefCell::new(String::new()));
+
+    let mut system = ZexSystem::build(
+      rom,
+      ZexSystemConfig {
+        output: Some(output.clone()),
+      },
+      platform.provider(),
+    );
+
+    for tick in 0..ticks {
+      system.tick().unwrap();
+
+      if tick.is_multiple_of(1_000_000) && output.borrow().contains(until) {
+        break;
+      }
+    }
+
+    let output = output.borrow().clone();
+    output
+  }
+
+  #[test]
+  fn test_bdos_output() {
+    let program = RomFile::new(vec![
+      0x0E, 0x09, // LD C,9
+      0x11, 0x0B, 0x01, // LD DE,message
+      0xCD, 0x05, 0x00, // CALL BDOS
+      0xC3, 0x00, 0x00, // JP 0
+      b'h', b'i', b'$', // message
+    ]);
+
+    assert_eq!("hi", run(program, "hi", 10));
+  }
+
+  // Frank Cringle's instruction exercisers are not bundled with the other test
+  // programs in bin/, and their results against this core have not been
+  // recorded yet. Copy zexdoc.com and zexall.com (from the z80emu or yaze
+  // distributions) into bin/ and run these with `cargo test -- --ignored`.
+  fn run_exerciser(path: &str) {
+    let output = run(RomFile::from_file(path), "Tests complete", 20_000_000_000);
+    assert!(output.contains("Tests complete"), "{output}");
+    assert!(!output.contains("ERROR"), "{output}");
+  }
+
+  #[test]
+  #[ignore = "bin/zexdoc.com is not bundled and has not been run against this core"]
+  fn test_zexdoc() {
+    run_exerciser("bin/zexdoc.com");
+  }
+
+  #[test]
+  #[ignore = "bin/zexall.com is not bundled and has not been run against this core"]
+  fn test_zexall() {
+    run_exerciser("bin/zexall.com");
+  }
+}
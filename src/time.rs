@@ -1,20 +1,21 @@
 use std::thread;
 
+use crate::cpu::CpuError;
 use instant::{Duration, Instant};
 
 const TIMEOUT_CHECK_INTERVAL: u32 = 8;
 
 pub fn tick_until_target(
-  tick: &mut dyn FnMut() -> Duration,
+  tick: &mut dyn FnMut() -> Result<Duration, CpuError>,
   target: Duration,
   timeout: Duration,
-) -> Duration {
+) -> Result<Duration, CpuError> {
   let realtime_now = Instant::now();
   let mut elapsed = Duration::ZERO;
 
   let mut ticks = 0;
   while elapsed < target {
-    elapsed += tick();
+    elapsed += tick()?;
     ticks += 1;
 
     if ticks % TIMEOUT_CHECK_INTERVAL == 0 && realtime_now.elapsed() > timeout {
@@ -26,7 +27,7 @@ pub fn tick_until_target(
       break;
     }
   }
-  elapsed
+  Ok(elapsed)
 }
 
 pub struct VariableTimeStep {
@@ -49,8 +50,12 @@ impl VariableTimeStep {
     elapsed
   }
 
-  pub fn do_update(&mut self, tick: &mut dyn FnMut() -> Duration) {
-    tick_until_target(tick, self.next_update_interval(), self.timeout);
+  pub fn do_update(
+    &mut self,
+    tick: &mut dyn FnMut() -> Result<Duration, CpuError>,
+  ) -> Result<(), CpuError> {
+    tick_until_target(tick, self.next_update_interval(), self.timeout)?;
+    Ok(())
   }
 }
 
@@ -67,13 +72,18 @@ impl FixedTimeStep {
     }
   }
 
-  pub fn do_update(&mut self, tick: &mut dyn FnMut() -> Duration) {
+  pub fn do_update(
+    &mut self,
+    tick: &mut dyn FnMut() -> Result<Duration, CpuError>,
+  ) -> Result<(), CpuError> {
     let now = Instant::now();
-    tick_until_target(tick, self.target_interval, self.timeout);
+    tick_until_target(tick, self.target_interval, self.timeout)?;
     let elapsed = now.elapsed();
 
     if elapsed < self.target_interval {
       thread::sleep(self.target_interval - elapsed);
     }
+
+    Ok(())
   }
 }
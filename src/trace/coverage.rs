@@ -0,0 +1,599 @@
+use crate::cpu::mos6502::disasm::{AddressingMode, Instruction};
+use crate::cpu::mos6502::Mos6502Variant;
+use crate::symbols::ca65;
+use crate::trace::{CpuTrace, TraceHandler};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// A run of bytes assembled from a single line of source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SourceRange {
+  start: u16,
+  length: u16,
+  file: usize,
+  line: u32,
+
+  /// Whether the line is an instruction, rather than data.
+  code: bool,
+
+  /// The first byte assembled from the line, if the listing shows it.
+  opcode: Option<u8>,
+}
+
+/// A mapping from addresses back to the lines of assembly source they were
+/// assembled from, read from an assembler listing or debug info file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+  files: Vec<String>,
+  ranges: Vec<SourceRange>,
+}
+
+/// Return the mnemonics of every 6502 and 65C02 instruction, in upper case.
+fn mnemonics() -> HashSet<&'static str> {
+  (0..=255u8)
+    .flat_map(|opcode| {
+      [Mos6502Variant::NMOS, Mos6502Variant::CMOS]
+        .map(|variant| Instruction::decode(opcode, variant).mnemonic)
+    })
+    .collect()
+}
+
+/// Guess whether a line of assembly source is an instruction (as opposed to a
+/// directive, data, or a lone label), from the first word after any labels.
+fn is_instruction(source: &str, mnemonics: &HashSet<&str>) -> bool {
+  let source = source.split(';').next().unwrap();
+  let is_mnemonic = |word: &str| mnemonics.contains(word.to_ascii_uppercase().as_str());
+  let mut words = source
+    .split_whitespace()
+    .skip_while(|word| word.ends_with(':'));
+
+  match words.next() {
+    Some(word) if is_mnemonic(word) => true,
+    // Labels without a colon start in the first column
+    Some(_) if !source.starts_with(char::is_whitespace) => words.next().is_some_and(is_mnemonic),
+    _ => false,
+  }
+}
+
+impl SourceMap {
+  /// Read a listing produced by vasm's `-L` option. Each line of the listing
+  /// shows the address and bytes assembled from a line of source.
+  pub fn parse_vasm_listing(text: &str) -> Result<Self, String> {
+    let mnemonics = mnemonics();
+    let mut map = SourceMap::default();
+    let mut file = None;
+
+    for line in text.lines() {
+      if line.starts_with("Symbols by") {
+        break;
+      }
+
+      if let Some(name) = line.strip_prefix("Source: ") {
+        map.files.push(name.trim_matches('"').to_owned());
+        file = Some(map.files.len() - 1);
+        continue;
+      }
+
+      let (left, right) = line.split_once('\t').unwrap_or((line, ""));
+
+      // The address is shown as the section number and offset, e.g. "00:8000"
+      let mut columns = left.split_whitespace();
+      let location = columns.next().and_then(|location| {
+        let (section, address) = location.split_once(':')?;
+        u8::from_str_radix(section, 16).ok()?;
+        u16::from_str_radix(address, 16).ok()
+      });
+      let bytes: Vec<u8> = columns
+        .next()
+        .map(|hex| {
+          (0..hex.len() / 2)
+            .filter_map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok())
+            .collect()
+        })
+        .unwrap_or_default();
+
+      let source = right
+        .trim_start()
+        .split_once(": ")
+        .and_then(|(number, source)| {
+          let number = number.parse::<u32>().ok()?;
+          Some((number, source))
+        });
+
+      match (location, source, file) {
+        (Some(start), Some((number, source)), Some(file)) if !bytes.is_empty() => {
+          map.ranges.push(SourceRange {
+            start,
+            length: bytes.len() as u16,
+            file,
+            line: number,
+            code: is_instruction(source, &mnemonics),
+            opcode: Some(bytes[0]),
+          });
+        }
+        // Bytes which didn't fit on the previous line
+        (Some(start), None, _) => {
+          if let Some(range) = map.ranges.last_mut() {
+            if range.start.wrapping_add(range.length) == start {
+              range.length += bytes.len() as u16;
+            }
+          }
+        }
+        _ => {}
+      }
+    }
+
+    if map.files.is_empty() {
+      return Err("not a vasm listing".to_owned());
+    }
+
+    Ok(map)
+  }
+
+  /// Read a debug info file produced by ld65's `--dbgfile` option (for code
+  /// assembled with ca65 `-g`). Source files named in it are read, relative to
+  /// the given directory if they can't be found as-is, to tell code from data.
+  pub fn parse_ca65_dbg(text: &str, directory: Option<&Path>) -> Result<Self, String> {
+    let mut files = BTreeMap::new();
+    let mut segments = HashMap::new();
+    let mut spans = HashMap::new();
+    let mut lines = Vec::new();
+
+    for line in text.lines() {
+      let (kind, fields) = match ca65::record(line) {
+        Some(record) => record,
+        None => continue,
+      };
+
+      match kind {
+        "file" => {
+          let name = fields.get("name").ok_or("file without a name")?;
+          files.insert(ca65::number(fields.get("id"))?, name.to_string());
+        }
+        "seg" => {
+          segments.insert(
+            ca65::number(fields.get("id"))?,
+            ca65::number(fields.get("start"))?,
+          );
+        }
+        "span" => {
+          spans.insert(
+            ca65::number(fields.get("id"))?,
+            (
+              ca65::number(fields.get("seg"))?,
+              ca65::number(fields.get("start"))?,
+              ca65::number(fields.get("size"))?,
+            ),
+          );
+        }
+        // Lines of macro definitions (type 2) are skipped, since the line
+        // invoking the macro covers the same bytes
+        "line" if fields.get("type").is_none_or(|kind| *kind == "0") => {
+          if let Some(span) = fields.get("span") {
+            let file = ca65::number(fields.get("file"))?;
+            let number = ca65::number(fields.get("line"))?;
+
+            for span in span.split('+') {
+              lines.push((file, number, ca65::number(Some(&span))?));
+            }
+          }
+        }
+        _ => {}
+      }
+    }
+
+    if files.is_empty() {
+      return Err("not a ca65 debug info file".to_owned());
+    }
+
+    let mnemonics = mnemonics();
+    let mut map = SourceMap::default();
+    let mut indices = HashMap::new();
+    let mut sources = Vec::new();
+
+    for (id, name) in files {
+      let text = std::fs::read_to_string(&name)
+        .or_else(|error| match directory {
+          Some(directory) => std::fs::read_to_string(directory.join(&name)),
+          None => Err(error),
+        })
+        .ok();
+
+      indices.insert(id, map.files.len());
+      map.files.push(name);
+      sources.push(text);
+    }
+
+    for (file, number, span) in lines {
+      let (segment, offset, size) = *spans.get(&span).ok_or("line refers to an unknown span")?;
+      let base = *segments
+        .get(&segment)
+        .ok_or("span refers to an unknown segment")?;
+      let file = *indices.get(&file).ok_or("line refers to an unknown file")?;
+
+      let code = match &sources[file] {
+        Some(text) => text
+          .lines()
+          .nth(number.saturating_sub(1) as usize)
+          .is_some_and(|source| is_instruction(source, &mnemonics)),
+        // Without the source, assume every line is code
+        None => true,
+      };
+
+      if size > 0 {
+        map.ranges.push(SourceRange {
+          start: (base + offset) as u16,
+          length: size as u16,
+          file,
+          line: number,
+          code,
+          opcode: None,
+        });
+      }
+    }
+
+    Ok(map)
+  }
+
+  /// Load a vasm listing or ca65 debug info file, detecting which it is from
+  /// its contents.
+  pub fn load(path: &str) -> Result<Self, String> {
+    let text = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+
+    if text.starts_with("version\t") {
+      Self::parse_ca65_dbg(&text, Path::new(path).parent())
+    } else {
+      Self::parse_vasm_listing(&text)
+    }
+  }
+}
+
+/// The number of times a line executed, and the number of times each branch on
+/// it was taken and not taken (or `None` if it never executed).
+type LineCoverage = (u64, Vec<Option<(u64, u64)>>);
+
+/// A trace handler which records which instructions were executed, and which
+/// way each branch went, so that the parts of a program that a run did not
+/// reach can be found. When the system is cleaned up, the results are written
+/// as an lcov tracefile, mapped back to the assembly source lines with a
+/// [`SourceMap`]. Lines of data are left out, since they are never executed.
+pub struct CoverageTraceHandler {
+  sources: SourceMap,
+  output: Option<String>,
+
+  /// The number of times an instruction starting at each address executed.
+  hits: Vec<u64>,
+
+  /// The number of times each branch was taken and not taken.
+  branches: HashMap<u16, (u64, u64)>,
+
+  /// The address of the previous instruction, and where it goes if it is a
+  /// branch which is taken or not taken.
+  previous: Option<(u16, u16, u16)>,
+
+  variant: Mos6502Variant,
+}
+
+impl CoverageTraceHandler {
+  /// Create a coverage collector which writes an lcov tracefile to the given
+  /// path when flushed.
+  pub fn new(sources: SourceMap, output: String) -> Self {
+    Self {
+      output: Some(output),
+      ..Self::in_memory(sources)
+    }
+  }
+
+  /// Create a coverage collector which does not write any files. Its results
+  /// can be read with [`CoverageTraceHandler::lcov`].
+  pub fn in_memory(sources: SourceMap) -> Self {
+    Self {
+      sources,
+      output: None,
+      hits: vec![0; 0x10000],
+      branches: HashMap::new(),
+      previous: None,
+      variant: Mos6502Variant::NMOS,
+    }
+  }
+
+  /// Return the number of times the instruction at the given address executed.
+  pub fn hits(&self, address: u16) -> u64 {
+    self.hits[address as usize]
+  }
+
+  /// Return the number of times the branch at the given address was taken and
+  /// not taken, if it executed.
+  pub fn branch(&self, address: u16) -> Option<(u64, u64)> {
+    self.branches.get(&address).copied()
+  }
+
+  /// Return the coverage in the lcov tracefile format.
+  pub fn lcov(&self) -> String {
+    let mut lcov = String::new();
+
+    for (index, file) in self.sources.files.iter().enumerate() {
+      let mut lines: BTreeMap<u32, LineCoverage> = BTreeMap::new();
+
+      for range in &self.sources.ranges {
+        if range.file != index || !range.code {
+          continue;
+        }
+
+        let (count, branches) = lines.entry(range.line).or_default();
+        let addresses = (0..range.length).map(|offset| range.start.wrapping_add(offset));
+
+        for address in addresses {
+          *count = (*count).max(self.hits(address));
+
+          if let Some(branch) = self.branch(address) {
+            branches.push(Some(branch));
+          }
+        }
+
+        // Branches which never executed are only known from the listing
+        let is_branch = range.opcode.is_some_and(|opcode| self.is_branch(opcode));
+        if is_branch && self.branch(range.start).is_none() {
+          branches.push(None);
+        }
+      }
+
+      writeln!(lcov, "TN:\nSF:{file}").unwrap();
+
+      let (mut found, mut hit) = (0, 0);
+      for (number, (_, branches)) in &lines {
+        for (block, branch) in branches.iter().enumerate() {
+          let counts = match branch {
+            Some((taken, not_taken)) => [taken.to_string(), not_taken.to_string()],
+            None => ["-".to_owned(), "-".to_owned()],
+          };
+
+          for (index, count) in counts.iter().enumerate() {
+            writeln!(lcov, "BRDA:{number},{block},{index},{count}").unwrap();
+            found += 1;
+            hit += (count != "-" && count != "0") as u32;
+          }
+        }
+      }
+      writeln!(lcov, "BRF:{found}\nBRH:{hit}").unwrap();
+
+      for (number, (count, _)) in &lines {
+        writeln!(lcov, "DA:{number},{count}").unwrap();
+      }
+
+      let hit = lines.values().filter(|(count, _)| *count > 0).count();
+      writeln!(lcov, "LF:{}\nLH:{hit}\nend_of_record", lines.len()).unwrap();
+    }
+
+    lcov
+  }
+
+  /// Return true if the given opcode is a conditional branch.
+  fn is_branch(&self, opcode: u8) -> bool {
+    let instruction = Instruction::decode(opcode, self.variant);
+
+    match instruction.mode {
+      AddressingMode::Relative => instruction.mnemonic != "BRA",
+      AddressingMode::ZeroPageRelative => true,
+      _ => false,
+    }
+  }
+}
+
+impl TraceHandler for CoverageTraceHandler {
+  fn handle(&mut self, trace: &CpuTrace) {
+    if let Some((address, taken, not_taken)) = self.previous.take() {
+      let counts = self.branches.entry(address).or_default();
+
+      // A branch to the next instruction covers both arms at once. Anything
+      // else means an interrupt occurred before the next instruction.
+      if trace.address == taken {
+        counts.0 += 1;
+      }
+      if trace.address == not_taken {
+        counts.1 += 1;
+      }
+    }
+
+    self.variant = trace.variant;
+    self.hits[trace.address as usize] += 1;
+
+    if self.is_branch(trace.opcode) {
+      let offset = *trace.operand.last().unwrap() as i8;
+      let not_taken = trace.address.wrapping_add(1 + trace.operand.len() as u16);
+      let taken = not_taken.wrapping_add(offset as u16);
+      self.previous = Some((trace.address, taken, not_taken));
+    }
+  }
+
+  fn flush(&mut self) -> Result<(), &str> {
+    match &self.output {
+      Some(path) => std::fs::write(path, self.lcov()).map_err(|_| "failed to write coverage"),
+      None => Ok(()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cpu::{
+    mos6502::{MemoryIO, Mos6502},
+    Cpu,
+  };
+  use crate::memory::BlockMemory;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  // count.s, assembled with `vasm6502_oldstyle -Fbin -dotdir -L count.lst`
+  const LISTING: &str = "Sections:
+00: \"org0001:200\" (200-20E)
+
+
+Source: \"count.s\"
+                        \t     1: ; count down from 3
+                        \t     2:   .org $0200
+00:0200 A203            \t     3: start: ldx #3
+00:0202 CA              \t     4: loop: dex
+00:0203 D0FD            \t     5:   bne loop
+00:0205 E000            \t     6:   cpx #0
+00:0207 F002            \t     7:   beq done
+00:0209 A9FF            \t     8:   lda #$ff
+00:020B 4C0B02          \t     9: done: jmp done
+00:020E 48454C4C4F2C2057\t    10: message: .byte \"HELLO, WORLD\"
+00:0216 4F524C44
+                        \t    11:
+
+
+Symbols by name:
+done                             A:020B
+loop                             A:0202
+";
+
+  /// Forwards traces to a shared collector, so it can be inspected afterwards.
+  struct Shared(Rc<RefCell<CoverageTraceHandler>>);
+
+  impl TraceHandler for Shared {
+    fn handle(&mut self, trace: &CpuTrace) {
+      self.0.borrow_mut().handle(trace);
+    }
+  }
+
+  #[test]
+  fn test_vasm_listing() {
+    let map = SourceMap::parse_vasm_listing(LISTING).unwrap();
+    assert_eq!(vec!["count.s".to_owned()], map.files);
+    assert_eq!(8, map.ranges.len());
+
+    assert_eq!(
+      SourceRange {
+        start: 0x0203,
+        length: 2,
+        file: 0,
+        line: 5,
+        code: true,
+        opcode: Some(0xD0),
+      },
+      map.ranges[2]
+    );
+
+    // data continues onto the next line of the listing
+    assert_eq!((0x020E, 12, false), {
+      let range = &map.ranges[7];
+      (range.start, range.length, range.code)
+    });
+
+    assert!(SourceMap::parse_vasm_listing("not a listing").is_err());
+  }
+
+  #[test]
+  fn test_ca65_dbg() {
+    let dbg = "version\tmajor=2,minor=0
+info\tcsym=0,file=1,lib=0,line=3,mod=1,scope=1,seg=1,span=3,sym=0,type=0
+file\tid=0,name=\"missing, on purpose.s\",size=100,mtime=0x00000000,mod=0
+line\tid=0,file=0,line=4,span=0
+line\tid=1,file=0,line=5,span=1+2
+line\tid=2,file=0,line=20,type=2,span=1
+seg\tid=0,name=\"CODE\",start=0x00C000,size=0x0007,addrsize=absolute,type=ro,oname=\"a.bin\",ooffs=0
+span\tid=0,seg=0,start=0,size=2
+span\tid=1,seg=0,start=2,size=3
+span\tid=2,seg=0,start=5,size=2
+";
+
+    let map = SourceMap::parse_ca65_dbg(dbg, None).unwrap();
+    assert_eq!(vec!["missing, on purpose.s".to_owned()], map.files);
+
+    let ranges: Vec<(u16, u16, u32)> = map
+      .ranges
+      .iter()
+      .map(|range| (range.start, range.length, range.line))
+      .collect();
+    assert_eq!(vec![(0xC000, 2, 4), (0xC002, 3, 5), (0xC005, 2, 5)], ranges);
+  }
+
+  #[test]
+  fn test_lcov() {
+    let map = SourceMap::parse_vasm_listing(LISTING).unwrap();
+    let coverage = Rc::new(RefCell::new(CoverageTraceHandler::in_memory(map)));
+
+    let mut memory = vec![0; 0x10000];
+    memory[0xFFFC..].copy_from_slice(&[0x00, 0x02, 0x00, 0x00]);
+    let memory = BlockMemory::from_file(0x10000, crate::roms::RomFile::new(memory));
+    let mut cpu = Mos6502::new(memory.set_writeable(true), Mos6502Variant::NMOS);
+    cpu.reset();
+
+    let program = [
+      0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0xE0, 0x00, 0xF0, 0x02, 0xA9, 0xFF, 0x4C, 0x0B, 0x02,
+    ];
+    for (offset, byte) in program.iter().enumerate() {
+      cpu.write(0x0200 + offset as u16, *byte);
+    }
+
+    cpu.attach_trace_handler(Box::new(Shared(coverage.clone())));
+    for _ in 0..12 {
+      cpu.tick().unwrap();
+    }
+
+    let coverage = coverage.borrow();
+    assert_eq!(3, coverage.hits(0x0202));
+    assert_eq!(Some((2, 1)), coverage.branch(0x0203));
+    assert_eq!(Some((1, 0)), coverage.branch(0x0207));
+    assert_eq!(0, coverage.hits(0x0209));
+
+    assert_eq!(
+      "TN:
+SF:count.s
+BRDA:5,0,0,2
+BRDA:5,0,1,1
+BRDA:7,0,0,1
+BRDA:7,0,1,0
+BRF:4
+BRH:3
+DA:3,1
+DA:4,3
+DA:5,3
+DA:6,1
+DA:7,1
+DA:8,0
+DA:9,3
+LF:7
+LH:6
+end_of_record
+",
+      coverage.lcov()
+    );
+  }
+
+  #[test]
+  fn test_branch_to_next() {
+    let coverage = Rc::new(RefCell::new(CoverageTraceHandler::in_memory(
+      SourceMap::default(),
+    )));
+
+    let mut memory = vec![0; 0x10000];
+    memory[0xFFFC..].copy_from_slice(&[0x00, 0x02, 0x00, 0x00]);
+    // BEQ with an offset of zero, then JMP back to it
+    memory[0x0200..0x0205].copy_from_slice(&[0xF0, 0x00, 0x4C, 0x00, 0x02]);
+    let memory = BlockMemory::from_file(0x10000, crate::roms::RomFile::new(memory));
+    let mut cpu = Mos6502::new(memory, Mos6502Variant::NMOS);
+    cpu.reset();
+
+    cpu.attach_trace_handler(Box::new(Shared(coverage.clone())));
+    for _ in 0..4 {
+      cpu.tick().unwrap();
+    }
+
+    assert_eq!(Some((2, 2)), coverage.borrow().branch(0x0200));
+  }
+
+  #[test]
+  fn test_unexecuted_branch() {
+    let map = SourceMap::parse_vasm_listing(LISTING).unwrap();
+    let coverage = CoverageTraceHandler::in_memory(map);
+
+    assert!(coverage
+      .lcov()
+      .contains("BRDA:5,0,0,-\nBRDA:5,0,1,-\nBRDA:7,0,0,-\nBRDA:7,0,1,-\nBRF:4\nBRH:0\n"));
+  }
+}
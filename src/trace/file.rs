@@ -1,17 +1,161 @@
+use crate::cpu::mos6502::disasm::Instruction;
+use crate::symbols::SymbolTable;
 use crate::trace::{CpuTrace, TraceHandler};
 use std::{
   fs::File,
   io::{BufWriter, Write},
 };
 
+/// The format of each line (or record) written to a trace file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraceFormat {
+  /// A short line with the disassembled instruction, registers, effective
+  /// address and cycle count.
+  Compact,
+
+  /// The format used by the VICE monitor's `chis` and trace output.
+  Vice,
+
+  /// The format of the `nestest.log` reference trace (without the PPU columns).
+  Nestest,
+
+  /// Fixed-size binary records of 22 bytes each, all little-endian:
+  /// cycle count (8 bytes), address (2), opcode (1), operand length (1),
+  /// operand (2, zero-padded), A, X, Y, SP, P (1 each), a flag which is 1 if an
+  /// effective address is present (1), and the effective address (2).
+  Binary,
+}
+
 pub struct FileTraceHandler {
   file: BufWriter<File>,
+  format: TraceFormat,
+  symbols: SymbolTable,
 }
 
 impl FileTraceHandler {
-  pub fn new(filename: String) -> Self {
+  pub fn new(filename: String, format: TraceFormat) -> Self {
     Self {
       file: BufWriter::new(File::create(filename).expect("Invalid filename")),
+      format,
+      symbols: SymbolTable::new(),
+    }
+  }
+
+  /// Name addresses in the trace using the given symbols. Only the compact
+  /// format shows them, since the others match the output of other tools.
+  pub fn with_symbols(mut self, symbols: SymbolTable) -> Self {
+    self.symbols = symbols;
+    self
+  }
+}
+
+/// Return the opcode and operand of the traced instruction as hex bytes.
+fn format_bytes(trace: &CpuTrace) -> String {
+  let mut bytes = format!("{:02X}", trace.opcode);
+  for byte in &trace.operand {
+    bytes += &format!(" {byte:02X}");
+  }
+  bytes
+}
+
+/// Return the status register as a string of flags, with `.` for unset flags.
+fn format_flags(p: u8) -> String {
+  "NV-BDIZC"
+    .chars()
+    .enumerate()
+    .map(|(i, flag)| match p & (0x80 >> i) {
+      0 if flag != '-' => '.',
+      _ => flag,
+    })
+    .collect()
+}
+
+/// Format a trace event in the given format. If there are any symbols, the
+/// compact format names the address of the instruction in a column after it,
+/// and names the addresses in the operand.
+pub fn format_trace(trace: &CpuTrace, format: TraceFormat, symbols: &SymbolTable) -> Vec<u8> {
+  let instruction = Instruction::decode(trace.opcode, trace.variant);
+  let text = instruction.format(trace.address, &trace.operand);
+
+  match format {
+    TraceFormat::Compact => {
+      let effective_address = match trace.effective_address {
+        Some(address) => format!("${address:04X}"),
+        None => "".to_owned(),
+      };
+
+      let (label, text) = if symbols.is_empty() {
+        ("".to_owned(), text)
+      } else {
+        (
+          format!("{:<16}  ", symbols.label(trace.address).unwrap_or_default()),
+          instruction.format_with_symbols(trace.address, &trace.operand, symbols),
+        )
+      };
+
+      format!(
+        "{:04X}  {}{:<8}  {:<16} {:<5}  A:{:02X} X:{:02X} Y:{:02X} SP:{:02X} P:{:02X} CYC:{}\n",
+        trace.address,
+        label,
+        format_bytes(trace),
+        text,
+        effective_address,
+        trace.a,
+        trace.x,
+        trace.y,
+        trace.sp,
+        trace.p,
+        trace.cycle_count
+      )
+      .into_bytes()
+    }
+    TraceFormat::Vice => format!(
+      ".C:{:04x}  {:<9}  {:<14} - A:{:02X} X:{:02X} Y:{:02X} SP:{:02x} {} {:>10}\n",
+      trace.address,
+      format_bytes(trace),
+      text,
+      trace.a,
+      trace.x,
+      trace.y,
+      trace.sp,
+      format_flags(trace.p),
+      trace.cycle_count
+    )
+    .into_bytes(),
+    TraceFormat::Nestest => {
+      let marker = if instruction.is_documented() {
+        ' '
+      } else {
+        '*'
+      };
+
+      format!(
+        "{:04X}  {:<8} {}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}\n",
+        trace.address,
+        format_bytes(trace),
+        marker,
+        text,
+        trace.a,
+        trace.x,
+        trace.y,
+        trace.p,
+        trace.sp,
+        trace.cycle_count
+      )
+      .into_bytes()
+    }
+    TraceFormat::Binary => {
+      let mut record = Vec::with_capacity(22);
+      record.extend_from_slice(&trace.cycle_count.to_le_bytes());
+      record.extend_from_slice(&trace.address.to_le_bytes());
+      record.push(trace.opcode);
+      record.push(trace.operand.len() as u8);
+      record.push(trace.operand.first().copied().unwrap_or(0));
+      record.push(trace.operand.get(1).copied().unwrap_or(0));
+      record.extend_from_slice(&[trace.a, trace.x, trace.y, trace.sp, trace.p]);
+      record.push(trace.effective_address.is_some() as u8);
+      record.extend_from_slice(&trace.effective_address.unwrap_or(0).to_le_bytes());
+      record
     }
   }
 }
@@ -20,7 +164,7 @@ impl TraceHandler for FileTraceHandler {
   fn handle(&mut self, trace: &CpuTrace) {
     self
       .file
-      .write_all(format!("{:04X}: {:02X}\n", trace.address, trace.opcode).as_bytes())
+      .write_all(&format_trace(trace, self.format, &self.symbols))
       .unwrap();
   }
 
@@ -28,3 +172,74 @@ impl TraceHandler for FileTraceHandler {
     self.file.flush().map_err(|_| "failed to flush file")
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cpu::mos6502::Mos6502Variant;
+
+  fn trace() -> CpuTrace {
+    CpuTrace {
+      address: 0xC000,
+      opcode: 0xBD,
+      operand: vec![0x00, 0x04],
+      a: 0x01,
+      x: 0x10,
+      y: 0x00,
+      sp: 0xFD,
+      p: 0x24,
+      effective_address: Some(0x0410),
+      cycle_count: 7,
+      variant: Mos6502Variant::NMOS,
+    }
+  }
+
+  fn format(format: TraceFormat) -> String {
+    String::from_utf8(format_trace(&trace(), format, &SymbolTable::new())).unwrap()
+  }
+
+  #[test]
+  fn test_text_formats() {
+    assert_eq!(
+      "C000  BD 00 04  LDA $0400,X      $0410  A:01 X:10 Y:00 SP:FD P:24 CYC:7\n",
+      format(TraceFormat::Compact)
+    );
+    assert_eq!(
+      ".C:c000  BD 00 04   LDA $0400,X    - A:01 X:10 Y:00 SP:fd ..-..I..          7\n",
+      format(TraceFormat::Vice)
+    );
+    assert_eq!(
+      "C000  BD 00 04  LDA $0400,X                     A:01 X:10 Y:00 P:24 SP:FD CYC:7\n",
+      format(TraceFormat::Nestest)
+    );
+  }
+
+  #[test]
+  fn test_binary_format() {
+    assert_eq!(
+      vec![
+        7, 0, 0, 0, 0, 0, 0, 0, 0x00, 0xC0, 0xBD, 2, 0x00, 0x04, 0x01, 0x10, 0x00, 0xFD, 0x24, 1,
+        0x10, 0x04
+      ],
+      format_trace(&trace(), TraceFormat::Binary, &SymbolTable::new())
+    );
+  }
+
+  #[test]
+  fn test_symbols() {
+    let mut symbols = SymbolTable::new();
+    symbols.insert("print", 0xBFFD);
+    symbols.insert("screen", 0x0400);
+
+    let format = |format| String::from_utf8(format_trace(&trace(), format, &symbols)).unwrap();
+
+    assert_eq!(
+      "C000  print+3           BD 00 04  LDA screen,X     $0410  A:01 X:10 Y:00 SP:FD P:24 CYC:7\n",
+      format(TraceFormat::Compact)
+    );
+    assert_eq!(
+      ".C:c000  BD 00 04   LDA $0400,X    - A:01 X:10 Y:00 SP:fd ..-..I..          7\n",
+      format(TraceFormat::Vice)
+    );
+  }
+}
@@ -1,10 +1,35 @@
+use crate::cpu::mos6502::Mos6502Variant;
+
+pub mod coverage;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod file;
+pub mod profile;
 
-/// Trace information provided after each instruction by the CPU.
+/// Trace information provided by the CPU before each instruction is executed.
 pub struct CpuTrace {
+  /// The address of the instruction's opcode.
   pub address: u16,
   pub opcode: u8,
+
+  /// The operand bytes following the opcode (zero to two bytes, depending on
+  /// the addressing mode).
+  pub operand: Vec<u8>,
+
+  /// The register values before the instruction is executed.
+  pub a: u8,
+  pub x: u8,
+  pub y: u8,
+  pub sp: u8,
+  pub p: u8,
+
+  /// The address in memory which the instruction operates on, if any.
+  pub effective_address: Option<u16>,
+
+  /// The number of cycles elapsed since the CPU was reset, before this
+  /// instruction is executed.
+  pub cycle_count: u64,
+
+  pub variant: Mos6502Variant,
 }
 
 /// An item which can handle a CPU trace (e.g. logging to a file)
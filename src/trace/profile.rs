@@ -0,0 +1,477 @@
+use crate::cpu::mos6502::Mos6502Variant;
+use crate::symbols::SymbolTable;
+use crate::trace::{CpuTrace, TraceHandler};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+/// The instruction and CPU state most recently traced, whose cycle count is
+/// only known once the next instruction is traced.
+struct Previous {
+  address: u16,
+  opcode: u8,
+  operand: Vec<u8>,
+  x: u8,
+  sp: u8,
+  cycle_count: u64,
+  variant: Mos6502Variant,
+  node: usize,
+}
+
+impl Previous {
+  /// Return the stack pointer expected after this instruction executes,
+  /// assuming that no interrupt occurs.
+  fn expected_sp(&self) -> u8 {
+    let cmos = self.variant == Mos6502Variant::CMOS;
+
+    match self.opcode {
+      0x9A => self.x,                                 // TXS
+      0x00 => self.sp.wrapping_sub(3),                // BRK
+      0x20 => self.sp.wrapping_sub(2),                // JSR
+      0x48 | 0x08 => self.sp.wrapping_sub(1),         // PHA, PHP
+      0xDA | 0x5A if cmos => self.sp.wrapping_sub(1), // PHX, PHY
+      0x68 | 0x28 => self.sp.wrapping_add(1),         // PLA, PLP
+      0xFA | 0x7A if cmos => self.sp.wrapping_add(1), // PLX, PLY
+      0x60 => self.sp.wrapping_add(2),                // RTS
+      0x40 => self.sp.wrapping_add(3),                // RTI
+      _ => self.sp,
+    }
+  }
+}
+
+/// A node in the call tree: a subroutine (or interrupt handler), reached
+/// through a particular chain of callers.
+struct Node {
+  entry: u16,
+  parent: usize,
+  children: HashMap<u16, usize>,
+  calls: u64,
+  instructions: u64,
+  cycles: u64,
+}
+
+/// Totals for a single subroutine, across every chain of callers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubroutineProfile {
+  /// The number of times the subroutine was called (or the interrupt handler
+  /// was entered).
+  pub calls: u64,
+
+  /// The number of cycles spent in the subroutine itself.
+  pub self_cycles: u64,
+
+  /// The number of cycles spent in the subroutine and everything it called.
+  pub total_cycles: u64,
+}
+
+/// A trace handler which measures where a 6502 spends its time. It counts the
+/// instructions executed and cycles taken at each address, and follows the
+/// call stack (through `JSR`, `BRK`, interrupts and the matching returns) to
+/// total the cycles spent in each subroutine.
+///
+/// When the system is cleaned up, it writes a report of the hottest addresses
+/// and subroutines, and the call stacks in the "folded" format read by
+/// flamegraph tools (e.g. `inferno-flamegraph` or `flamegraph.pl`).
+/// Subroutines are named by their entry address (or by symbol, if given a
+/// [`SymbolTable`]), and the root of every stack is the code running when
+/// profiling started.
+pub struct ProfileTraceHandler {
+  report_path: Option<String>,
+  folded_path: Option<String>,
+
+  /// Instructions executed and cycles taken at each address.
+  addresses: Vec<(u64, u64)>,
+
+  /// The call tree. The first node is the root.
+  nodes: Vec<Node>,
+
+  /// The nodes of the subroutines currently being executed, with the stack
+  /// pointer that each will return to.
+  stack: Vec<(usize, u8)>,
+
+  previous: Option<Previous>,
+
+  symbols: SymbolTable,
+}
+
+impl ProfileTraceHandler {
+  /// Create a profiler which writes its report and folded call stacks to the
+  /// given files when flushed.
+  pub fn new(report_path: String, folded_path: String) -> Self {
+    Self {
+      report_path: Some(report_path),
+      folded_path: Some(folded_path),
+      ..Self::in_memory()
+    }
+  }
+
+  /// Create a profiler which does not write any files. Its results can be read
+  /// with [`ProfileTraceHandler::report`] and [`ProfileTraceHandler::folded`].
+  pub fn in_memory() -> Self {
+    Self {
+      report_path: None,
+      folded_path: None,
+      addresses: vec![(0, 0); 0x10000],
+      nodes: Vec::new(),
+      stack: Vec::new(),
+      previous: None,
+      symbols: SymbolTable::new(),
+    }
+  }
+
+  /// Name addresses and subroutines in the report and call stacks using the
+  /// given symbols.
+  pub fn with_symbols(mut self, symbols: SymbolTable) -> Self {
+    self.symbols = symbols;
+    self
+  }
+
+  /// Return the node of the subroutine currently being executed.
+  fn current(&self) -> usize {
+    self.stack.last().map_or(0, |(node, _)| *node)
+  }
+
+  /// Enter the subroutine at the given address from the current one, which
+  /// will return to the given stack pointer.
+  fn call(&mut self, entry: u16, return_sp: u8) {
+    let parent = self.current();
+
+    let node = match self.nodes[parent].children.get(&entry) {
+      Some(node) => *node,
+      None => {
+        let node = self.nodes.len();
+        self.nodes.push(Node {
+          entry,
+          parent,
+          children: HashMap::new(),
+          calls: 0,
+          instructions: 0,
+          cycles: 0,
+        });
+        self.nodes[parent].children.insert(entry, node);
+        node
+      }
+    };
+
+    self.nodes[node].calls += 1;
+    self.stack.push((node, return_sp));
+  }
+
+  /// Update the call stack to follow the previous instruction, given the
+  /// state of the CPU after it executed.
+  fn follow(&mut self, previous: &Previous, trace: &CpuTrace) {
+    let expected_sp = previous.expected_sp();
+
+    // Leave any subroutine whose return address has been popped off the stack
+    // (by RTS or RTI, or by code which adjusts the stack itself)
+    while let Some((_, return_sp)) = self.stack.last() {
+      if *return_sp <= expected_sp {
+        self.stack.pop();
+      } else {
+        break;
+      }
+    }
+
+    match previous.opcode {
+      0x20 => {
+        let target = u16::from_le_bytes([previous.operand[0], previous.operand[1]]);
+        self.call(target, previous.sp);
+      }
+      0x00 if trace.sp == expected_sp => self.call(trace.address, previous.sp),
+      _ => {}
+    }
+
+    // Three more bytes (the program counter and status) were pushed than the
+    // instruction itself would push, so an interrupt occurred
+    if trace.sp == expected_sp.wrapping_sub(3) {
+      self.call(trace.address, expected_sp);
+    }
+  }
+
+  /// Return the totals for each subroutine, by entry address. The code running
+  /// when profiling started is not included.
+  pub fn subroutines(&self) -> HashMap<u16, SubroutineProfile> {
+    // Children are always added after their parents, so walking backwards
+    // totals each subtree before its parent is reached
+    let mut totals: Vec<u64> = self.nodes.iter().map(|node| node.cycles).collect();
+    for index in (1..self.nodes.len()).rev() {
+      totals[self.nodes[index].parent] += totals[index];
+    }
+
+    let mut subroutines: HashMap<u16, SubroutineProfile> = HashMap::new();
+
+    for (index, node) in self.nodes.iter().enumerate().skip(1) {
+      let subroutine = subroutines.entry(node.entry).or_default();
+      subroutine.calls += node.calls;
+      subroutine.self_cycles += node.cycles;
+
+      // Recursive calls are already included in the outermost call's total
+      let mut ancestor = node.parent;
+      let mut recursive = false;
+      while ancestor != 0 {
+        recursive |= self.nodes[ancestor].entry == node.entry;
+        ancestor = self.nodes[ancestor].parent;
+      }
+
+      if !recursive {
+        subroutine.total_cycles += totals[index];
+      }
+    }
+
+    subroutines
+  }
+
+  /// Return a human-readable report of the addresses and subroutines where
+  /// the most cycles were spent.
+  pub fn report(&self) -> String {
+    let instructions: u64 = self.addresses.iter().map(|(count, _)| count).sum();
+    let cycles: u64 = self.addresses.iter().map(|(_, cycles)| cycles).sum();
+    let percent = |part: u64| part as f64 * 100.0 / cycles.max(1) as f64;
+
+    let mut report = String::new();
+    writeln!(report, "{instructions} instructions, {cycles} cycles").unwrap();
+
+    let mut subroutines: Vec<(u16, SubroutineProfile)> = self.subroutines().into_iter().collect();
+    subroutines.sort_by_key(|(entry, profile)| (std::cmp::Reverse(profile.total_cycles), *entry));
+
+    writeln!(report, "\nSubroutines:").unwrap();
+    writeln!(
+      report,
+      "  entry       calls   self cycles      %  total cycles      %"
+    )
+    .unwrap();
+    for (entry, profile) in subroutines {
+      writeln!(
+        report,
+        "  {:<5} {:>11} {:>13} {:>5.1}% {:>13} {:>5.1}%",
+        self.symbols.format(entry),
+        profile.calls,
+        profile.self_cycles,
+        percent(profile.self_cycles),
+        profile.total_cycles,
+        percent(profile.total_cycles),
+      )
+      .unwrap();
+    }
+
+    let mut addresses: Vec<(usize, (u64, u64))> = self
+      .addresses
+      .iter()
+      .copied()
+      .enumerate()
+      .filter(|(_, (count, _))| *count > 0)
+      .collect();
+    addresses.sort_by_key(|(address, (_, cycles))| (std::cmp::Reverse(*cycles), *address));
+
+    writeln!(report, "\nAddresses:").unwrap();
+    writeln!(report, "  address  instructions        cycles      %").unwrap();
+    for (address, (count, cycles)) in addresses {
+      writeln!(
+        report,
+        "  {:<5}  {:>13} {:>13} {:>5.1}%",
+        self.symbols.format(address as u16),
+        count,
+        cycles,
+        percent(cycles),
+      )
+      .unwrap();
+    }
+
+    report
+  }
+
+  /// Return the call stacks in the folded format, with one line per stack
+  /// giving the cycles spent with exactly that stack.
+  pub fn folded(&self) -> String {
+    let mut folded = String::new();
+
+    for (index, node) in self.nodes.iter().enumerate() {
+      if node.cycles == 0 {
+        continue;
+      }
+
+      let mut names = Vec::new();
+      let mut current = index;
+      loop {
+        names.push(self.symbols.format(self.nodes[current].entry));
+        if current == 0 {
+          break;
+        }
+        current = self.nodes[current].parent;
+      }
+      names.reverse();
+
+      writeln!(folded, "{} {}", names.join(";"), node.cycles).unwrap();
+    }
+
+    folded
+  }
+}
+
+impl TraceHandler for ProfileTraceHandler {
+  fn handle(&mut self, trace: &CpuTrace) {
+    if self.nodes.is_empty() {
+      self.nodes.push(Node {
+        entry: trace.address,
+        parent: 0,
+        children: HashMap::new(),
+        calls: 0,
+        instructions: 0,
+        cycles: 0,
+      });
+    }
+
+    if let Some(previous) = self.previous.take() {
+      let cycles = trace.cycle_count.saturating_sub(previous.cycle_count);
+      self.addresses[previous.address as usize].1 += cycles;
+      self.nodes[previous.node].cycles += cycles;
+      self.follow(&previous, trace);
+    }
+
+    let node = self.current();
+    self.addresses[trace.address as usize].0 += 1;
+    self.nodes[node].instructions += 1;
+
+    self.previous = Some(Previous {
+      address: trace.address,
+      opcode: trace.opcode,
+      operand: trace.operand.clone(),
+      x: trace.x,
+      sp: trace.sp,
+      cycle_count: trace.cycle_count,
+      variant: trace.variant,
+      node,
+    });
+  }
+
+  fn flush(&mut self) -> Result<(), &str> {
+    if let Some(path) = &self.report_path {
+      std::fs::write(path, self.report()).map_err(|_| "failed to write profile report")?;
+    }
+
+    if let Some(path) = &self.folded_path {
+      std::fs::write(path, self.folded()).map_err(|_| "failed to write folded call stacks")?;
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cpu::{mos6502::Mos6502, Cpu};
+  use crate::memory::BlockMemory;
+  use crate::roms::RomFile;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  /// Forwards traces to a shared profiler, so it can be inspected afterwards.
+  struct Shared(Rc<RefCell<ProfileTraceHandler>>);
+
+  impl TraceHandler for Shared {
+    fn handle(&mut self, trace: &CpuTrace) {
+      self.0.borrow_mut().handle(trace);
+    }
+  }
+
+  fn profile(program: &[u8], instructions: usize) -> Rc<RefCell<ProfileTraceHandler>> {
+    let mut memory = vec![0; 0x10000];
+    memory[0x0200..0x0200 + program.len()].copy_from_slice(program);
+    memory[0xFFFC..].copy_from_slice(&[0x00, 0x02, 0x00, 0x00]);
+
+    let memory = BlockMemory::from_file(0x10000, RomFile::new(memory)).set_writeable(true);
+    let mut cpu = Mos6502::new(memory, Mos6502Variant::NMOS);
+    cpu.reset();
+
+    let profiler = Rc::new(RefCell::new(ProfileTraceHandler::in_memory()));
+    cpu.attach_trace_handler(Box::new(Shared(profiler.clone())));
+
+    for _ in 0..instructions {
+      cpu.tick().unwrap();
+    }
+
+    profiler
+  }
+
+  #[test]
+  fn test_subroutines() {
+    let profiler = profile(
+      &[
+        // main: JSR outer; JMP main
+        0x20, 0x10, 0x02, 0x4C, 0x00, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        // outer ($0210): JSR inner; JSR inner; RTS
+        0x20, 0x20, 0x02, 0x20, 0x20, 0x02, 0x60, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        // inner ($0220): NOP; RTS
+        0xEA, 0x60,
+      ],
+      // 10 iterations of the main loop
+      10 * 9,
+    );
+    let profiler = profiler.borrow();
+    let subroutines = profiler.subroutines();
+
+    // each iteration: inner is NOP (2) + RTS (6), outer is 2 JSRs (6 each) + RTS (6)
+    let inner = subroutines[&0x0220];
+    assert_eq!(20, inner.calls);
+    assert_eq!(20 * 8, inner.self_cycles);
+    assert_eq!(20 * 8, inner.total_cycles);
+
+    let outer = subroutines[&0x0210];
+    assert_eq!(10, outer.calls);
+    assert_eq!(10 * 18, outer.self_cycles);
+    assert_eq!(10 * 34, outer.total_cycles);
+
+    // the cycles of the final JMP are not known yet
+    let folded = profiler.folded();
+    assert!(folded.contains("$0200 87\n"));
+    assert!(folded.contains("$0200;$0210 180\n"));
+    assert!(folded.contains("$0200;$0210;$0220 160\n"));
+
+    let report = profiler.report();
+    assert!(report.starts_with("90 instructions, 427 cycles\n"));
+    assert!(report.contains("  $0210          10           180  42.2%           340  79.6%\n"));
+    assert!(report.contains("  $0221             20           120  28.1%\n"));
+  }
+
+  #[test]
+  fn test_stack_manipulation() {
+    let profiler = profile(
+      &[
+        // main: JSR sub; JMP main
+        0x20, 0x10, 0x02, 0x4C, 0x00, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        // sub ($0210): PLA; PLA; JMP main (discarding the return address)
+        0x68, 0x68, 0x4C, 0x00, 0x02,
+      ],
+      5 * 4,
+    );
+    let profiler = profiler.borrow();
+
+    // the stack does not grow with each call
+    assert_eq!(5, profiler.subroutines()[&0x0210].calls);
+    assert!(profiler.stack.len() <= 1);
+  }
+
+  #[test]
+  fn test_symbols() {
+    let profiler = profile(
+      &[
+        // main: JSR sub; JMP main
+        0x20, 0x10, 0x02, 0x4C, 0x00, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        // sub ($0210): NOP; RTS
+        0xEA, 0x60,
+      ],
+      5 * 4,
+    );
+
+    let mut symbols = SymbolTable::new();
+    symbols.insert("main", 0x0200);
+    symbols.insert("sub", 0x0210);
+    let profiler = profiler.replace(ProfileTraceHandler::in_memory());
+    let profiler = profiler.with_symbols(symbols);
+
+    assert!(profiler.folded().contains("main;sub 40\n"));
+    assert!(profiler
+      .report()
+      .contains("  sub+1              5            30"));
+  }
+}
@@ -83,6 +83,7 @@ impl NoentiendoBuilder {
         pet_roms,
         PetSystemConfig {
           mapping: KeyMappingStrategy::Symbolic,
+          ..Default::default()
         },
         platform.provider(),
       ),
@@ -90,6 +91,7 @@ impl NoentiendoBuilder {
         vic_roms,
         Vic20SystemConfig {
           mapping: KeyMappingStrategy::Symbolic,
+          ..Default::default()
         },
         platform.provider(),
       ),
@@ -97,6 +99,7 @@ impl NoentiendoBuilder {
         c64_roms,
         C64SystemConfig {
           mapping: KeyMappingStrategy::Symbolic,
+          ..Default::default()
         },
         platform.provider(),
       ),
@@ -0,0 +1,202 @@
+// Boot each of the bundled Commodore systems on the headless platform, and
+// compare what ends up on screen against the golden images in tests/golden.
+//
+// If a change to the emulator is expected to change what's on screen, check
+// the images written to target/golden, then regenerate the goldens with:
+//
+//   NOENTIENDO_UPDATE_GOLDEN=1 cargo test --test boot
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use libnoentiendo::{
+  keyboard::KeyPosition,
+  platform::{write_png, HeadlessPlatform, InputScript, Platform, SyncPlatform},
+  systems::{
+    c64::{C64System, C64SystemConfig, C64SystemRoms},
+    pet::{PetSystem, PetSystemConfig, PetSystemRoms},
+    vic::{Vic20RamExpansion, Vic20System, Vic20SystemConfig, Vic20SystemRoms},
+    BuildableSystem,
+  },
+};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// Set this environment variable to overwrite the goldens instead of checking
+/// against them.
+const UPDATE_GOLDEN: &str = "NOENTIENDO_UPDATE_GOLDEN";
+
+/// The number of frames to run each system for before comparing, enough for
+/// each of them to reach the BASIC ready prompt.
+const BOOT_FRAMES: u64 = 3 * 60;
+
+/// Compare the framebuffer of the finished run against the golden image with
+/// the given name, or replace the golden if regenerating them.
+fn check_golden(name: &str, platform: &HeadlessPlatform) {
+  let config = platform.window().expect("system did not request a window");
+  let golden = Path::new("tests/golden").join(format!("{name}.png"));
+
+  if std::env::var_os(UPDATE_GOLDEN).is_some() {
+    let file = File::create(&golden).unwrap();
+    write_png(BufWriter::new(file), config, platform.framebuffer()).unwrap();
+    return;
+  }
+
+  let decoder = png::Decoder::new(
+    File::open(&golden).unwrap_or_else(|_| panic!("missing golden {}", golden.display())),
+  );
+  let mut reader = decoder.read_info().unwrap();
+  let mut expected = vec![0; reader.output_buffer_size()];
+  let info = reader.next_frame(&mut expected).unwrap();
+
+  let matches = (info.width, info.height) == (config.width, config.height)
+    && expected == platform.framebuffer();
+
+  if !matches {
+    std::fs::create_dir_all("target/golden").unwrap();
+    let actual = Path::new("target/golden").join(format!("{name}.png"));
+    platform.screenshot(actual.to_str().unwrap()).unwrap();
+
+    let differing = expected
+      .chunks(4)
+      .zip(platform.framebuffer().chunks(4))
+      .filter(|(a, b)| a != b)
+      .count();
+
+    panic!(
+      "{name} does not match {} ({}x{} expected, {}x{} rendered, {differing} pixels differ); \
+       the rendered frame was saved to {}. Set {UPDATE_GOLDEN}=1 to regenerate the goldens.",
+      golden.display(),
+      info.width,
+      info.height,
+      config.width,
+      config.height,
+      actual.display(),
+    );
+  }
+}
+
+/// Type the given keys one after another, starting at the given frame.
+fn type_keys(mut script: InputScript, start: u64, keys: &[KeyPosition]) -> InputScript {
+  for (i, key) in keys.iter().enumerate() {
+    script = script.tap(start + i as u64 * 6, 3, *key);
+  }
+
+  script
+}
+
+#[test]
+fn test_pet_boot() {
+  let mut platform = HeadlessPlatform::new().with_frames(BOOT_FRAMES);
+  let system = PetSystem::build(
+    PetSystemRoms::from_disk(),
+    PetSystemConfig::default(),
+    platform.provider(),
+  );
+
+  platform.run(system);
+  check_golden("pet", &platform);
+}
+
+#[test]
+fn test_vic_boot() {
+  let mut platform = HeadlessPlatform::new().with_frames(BOOT_FRAMES);
+  let system = Vic20System::build(
+    Vic20SystemRoms::from_disk(None),
+    Vic20SystemConfig::default(),
+    platform.provider(),
+  );
+
+  platform.run(system);
+  check_golden("vic", &platform);
+}
+
+#[test]
+fn test_vic_boot_open_bus() {
+  // The KERNAL's memory test reads back the data bus from unconnected RAM
+  // expansion sockets, and should still find only the built-in RAM
+  let mut platform = HeadlessPlatform::new().with_frames(BOOT_FRAMES);
+  let system = Vic20System::build(
+    Vic20SystemRoms::from_disk(None),
+    Vic20SystemConfig {
+      open_bus: true,
+      ..Default::default()
+    },
+    platform.provider(),
+  );
+
+  platform.run(system);
+  check_golden("vic", &platform);
+}
+
+#[test]
+fn test_vic_boot_8k() {
+  // With 8K of expansion RAM, the KERNAL moves the screen to $1000 and the
+  // color RAM to $9400
+  let mut platform = HeadlessPlatform::new().with_frames(BOOT_FRAMES);
+  let system = Vic20System::build(
+    Vic20SystemRoms::from_disk(None),
+    Vic20SystemConfig {
+      ram_expansion: Vic20RamExpansion::Ram8K,
+      ..Default::default()
+    },
+    platform.provider(),
+  );
+
+  platform.run(system);
+  check_golden("vic_8k", &platform);
+}
+
+#[test]
+fn test_c64_boot() {
+  let mut platform = HeadlessPlatform::new().with_frames(BOOT_FRAMES);
+  let system = C64System::build(
+    C64SystemRoms::from_disk(),
+    C64SystemConfig::default(),
+    platform.provider(),
+  );
+
+  platform.run(system);
+  check_golden("c64", &platform);
+}
+
+#[test]
+fn test_c64_boot_ram_pattern() {
+  // The KERNAL clears the memory it uses, so the power-on contents of RAM
+  // should not show on the screen
+  let mut platform = HeadlessPlatform::new().with_frames(BOOT_FRAMES);
+  let system = C64System::build(
+    C64SystemRoms::from_disk(),
+    C64SystemConfig {
+      ram_pattern: C64System::RAM_PATTERN,
+      ..Default::default()
+    },
+    platform.provider(),
+  );
+
+  platform.run(system);
+  check_golden("c64", &platform);
+}
+
+#[test]
+fn test_c64_print() {
+  use KeyPosition::*;
+
+  let script = type_keys(
+    InputScript::new(),
+    BOOT_FRAMES,
+    &[P, R, I, N, T, Space, Digit4, Digit2, Enter],
+  );
+
+  let mut platform = HeadlessPlatform::new()
+    .with_frames(BOOT_FRAMES + 90)
+    .with_script(script);
+  let system = C64System::build(
+    C64SystemRoms::from_disk(),
+    C64SystemConfig::default(),
+    platform.provider(),
+  );
+
+  platform.run(system);
+  check_golden("c64_print", &platform);
+}